@@ -8,7 +8,8 @@ pub(crate) enum Annot {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub(crate) enum Atom {
+#[non_exhaustive]
+pub enum Atom {
   Str,
   Escape,
   Hardbreak,
@@ -16,25 +17,50 @@ pub(crate) enum Atom {
   Blankline,
   ImageMarker,
   LeftDoubleQuote,
+  RightDoubleQuote,
+  LeftSingleQuote,
+  RightSingleQuote,
   Ellipses,
+  EnDash,
+  EmDash,
   Softbreak,
   FootnoteReference,
+  NoteLabel,
   OpenMarker,
   Emoji,
   ReferenceKey,
   ReferenceValue,
   CodeLanguage,
+  RawFormat,
+  ThematicBreak,
+  Unparsed,
+  AbbreviationTerm,
+  AbbreviationTitle,
+  Id,
+  Class,
+  Key,
+  Value,
+  CheckboxChecked,
+  CheckboxUnchecked,
+  SeparatorDefault,
+  SeparatorLeft,
+  SeparatorRight,
+  SeparatorCenter,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub(crate) enum Comp {
+#[non_exhaustive]
+pub enum Comp {
   Doc,
   Verbatim,
+  InlineMath,
+  DisplayMath,
   Email,
   Url,
   Subscript,
   Superscript,
   Para,
+  Heading,
   CodeBlock,
   Imagetext,
   Linktext,
@@ -42,8 +68,24 @@ pub(crate) enum Comp {
   Destination,
   Emph,
   Strong,
+  Insert,
+  Delete,
+  Mark,
+  SingleQuoted,
   DoubleQuoted,
   ReferenceDefinition,
+  BlockQuote,
+  ListItem,
+  AbbreviationDefinition,
+  Caption,
+  Table,
+  Row,
+  Cell,
+  Footnote,
+  Div,
+  Span,
+  Attributes,
+  BlockAttributes,
 }
 
 impl PartialEq<Atom> for Annot {
@@ -81,14 +123,35 @@ impl fmt::Display for Atom {
       Atom::Blankline => "blankline",
       Atom::ImageMarker => "image_marker",
       Atom::LeftDoubleQuote => "left_double_quote",
+      Atom::RightDoubleQuote => "right_double_quote",
+      Atom::LeftSingleQuote => "left_single_quote",
+      Atom::RightSingleQuote => "right_single_quote",
       Atom::Ellipses => "ellipses",
+      Atom::EnDash => "en_dash",
+      Atom::EmDash => "em_dash",
       Atom::Softbreak => "softbreak",
       Atom::FootnoteReference => "footnote_reference",
+      Atom::NoteLabel => "note_label",
       Atom::OpenMarker => "open_marker",
       Atom::Emoji => "emoji",
       Atom::ReferenceKey => "reference_key",
       Atom::ReferenceValue => "reference_value",
       Atom::CodeLanguage => "code_language",
+      Atom::RawFormat => "raw_format",
+      Atom::ThematicBreak => "thematic_break",
+      Atom::Unparsed => "unparsed",
+      Atom::AbbreviationTerm => "abbreviation_term",
+      Atom::AbbreviationTitle => "abbreviation_title",
+      Atom::Id => "id",
+      Atom::Class => "class",
+      Atom::Key => "key",
+      Atom::Value => "value",
+      Atom::CheckboxChecked => "checkbox_checked",
+      Atom::CheckboxUnchecked => "checkbox_unchecked",
+      Atom::SeparatorDefault => "separator_default",
+      Atom::SeparatorLeft => "separator_left",
+      Atom::SeparatorRight => "separator_right",
+      Atom::SeparatorCenter => "separator_center",
     };
     f.write_str(s)
   }
@@ -108,11 +171,14 @@ impl fmt::Display for Comp {
     let s = match self {
       Comp::Doc => "doc",
       Comp::Verbatim => "verbatim",
+      Comp::InlineMath => "inline_math",
+      Comp::DisplayMath => "display_math",
       Comp::Email => "email",
       Comp::Url => "url",
       Comp::Subscript => "subscript",
       Comp::Superscript => "superscript",
       Comp::Para => "para",
+      Comp::Heading => "heading",
       Comp::CodeBlock => "code_block",
       Comp::Imagetext => "imagetext",
       Comp::Linktext => "linktext",
@@ -120,8 +186,24 @@ impl fmt::Display for Comp {
       Comp::Destination => "destination",
       Comp::Emph => "emph",
       Comp::Strong => "strong",
+      Comp::Insert => "insert",
+      Comp::Delete => "delete",
+      Comp::Mark => "mark",
+      Comp::SingleQuoted => "single_quoted",
       Comp::DoubleQuoted => "double_quoted",
       Comp::ReferenceDefinition => "reference_definition",
+      Comp::BlockQuote => "blockquote",
+      Comp::ListItem => "list_item",
+      Comp::AbbreviationDefinition => "abbreviation_definition",
+      Comp::Caption => "caption",
+      Comp::Table => "table",
+      Comp::Row => "row",
+      Comp::Cell => "cell",
+      Comp::Footnote => "footnote",
+      Comp::Div => "div",
+      Comp::Span => "span",
+      Comp::Attributes => "attributes",
+      Comp::BlockAttributes => "block_attributes",
     };
     f.write_str(s)
   }
@@ -1,31 +1,70 @@
+use std::ops::Range;
+
 use indexmap::IndexMap;
 
 pub type Attrs = IndexMap<String, String>;
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tag {
-  #[serde(flatten)]
+  /// Identifies the node across reparses of an edited source, see
+  /// [`Document::reparse`](crate::Document::reparse). Not part of equality.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  pub id: NodeId,
+  /// Byte range of the source text the node was parsed from, empty for
+  /// nodes which were not produced by the parser. Not part of equality.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  pub span: Range<usize>,
+  #[cfg_attr(feature = "serde", serde(flatten))]
   pub kind: TagKind,
-  #[serde(skip_serializing_if = "Attrs::is_empty")]
+  #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Attrs::is_empty"))]
   pub attrs: Attrs,
-  #[serde(skip_serializing_if = "Vec::is_empty")]
+  #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
   pub children: Vec<Tag>,
 }
 
+impl PartialEq for Tag {
+  fn eq(&self, other: &Tag) -> bool {
+    self.kind == other.kind && self.attrs == other.attrs && self.children == other.children
+  }
+}
+
+/// Node identifier, unique within a document. Nodes which were not produced
+/// by the parser have the default (zero) id.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub u32);
+
 macro_rules!  tags {
     ($($tag:ident,)*) => {
-#[derive(Debug, Clone, serde::Serialize)]
-#[serde(tag = "tag", rename_all = "lowercase")]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "tag", rename_all = "lowercase"))]
+#[non_exhaustive]
 pub enum TagKind {$(
   $tag($tag)
 ),*}
 
+impl TagKind {
+  /// Name of the kind, e.g. `"CodeBlock"`.
+  pub fn name(&self) -> &'static str {
+    match self {$(
+      TagKind::$tag(_) => stringify!($tag)
+    ),*}
+  }
+}
+
 $(
 impl From<$tag> for TagKind {
   fn from(kind: $tag) -> TagKind {
     TagKind::$tag(kind)
   }
 }
+
+impl From<$tag> for Tag {
+  fn from(kind: $tag) -> Tag {
+    Tag::new(kind)
+  }
+}
 )*
 
 $(
@@ -40,104 +79,485 @@ impl Cast<$tag> for Tag {
 
 tags![
   Doc,
+  Section,
   Heading,
   Para,
+  ThematicBreak,
+  BlockQuote,
+  Callout,
+  List,
+  ListItem,
+  TaskListItem,
+  DefinitionList,
+  DefinitionListItem,
+  Term,
+  Definition,
+  Figure,
+  Caption,
+  Table,
+  Row,
+  Cell,
+  Footnote,
+  Div,
   Link,
   Image,
   CodeBlock,
+  RawBlock,
   ReferenceDefinition,
+  AbbreviationDefinition,
   Strong,
   Emph,
+  Subscript,
+  Superscript,
+  Insert,
+  Delete,
+  Highlight,
   Span,
+  Abbreviation,
+  Citation,
+  CrossReference,
+  FootnoteReference,
+  SingleQuoted,
   DoubleQuoted,
   Verbatim,
+  RawInline,
+  InlineMath,
+  DisplayMath,
   Softbreak,
+  HardBreak,
+  NonBreakingSpace,
+  SmartPunctuation,
+  Symbol,
   Url,
   Str,
+  Unparsed,
   ReferenceKey,
   ReferenceValue,
 ];
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Doc {}
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Section {}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Heading {
   pub level: u32,
+  /// Hierarchical section number (`1`, `1.2`, ...), filled in by
+  /// [`Document::number_headings`](crate::Document::number_headings).
+  #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+  pub number: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Para {}
 
-#[derive(Debug, Clone, serde::Serialize)]
+/// `* * *` or `---`, a horizontal rule.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct ThematicBreak {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct BlockQuote {}
+
+/// A bullet, ordered or task list, holding `ListItem` or `TaskListItem`
+/// children.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct List {
+  /// The marker of the items with the number replaced by `1`, `a`, `A`, `i`
+  /// or `I`, e.g. `(a)` for an ordered list, or `-`, `*` or `+`.
+  pub style: String,
+  /// Number of the first item of ordered lists.
+  #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+  pub start: Option<u64>,
+  /// Whether the items are neither separated by blank lines nor contain
+  /// blocks separated by them. The paragraphs of tight lists are rendered
+  /// without `<p>` tags.
+  pub tight: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct ListItem {}
+
+/// A list of `: term` items, each holding a `Term` followed by a
+/// `Definition`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct DefinitionList {
+  /// Whether the items are neither separated by blank lines nor contain
+  /// blocks separated by them, like [`List::tight`].
+  pub tight: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct DefinitionListItem {}
+
+/// The first paragraph of a definition list item.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Term {}
+
+/// The blocks of a definition list item after the term.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Definition {}
+
+/// `- [x] done`
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct TaskListItem {
+  pub checked: bool,
+}
+
+/// A highlighted note or warning, see
+/// [`ParseOpts::github_alerts`](crate::ParseOpts::github_alerts).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Callout {
+  /// `note`, `tip`, `important`, `warning` or `caution`.
+  pub kind: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Link {
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
   pub destination: Option<String>,
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
   pub reference: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Image {
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
   pub destination: Option<String>,
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
   pub reference: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct CodeBlock {
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
   pub lang: Option<String>,
   pub text: String,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+/// A ` ```=html ` block, passed through to output in `format` as is.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct RawBlock {
+  pub format: String,
+  pub text: String,
+}
+
+/// A block followed by a caption, the first and second child.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Figure {}
+
+/// `^ caption`, below the block it describes.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Caption {}
+
+/// A pipe table, holding `Row`s and an optional `Caption` as the first
+/// child.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Table {}
+
+/// `[^label]: text`, the definition of a footnote. Rendered at the end of
+/// the document in the order of the references.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Footnote {
+  pub label: String,
+}
+
+/// A fenced `::: name` block, the name being its first class.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Div {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Row {
+  /// The row is followed by a separator line like `|---|---|`.
+  pub head: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Cell {
+  pub head: bool,
+  pub align: Alignment,
+}
+
+/// Column alignment, set by the colons of a separator line like
+/// `|:--|:-:|--:|`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Alignment {
+  #[default]
+  Default,
+  Left,
+  Right,
+  Center,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct ReferenceDefinition {}
 
-#[derive(Debug, Clone, serde::Serialize)]
+/// `*[HTML]: HyperText Markup Language`, see
+/// [`ParseOpts::abbreviations`](crate::ParseOpts::abbreviations).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct AbbreviationDefinition {
+  pub term: String,
+  pub title: String,
+}
+
+/// An occurrence of a defined abbreviation, containing the term.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Abbreviation {
+  pub title: String,
+}
+
+/// `[@knuth84, p. 3]` or `@knuth84`, see
+/// [`ParseOpts::citations`](crate::ParseOpts::citations).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Citation {
+  pub key: String,
+  /// The text after the key in a bracketed citation, like `p. 3`.
+  pub locator: Option<String>,
+  pub bracketed: bool,
+}
+
+/// `[@intro]` or `@intro` referring to the element with that identifier,
+/// see [`ParseOpts::cross_references`](crate::ParseOpts::cross_references).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct CrossReference {
+  pub target: String,
+  pub bracketed: bool,
+}
+
+/// `[^label]`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct FootnoteReference {
+  pub label: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Softbreak {}
 
-#[derive(Debug, Clone, serde::Serialize)]
+/// A line break, written as `\` at the end of a line.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct HardBreak {}
+
+/// Written as `\ `.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct NonBreakingSpace {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Strong {}
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Emph {}
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Subscript {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Superscript {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Insert {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Delete {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Highlight {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Span {}
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct SingleQuoted {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct DoubleQuoted {}
 
-#[derive(Debug, Clone, serde::Serialize)]
+/// A dash, an ellipsis or a quote without a matching one, e.g. an
+/// apostrophe.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct SmartPunctuation {
+  /// `left_single_quote`, `right_single_quote`, `left_double_quote`,
+  /// `right_double_quote`, `en_dash`, `em_dash` or `ellipses`.
+  pub kind: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct ReferenceKey {
   pub text: String,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct ReferenceValue {
   pub text: String,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Symbol {
+  pub alias: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Url {
   pub destination: String,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Verbatim {
   pub text: String,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+/// `` `<b>`{=html} ``, passed through to output in `format` as is.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct RawInline {
+  pub format: String,
+  pub text: String,
+}
+
+/// `` $`x^2` ``, TeX rendered within the text.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct InlineMath {
+  pub text: String,
+}
+
+/// `` $$`x^2` ``, TeX rendered on a line of its own.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct DisplayMath {
+  pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Str {
   pub text: String,
 }
 
+/// Inline content which hasn't been parsed yet, see
+/// [`ParseOpts::lazy_inlines`](crate::ParseOpts::lazy_inlines).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Unparsed {
+  pub text: String,
+}
+
 impl Tag {
   pub fn new(kind: impl Into<TagKind>) -> Tag {
-    Tag { kind: kind.into(), attrs: Attrs::new(), children: Vec::new() }
+    Tag {
+      id: NodeId::default(),
+      span: 0..0,
+      kind: kind.into(),
+      attrs: Attrs::new(),
+      children: Vec::new(),
+    }
   }
 
   pub fn with_attrs(mut self, attrs: Attrs) -> Tag {
@@ -150,6 +570,100 @@ impl Tag {
     self
   }
 
+  pub fn get_attr(&self, key: &str) -> Option<&str> {
+    self.attrs.get(key).map(|it| it.as_str())
+  }
+
+  /// Sets an attribute, replacing any previous value (`class` included, use
+  /// [`Tag::add_class`] to extend it instead).
+  pub fn set_attr(&mut self, key: impl Into<String>, value: impl Into<String>) {
+    self.attrs.insert(key.into(), value.into());
+  }
+
+  pub fn remove_attr(&mut self, key: &str) -> Option<String> {
+    self.attrs.shift_remove(key)
+  }
+
+  pub fn id(&self) -> Option<&str> {
+    self.get_attr("id")
+  }
+
+  pub fn set_id(&mut self, id: impl Into<String>) {
+    self.set_attr("id", id)
+  }
+
+  pub fn has_class(&self, class: &str) -> bool {
+    has_class(&self.attrs, class)
+  }
+
+  /// Appends `class` to the space separated `class` attribute, unless it is
+  /// already there.
+  pub fn add_class(&mut self, class: &str) {
+    if self.has_class(class) {
+      return;
+    }
+    match self.attrs.get_mut("class") {
+      Some(classes) if !classes.trim().is_empty() => {
+        classes.push(' ');
+        classes.push_str(class);
+      }
+      _ => self.set_attr("class", class),
+    }
+  }
+
+  /// Removes `class` from the `class` attribute, dropping the attribute
+  /// altogether once no classes are left.
+  pub fn remove_class(&mut self, class: &str) {
+    let Some(classes) = self.attrs.get_mut("class") else { return };
+    *classes =
+      classes.split_ascii_whitespace().filter(|&it| it != class).collect::<Vec<_>>().join(" ");
+    if classes.is_empty() {
+      self.remove_attr("class");
+    }
+  }
+
+  /// Merges `attrs` into the tag's attributes the way djot combines
+  /// attributes: classes are appended, other keys overwrite.
+  pub fn merge_attrs(&mut self, attrs: &Attrs) {
+    for (key, value) in attrs {
+      if key == "class" {
+        for class in value.split_ascii_whitespace() {
+          self.add_class(class)
+        }
+      } else {
+        self.set_attr(key, value)
+      }
+    }
+  }
+
+  /// The text content of the subtree, without any markup.
+  pub fn text(&self) -> String {
+    crate::tree::get_string_content(self)
+  }
+
+  /// Renders just this subtree, e.g. to update the preview of a single
+  /// block. Document level output, like the bibliography or the index, is
+  /// left out.
+  #[cfg(feature = "html")]
+  pub fn to_html(&self, opts: &crate::HtmlOpts) -> String {
+    crate::html::convert_tag(opts, self)
+  }
+
+  /// The first node in the subtree (including this one) with the given
+  /// `id` attribute.
+  pub fn find_by_id(&self, id: &str) -> Option<&Tag> {
+    if self.id() == Some(id) {
+      return Some(self);
+    }
+    self.children.iter().find_map(|it| it.find_by_id(id))
+  }
+
+  /// Parses the inline content of this subtree left unparsed by
+  /// [`ParseOpts::lazy_inlines`](crate::ParseOpts::lazy_inlines).
+  pub fn parse_inlines(&mut self) {
+    crate::tree::parse_inlines(&mut self.children)
+  }
+
   pub(crate) fn cast<T>(&mut self) -> &mut T
   where
     Self: Cast<T>,
@@ -158,12 +672,232 @@ impl Tag {
   }
 }
 
+pub(crate) fn has_class(attrs: &Attrs, class: &str) -> bool {
+  attrs.get("class").is_some_and(|it| it.split_ascii_whitespace().any(|it| it == class))
+}
+
+impl Heading {
+  pub fn new(level: u32) -> Heading {
+    Heading { level, number: None }
+  }
+}
+
+impl List {
+  pub fn new(style: impl Into<String>) -> List {
+    let style = style.into();
+    let start = crate::lists::start(&style, &style);
+    List { style, start, tight: true }
+  }
+}
+
+impl DefinitionList {
+  pub fn new(tight: bool) -> DefinitionList {
+    DefinitionList { tight }
+  }
+}
+
+impl TaskListItem {
+  pub fn new(checked: bool) -> TaskListItem {
+    TaskListItem { checked }
+  }
+}
+
+impl Alignment {
+  /// `default`, `left`, `right` or `center`.
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Alignment::Default => "default",
+      Alignment::Left => "left",
+      Alignment::Right => "right",
+      Alignment::Center => "center",
+    }
+  }
+}
+
+impl Row {
+  pub fn new(head: bool) -> Row {
+    Row { head }
+  }
+}
+
+impl Cell {
+  pub fn new(head: bool, align: Alignment) -> Cell {
+    Cell { head, align }
+  }
+}
+
+impl AbbreviationDefinition {
+  pub fn new(term: impl Into<String>, title: impl Into<String>) -> AbbreviationDefinition {
+    AbbreviationDefinition { term: term.into(), title: title.into() }
+  }
+}
+
+impl Abbreviation {
+  pub fn new(title: impl Into<String>) -> Abbreviation {
+    Abbreviation { title: title.into() }
+  }
+}
+
+impl Callout {
+  pub fn new(kind: impl Into<String>) -> Callout {
+    Callout { kind: kind.into() }
+  }
+}
+
+impl Link {
+  pub fn new(destination: impl Into<String>) -> Link {
+    Link { destination: Some(destination.into()), reference: None }
+  }
+
+  /// A link to a reference definition, `[text][reference]`.
+  pub fn from_reference(reference: impl Into<String>) -> Link {
+    Link { destination: None, reference: Some(reference.into()) }
+  }
+}
+
+impl Image {
+  pub fn new(destination: impl Into<String>) -> Image {
+    Image { destination: Some(destination.into()), reference: None }
+  }
+
+  pub fn from_reference(reference: impl Into<String>) -> Image {
+    Image { destination: None, reference: Some(reference.into()) }
+  }
+}
+
+impl CodeBlock {
+  pub fn new(text: impl Into<String>) -> CodeBlock {
+    CodeBlock { lang: None, text: text.into() }
+  }
+
+  pub fn with_lang(mut self, lang: impl Into<String>) -> CodeBlock {
+    self.lang = Some(lang.into());
+    self
+  }
+}
+
+impl ReferenceKey {
+  pub fn new(text: impl Into<String>) -> ReferenceKey {
+    ReferenceKey { text: text.into() }
+  }
+}
+
+impl ReferenceValue {
+  pub fn new(text: impl Into<String>) -> ReferenceValue {
+    ReferenceValue { text: text.into() }
+  }
+}
+
+impl SmartPunctuation {
+  pub fn new(kind: impl Into<String>) -> SmartPunctuation {
+    SmartPunctuation { kind: kind.into() }
+  }
+
+  /// The typographic character, e.g. `’` for `right_single_quote`.
+  pub fn text(&self) -> &'static str {
+    match self.kind.as_str() {
+      "left_single_quote" => "‘",
+      "right_single_quote" => "’",
+      "left_double_quote" => "“",
+      "right_double_quote" => "”",
+      "en_dash" => "–",
+      "em_dash" => "—",
+      "ellipses" => "…",
+      _ => "",
+    }
+  }
+}
+
+impl Symbol {
+  pub fn new(alias: impl Into<String>) -> Symbol {
+    Symbol { alias: alias.into() }
+  }
+}
+
+impl Citation {
+  pub fn new(key: impl Into<String>) -> Citation {
+    Citation { key: key.into(), locator: None, bracketed: false }
+  }
+}
+
+impl CrossReference {
+  pub fn new(target: impl Into<String>) -> CrossReference {
+    CrossReference { target: target.into(), bracketed: false }
+  }
+}
+
+impl Footnote {
+  pub fn new(label: impl Into<String>) -> Footnote {
+    Footnote { label: label.into() }
+  }
+}
+
+impl FootnoteReference {
+  pub fn new(label: impl Into<String>) -> FootnoteReference {
+    FootnoteReference { label: label.into() }
+  }
+}
+
+impl Url {
+  pub fn new(destination: impl Into<String>) -> Url {
+    Url { destination: destination.into() }
+  }
+}
+
+impl RawBlock {
+  pub fn new(format: impl Into<String>, text: impl Into<String>) -> RawBlock {
+    RawBlock { format: format.into(), text: text.into() }
+  }
+}
+
+impl RawInline {
+  pub fn new(format: impl Into<String>, text: impl Into<String>) -> RawInline {
+    RawInline { format: format.into(), text: text.into() }
+  }
+}
+
+impl Verbatim {
+  pub fn new(text: impl Into<String>) -> Verbatim {
+    Verbatim { text: text.into() }
+  }
+}
+
+impl InlineMath {
+  pub fn new(text: impl Into<String>) -> InlineMath {
+    InlineMath { text: text.into() }
+  }
+}
+
+impl DisplayMath {
+  pub fn new(text: impl Into<String>) -> DisplayMath {
+    DisplayMath { text: text.into() }
+  }
+}
+
 impl Str {
   pub fn new(text: impl Into<String>) -> Str {
     Str { text: text.into() }
   }
 }
 
+impl Unparsed {
+  pub fn new(text: impl Into<String>) -> Unparsed {
+    Unparsed { text: text.into() }
+  }
+}
+
+impl From<&str> for Str {
+  fn from(text: &str) -> Str {
+    Str::new(text)
+  }
+}
+
+impl From<String> for Str {
+  fn from(text: String) -> Str {
+    Str::new(text)
+  }
+}
+
 pub(crate) trait Cast<T> {
   fn cast(&mut self) -> &mut T;
 }
@@ -0,0 +1,257 @@
+use std::ops::Range;
+
+use crate::{annot::Atom, ast::Attrs, Diagnostic};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum State {
+  #[default]
+  Start,
+  Scanning,
+  Id,
+  Class,
+  Key,
+  Value,
+  BareValue,
+  QuotedValue,
+  QuotedValueContinuation,
+  Escaped,
+  EscapedInContinuation,
+  Comment,
+  Fail,
+  Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Status {
+  /// At the closing `}`.
+  Done(usize),
+  /// At the first byte which isn't valid there.
+  Fail(usize),
+  /// The input ended inside the attributes, feed the next line.
+  Continue,
+}
+
+/// Parses `{#id .class key="value"}` incrementally, so that attributes can
+/// span several lines of a block.
+#[derive(Debug, Default)]
+pub(crate) struct AttributeParser {
+  state: State,
+  begin: Option<usize>,
+  lastpos: usize,
+  pub(crate) matches: Vec<(Range<usize>, Atom)>,
+}
+
+fn is_key_byte(b: u8) -> bool {
+  b.is_ascii_alphanumeric() || matches!(b, b'_' | b':' | b'-')
+}
+
+fn is_id_byte(b: u8) -> bool {
+  // anything but whitespace and punctuation, which includes non-ASCII text
+  !(b.is_ascii_whitespace() || b.is_ascii_punctuation()) || matches!(b, b'_' | b'-' | b':')
+}
+
+impl AttributeParser {
+  /// Feeds the bytes of `subject` in `range`, starting with the opening `{`.
+  pub(crate) fn feed(&mut self, subject: &str, range: Range<usize>) -> Status {
+    let bytes = subject.as_bytes();
+    for pos in range {
+      self.state = self.step(bytes, pos);
+      match self.state {
+        State::Done => return Status::Done(pos),
+        State::Fail => {
+          self.lastpos = pos;
+          return Status::Fail(pos);
+        }
+        _ => self.lastpos = pos,
+      }
+    }
+    Status::Continue
+  }
+
+  fn add_match(&mut self, range: Range<usize>, atom: Atom) {
+    self.matches.push((range, atom))
+  }
+
+  fn step(&mut self, bytes: &[u8], pos: usize) -> State {
+    let c = bytes[pos];
+    match self.state {
+      State::Start if c == b'{' => State::Scanning,
+      State::Start | State::Fail => State::Fail,
+      State::Done => State::Done,
+      State::Scanning => match c {
+        b' ' | b'\t' | b'\n' | b'\r' => State::Scanning,
+        b'}' => State::Done,
+        b'#' | b'%' | b'.' => {
+          self.begin = Some(pos);
+          match c {
+            b'#' => State::Id,
+            b'%' => State::Comment,
+            _ => State::Class,
+          }
+        }
+        _ if is_key_byte(c) => {
+          self.begin = Some(pos);
+          State::Key
+        }
+        _ => State::Fail,
+      },
+      State::Comment => match c {
+        b'%' => State::Scanning,
+        b'}' => State::Done,
+        _ => State::Comment,
+      },
+      State::Id | State::Class => {
+        if is_id_byte(c) {
+          return self.state;
+        }
+        if c != b'}' && !c.is_ascii_whitespace() {
+          return State::Fail;
+        }
+        let begin = self.begin.take().unwrap_or(pos);
+        if self.lastpos > begin {
+          let atom = if self.state == State::Id { Atom::Id } else { Atom::Class };
+          self.add_match(begin + 1..self.lastpos + 1, atom)
+        }
+        if c == b'}' {
+          State::Done
+        } else {
+          State::Scanning
+        }
+      }
+      State::Key => match c {
+        b'=' => {
+          let begin = self.begin.take().unwrap_or(pos);
+          self.add_match(begin..pos, Atom::Key);
+          State::Value
+        }
+        _ if is_key_byte(c) => State::Key,
+        _ => State::Fail,
+      },
+      State::Value => match c {
+        b'"' => {
+          self.begin = Some(pos + 1);
+          State::QuotedValue
+        }
+        _ if is_key_byte(c) => {
+          self.begin = Some(pos);
+          State::BareValue
+        }
+        _ => State::Fail,
+      },
+      State::BareValue => match c {
+        _ if is_key_byte(c) => State::BareValue,
+        b'}' | b' ' | b'\t' | b'\n' | b'\r' => {
+          let begin = self.begin.take().unwrap_or(pos);
+          self.add_match(begin..pos, Atom::Value);
+          if c == b'}' {
+            State::Done
+          } else {
+            State::Scanning
+          }
+        }
+        _ => State::Fail,
+      },
+      State::Escaped => State::QuotedValue,
+      State::EscapedInContinuation => State::QuotedValueContinuation,
+      State::QuotedValue | State::QuotedValueContinuation => {
+        let begin = *self.begin.get_or_insert(pos);
+        match c {
+          b'"' => {
+            self.begin = None;
+            self.add_match(begin..pos, Atom::Value);
+            State::Scanning
+          }
+          b'\n' => {
+            self.begin = None;
+            self.add_match(begin..pos, Atom::Value);
+            State::QuotedValueContinuation
+          }
+          b'\\' if self.state == State::QuotedValue => State::Escaped,
+          b'\\' => State::EscapedInContinuation,
+          _ => self.state,
+        }
+      }
+    }
+  }
+}
+
+/// Collects the attributes from the matches of an [`AttributeParser`]:
+/// classes accumulate, anything else is overwritten.
+pub(crate) fn to_attrs(subject: &str, matches: &[(Range<usize>, Atom)], attrs: &mut Attrs) {
+  let mut key = None;
+  let mut value: Option<String> = None;
+  for (range, atom) in matches {
+    let text = &subject[range.clone()];
+    match atom {
+      Atom::Value => match &mut value {
+        Some(value) => {
+          value.push(' ');
+          value.push_str(text.trim_start())
+        }
+        None => value = Some(text.to_string()),
+      },
+      _ => {
+        flush(attrs, &mut key, &mut value);
+        match atom {
+          Atom::Id => insert(attrs, "id", text.to_string()),
+          Atom::Class => insert(attrs, "class", text.to_string()),
+          _ => key = Some(text),
+        }
+      }
+    }
+  }
+  flush(attrs, &mut key, &mut value)
+}
+
+/// Values continued on the next line are joined with a space.
+fn flush(attrs: &mut Attrs, key: &mut Option<&str>, value: &mut Option<String>) {
+  if let (Some(key), Some(value)) = (key.take(), value.take()) {
+    insert(attrs, key, unescape(&value))
+  }
+}
+
+fn insert(attrs: &mut Attrs, key: &str, value: String) {
+  match attrs.get_mut(key) {
+    Some(classes) if key == "class" => {
+      classes.push(' ');
+      classes.push_str(&value)
+    }
+    _ => {
+      attrs.insert(key.to_string(), value);
+    }
+  }
+}
+
+fn unescape(text: &str) -> String {
+  let mut res = String::new();
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    match chars.peek() {
+      Some(&next) if c == '\\' && next.is_ascii_punctuation() => {
+        res.push(next);
+        chars.next();
+      }
+      _ => res.push(c),
+    }
+  }
+  res
+}
+
+/// Parses a complete attribute block like `{#id .class key="value"}`.
+pub(crate) fn parse(text: &str) -> Result<Attrs, Diagnostic> {
+  let start = text.len() - text.trim_start().len();
+  let mut parser = AttributeParser::default();
+  match parser.feed(text, start..text.len()) {
+    Status::Done(end) if text[end + 1..].trim().is_empty() => {
+      let mut attrs = Attrs::new();
+      to_attrs(text, &parser.matches, &mut attrs);
+      Ok(attrs)
+    }
+    Status::Done(end) => Err(Diagnostic::error(end + 1..text.len(), "text after attributes")),
+    Status::Fail(pos) => {
+      let end = pos + text[pos..].chars().next().map_or(0, char::len_utf8);
+      Err(Diagnostic::error(pos..end, "invalid attribute syntax"))
+    }
+    Status::Continue => Err(Diagnostic::error(start..text.len(), "unclosed attributes")),
+  }
+}
@@ -0,0 +1,34 @@
+use crate::{
+  ast::{Tag, TagKind},
+  TextDirection,
+};
+
+/// The direction of the first character with a strong direction, which is
+/// how browsers resolve `dir="auto"`.
+pub(crate) fn first_strong(tag: &Tag) -> Option<TextDirection> {
+  match &tag.kind {
+    TagKind::Str(str) => str.text.chars().find_map(char_direction),
+    TagKind::Unparsed(unparsed) => unparsed.text.chars().find_map(char_direction),
+    // code and URLs don't tell the direction of the surrounding text
+    TagKind::Verbatim(_)
+    | TagKind::RawInline(_)
+    | TagKind::InlineMath(_)
+    | TagKind::DisplayMath(_)
+    | TagKind::Url(_)
+    | TagKind::CodeBlock(_)
+    | TagKind::RawBlock(_) => None,
+    _ => tag.children.iter().find_map(first_strong),
+  }
+}
+
+fn char_direction(c: char) -> Option<TextDirection> {
+  match c {
+    // Hebrew, Arabic, Syriac, Thaana, NKo, Samaritan, Mandaic and their
+    // presentation forms
+    '\u{0590}'..='\u{08ff}' | '\u{fb1d}'..='\u{fdff}' | '\u{fe70}'..='\u{feff}' => {
+      Some(TextDirection::Rtl)
+    }
+    _ if c.is_alphabetic() => Some(TextDirection::Ltr),
+    _ => None,
+  }
+}
@@ -0,0 +1,391 @@
+//! Converts djot to HTML.
+//!
+//! ```text
+//! djot [--mmap] [--from FORMAT] [--to FORMAT] [--standalone] [-o OUTPUT] [FILE]...
+//! djot --watch [--serve ADDR] [-o OUTPUT] FILE
+//! djot --site [--template FILE] -o OUTPUT DIR
+//! ```
+//!
+//! Reads the files (or stdin, when none are given or for `-`) and writes
+//! the HTML to stdout or `OUTPUT`. `--to` selects another output format:
+//!
+//! - `html` (the default)
+//! - `djot`, normalized djot source
+//! - `text`, the plain text of each block, wrapped at `--width N`
+//!   characters if given
+//! - `latex`, with the `latex` feature
+//! - `ast`, an indented outline of the syntax tree
+//! - `json`, the syntax tree as JSON
+//! - `matches`, the raw matches produced by the parser
+//!
+//! As in djot.lua, `--ast` (or `-a`) is short for `--to json` and
+//! `--matches` (or `-m`) for `--to matches`. `--sourcepos` (or `-p`) adds
+//! the source positions of the elements to the HTML and JSON output.
+//!
+//! `--standalone` (or `-s`) renders complete HTML pages (or LaTeX
+//! documents) rather than fragments.
+//!
+//! `--from html` reads HTML instead of djot, converting the elements with a
+//! djot equivalent (see `djot::from_html`). This requires the `from-html`
+//! feature. `--from json` reads the syntax tree as written by `--to json`.
+//!
+//! `--filter FILE` applies a Rhai filter script (see `djot::script`) to the
+//! document before rendering, and can be given several times. This
+//! requires the `rhai` feature.
+//!
+//! Options are read from `--config FILE`, or from `djot.toml` in the current
+//! directory if there is one (see `djot::config`), which requires the
+//! `config` feature. `--template` and `--filter` are used in addition to the
+//! ones given there.
+//!
+//! With `--watch`, `FILE` is rendered again whenever it changes. `--serve`
+//! additionally serves the result on `ADDR` (e.g. `localhost:8000`),
+//! reloading the page in the browser on every change.
+//!
+//! With `--site`, the `.dj` files in `DIR` and its subdirectories are
+//! converted to `.html` pages in the `OUTPUT` directory, using a template
+//! with `{{title}}` and `{{content}}` placeholders, and any other files are
+//! copied. Links to `.dj` files are changed to point at the `.html` pages.
+
+mod site;
+mod watch;
+
+use std::{
+  env, fs,
+  io::{self, Read, Write},
+  path::{Path, PathBuf},
+  process::ExitCode,
+};
+
+#[cfg(feature = "config")]
+use djot::config::Config;
+#[cfg(feature = "latex")]
+use djot::LatexOpts;
+use djot::{Document, HtmlOpts, ParseOpts, TextOpts, Utf8Validation};
+
+const USAGE: &str =
+  "usage: djot [--config FILE] [--mmap] [--from FORMAT] [--to FORMAT] [--ast] [--matches] [--sourcepos] [--standalone] [--width N] [--filter FILE]... [-o OUTPUT] [FILE]...
+       djot --watch [--serve ADDR] [-o OUTPUT] FILE
+       djot --site [--template FILE] -o OUTPUT DIR";
+
+struct Args {
+  config: Option<String>,
+  mmap: bool,
+  watch: bool,
+  serve: Option<String>,
+  site: bool,
+  template: Option<String>,
+  from: InputFormat,
+  to: To,
+  sourcepos: bool,
+  standalone: bool,
+  width: Option<usize>,
+  filters: Vec<String>,
+  output: Option<String>,
+  files: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum InputFormat {
+  Djot,
+  Html,
+  Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum To {
+  Html,
+  Djot,
+  Text,
+  #[cfg(feature = "latex")]
+  Latex,
+  Ast,
+  Json,
+  Matches,
+}
+
+impl To {
+  fn parse(name: &str) -> Result<To, String> {
+    let res = match name {
+      "html" => To::Html,
+      "djot" => To::Djot,
+      "text" => To::Text,
+      #[cfg(feature = "latex")]
+      "latex" => To::Latex,
+      #[cfg(not(feature = "latex"))]
+      "latex" => return Err("`--to latex` requires building with the `latex` feature".to_string()),
+      "ast" => To::Ast,
+      "json" => To::Json,
+      "matches" => To::Matches,
+      _ => return Err(format!("unknown output format `{name}`")),
+    };
+    Ok(res)
+  }
+
+  fn render(self, doc: &Document, opts: &HtmlOpts, text_opts: &TextOpts) -> String {
+    match self {
+      To::Html => doc.to_html_opts(opts),
+      To::Djot => doc.to_djot(),
+      To::Text => doc.to_text(text_opts),
+      #[cfg(feature = "latex")]
+      To::Latex => doc.to_latex(&LatexOpts { standalone: opts.standalone }),
+      To::Ast => doc.dump(),
+      To::Json => format!("{}\n", doc.to_json()),
+      To::Matches => doc.debug.clone(),
+    }
+  }
+}
+
+fn main() -> ExitCode {
+  match try_main() {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(err) => {
+      eprintln!("djot: {err}");
+      ExitCode::FAILURE
+    }
+  }
+}
+
+fn try_main() -> Result<(), String> {
+  let args = parse_args(env::args().skip(1))?;
+  let config = load_config(args.config.as_deref())?;
+  let opts = HtmlOpts {
+    standalone: config.html.standalone || args.standalone,
+    include_positions: config.html.include_positions || args.sourcepos,
+    ..config.html
+  };
+  let parse_opts = ParseOpts {
+    debug_matches: args.to == To::Matches,
+    source_positions: config.parse.source_positions || args.sourcepos,
+    ..config.parse
+  };
+  let text_opts = TextOpts { width: args.width, hyphenate: true, ..TextOpts::default() };
+  let filter_files: Vec<PathBuf> =
+    config.filters.into_iter().chain(args.filters.iter().map(PathBuf::from)).collect();
+  let filters = Filters::load(&filter_files)?;
+  if args.watch {
+    let [file] = args.files.as_slice() else {
+      return Err(format!("`--watch` takes a single file\n{USAGE}"));
+    };
+    let render = |file: &str| {
+      let mut doc = parse_read(file, &parse_opts, args.from)?;
+      filters.apply(&mut doc)?;
+      Ok(args.to.render(&doc, &opts, &text_opts))
+    };
+    return watch::watch(file, args.output.as_deref(), args.serve.as_deref(), render);
+  }
+  if args.site {
+    if args.to != To::Html {
+      return Err("`--site` only supports HTML output".to_string());
+    }
+    let ([dir], Some(output)) = (args.files.as_slice(), &args.output) else {
+      return Err(format!("`--site` takes a directory and `-o OUTPUT`\n{USAGE}"));
+    };
+    let template = match args.template.map(PathBuf::from).or(config.template) {
+      Some(file) => {
+        fs::read_to_string(&file).map_err(|err| format!("{}: {err}", file.display()))?
+      }
+      None => site::DEFAULT_TEMPLATE.to_string(),
+    };
+    let site = site::Site { template: &template, opts: &opts, filters: &filters };
+    return site.convert(Path::new(dir), Path::new(output));
+  }
+
+  let mut out: Box<dyn Write> = match &args.output {
+    Some(output) => Box::new(fs::File::create(output).map_err(|err| format!("{output}: {err}"))?),
+    None => Box::new(io::stdout().lock()),
+  };
+  for file in &args.files {
+    let mut doc = match file.as_str() {
+      "-" => parse_stdin(&parse_opts, args.from),
+      _ if args.mmap => parse_mmap(file, &parse_opts, args.from),
+      _ => parse_read(file, &parse_opts, args.from),
+    }
+    .map_err(|err| format!("{file}: {err}"))?;
+    filters.apply(&mut doc).map_err(|err| format!("{file}: {err}"))?;
+    let res = match args.to {
+      To::Html => write!(out, "{}", doc.html(&opts)),
+      to => out.write_all(to.render(&doc, &opts, &text_opts).as_bytes()),
+    };
+    res.map_err(|err| err.to_string())?;
+  }
+  Ok(())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+  let mut res = Args {
+    config: None,
+    mmap: false,
+    watch: false,
+    serve: None,
+    site: false,
+    template: None,
+    from: InputFormat::Djot,
+    to: To::Html,
+    sourcepos: false,
+    standalone: false,
+    width: None,
+    filters: Vec::new(),
+    output: None,
+    files: Vec::new(),
+  };
+  while let Some(arg) = args.next() {
+    let mut value = || args.next().ok_or_else(|| format!("`{arg}` needs a value\n{USAGE}"));
+    match arg.as_str() {
+      "--config" => res.config = Some(value()?),
+      "--mmap" => res.mmap = true,
+      "--watch" => res.watch = true,
+      "--serve" => res.serve = Some(value()?),
+      "--site" => res.site = true,
+      "--template" => res.template = Some(value()?),
+      "--from" => match value()?.as_str() {
+        "djot" => res.from = InputFormat::Djot,
+        "html" => res.from = InputFormat::Html,
+        "json" => res.from = InputFormat::Json,
+        name => return Err(format!("unknown input format `{name}`")),
+      },
+      "--to" => res.to = To::parse(&value()?)?,
+      "-a" | "--ast" => res.to = To::Json,
+      "-m" | "--matches" => res.to = To::Matches,
+      "-p" | "--sourcepos" => res.sourcepos = true,
+      "-s" | "--standalone" => res.standalone = true,
+      "--width" => {
+        let width = value()?;
+        res.width = Some(width.parse().map_err(|_| format!("invalid width `{width}`"))?)
+      }
+      "--filter" => res.filters.push(value()?),
+      "-o" | "--output" => res.output = Some(value()?),
+      "-h" | "--help" => return Err(USAGE.to_string()),
+      _ if arg.starts_with('-') && arg != "-" => {
+        return Err(format!("unknown flag `{arg}`\n{USAGE}"))
+      }
+      _ => res.files.push(arg),
+    }
+  }
+  if res.serve.is_some() && !res.watch {
+    return Err(format!("`--serve` requires `--watch`\n{USAGE}"));
+  }
+  if res.template.is_some() && !res.site {
+    return Err(format!("`--template` requires `--site`\n{USAGE}"));
+  }
+  if res.files.is_empty() && !res.watch && !res.site {
+    res.files.push("-".to_string())
+  }
+  Ok(res)
+}
+
+#[cfg(feature = "rhai")]
+struct Filters(Vec<djot::script::ScriptFilter>);
+
+#[cfg(feature = "rhai")]
+impl Filters {
+  fn load(files: &[PathBuf]) -> Result<Filters, String> {
+    let mut res = Vec::new();
+    for file in files {
+      let err = |err: &dyn std::fmt::Display| format!("{}: {err}", file.display());
+      let script = fs::read_to_string(file).map_err(|it| err(&it))?;
+      res.push(djot::script::ScriptFilter::new(&script).map_err(|it| err(&it))?)
+    }
+    Ok(Filters(res))
+  }
+
+  fn apply(&self, doc: &mut Document) -> Result<(), String> {
+    for filter in &self.0 {
+      filter.apply(doc).map_err(|err| err.to_string())?
+    }
+    Ok(())
+  }
+}
+
+#[cfg(not(feature = "rhai"))]
+struct Filters;
+
+#[cfg(not(feature = "rhai"))]
+impl Filters {
+  fn load(files: &[PathBuf]) -> Result<Filters, String> {
+    if !files.is_empty() {
+      return Err("`--filter` requires building with the `rhai` feature".to_string());
+    }
+    Ok(Filters)
+  }
+
+  fn apply(&self, _doc: &mut Document) -> Result<(), String> {
+    Ok(())
+  }
+}
+
+#[cfg(feature = "config")]
+fn load_config(file: Option<&str>) -> Result<Config, String> {
+  let file = match file {
+    Some(file) => file,
+    None if Path::new("djot.toml").exists() => "djot.toml",
+    None => return Ok(Config::default()),
+  };
+  Config::from_config_file(file).map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "config"))]
+#[derive(Default)]
+struct Config {
+  parse: ParseOpts,
+  html: HtmlOpts,
+  template: Option<PathBuf>,
+  filters: Vec<PathBuf>,
+}
+
+#[cfg(not(feature = "config"))]
+fn load_config(file: Option<&str>) -> Result<Config, String> {
+  if file.is_some() {
+    return Err("`--config` requires building with the `config` feature".to_string());
+  }
+  Ok(Config::default())
+}
+
+fn parse(source: &[u8], opts: &ParseOpts, from: InputFormat) -> Result<Document, String> {
+  match from {
+    InputFormat::Djot => Document::parse_bytes(source, opts.clone(), Utf8Validation::Strict)
+      .map_err(|err| err.to_string()),
+    InputFormat::Html => from_html(source, opts),
+    InputFormat::Json => {
+      let json = std::str::from_utf8(source).map_err(|err| err.to_string())?;
+      Document::from_json(json).map_err(|err| format!("invalid JSON AST: {err}"))
+    }
+  }
+}
+
+#[cfg(feature = "from-html")]
+fn from_html(source: &[u8], opts: &ParseOpts) -> Result<Document, String> {
+  let html = std::str::from_utf8(source).map_err(|err| err.to_string())?;
+  Document::try_parse_opts(opts.clone(), &djot::from_html::to_djot(html))
+    .map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "from-html"))]
+fn from_html(_source: &[u8], _opts: &ParseOpts) -> Result<Document, String> {
+  Err("`--from html` requires building with the `from-html` feature".to_string())
+}
+
+fn parse_stdin(opts: &ParseOpts, from: InputFormat) -> Result<Document, String> {
+  let mut source = Vec::new();
+  io::stdin().read_to_end(&mut source).map_err(|err| err.to_string())?;
+  parse(&source, opts, from)
+}
+
+fn parse_read(file: &str, opts: &ParseOpts, from: InputFormat) -> Result<Document, String> {
+  let source = fs::read(file).map_err(|err| err.to_string())?;
+  parse(&source, opts, from)
+}
+
+#[cfg(feature = "mmap")]
+fn parse_mmap(file: &str, opts: &ParseOpts, from: InputFormat) -> Result<Document, String> {
+  let file = fs::File::open(file).map_err(|err| err.to_string())?;
+  // SAFETY: the file must not be truncated while it is mapped, which is what
+  // passing `--mmap` accepts in exchange for not reading the file up front.
+  let map = unsafe { memmap2::Mmap::map(&file) }.map_err(|err| err.to_string())?;
+  parse(&map, opts, from)
+}
+
+#[cfg(not(feature = "mmap"))]
+fn parse_mmap(_file: &str, _opts: &ParseOpts, _from: InputFormat) -> Result<Document, String> {
+  Err("`--mmap` requires building with the `mmap` feature".to_string())
+}
@@ -0,0 +1,101 @@
+//! `--site`: converts a directory of `.dj` files into a directory of HTML
+//! pages, copying everything else along.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use crate::Filters;
+use djot::{
+  ast::{Tag, TagKind},
+  Document, HtmlOpts, ParseOpts,
+};
+
+pub(crate) const DEFAULT_TEMPLATE: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{{title}}</title>
+</head>
+<body>
+{{content}}</body>
+</html>
+";
+
+pub(crate) struct Site<'a> {
+  /// Page template, `{{title}}` and `{{content}}` are replaced by the
+  /// title (the first heading) and HTML of each page.
+  pub(crate) template: &'a str,
+  pub(crate) opts: &'a HtmlOpts,
+  pub(crate) filters: &'a Filters,
+}
+
+impl Site<'_> {
+  pub(crate) fn convert(&self, src: &Path, out: &Path) -> Result<(), String> {
+    fs::create_dir_all(out).map_err(|err| format!("{}: {err}", out.display()))?;
+    // the output may live inside the source directory
+    let skip = out.canonicalize().map_err(|err| format!("{}: {err}", out.display()))?;
+    self.convert_dir(src, out, &skip)
+  }
+
+  fn convert_dir(&self, src: &Path, out: &Path, skip: &Path) -> Result<(), String> {
+    let entries = fs::read_dir(src).map_err(|err| format!("{}: {err}", src.display()))?;
+    let mut paths: Vec<PathBuf> = entries.filter_map(|it| Some(it.ok()?.path())).collect();
+    paths.sort();
+    for path in paths {
+      let name = path.file_name().unwrap();
+      let context = |err: std::io::Error| format!("{}: {err}", path.display());
+      if path.canonicalize().map_err(context)? == skip {
+        continue;
+      }
+      if path.is_dir() {
+        let out = out.join(name);
+        fs::create_dir_all(&out).map_err(context)?;
+        self.convert_dir(&path, &out, skip)?;
+      } else if path.extension().is_some_and(|it| it == "dj") {
+        let source = fs::read_to_string(&path).map_err(context)?;
+        let html = self.render_page(&source).map_err(|err| format!("{}: {err}", path.display()))?;
+        fs::write(out.join(name).with_extension("html"), html).map_err(context)?;
+      } else {
+        fs::copy(&path, out.join(name)).map_err(context)?;
+      }
+    }
+    Ok(())
+  }
+
+  fn render_page(&self, source: &str) -> Result<String, String> {
+    let mut doc =
+      Document::try_parse_opts(ParseOpts::default(), source).map_err(|err| err.to_string())?;
+    self.filters.apply(&mut doc)?;
+    rewrite_links(&mut doc.children);
+    let title = doc.title().unwrap_or_default();
+    let title = title.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    // the template provides the rest of the page
+    let content = doc.to_html_opts(&HtmlOpts { standalone: false, ..self.opts.clone() });
+    Ok(self.template.replace("{{title}}", &title).replace("{{content}}", &content))
+  }
+}
+
+/// Points links to other `.dj` documents at the converted `.html` pages.
+fn rewrite_links(tags: &mut [Tag]) {
+  for tag in tags {
+    if let TagKind::Link(link) = &mut tag.kind {
+      if let Some(destination) = &mut link.destination {
+        if let Some(rewritten) = rewrite_link(destination) {
+          *destination = rewritten
+        }
+      }
+    }
+    rewrite_links(&mut tag.children)
+  }
+}
+
+fn rewrite_link(destination: &str) -> Option<String> {
+  if destination.contains("://") || destination.starts_with("mailto:") {
+    return None;
+  }
+  let end = destination.find(['#', '?']).unwrap_or(destination.len());
+  let path = destination[..end].strip_suffix(".dj")?;
+  Some(format!("{path}.html{}", &destination[end..]))
+}
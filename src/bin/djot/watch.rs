@@ -0,0 +1,106 @@
+//! `--watch`: renders the input again whenever it changes, optionally
+//! serving the result with a script which reloads the page on every change.
+
+use std::{
+  fs,
+  io::{BufRead, BufReader, Write},
+  net::{TcpListener, TcpStream},
+  sync::{Arc, Condvar, Mutex},
+  thread,
+  time::{Duration, SystemTime},
+};
+
+const RELOAD_SCRIPT: &str =
+  "<script>new EventSource(\"/events\").onmessage = () => location.reload()</script>\n";
+
+#[derive(Default)]
+struct Page {
+  html: String,
+  version: u64,
+}
+
+#[derive(Default)]
+struct State {
+  page: Mutex<Page>,
+  changed: Condvar,
+}
+
+pub(crate) fn watch(
+  file: &str,
+  output: Option<&str>,
+  serve: Option<&str>,
+  render: impl Fn(&str) -> Result<String, String>,
+) -> Result<(), String> {
+  let state = Arc::new(State::default());
+  if let Some(addr) = serve {
+    let listener = TcpListener::bind(addr).map_err(|err| format!("{addr}: {err}"))?;
+    eprintln!("djot: serving {file} on http://{addr}");
+    let state = Arc::clone(&state);
+    thread::spawn(move || accept(listener, state));
+  }
+
+  let mut last_modified: Option<SystemTime> = None;
+  loop {
+    let modified = fs::metadata(file).and_then(|it| it.modified()).ok();
+    if modified.is_some() && modified != last_modified {
+      last_modified = modified;
+      // errors are reported, but keep the preview running
+      match render(file) {
+        Ok(html) => {
+          if let Some(output) = output {
+            if let Err(err) = fs::write(output, &html) {
+              eprintln!("djot: {output}: {err}")
+            }
+          }
+          let mut page = state.page.lock().unwrap();
+          page.html = html;
+          page.version += 1;
+          state.changed.notify_all();
+        }
+        Err(err) => eprintln!("djot: {file}: {err}"),
+      }
+    }
+    thread::sleep(Duration::from_millis(200));
+  }
+}
+
+fn accept(listener: TcpListener, state: Arc<State>) {
+  for stream in listener.incoming().flatten() {
+    let state = Arc::clone(&state);
+    thread::spawn(move || {
+      // the client going away is not an error worth reporting
+      let _ = respond(stream, &state);
+    });
+  }
+}
+
+fn respond(mut stream: TcpStream, state: &State) -> std::io::Result<()> {
+  let mut request_line = String::new();
+  let mut reader = BufReader::new(stream.try_clone()?);
+  reader.read_line(&mut request_line)?;
+  let mut header = String::new();
+  while reader.read_line(&mut header)? > 2 {
+    header.clear()
+  }
+
+  let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+  if path == "/events" {
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n")?;
+    let mut version = state.page.lock().unwrap().version;
+    loop {
+      let page = state.page.lock().unwrap();
+      let page = state.changed.wait_while(page, |it| it.version == version).unwrap();
+      version = page.version;
+      drop(page);
+      stream.write_all(b"data: reload\n\n")?;
+    }
+  }
+
+  let body = format!("{}{RELOAD_SCRIPT}", state.page.lock().unwrap().html);
+  write!(
+    stream,
+    "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+     Connection: close\r\n\r\n{body}",
+    body.len()
+  )
+}
@@ -1,29 +1,44 @@
-use std::fmt::Write;
+use std::{borrow::Cow, fmt::Write};
 
 use crate::{
   annot::{Annot, Atom, Comp},
+  ast::Tag,
+  attributes::{AttributeParser, Status},
+  budget::Budget,
   inline,
   patterns::{find, find_at, PatMatch, capture_at},
-  Match, ParseOpts,
+  Diagnostic, Match, ParseOpts,
 };
 
 #[derive(Default)]
-pub struct Parser {
-  pub subject: String,
+pub struct Parser<'s> {
+  pub subject: Cow<'s, str>,
   indent: usize,
   startline: usize,
   starteol: usize,
   endeol: usize,
   pub(crate) matches: Vec<Match>,
+  pub(crate) warnings: Vec<Diagnostic>,
+  // the length of the input before the subject, which was dropped once
+  // its blocks were built, see `drop_parsed`
+  pub(crate) offset: usize,
+  pub(crate) blocks: Vec<Tag>,
+  // the warnings whose ranges already count from the start of the input
+  shifted_warnings: usize,
   pos: usize,
+  containers: Vec<Box<dyn Container>>,
   last_matched_container: usize,
-  opts: ParseOpts,
+  inline_parsers: Vec<inline::Parser>,
+  pub(crate) opts: ParseOpts,
+  pub(crate) budget: Budget,
   finished_line: bool,
+  // content of the innermost container while checking for continuations
+  tip_content: &'static str,
 
   pub(crate) debug: String,
 }
 
-trait Container {
+trait Container: Send + Sync {
   fn content(&self) -> &'static str;
   fn inline_parser(&mut self) -> Option<&mut inline::Parser> {
     None
@@ -31,15 +46,32 @@ trait Container {
   fn restore_indent(&self) -> Option<usize> {
     None
   }
-  fn open(p: &mut Parser) -> Option<Box<dyn Container>>
+  fn open(p: &mut Parser) -> bool
   where
     Self: Sized;
   fn cont(&mut self, p: &mut Parser) -> bool;
   fn close(self: Box<Self>, p: &mut Parser);
 }
 
-const CONTAINERS: &[fn(&mut Parser) -> Option<Box<dyn Container>>] =
-  &[Para::open, CodeBlock::open, ReferenceDefinition::open];
+/// A container's `open`, and for containers of blocks a check for whether one
+/// starts at the current position (used to stop at the nesting limit).
+type Opener = (fn(&mut Parser) -> bool, Option<fn(&Parser) -> bool>);
+
+const CONTAINERS: &[Opener] = &[
+  (Para::open, None),
+  (BlockQuote::open, Some(BlockQuote::starts)),
+  (Heading::open, None),
+  (ThematicBreak::open, None),
+  (ListItem::open, Some(ListItem::starts)),
+  (Footnote::open, Some(Footnote::starts)),
+  (CodeBlock::open, None),
+  (Div::open, Some(Div::starts)),
+  (BlockAttributes::open, None),
+  (ReferenceDefinition::open, None),
+  (AbbreviationDefinition::open, None),
+  (Table::open, None),
+  (Caption::open, None),
+];
 
 struct Para {
   inline_parser: inline::Parser,
@@ -52,12 +84,14 @@ impl Container for Para {
   fn inline_parser(&mut self) -> Option<&mut inline::Parser> {
     Some(&mut self.inline_parser)
   }
-  fn open(p: &mut Parser) -> Option<Box<dyn Container>>
+  fn open(p: &mut Parser) -> bool
   where
     Self: Sized,
   {
+    let inline_parser = p.new_inline_parser();
+    p.add_container(Box::new(Para { inline_parser }));
     p.add_match(p.pos, p.pos, Comp::Para.add());
-    Some(Box::new(Para { inline_parser: inline::Parser::new(p.subject.clone(), p.opts.clone()) }))
+    true
   }
 
   fn cont(&mut self, p: &mut Parser) -> bool {
@@ -65,14 +99,228 @@ impl Container for Para {
   }
 
   fn close(mut self: Box<Self>, p: &mut Parser) {
-    p.matches.extend(self.inline_parser.get_matches());
+    self.inline_parser.get_matches(&p.subject, &mut p.matches, &mut p.warnings);
+    p.inline_parsers.push(self.inline_parser);
     p.add_match(p.pos - 1, p.pos - 1, Comp::Para.sub())
   }
 }
 
+struct BlockQuote {}
+
+impl BlockQuote {
+  fn starts(p: &Parser) -> bool {
+    p.find("^>%s").is_match
+  }
+}
+
+impl Container for BlockQuote {
+  fn content(&self) -> &'static str {
+    "block"
+  }
+  fn open(p: &mut Parser) -> bool
+  where
+    Self: Sized,
+  {
+    if !Self::starts(p) {
+      return false;
+    }
+    p.add_container(Box::new(BlockQuote {}));
+    p.add_match(p.pos, p.pos, Comp::BlockQuote.add());
+    p.pos += 1;
+    true
+  }
+
+  fn cont(&mut self, p: &mut Parser) -> bool {
+    if p.find("^>%s").is_match {
+      p.pos += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn close(self: Box<Self>, p: &mut Parser) {
+    p.add_match(p.pos, p.pos, Comp::BlockQuote.sub())
+  }
+}
+
+struct ThematicBreak {}
+
+impl Container for ThematicBreak {
+  fn content(&self) -> &'static str {
+    ""
+  }
+  fn open(p: &mut Parser) -> bool
+  where
+    Self: Sized,
+  {
+    // before list items, `* * *` isn't a list
+    let m = p.find("^[-*][ \t]*[-*][ \t]*[-*][-* \t]*[\r\n]");
+    if !m.is_match {
+      return false;
+    }
+    p.add_container(Box::new(ThematicBreak {}));
+    p.add_match(m.start, m.end - 1, Atom::ThematicBreak);
+    p.pos = m.end - 1;
+    p.finished_line = true;
+    true
+  }
+
+  fn cont(&mut self, _p: &mut Parser) -> bool {
+    false
+  }
+
+  fn close(self: Box<Self>, _p: &mut Parser) {}
+}
+
+struct ListItem {
+  indent: usize,
+}
+
+impl ListItem {
+  fn marker(p: &Parser) -> Option<PatMatch> {
+    let markers = [
+      "^[-*+:]%s",
+      "^%d+[.)]%s",
+      "^%(%d+%)%s",
+      "^[ivxlcdmIVXLCDM]+[.)]%s",
+      "^%([ivxlcdmIVXLCDM]+%)%s",
+      "^%a[.)]%s",
+      "^%(%a%)%s",
+    ];
+    markers.into_iter().map(|it| p.find(it)).find(|it| it.is_match)
+  }
+
+  fn starts(p: &Parser) -> bool {
+    Self::marker(p).is_some()
+  }
+}
+
+impl Container for ListItem {
+  fn content(&self) -> &'static str {
+    "block"
+  }
+  fn open(p: &mut Parser) -> bool
+  where
+    Self: Sized,
+  {
+    let Some(m) = Self::marker(p) else {
+      return false;
+    };
+    // adding container will close others
+    p.add_container(Box::new(ListItem { indent: p.indent }));
+    p.add_match(m.start, m.end - 1, Comp::ListItem.add());
+    p.pos = m.end - 1;
+    let bullet = b"-*+".contains(&p.subject.as_bytes()[m.start]);
+    if bullet && find_at(&p.subject, "^%[[ xX]%]%s", m.end).is_match {
+      let atom = match p.subject.as_bytes()[m.end + 1] {
+        b' ' => Atom::CheckboxUnchecked,
+        _ => Atom::CheckboxChecked,
+      };
+      p.add_match(m.end, m.end + 3, atom);
+      p.pos = m.end + 3;
+    }
+    true
+  }
+
+  fn cont(&mut self, p: &mut Parser) -> bool {
+    p.indent > self.indent || p.find("^[\r\n]").is_match
+  }
+
+  fn close(self: Box<Self>, p: &mut Parser) {
+    p.add_match(p.pos, p.pos, Comp::ListItem.sub())
+  }
+}
+
+struct Footnote {
+  indent: usize,
+}
+
+impl Footnote {
+  fn starts(p: &Parser) -> bool {
+    p.find("^[[]%^([^%]]+)%]:%s").is_match
+  }
+}
+
+impl Container for Footnote {
+  fn content(&self) -> &'static str {
+    "block"
+  }
+  fn open(p: &mut Parser) -> bool
+  where
+    Self: Sized,
+  {
+    let m = p.capture("^[[]%^([^%]]+)%]:%s");
+    if !m.is_match {
+      return false;
+    }
+    p.add_container(Box::new(Footnote { indent: p.indent }));
+    p.add_match(m.start, m.start, Comp::Footnote.add());
+    p.add_match(p.pos + m.cap1.start, p.pos + m.cap1.end, Atom::NoteLabel);
+    p.pos = m.end - 1;
+    true
+  }
+
+  fn cont(&mut self, p: &mut Parser) -> bool {
+    p.indent > self.indent || p.find("^[\r\n]").is_match
+  }
+
+  fn close(self: Box<Self>, p: &mut Parser) {
+    p.add_match(p.pos, p.pos, Comp::Footnote.sub())
+  }
+}
+
+struct Heading {
+  level: usize,
+  inline_parser: inline::Parser,
+}
+
+impl Container for Heading {
+  fn content(&self) -> &'static str {
+    "inline"
+  }
+  fn inline_parser(&mut self) -> Option<&mut inline::Parser> {
+    Some(&mut self.inline_parser)
+  }
+  fn open(p: &mut Parser) -> bool
+  where
+    Self: Sized,
+  {
+    let m = p.find("^#+");
+    if !m.is_match || !find_at(&p.subject, "^%s", m.end).is_match {
+      return false;
+    }
+    let level = m.end - m.start;
+    let inline_parser = p.new_inline_parser();
+    p.add_container(Box::new(Heading { level, inline_parser }));
+    p.add_match(m.start, m.end, Comp::Heading.add());
+    p.pos = m.end;
+    true
+  }
+
+  fn cont(&mut self, p: &mut Parser) -> bool {
+    let m = p.find("^#+%s");
+    if m.is_match && m.end - m.start - 1 == self.level {
+      p.pos = m.end - 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn close(mut self: Box<Self>, p: &mut Parser) {
+    self.inline_parser.get_matches(&p.subject, &mut p.matches, &mut p.warnings);
+    p.inline_parsers.push(self.inline_parser);
+    let e = p.matches.last().map_or(p.pos, |m| m.end());
+    p.add_match(e, e, Comp::Heading.sub())
+  }
+}
+
 struct CodeBlock {
   border: char,
   indent: usize,
+  start: usize,
+  fenced: bool,
 }
 
 impl Container for CodeBlock {
@@ -82,7 +330,7 @@ impl Container for CodeBlock {
   fn restore_indent(&self) -> Option<usize> {
     Some(self.indent)
   }
-  fn open(p: &mut Parser) -> Option<Box<dyn Container>>
+  fn open(p: &mut Parser) -> bool
   where
     Self: Sized,
   {
@@ -93,18 +341,19 @@ impl Container for CodeBlock {
       m = p.capture("^~~~([ \t]*)([^%s`]*)[ \t]*[\r\n]");
     }
     if !m.is_match {
-      return None;
+      return false;
     }
     let lang = m.cap2;
 
+    p.add_container(Box::new(CodeBlock { border, indent: p.indent, start: p.pos, fenced: false }));
     p.add_match(p.pos, p.pos + 3, Comp::CodeBlock.add());
     if !lang.is_empty() {
-      p.add_match(lang.start, lang.end, Atom::CodeLanguage)
+      p.add_match(p.pos + lang.start, p.pos + lang.end, Atom::CodeLanguage)
     }
 
     p.pos = p.pos + 2;
     p.finished_line = true;
-    Some(Box::new(CodeBlock { border, indent: p.indent }))
+    true
   }
 
   fn cont(&mut self, p: &mut Parser) -> bool {
@@ -113,6 +362,7 @@ impl Container for CodeBlock {
     if m.is_match {
       p.pos = m.end - 1;
       p.finished_line = true;
+      self.fenced = true;
       false
     } else {
       true
@@ -120,12 +370,206 @@ impl Container for CodeBlock {
   }
 
   fn close(self: Box<Self>, p: &mut Parser) {
+    if !self.fenced {
+      p.warnings.push(Diagnostic::warning(self.start..self.start + 3, "unclosed code block"))
+    }
     p.add_match(p.pos - 3, p.pos, Comp::CodeBlock.sub());
   }
 }
 
+struct Div {
+  colons: usize,
+  start: usize,
+  fence: Option<(usize, usize)>,
+}
+
+impl Div {
+  fn fence(p: &Parser) -> Option<(PatMatch, AttributeParser)> {
+    let m = p.capture("^(::::*)[ \t]*([%w_-]*)[ \t]*");
+    if !m.is_match {
+      return None;
+    }
+    let mut attrs = AttributeParser::default();
+    if p.subject.as_bytes().get(m.end) == Some(&b'{') {
+      match attrs.feed(&p.subject, m.end..p.starteol) {
+        Status::Done(end) if find_at(&p.subject, "^[ \t]*[\r\n]", end + 1).is_match => (),
+        _ => return None,
+      }
+    } else if !find_at(&p.subject, "^[\r\n]", m.end).is_match {
+      return None;
+    }
+    Some((m, attrs))
+  }
+
+  fn starts(p: &Parser) -> bool {
+    Self::fence(p).is_some()
+  }
+}
+
+impl Container for Div {
+  fn content(&self) -> &'static str {
+    "block"
+  }
+  fn open(p: &mut Parser) -> bool
+  where
+    Self: Sized,
+  {
+    let Some((m, attrs)) = Self::fence(p) else {
+      return false;
+    };
+    let (colons, class) = (m.cap1.len(), m.cap2);
+    p.add_container(Box::new(Div { colons, start: p.pos, fence: None }));
+    p.add_match(p.pos, p.pos + colons, Comp::Div.add());
+    if !class.is_empty() {
+      p.add_match(p.pos + class.start, p.pos + class.end, Atom::Class)
+    }
+    for (range, atom) in attrs.matches {
+      p.add_match(range.start, range.end, atom)
+    }
+    p.pos = p.starteol;
+    p.finished_line = true;
+    true
+  }
+
+  fn cont(&mut self, p: &mut Parser) -> bool {
+    if p.tip_content == "text" {
+      // `:::` inside a code block is code
+      return true;
+    }
+    let m = p.capture("^(::::*)[ \t]*[\r\n]");
+    if m.is_match && m.cap1.len() >= self.colons {
+      self.fence = Some((p.pos, p.pos + m.cap1.len()));
+      p.pos = m.end - 1;
+      p.finished_line = true;
+      false
+    } else {
+      true
+    }
+  }
+
+  fn close(self: Box<Self>, p: &mut Parser) {
+    let (start, end) = match self.fence {
+      Some(fence) => fence,
+      None => {
+        let message = "unclosed div";
+        p.warnings.push(Diagnostic::warning(self.start..self.start + self.colons, message));
+        (p.pos, p.pos)
+      }
+    };
+    p.add_match(start, end, Comp::Div.sub());
+  }
+}
+
+struct BlockAttributes {
+  indent: usize,
+  start: usize,
+  end: usize,
+  parser: AttributeParser,
+  done: bool,
+  // the lines fed to the attribute parser, which become a paragraph when
+  // they turn out not to be attributes
+  lines: Vec<(usize, usize)>,
+  para: Option<inline::Parser>,
+}
+
+impl BlockAttributes {
+  fn start_para(&mut self, p: &mut Parser) {
+    let mut inline_parser = p.new_inline_parser();
+    p.add_match(self.start, self.start, Comp::Para.add());
+    for &(start, end) in &self.lines {
+      inline_parser.feed(&p.subject, start, end, &mut p.budget)
+    }
+    self.para = Some(inline_parser)
+  }
+}
+
+impl Container for BlockAttributes {
+  fn content(&self) -> &'static str {
+    if self.para.is_some() {
+      "inline"
+    } else {
+      ""
+    }
+  }
+  fn inline_parser(&mut self) -> Option<&mut inline::Parser> {
+    self.para.as_mut()
+  }
+  fn open(p: &mut Parser) -> bool
+  where
+    Self: Sized,
+  {
+    if p.subject.as_bytes().get(p.pos) != Some(&b'{') {
+      return false;
+    }
+    let mut parser = AttributeParser::default();
+    let (done, end) = match parser.feed(&p.subject, p.pos..p.endeol) {
+      Status::Done(end) if find_at(&p.subject, "^[ \t]*[\r\n]", end + 1).is_match => {
+        (true, end + 1)
+      }
+      Status::Continue => (false, p.starteol),
+      _ => return false,
+    };
+    let (indent, start, lines) = (p.indent, p.pos, vec![(p.pos, p.endeol)]);
+    let container = BlockAttributes { indent, start, end, parser, done, lines, para: None };
+    p.add_container(Box::new(container));
+    p.pos = p.starteol;
+    p.finished_line = true;
+    true
+  }
+
+  fn cont(&mut self, p: &mut Parser) -> bool {
+    if self.para.is_some() {
+      return p.find("^%S").is_match;
+    }
+    if self.done {
+      return false;
+    }
+    // the attributes continue on indented lines
+    if p.indent > self.indent {
+      let status = self.parser.feed(&p.subject, p.pos..p.endeol);
+      let done = match status {
+        Status::Done(end) if find_at(&p.subject, "^[ \t]*[\r\n]", end + 1).is_match => {
+          Some(end + 1)
+        }
+        Status::Continue => None,
+        _ => {
+          // the current line is fed to the paragraph as usual
+          self.start_para(p);
+          return true;
+        }
+      };
+      self.lines.push((p.pos, p.endeol));
+      self.done = done.is_some();
+      self.end = done.unwrap_or(p.starteol);
+      p.pos = p.starteol;
+      p.finished_line = true;
+      return true;
+    }
+    self.start_para(p);
+    p.find("^%S").is_match
+  }
+
+  fn close(mut self: Box<Self>, p: &mut Parser) {
+    if !self.done && self.para.is_none() {
+      // unclosed at the end of the input
+      self.start_para(p)
+    }
+    if let Some(mut inline_parser) = self.para.take() {
+      inline_parser.get_matches(&p.subject, &mut p.matches, &mut p.warnings);
+      p.inline_parsers.push(inline_parser);
+      p.add_match(p.pos - 1, p.pos - 1, Comp::Para.sub());
+      return;
+    }
+    p.add_match(self.start, self.start + 1, Comp::BlockAttributes.add());
+    for (range, atom) in std::mem::take(&mut self.parser.matches) {
+      p.add_match(range.start, range.end, atom)
+    }
+    p.add_match(self.end - 1, self.end, Comp::BlockAttributes.sub())
+  }
+}
+
 struct ReferenceDefinition {
-  _indent: usize,
+  indent: usize,
 }
 
 impl Container for ReferenceDefinition {
@@ -133,23 +577,35 @@ impl Container for ReferenceDefinition {
     ""
   }
 
-  fn open(p: &mut Parser) -> Option<Box<dyn Container>>
+  fn open(p: &mut Parser) -> bool
   where
     Self: Sized,
   {
-    let m = p.find("^[[]([^\r\n]*)%]:[ \t]*(%S*)");
+    let m = p.capture("^[[]([^\r\n]*)%]:[ \t]*(%S*)");
     if !m.is_match {
-      return None;
+      return false;
     }
+    p.add_container(Box::new(ReferenceDefinition { indent: p.indent }));
     p.add_match(m.start, m.start, Comp::ReferenceDefinition.add());
-    p.add_match(m.start, m.start + m.cap1.len() + 1, Atom::ReferenceKey);
-    p.add_match(m.end - m.cap2.len(), m.end, Atom::ReferenceValue);
+    p.add_match(p.pos + m.cap1.start, p.pos + m.cap1.end, Atom::ReferenceKey);
+    if !m.cap2.is_empty() {
+      p.add_match(p.pos + m.cap2.start, p.pos + m.cap2.end, Atom::ReferenceValue);
+    }
     p.pos = m.end;
-    Some(Box::new(ReferenceDefinition { _indent: p.indent }))
+    true
   }
 
-  fn cont(&mut self, _p: &mut Parser) -> bool {
-    false
+  fn cont(&mut self, p: &mut Parser) -> bool {
+    // the destination may continue on indented lines
+    if self.indent >= p.indent {
+      return false;
+    }
+    let m = p.find("^%S+");
+    if m.is_match {
+      p.add_match(m.start, m.end, Atom::ReferenceValue);
+      p.pos = m.end
+    }
+    true
   }
 
   fn close(self: Box<Self>, p: &mut Parser) {
@@ -161,14 +617,149 @@ impl Container for ReferenceDefinition {
   }
 }
 
-impl Parser {
-  pub fn new(mut subject: String, opts: ParseOpts) -> Parser {
-    if !find(&subject, "[\r\n]$").is_match {
-      subject.push('\n');
+struct AbbreviationDefinition {}
+
+impl Container for AbbreviationDefinition {
+  fn content(&self) -> &'static str {
+    ""
+  }
+
+  fn open(p: &mut Parser) -> bool
+  where
+    Self: Sized,
+  {
+    if !p.opts.abbreviations {
+      return false;
+    }
+    let m = p.capture("^%*[[]([^\r\n]-)%]:[ \t]*([^\r\n]*)");
+    if !m.is_match {
+      return false;
     }
-    let mut res = Parser::default();
-    res.subject = subject;
-    res.opts = opts;
+    p.add_container(Box::new(AbbreviationDefinition {}));
+    p.add_match(m.start, m.start, Comp::AbbreviationDefinition.add());
+    p.add_match(p.pos + m.cap1.start, p.pos + m.cap1.end, Atom::AbbreviationTerm);
+    p.add_match(p.pos + m.cap2.start, p.pos + m.cap2.end, Atom::AbbreviationTitle);
+    p.pos = m.end;
+    true
+  }
+
+  fn cont(&mut self, _p: &mut Parser) -> bool {
+    false
+  }
+
+  fn close(self: Box<Self>, p: &mut Parser) {
+    p.add_match(p.pos, p.pos, Comp::AbbreviationDefinition.sub())
+  }
+}
+
+struct Caption {
+  inline_parser: inline::Parser,
+}
+
+impl Container for Caption {
+  fn content(&self) -> &'static str {
+    "inline"
+  }
+  fn inline_parser(&mut self) -> Option<&mut inline::Parser> {
+    Some(&mut self.inline_parser)
+  }
+  fn open(p: &mut Parser) -> bool
+  where
+    Self: Sized,
+  {
+    if !p.find("^%^%s").is_match {
+      return false;
+    }
+    let inline_parser = p.new_inline_parser();
+    p.add_container(Box::new(Caption { inline_parser }));
+    p.add_match(p.pos, p.pos + 1, Comp::Caption.add());
+    p.pos += 1;
+    true
+  }
+
+  fn cont(&mut self, p: &mut Parser) -> bool {
+    p.find("^%S").is_match
+  }
+
+  fn close(mut self: Box<Self>, p: &mut Parser) {
+    self.inline_parser.get_matches(&p.subject, &mut p.matches, &mut p.warnings);
+    p.inline_parsers.push(self.inline_parser);
+    p.add_match(p.pos - 1, p.pos - 1, Comp::Caption.sub())
+  }
+}
+
+struct Table {}
+
+impl Container for Table {
+  fn content(&self) -> &'static str {
+    "cells"
+  }
+  fn open(p: &mut Parser) -> bool
+  where
+    Self: Sized,
+  {
+    let start = p.pos;
+    let Some(row) = p.table_row() else {
+      return false;
+    };
+    p.add_container(Box::new(Table {}));
+    p.add_match(start, start, Comp::Table.add());
+    p.matches.extend(row);
+    true
+  }
+
+  fn cont(&mut self, p: &mut Parser) -> bool {
+    match p.table_row() {
+      Some(row) => {
+        p.matches.extend(row);
+        true
+      }
+      None => false,
+    }
+  }
+
+  fn close(self: Box<Self>, p: &mut Parser) {
+    p.add_match(p.pos, p.pos, Comp::Table.sub())
+  }
+}
+
+impl<'s> Parser<'s> {
+  pub fn new(opts: ParseOpts) -> Parser<'s> {
+    Parser { opts, ..Parser::default() }
+  }
+
+  /// A parser for `subject`, which is parsed where it is rather than copied
+  /// into a buffer of the parser.
+  pub fn borrowed(opts: ParseOpts, subject: &'s str) -> Parser<'s> {
+    let budget = Budget::new(&opts);
+    Parser { subject: Cow::Borrowed(subject), opts, budget, ..Parser::default() }
+  }
+
+  /// Prepares the parser for a new subject, keeping allocated buffers.
+  pub fn reset(&mut self, subject: &str) {
+    let buf = self.subject.to_mut();
+    buf.clear();
+    buf.push_str(subject);
+    self.indent = 0;
+    self.startline = 0;
+    self.starteol = 0;
+    self.endeol = 0;
+    self.matches.clear();
+    self.warnings.clear();
+    self.offset = 0;
+    self.blocks.clear();
+    self.shifted_warnings = 0;
+    self.pos = 0;
+    self.containers.clear();
+    self.last_matched_container = 0;
+    self.finished_line = false;
+    self.debug.clear();
+    self.budget = Budget::new(&self.opts);
+  }
+
+  fn new_inline_parser(&mut self) -> inline::Parser {
+    let mut res = self.inline_parsers.pop().unwrap_or_default();
+    res.reset(&self.opts);
     res
   }
 
@@ -184,15 +775,116 @@ impl Parser {
     self.matches.push(Match::new(startpos..endpos, annot))
   }
 
-  //  fn add_container(&mut self, container: Container) {
-  //    let last_matched = self.last_matched_container;
-  //    while containers.len() > last_matched
-  //      || (containers.len() > 0 && containers.last().unwrap().spec.content != "block")
-  //    {
-  //      containers.last().unwrap().spec.close(self)
-  //    }
-  //    containers.push(container)
-  //  }
+  /// Parses the line at `pos` as a table row, a separator line like
+  /// `|:--|--:|` or cells with inline content, finishing the line.
+  fn table_row(&mut self) -> Option<Vec<Match>> {
+    let m = self.find("^|[^\r\n]*|");
+    if !m.is_match || !find_at(&self.subject, "^[ \t]*[\r\n]", m.end).is_match {
+      return None;
+    }
+    let (start, end) = (m.start, m.end - 1);
+    let mut res = vec![Match::new(start..start, Comp::Row.add())];
+    let warnings = self.warnings.len();
+    let separators = self.table_separators(start, end);
+    if !separators.is_empty() {
+      res.extend(separators)
+    } else {
+      let mut bar = start;
+      while bar < end {
+        let Some(cell) = self.table_cell(bar, end) else {
+          // not a table row after all
+          self.warnings.truncate(warnings);
+          return None;
+        };
+        res.push(Match::new(bar..bar + 1, Comp::Cell.add()));
+        res.extend(cell.1);
+        res.push(Match::new(cell.0..cell.0, Comp::Cell.sub()));
+        bar = cell.0
+      }
+    }
+    res.push(Match::new(end + 1..end + 1, Comp::Row.sub()));
+    self.pos = self.starteol;
+    self.finished_line = true;
+    Some(res)
+  }
+
+  /// The alignments of a separator line, or nothing for other rows.
+  fn table_separators(&self, start: usize, end: usize) -> Vec<Match> {
+    let mut res = Vec::new();
+    let mut pos = start + 1;
+    for cell in self.subject[start + 1..end].split('|') {
+      let text = cell.trim_matches([' ', '\t']);
+      let dashes = text.strip_prefix(':').unwrap_or(text);
+      let dashes = dashes.strip_suffix(':').unwrap_or(dashes);
+      if dashes.is_empty() || dashes.bytes().any(|c| c != b'-') {
+        return Vec::new();
+      }
+      let atom = match (text.starts_with(':'), text.ends_with(':')) {
+        (true, true) => Atom::SeparatorCenter,
+        (true, false) => Atom::SeparatorLeft,
+        (false, true) => Atom::SeparatorRight,
+        (false, false) => Atom::SeparatorDefault,
+      };
+      let offset = pos + cell.len() - cell.trim_start_matches([' ', '\t']).len();
+      res.push(Match::new(offset..offset + text.len(), atom));
+      pos += cell.len() + 1
+    }
+    res
+  }
+
+  /// Parses the cell after the `|` at `bar`, returning the position of the
+  /// `|` closing it and its inline matches. A `|` which is escaped or in
+  /// verbatim doesn't close the cell.
+  fn table_cell(&mut self, bar: usize, end: usize) -> Option<(usize, Vec<Match>)> {
+    let mut inline_parser = self.new_inline_parser();
+    let mut pos = find_at(&self.subject, "[^ \t]", bar + 1).start;
+    let mut closing = None;
+    while pos <= end {
+      let Some(next) = self.subject[pos..=end].find('|').map(|it| pos + it) else {
+        break;
+      };
+      if self.subject[..next].ends_with('\\') {
+        inline_parser.feed(&self.subject, pos, next + 1, &mut self.budget);
+      } else {
+        inline_parser.feed(&self.subject, pos, next, &mut self.budget);
+        if inline_parser.in_verbatim() {
+          inline_parser.feed(&self.subject, next, next + 1, &mut self.budget);
+        } else {
+          closing = Some(next);
+          break;
+        }
+      }
+      pos = next + 1
+    }
+    let mut matches = Vec::new();
+    inline_parser.get_matches(&self.subject, &mut matches, &mut self.warnings);
+    self.inline_parsers.push(inline_parser);
+    let closing = closing?;
+    if let Some(last) = matches.last_mut().filter(|it| it.is(Atom::Str)) {
+      // trailing spaces before the `|`
+      let text = self.subject[last.range()].trim_end_matches([' ', '\t']);
+      last.e = (last.start() + text.len()) as u32;
+      if text.is_empty() {
+        matches.pop();
+      }
+    }
+    Some((closing, matches))
+  }
+
+  fn add_container(&mut self, container: Box<dyn Container>) {
+    let last_matched = self.last_matched_container;
+    while self.containers.len() > last_matched
+      || matches!(self.containers.last(), Some(c) if c.content() != "block")
+    {
+      self.close_tip()
+    }
+    self.containers.push(container)
+  }
+
+  fn close_tip(&mut self) {
+    let tip = self.containers.pop().unwrap();
+    tip.close(self)
+  }
 
   fn skip_space(&mut self) {
     let m = find_at(&self.subject, "[^ \t]", self.pos);
@@ -211,11 +903,20 @@ impl Parser {
     self.endeol = m.end;
   }
 
-  pub fn parse(&mut self) {
-    let mut containers: Vec<Box<dyn Container>> = Vec::new();
+  /// Appends text to the subject, to be processed by
+  /// [`Parser::parse_lines`].
+  pub fn push_str(&mut self, text: &str) {
+    self.subject.to_mut().push_str(text)
+  }
 
+  /// Processes the lines of the subject which weren't processed yet. The
+  /// subject must end with a complete line.
+  pub fn parse_lines(&mut self) {
     let subjectlen = self.subject.len();
     while self.pos < subjectlen {
+      if !self.budget.step() {
+        break;
+      }
       self.indent = 0;
       self.startline = self.pos;
       self.finished_line = false;
@@ -223,20 +924,23 @@ impl Parser {
 
       // check open containers for continuation
       self.last_matched_container = 0;
-      for idx in 0..containers.len() {
+      self.tip_content = self.containers.last().map_or("", |it| it.content());
+      let mut containers = std::mem::take(&mut self.containers);
+      for (idx, container) in containers.iter_mut().enumerate() {
         // skip any indentation
         self.skip_space();
-        if containers[idx].cont(self) {
+        if container.cont(self) {
           self.last_matched_container = idx + 1
         } else {
           break;
         }
       }
+      self.containers = containers;
 
       // if we hit a close fence, we can move to next line
       if self.finished_line {
-        while containers.len() > self.last_matched_container {
-          containers.pop().unwrap().close(self)
+        while self.containers.len() > self.last_matched_container {
+          self.close_tip()
         }
       }
 
@@ -246,25 +950,33 @@ impl Parser {
         let mut is_blank = self.pos == self.starteol;
 
         let mut new_starts = false;
-        let last_match = containers[..self.last_matched_container].first();
+        let last_matched = self.last_matched_container.checked_sub(1).map(|i| &self.containers[i]);
         let mut check_starts = !is_blank
-          && !matches!(last_match, Some(c) if c.content() != "block")
+          && !matches!(last_matched, Some(c) if c.content() != "block")
           && !self.find("^%a+%s").is_match; // optimization
 
         while check_starts {
           check_starts = false;
-          for i in 1..CONTAINERS.len() {
-            let open = CONTAINERS[i];
-            if let Some(cont) = open(self) {
-              let content = cont.content();
-              containers.push(cont);
-              self.last_matched_container = containers.len();
+          let depth = self.containers.iter().filter(|c| c.content() == "block").count();
+          let limit = self.opts.max_nesting.filter(|&max| depth >= max);
+          for &(open, starts) in &CONTAINERS[1..] {
+            if let (Some(max), Some(starts)) = (limit, starts) {
+              if starts(self) {
+                // the remaining markers become paragraph text
+                let message = format!("nesting limit of {max} exceeded");
+                self.warnings.push(Diagnostic::warning(self.pos..self.starteol, message));
+                break;
+              }
+              continue;
+            }
+            if open(self) {
+              self.last_matched_container = self.containers.len();
               if self.finished_line {
                 check_starts = false
               } else {
                 self.skip_space();
                 new_starts = true;
-                check_starts = content != "text"
+                check_starts = self.containers.last().unwrap().content() == "block"
               }
               break;
             }
@@ -279,39 +991,46 @@ impl Parser {
 
           let is_lazy = !is_blank
             && !new_starts
-            && self.last_matched_container < containers.len()
-            && containers.last().unwrap().content() == "inline";
+            && self.last_matched_container < self.containers.len()
+            && self.containers.last().unwrap().content() == "inline";
 
-          if !is_lazy && self.last_matched_container < containers.len() {
-            while containers.len() > self.last_matched_container {
-              containers.pop().unwrap().close(self);
+          if !is_lazy {
+            while self.containers.len() > self.last_matched_container {
+              self.close_tip();
             }
           }
 
           // add para by default if there's text
-          if !matches!(containers.last(), Some(c) if c.content() != "block") {
+          if !matches!(self.containers.last(), Some(c) if c.content() != "block") {
             if is_blank {
               if !new_starts {
                 // need to track these for tight/loose lists
                 self.add_match(self.pos, self.endeol, Atom::Blankline);
               }
             } else {
-              let para = CONTAINERS[0](self).unwrap();
-              containers.push(para);
+              (CONTAINERS[0].0)(self);
             }
           }
 
-          if let Some(tip) = containers.last_mut() {
+          let (pos, indent, endeol) = (self.pos, self.indent, self.endeol);
+          let subject: &str = &self.subject;
+          let lazy = self.opts.lazy_inlines
+            && matches!(self.containers.last(), Some(c) if c.content() == "inline");
+          if lazy {
+            if !is_blank {
+              self.add_match(pos, self.starteol, Atom::Unparsed)
+            }
+          } else if let Some(tip) = self.containers.last_mut() {
             if let Some(tip_indent) = tip.restore_indent() {
-              let mut startpos = self.pos;
-              if self.indent > tip_indent {
+              let mut startpos = pos;
+              if indent > tip_indent {
                 // get back the leading spaces we gobbled
-                startpos = startpos - (self.indent - tip_indent)
+                startpos -= indent - tip_indent
               }
-              self.add_match(startpos, self.endeol, Atom::Str)
+              self.add_match(startpos, endeol, Atom::Str)
             } else if let Some(inline_parser) = tip.inline_parser() {
               if !is_blank {
-                inline_parser.feed(self.pos, self.endeol)
+                inline_parser.feed(subject, pos, endeol, &mut self.budget)
               }
             }
           }
@@ -320,18 +1039,64 @@ impl Parser {
 
       self.pos = self.endeol;
     }
-    self.finish(containers)
   }
 
-  fn finish(&mut self, mut containers: Vec<Box<dyn Container>>) {
+  /// Whether the lines parsed so far form complete top level blocks, which
+  /// no later line can continue or join, unlike the items of a list, so
+  /// that their source can be dropped once they are built. Not with the
+  /// options which need all of the source or matches in the end.
+  pub(crate) fn can_drop_parsed(&self) -> bool {
+    let opts = &self.opts;
+    if opts.source_positions || opts.cross_references || opts.debug_matches || opts.stats {
+      return false;
+    }
+    let last = self.matches.iter().rev().find(|it| !it.is(Atom::Blankline));
+    self.containers.is_empty()
+      && self.pos == self.subject.len()
+      && !last.is_some_and(|it| it.is(Comp::ListItem.sub()) || it.is(Comp::BlockAttributes.sub()))
+  }
+
+  /// Drops the parsed lines, whose blocks were built into `blocks`. The
+  /// positions of later lines count from the end of them.
+  pub(crate) fn drop_parsed(&mut self) {
+    self.shift_warnings();
+    self.offset += self.subject.len();
+    self.subject.to_mut().clear();
+    self.matches.clear();
+    self.pos = 0;
+  }
+
+  /// Makes the ranges of the warnings about the subject count from the
+  /// start of the input.
+  fn shift_warnings(&mut self) {
+    for it in &mut self.warnings[self.shifted_warnings..] {
+      it.range = it.range.start + self.offset..it.range.end + self.offset
+    }
+    self.shifted_warnings = self.warnings.len()
+  }
+
+  /// Processes the remaining input, completing the last line if needed, and
+  /// closes all open containers.
+  pub fn parse(&mut self) {
+    if !find(&self.subject, "[\r\n]$").is_match {
+      // which copies a borrowed subject
+      self.subject.to_mut().push('\n');
+    }
+    self.parse_lines();
     // close unmatched containers
-    while let Some(cont) = containers.pop() {
-      cont.close(self)
+    while !self.containers.is_empty() {
+      self.close_tip()
     }
+    self.shift_warnings();
     if self.opts.debug_matches {
       for &m in &self.matches {
-        let ms = format!("{} {}-{}", m.a, m.s + 1, if m.e == m.s { m.e + 1 } else { m.e });
-        writeln!(self.debug, "{ms:<20} {:?}", self.subject.get(m.s..m.e).unwrap_or_default())
+        let ms = format!(
+          "{} {}-{}",
+          m.a,
+          m.start() + 1,
+          if m.end() == m.start() { m.end() + 1 } else { m.end() }
+        );
+        writeln!(self.debug, "{ms:<20} {:?}", self.subject.get(m.range()).unwrap_or_default())
           .expect("str format can't fail");
       }
     }
@@ -0,0 +1,32 @@
+use std::time::Instant;
+
+use crate::ParseOpts;
+
+/// Bounds the work done by the parser, see [`ParseOpts::max_steps`].
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Budget {
+  steps: Option<u64>,
+  deadline: Option<Instant>,
+  taken: u64,
+  pub(crate) exceeded: bool,
+}
+
+impl Budget {
+  pub(crate) fn new(opts: &ParseOpts) -> Budget {
+    let deadline = opts.time_limit.map(|it| Instant::now() + it);
+    Budget { steps: opts.max_steps, deadline, taken: 0, exceeded: false }
+  }
+
+  /// Takes one step, returning `false` once the budget is used up.
+  pub(crate) fn step(&mut self) -> bool {
+    self.taken += 1;
+    if self.steps.is_some_and(|it| self.taken > it) {
+      self.exceeded = true
+    }
+    // reading the clock is comparatively slow
+    if self.taken.is_multiple_of(1024) && self.deadline.is_some_and(|it| Instant::now() > it) {
+      self.exceeded = true
+    }
+    !self.exceeded
+  }
+}
@@ -0,0 +1,236 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+  ast::{Tag, TagKind},
+  diff,
+  tree::{get_identifier, parse_inlines},
+  Document, Stats,
+};
+
+pub(crate) fn append(doc: &mut Document, mut other: Document) {
+  let offset = doc.children.last().map_or(0, |it| it.span.end);
+  let mut ids = HashSet::new();
+  collect_ids(&doc.children, &mut ids);
+  let mut references = HashMap::new();
+  collect_references(&doc.children, &mut references);
+  let mut labels = HashSet::new();
+  collect_footnotes(&doc.children, &mut labels);
+
+  // renaming needs the links, which may not be parsed yet
+  parse_inlines(&mut other.children);
+  let mut renamed_ids = HashMap::new();
+  rename_sections(&mut other.children, &mut ids, &mut renamed_ids);
+  let mut renamed_references = HashMap::new();
+  merge_references(&mut other.children, &mut references, &mut renamed_references);
+  let mut renamed_footnotes = HashMap::new();
+  rename_footnotes(&mut other.children, &mut labels, &mut renamed_footnotes);
+  for tag in &mut other.children {
+    rewrite(tag, offset, &renamed_ids, &renamed_references, &renamed_footnotes)
+  }
+
+  doc.children.append(&mut other.children);
+  doc.debug.push_str(&other.debug);
+  // the spans of `other` no longer match its source
+  doc.line_index = None;
+  doc.stats = match (doc.stats.take(), other.stats) {
+    (Some(stats), Some(other)) => Some(merge_stats(stats, other)),
+    (stats, other) => stats.or(other),
+  };
+  let mut next_id = 1;
+  for child in &mut doc.children {
+    diff::assign_ids(child, &mut next_id)
+  }
+}
+
+fn collect_ids(tags: &[Tag], acc: &mut HashSet<String>) {
+  for tag in tags {
+    if let Some(id) = tag.id() {
+      acc.insert(id.to_string());
+    }
+    collect_ids(&tag.children, acc)
+  }
+}
+
+/// Gives sections, whose identifiers are derived from their headings, new
+/// identifiers when they are taken.
+fn rename_sections(
+  tags: &mut [Tag],
+  ids: &mut HashSet<String>,
+  renamed: &mut HashMap<String, String>,
+) {
+  for tag in tags {
+    if let (TagKind::Section(_), Some(id)) = (&tag.kind, tag.id()) {
+      if ids.contains(id) {
+        let new_id = get_identifier(id, ids);
+        renamed.insert(id.to_string(), new_id.clone());
+        let id = id.to_string();
+        // the heading keeps an explicit identifier in sync
+        for child in &mut tag.children {
+          if matches!(child.kind, TagKind::Heading(_)) && child.id() == Some(id.as_str()) {
+            child.set_id(new_id.clone())
+          }
+        }
+        tag.set_id(new_id)
+      } else {
+        ids.insert(id.to_string());
+      }
+    }
+    rename_sections(&mut tag.children, ids, renamed)
+  }
+}
+
+fn reference_definition(tag: &Tag) -> Option<(String, String)> {
+  let mut key = None;
+  let mut value = String::new();
+  for child in &tag.children {
+    match &child.kind {
+      TagKind::ReferenceKey(it) => key = Some(it.text.clone()),
+      TagKind::ReferenceValue(it) => value.push_str(&it.text),
+      _ => (),
+    }
+  }
+  Some((key?, value))
+}
+
+fn collect_references(tags: &[Tag], acc: &mut HashMap<String, String>) {
+  for tag in tags {
+    match &tag.kind {
+      TagKind::ReferenceDefinition(_) => acc.extend(reference_definition(tag)),
+      _ => collect_references(&tag.children, acc),
+    }
+  }
+}
+
+/// Drops definitions which are already there, and renames the labels which
+/// are defined differently.
+fn merge_references(
+  tags: &mut Vec<Tag>,
+  references: &mut HashMap<String, String>,
+  renamed: &mut HashMap<String, String>,
+) {
+  tags.retain_mut(|tag| {
+    if !matches!(tag.kind, TagKind::ReferenceDefinition(_)) {
+      merge_references(&mut tag.children, references, renamed);
+      return true;
+    }
+    let Some((key, value)) = reference_definition(tag) else { return true };
+    match references.get(&key) {
+      None => {
+        references.insert(key, value);
+        true
+      }
+      Some(it) if *it == value => false,
+      Some(_) => {
+        let mut i = 1;
+        let new_key = loop {
+          let it = format!("{key}-{i}");
+          if !references.contains_key(&it) {
+            break it;
+          }
+          i += 1
+        };
+        for child in &mut tag.children {
+          if let TagKind::ReferenceKey(it) = &mut child.kind {
+            it.text = new_key.clone()
+          }
+        }
+        references.insert(new_key.clone(), value);
+        renamed.insert(key, new_key);
+        true
+      }
+    }
+  })
+}
+
+fn collect_footnotes(tags: &[Tag], acc: &mut HashSet<String>) {
+  for tag in tags {
+    if let TagKind::Footnote(note) = &tag.kind {
+      acc.insert(note.label.clone());
+    }
+    collect_footnotes(&tag.children, acc)
+  }
+}
+
+/// Gives footnotes new labels when they are taken, as notes of different
+/// documents are different notes even with the same label.
+fn rename_footnotes(
+  tags: &mut [Tag],
+  labels: &mut HashSet<String>,
+  renamed: &mut HashMap<String, String>,
+) {
+  for tag in tags {
+    if let TagKind::Footnote(note) = &mut tag.kind {
+      if labels.contains(&note.label) {
+        let mut i = 1;
+        let new_label = loop {
+          let it = format!("{}-{i}", note.label);
+          if !labels.contains(&it) {
+            break it;
+          }
+          i += 1
+        };
+        renamed.insert(note.label.clone(), new_label.clone());
+        note.label = new_label;
+      }
+      labels.insert(note.label.clone());
+    }
+    rename_footnotes(&mut tag.children, labels, renamed)
+  }
+}
+
+fn rewrite(
+  tag: &mut Tag,
+  offset: usize,
+  ids: &HashMap<String, String>,
+  references: &HashMap<String, String>,
+  footnotes: &HashMap<String, String>,
+) {
+  tag.span = tag.span.start + offset..tag.span.end + offset;
+  let rename_reference = |reference: &mut Option<String>| {
+    if let Some(new) = reference.as_ref().and_then(|it| references.get(it)) {
+      *reference = Some(new.clone())
+    }
+  };
+  let rename_destination = |destination: &mut Option<String>| {
+    let id = destination.as_ref().and_then(|it| it.strip_prefix('#'));
+    if let Some(new) = id.and_then(|it| ids.get(it)) {
+      *destination = Some(format!("#{new}"))
+    }
+  };
+  match &mut tag.kind {
+    TagKind::Link(link) => {
+      rename_reference(&mut link.reference);
+      rename_destination(&mut link.destination)
+    }
+    TagKind::Image(image) => rename_reference(&mut image.reference),
+    TagKind::CrossReference(reference) => {
+      if let Some(new) = ids.get(&reference.target) {
+        reference.target = new.clone()
+      }
+    }
+    TagKind::FootnoteReference(reference) => {
+      if let Some(new) = footnotes.get(&reference.label) {
+        reference.label = new.clone()
+      }
+    }
+    _ => (),
+  }
+  for child in &mut tag.children {
+    rewrite(child, offset, ids, references, footnotes)
+  }
+}
+
+fn merge_stats(mut stats: Stats, other: Stats) -> Stats {
+  stats.bytes += other.bytes;
+  stats.matches += other.matches;
+  stats.warnings += other.warnings;
+  for (kind, n) in other.nodes {
+    *stats.nodes.entry(kind).or_default() += n
+  }
+  #[cfg(feature = "timing")]
+  {
+    stats.parse_time += other.parse_time;
+    stats.build_time += other.build_time;
+  }
+  stats
+}
@@ -0,0 +1,263 @@
+//! Project settings loaded from a `djot.toml` file, enabled by the `config`
+//! feature.
+//!
+//! ```toml
+//! template = "template.html"
+//! filters = ["filters/links.rhai"]
+//!
+//! [parse]
+//! lazy_inlines = true
+//! max_nesting = 64
+//! time_limit_ms = 500
+//!
+//! [html]
+//! sections = true
+//! heading_offset = 1
+//! invisible_chars = "escape"
+//! dir = "rtl"
+//!
+//! [html.labels]
+//! footnotes = "Notes"
+//!
+//! [html.seo]
+//! site_name = "Notes"
+//!
+//! [html.symbols]
+//! smile = "😄"
+//! ```
+//!
+//! All keys are optional, unknown keys are rejected. Relative paths are
+//! resolved against the directory containing the file.
+
+use std::{
+  collections::HashMap,
+  error, fmt, fs,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{HtmlOpts, InvisibleChars, Labels, ParseOpts, SeoOpts, TextDirection, Typography};
+
+/// Options for the whole pipeline, from parsing to the page template.
+#[derive(Default, Clone)]
+pub struct Config {
+  pub parse: ParseOpts,
+  pub html: HtmlOpts,
+  /// Page template with `{{title}}` and `{{content}}` placeholders.
+  pub template: Option<PathBuf>,
+  /// Filter scripts applied in order between parsing and rendering, see
+  /// `script`.
+  pub filters: Vec<PathBuf>,
+}
+
+/// A configuration file which could not be read or is invalid.
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+  message: String,
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+
+impl error::Error for ConfigError {}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+  parse: RawParseOpts,
+  html: RawHtmlOpts,
+  template: Option<PathBuf>,
+  filters: Vec<PathBuf>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawParseOpts {
+  lazy_inlines: bool,
+  stats: bool,
+  max_input_bytes: Option<usize>,
+  truncate_input: bool,
+  max_steps: Option<u64>,
+  time_limit_ms: Option<u64>,
+  max_nesting: Option<usize>,
+  github_alerts: bool,
+  abbreviations: bool,
+  citations: bool,
+  cross_references: bool,
+  typography: RawTypography,
+  strict: bool,
+  source_positions: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawTypography {
+  arrows: bool,
+  math: bool,
+  fractions: bool,
+  symbols: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawHtmlOpts {
+  number_headings: bool,
+  sections: bool,
+  no_heading_ids: bool,
+  heading_offset: i32,
+  compact: bool,
+  line_numbers: bool,
+  invisible_chars: RawInvisibleChars,
+  labels: RawLabels,
+  label_footnotes: bool,
+  dir: Option<RawTextDirection>,
+  a11y_placeholders: bool,
+  index: bool,
+  list_of_figures: bool,
+  interactive_tasks: bool,
+  standalone: bool,
+  seo: Option<RawSeoOpts>,
+  symbols: HashMap<String, String>,
+  include_positions: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawSeoOpts {
+  description: Option<String>,
+  image: Option<String>,
+  url: Option<String>,
+  site_name: Option<String>,
+  twitter_site: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawTextDirection {
+  Ltr,
+  Rtl,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawLabels {
+  footnotes: Option<String>,
+  section: Option<String>,
+  figure: Option<String>,
+  figures: Option<String>,
+  index: Option<String>,
+  note: Option<String>,
+  tip: Option<String>,
+  important: Option<String>,
+  warning: Option<String>,
+  caution: Option<String>,
+  missing_alt_text: Option<String>,
+  empty_link: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawInvisibleChars {
+  #[default]
+  Keep,
+  Strip,
+  Escape,
+}
+
+impl Config {
+  /// Reads a `djot.toml` file.
+  pub fn from_config_file(path: impl AsRef<Path>) -> Result<Config, ConfigError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+      .map_err(|err| ConfigError { message: format!("{}: {err}", path.display()) })?;
+    let mut res = Config::from_toml(&text)
+      .map_err(|err| ConfigError { message: format!("{}: {err}", path.display()) })?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    res.template = res.template.map(|it| dir.join(it));
+    res.filters = res.filters.iter().map(|it| dir.join(it)).collect();
+    Ok(res)
+  }
+
+  /// Like [`Config::from_config_file`], but leaves paths as they are.
+  pub fn from_toml(text: &str) -> Result<Config, ConfigError> {
+    let raw: RawConfig =
+      toml::from_str(text).map_err(|err| ConfigError { message: err.to_string() })?;
+    let parse = ParseOpts {
+      lazy_inlines: raw.parse.lazy_inlines,
+      stats: raw.parse.stats,
+      max_input_bytes: raw.parse.max_input_bytes,
+      truncate_input: raw.parse.truncate_input,
+      max_steps: raw.parse.max_steps,
+      time_limit: raw.parse.time_limit_ms.map(Duration::from_millis),
+      max_nesting: raw.parse.max_nesting,
+      github_alerts: raw.parse.github_alerts,
+      abbreviations: raw.parse.abbreviations,
+      citations: raw.parse.citations,
+      cross_references: raw.parse.cross_references,
+      typography: Typography {
+        arrows: raw.parse.typography.arrows,
+        math: raw.parse.typography.math,
+        fractions: raw.parse.typography.fractions,
+        symbols: raw.parse.typography.symbols,
+      },
+      strict: raw.parse.strict,
+      source_positions: raw.parse.source_positions,
+      ..ParseOpts::default()
+    };
+    let (labels, default) = (raw.html.labels, Labels::default());
+    let labels = Labels {
+      footnotes: labels.footnotes.unwrap_or(default.footnotes),
+      section: labels.section.unwrap_or(default.section),
+      figure: labels.figure.unwrap_or(default.figure),
+      figures: labels.figures.unwrap_or(default.figures),
+      index: labels.index.unwrap_or(default.index),
+      note: labels.note.unwrap_or(default.note),
+      tip: labels.tip.unwrap_or(default.tip),
+      important: labels.important.unwrap_or(default.important),
+      warning: labels.warning.unwrap_or(default.warning),
+      caution: labels.caution.unwrap_or(default.caution),
+      missing_alt_text: labels.missing_alt_text.unwrap_or(default.missing_alt_text),
+      empty_link: labels.empty_link.unwrap_or(default.empty_link),
+    };
+    let html = HtmlOpts {
+      number_headings: raw.html.number_headings,
+      sections: raw.html.sections,
+      no_heading_ids: raw.html.no_heading_ids,
+      heading_offset: raw.html.heading_offset,
+      compact: raw.html.compact,
+      line_numbers: raw.html.line_numbers,
+      invisible_chars: match raw.html.invisible_chars {
+        RawInvisibleChars::Keep => InvisibleChars::Keep,
+        RawInvisibleChars::Strip => InvisibleChars::Strip,
+        RawInvisibleChars::Escape => InvisibleChars::Escape,
+      },
+      labels,
+      label_footnotes: raw.html.label_footnotes,
+      a11y_placeholders: raw.html.a11y_placeholders,
+      index: raw.html.index,
+      list_of_figures: raw.html.list_of_figures,
+      interactive_tasks: raw.html.interactive_tasks,
+      standalone: raw.html.standalone,
+      symbols: raw.html.symbols,
+      include_positions: raw.html.include_positions,
+      seo: raw.html.seo.map(|it| SeoOpts {
+        description: it.description,
+        image: it.image,
+        url: it.url,
+        site_name: it.site_name,
+        twitter_site: it.twitter_site,
+      }),
+      dir: raw.html.dir.map(|it| match it {
+        RawTextDirection::Ltr => TextDirection::Ltr,
+        RawTextDirection::Rtl => TextDirection::Rtl,
+      }),
+      ..HtmlOpts::default()
+    };
+    Ok(Config { parse, html, template: raw.template, filters: raw.filters })
+  }
+}
@@ -0,0 +1,85 @@
+use std::{fmt::Write, ops::Range};
+
+use crate::LineIndex;
+
+/// A message about a range of the source text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub message: String,
+  /// Byte range in the source text.
+  pub range: Range<usize>,
+  /// Identifies the kind of problem, e.g. the name of the lint rule which
+  /// produced the diagnostic.
+  pub code: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Severity {
+  Warning,
+  Error,
+}
+
+impl Diagnostic {
+  pub fn warning(range: Range<usize>, message: impl Into<String>) -> Diagnostic {
+    Diagnostic { severity: Severity::Warning, message: message.into(), range, code: None }
+  }
+
+  pub fn error(range: Range<usize>, message: impl Into<String>) -> Diagnostic {
+    Diagnostic { severity: Severity::Error, message: message.into(), range, code: None }
+  }
+
+  pub fn with_code(mut self, code: &'static str) -> Diagnostic {
+    self.code = Some(code);
+    self
+  }
+
+  /// Renders the diagnostic together with the offending source line and a
+  /// caret marker under the range:
+  ///
+  /// ```text
+  /// warning: unclosed verbatim
+  ///  --> 1:5
+  ///   |
+  /// 1 | foo `bar
+  ///   |     ^^^^
+  /// ```
+  ///
+  /// With `color`, ANSI escapes are used to highlight the output; pass
+  /// `std::io::IsTerminal::is_terminal(&std::io::stderr())` to only color
+  /// output going to a terminal.
+  pub fn render(&self, source: &str, index: &LineIndex, color: bool) -> String {
+    let (severity, style) = match self.severity {
+      Severity::Warning => ("warning", "\x1b[1;33m"),
+      Severity::Error => ("error", "\x1b[1;31m"),
+    };
+    let (style, gutter_style, bold, reset) =
+      if color { (style, "\x1b[1;34m", "\x1b[1m", "\x1b[0m") } else { ("", "", "", "") };
+
+    let start = self.range.start.min(source.len());
+    let end = self.range.end.clamp(start, source.len());
+    let pos = index.line_col(start);
+    let line_start = start - pos.col as usize;
+    let line_end =
+      source[line_start..].find(['\r', '\n']).map_or(source.len(), |it| line_start + it);
+    let line = &source[line_start..line_end];
+
+    let line_no = (pos.line + 1).to_string();
+    let pad = " ".repeat(line_no.len());
+    // keep tabs so that the marker lines up with the source line
+    let indent: String =
+      source[line_start..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    let width = source[start..end.min(line_end).max(start)].chars().count().max(1);
+
+    let mut res = String::new();
+    let code = self.code.map(|it| format!("[{it}]")).unwrap_or_default();
+    let _ = writeln!(res, "{style}{severity}{code}{reset}{bold}: {}{reset}", self.message);
+    let _ = writeln!(res, "{pad}{gutter_style}-->{reset} {}:{}", pos.line + 1, pos.col + 1);
+    let _ = writeln!(res, "{pad} {gutter_style}|{reset}");
+    let _ = writeln!(res, "{gutter_style}{line_no} |{reset} {line}");
+    let _ =
+      writeln!(res, "{pad} {gutter_style}|{reset} {indent}{style}{}{reset}", "^".repeat(width));
+    res
+  }
+}
@@ -0,0 +1,163 @@
+use crate::{
+  ast::{NodeId, Tag},
+  Document,
+};
+
+/// A single edit turning one document into another, see [`diff`].
+///
+/// Paths are child indices starting from [`Document::children`]. Changes
+/// are meant to be applied in order, and each path refers to the tree as
+/// left by the changes before it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+  Insert { path: Vec<usize>, tag: Tag },
+  Delete { path: Vec<usize> },
+  Replace { path: Vec<usize>, tag: Tag },
+}
+
+/// Computes the changes turning `old` into `new`.
+///
+/// Unchanged subtrees are matched up first, remaining nodes of the same kind
+/// and attributes are diffed recursively, everything else is replaced,
+/// inserted or deleted as a whole.
+pub fn diff(old: &Document, new: &Document) -> Vec<Change> {
+  let mut res = Vec::new();
+  diff_children(&old.children, &new.children, &mut Vec::new(), &mut res);
+  res
+}
+
+fn diff_children(old: &[Tag], new: &[Tag], path: &mut Vec<usize>, res: &mut Vec<Change>) {
+  let mut idx = 0;
+  for step in align(old, new) {
+    path.push(idx);
+    match step {
+      Step::Same(..) => idx += 1,
+      Step::Pair(i, j) => {
+        if is_shallow_eq(&old[i], &new[j]) {
+          diff_children(&old[i].children, &new[j].children, path, res);
+        } else {
+          res.push(Change::Replace { path: path.clone(), tag: new[j].clone() });
+        }
+        idx += 1;
+      }
+      Step::Delete => res.push(Change::Delete { path: path.clone() }),
+      Step::Insert(j) => {
+        res.push(Change::Insert { path: path.clone(), tag: new[j].clone() });
+        idx += 1;
+      }
+    }
+    path.pop();
+  }
+}
+
+/// Carries node ids over from `old` to the matching nodes of `new`, nodes
+/// without a counterpart get fresh ids.
+pub(crate) fn reconcile_ids(old: &[Tag], new: &mut [Tag]) {
+  let mut next_id = max_id(old) + 1;
+  reconcile_children(old, new, &mut next_id)
+}
+
+fn reconcile_children(old: &[Tag], new: &mut [Tag], next_id: &mut u32) {
+  for step in align(old, new) {
+    match step {
+      Step::Same(i, j) => copy_ids(&old[i], &mut new[j]),
+      Step::Pair(i, j) if is_shallow_eq(&old[i], &new[j]) => {
+        new[j].id = old[i].id;
+        reconcile_children(&old[i].children, &mut new[j].children, next_id)
+      }
+      Step::Pair(_, j) | Step::Insert(j) => assign_ids(&mut new[j], next_id),
+      Step::Delete => (),
+    }
+  }
+}
+
+/// Numbers all nodes in pre-order, starting at `next_id`.
+pub(crate) fn assign_ids(tag: &mut Tag, next_id: &mut u32) {
+  tag.id = NodeId(*next_id);
+  *next_id += 1;
+  for child in &mut tag.children {
+    assign_ids(child, next_id)
+  }
+}
+
+fn copy_ids(from: &Tag, to: &mut Tag) {
+  to.id = from.id;
+  for (from, to) in from.children.iter().zip(&mut to.children) {
+    copy_ids(from, to)
+  }
+}
+
+fn max_id(tags: &[Tag]) -> u32 {
+  tags.iter().map(|it| it.id.0.max(max_id(&it.children))).max().unwrap_or(0)
+}
+
+fn is_shallow_eq(a: &Tag, b: &Tag) -> bool {
+  a.kind == b.kind && a.attrs == b.attrs
+}
+
+enum Step {
+  /// Equal subtrees.
+  Same(usize, usize),
+  /// Different nodes at the same position.
+  Pair(usize, usize),
+  Delete,
+  Insert(usize),
+}
+
+/// Lines up two lists of siblings, matching up equal subtrees along their
+/// longest common subsequence.
+fn align(old: &[Tag], new: &[Tag]) -> Vec<Step> {
+  let mut res = Vec::new();
+  // trim common prefix and suffix before running the quadratic part
+  let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+  let suffix =
+    old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+  let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+  res.extend((0..prefix).map(|i| Step::Same(i, i)));
+
+  let (n, m) = (old_end - prefix, new_end - prefix);
+  // lcs[i][j]: length of the longest common subsequence of the remaining
+  // old[prefix + i..] and new[prefix + j..]
+  let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+  for i in (0..n).rev() {
+    for j in (0..m).rev() {
+      lcs[i][j] = if old[prefix + i] == new[prefix + j] {
+        lcs[i + 1][j + 1] + 1
+      } else {
+        lcs[i + 1][j].max(lcs[i][j + 1])
+      };
+    }
+  }
+
+  let (mut i, mut j) = (0, 0);
+  while i < n || j < m {
+    if i < n && j < m && old[prefix + i] == new[prefix + j] {
+      res.push(Step::Same(prefix + i, prefix + j));
+      i += 1;
+      j += 1;
+      continue;
+    }
+    // collect the unmatched run on both sides up to the next common node
+    let (si, sj) = (i, j);
+    while i < n || j < m {
+      if i < n && j < m && old[prefix + i] == new[prefix + j] {
+        break;
+      }
+      if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+        i += 1;
+      } else {
+        j += 1;
+      }
+    }
+    let (old_run, new_run) = (si..i, sj..j);
+    for k in 0..old_run.len().max(new_run.len()) {
+      res.push(match (k < old_run.len(), k < new_run.len()) {
+        (true, true) => Step::Pair(prefix + si + k, prefix + sj + k),
+        (true, false) => Step::Delete,
+        _ => Step::Insert(prefix + sj + k),
+      });
+    }
+  }
+  res.extend((0..suffix).map(|k| Step::Same(old_end + k, new_end + k)));
+  res
+}
@@ -0,0 +1,56 @@
+use std::fmt::Write;
+
+use crate::{ast::Tag, Document};
+
+/// Longer string fields are cut off in the dump.
+const MAX_TEXT_LEN: usize = 40;
+
+pub(crate) fn dump(doc: &Document) -> String {
+  let mut res = String::from("doc\n");
+  for child in &doc.children {
+    dump_tag(child, 1, &mut res)
+  }
+  res
+}
+
+fn dump_tag(tag: &Tag, depth: usize, res: &mut String) {
+  for _ in 0..depth {
+    res.push_str("  ");
+  }
+  let fields = match serde_json::to_value(&tag.kind) {
+    Ok(serde_json::Value::Object(fields)) => fields,
+    _ => unreachable!("tags serialize to objects"),
+  };
+  res.push_str(fields["tag"].as_str().unwrap_or_default());
+  for (key, value) in &fields {
+    if key == "tag" {
+      continue;
+    }
+    match value {
+      serde_json::Value::String(s) => write!(res, " {key}={:?}", truncate(s)),
+      _ => write!(res, " {key}={value}"),
+    }
+    .expect("str format can't fail");
+  }
+  if !tag.attrs.is_empty() {
+    res.push_str(" {");
+    for (i, (key, value)) in tag.attrs.iter().enumerate() {
+      if i > 0 {
+        res.push(' ');
+      }
+      write!(res, "{key}={:?}", truncate(value)).expect("str format can't fail");
+    }
+    res.push('}');
+  }
+  res.push('\n');
+  for child in &tag.children {
+    dump_tag(child, depth + 1, res)
+  }
+}
+
+fn truncate(s: &str) -> String {
+  match s.char_indices().nth(MAX_TEXT_LEN) {
+    Some((idx, _)) => format!("{}…", &s[..idx]),
+    None => s.to_string(),
+  }
+}
@@ -0,0 +1,51 @@
+use std::{error, fmt, str};
+
+use crate::{Diagnostic, Document};
+
+/// The reason an input was rejected by the parser.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ParseError {
+  /// The input is longer than
+  /// [`ParseOpts::max_input_bytes`](crate::ParseOpts::max_input_bytes) or
+  /// [`MAX_INPUT_LEN`](crate::MAX_INPUT_LEN) bytes.
+  InputTooLarge { len: usize, limit: usize },
+  /// The input is not valid UTF-8.
+  Utf8(str::Utf8Error),
+  /// Parsing took more than
+  /// [`ParseOpts::max_steps`](crate::ParseOpts::max_steps) or
+  /// [`ParseOpts::time_limit`](crate::ParseOpts::time_limit). The document
+  /// parsed up to that point is kept in `partial`.
+  BudgetExceeded { partial: Box<Document> },
+  /// The input produced warnings while parsing with
+  /// [`ParseOpts::strict`](crate::ParseOpts::strict), reported as errors.
+  Warnings(Vec<Diagnostic>),
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ParseError::InputTooLarge { len, limit } => {
+        write!(f, "input of {len} bytes exceeds the limit of {limit} bytes")
+      }
+      ParseError::Utf8(err) => write!(f, "invalid UTF-8: {err}"),
+      ParseError::BudgetExceeded { .. } => write!(f, "parsing exceeded its budget"),
+      ParseError::Warnings(diagnostics) => match diagnostics.as_slice() {
+        [first] => f.write_str(&first.message),
+        [first, rest @ ..] => write!(f, "{} (and {} more)", first.message, rest.len()),
+        [] => f.write_str("the input produced warnings"),
+      },
+    }
+  }
+}
+
+impl error::Error for ParseError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      ParseError::InputTooLarge { .. }
+      | ParseError::BudgetExceeded { .. }
+      | ParseError::Warnings(_) => None,
+      ParseError::Utf8(err) => Some(err),
+    }
+  }
+}
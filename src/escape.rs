@@ -0,0 +1,78 @@
+#[cfg(feature = "html")]
+use std::{borrow::Cow, fmt::Write};
+
+#[cfg(feature = "html")]
+use crate::InvisibleChars;
+
+/// Escapes HTML text content.
+#[cfg(feature = "html")]
+pub(crate) fn escape_html_text(s: &str) -> Cow<'_, str> {
+  escape(s, |b| match b {
+    b'&' => Some("&amp;"),
+    b'<' => Some("&lt;"),
+    b'>' => Some("&gt;"),
+    _ => None,
+  })
+}
+
+/// Escapes a double-quoted HTML attribute value.
+#[cfg(feature = "html")]
+pub(crate) fn escape_html_attr(s: &str) -> Cow<'_, str> {
+  escape(s, |b| match b {
+    b'&' => Some("&amp;"),
+    b'<' => Some("&lt;"),
+    b'>' => Some("&gt;"),
+    b'"' => Some("&quot;"),
+    _ => None,
+  })
+}
+
+/// Replaces the ASCII bytes for which `table` returns a replacement,
+/// borrowing the input when there is nothing to replace.
+#[cfg(feature = "html")]
+fn escape(s: &str, table: impl Fn(u8) -> Option<&'static str>) -> Cow<'_, str> {
+  let Some(first) = s.bytes().position(|b| table(b).is_some()) else {
+    return Cow::Borrowed(s);
+  };
+  let mut res = String::with_capacity(s.len() + 8);
+  let mut last = 0;
+  for (idx, b) in s.bytes().enumerate().skip(first) {
+    if let Some(replacement) = table(b) {
+      res.push_str(&s[last..idx]);
+      res.push_str(replacement);
+      last = idx + 1;
+    }
+  }
+  res.push_str(&s[last..]);
+  Cow::Owned(res)
+}
+
+/// Classifies characters which change or hide how the surrounding text is
+/// displayed, as used in "trojan source" attacks.
+pub(crate) fn invisible_char_kind(c: char) -> Option<&'static str> {
+  match c {
+    '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' | '\u{200e}' | '\u{200f}' | '\u{61c}' => {
+      Some("bidi control character")
+    }
+    // zero width (non-)joiners are left alone, they are needed by emoji
+    // sequences and several scripts
+    '\u{200b}' | '\u{2060}'..='\u{2064}' | '\u{feff}' | '\u{180e}' => Some("invisible character"),
+    _ => None,
+  }
+}
+
+#[cfg(feature = "html")]
+pub(crate) fn replace_invisible(s: &str, mode: InvisibleChars) -> Cow<'_, str> {
+  if mode == InvisibleChars::Keep || !s.chars().any(|c| invisible_char_kind(c).is_some()) {
+    return Cow::Borrowed(s);
+  }
+  let mut res = String::with_capacity(s.len());
+  for c in s.chars() {
+    match invisible_char_kind(c) {
+      None => res.push(c),
+      Some(_) if mode == InvisibleChars::Strip => (),
+      Some(_) => write!(res, "<U+{:04X}>", c as u32).unwrap(),
+    }
+  }
+  Cow::Owned(res)
+}
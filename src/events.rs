@@ -0,0 +1,73 @@
+//! The matches of the parser as a flat sequence of events, for renderers
+//! which don't need the [`Document`](crate::Document) tree.
+
+use std::{fmt, ops::Range};
+
+pub use crate::annot::{Atom, Comp as Container};
+use crate::{annot::Annot, Match};
+
+/// A container being opened or closed, or a leaf element, together with the
+/// range of the input it was matched at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+  pub kind: EventKind,
+  pub range: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+  Start(Container),
+  End(Container),
+  Atom(Atom),
+}
+
+impl Event {
+  fn new(m: Match) -> Event {
+    let kind = match m.a {
+      Annot::Add(it) => EventKind::Start(it),
+      Annot::Sub(it) => EventKind::End(it),
+      Annot::Atom(it) => EventKind::Atom(it),
+    };
+    Event { kind, range: m.range() }
+  }
+}
+
+/// Displays the event the way djot.lua prints matches, with 1-based inclusive
+/// offsets, e.g. `+emph 1-1`.
+impl fmt::Display for Event {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.kind {
+      EventKind::Start(it) => write!(f, "+{it}")?,
+      EventKind::End(it) => write!(f, "-{it}")?,
+      EventKind::Atom(it) => write!(f, "{it}")?,
+    }
+    let (start, end) = (self.range.start, self.range.end);
+    write!(f, " {}-{}", start + 1, if end == start { end + 1 } else { end })
+  }
+}
+
+/// The events of a parsed input, in document order.
+#[derive(Debug)]
+pub struct Events {
+  matches: std::vec::IntoIter<Match>,
+}
+
+impl Events {
+  pub(crate) fn new(matches: Vec<Match>) -> Events {
+    Events { matches: matches.into_iter() }
+  }
+}
+
+impl Iterator for Events {
+  type Item = Event;
+
+  fn next(&mut self) -> Option<Event> {
+    self.matches.next().map(Event::new)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.matches.size_hint()
+  }
+}
+
+impl ExactSizeIterator for Events {}
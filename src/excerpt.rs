@@ -0,0 +1,38 @@
+use crate::{
+  ast::{Tag, TagKind},
+  tree::get_string_content,
+  Document, ExcerptFormat, ExcerptOpts,
+};
+
+pub(crate) fn excerpt(doc: &Document, opts: &ExcerptOpts) -> String {
+  let mut blocks = Vec::new();
+  flatten(&doc.children, &mut blocks);
+  let blocks = match blocks.iter().position(|it| it.has_class(&opts.marker)) {
+    Some(idx) => &blocks[..idx],
+    None => match blocks.iter().position(|it| matches!(it.kind, TagKind::Para(_))) {
+      Some(idx) => &blocks[idx..idx + 1],
+      None => &[],
+    },
+  };
+  match &opts.format {
+    ExcerptFormat::Text => {
+      let text: Vec<String> = blocks.iter().map(|it| get_string_content(it)).collect();
+      text.join("\n\n")
+    }
+    #[cfg(feature = "html")]
+    ExcerptFormat::Html(html_opts) => {
+      let children = blocks.iter().map(|&it| it.clone()).collect();
+      Document { children, ..Document::default() }.to_html_opts(html_opts)
+    }
+  }
+}
+
+// Sections don't count as blocks of their own, their headings do.
+fn flatten<'a>(tags: &'a [Tag], acc: &mut Vec<&'a Tag>) {
+  for tag in tags {
+    match tag.kind {
+      TagKind::Section(_) => flatten(&tag.children, acc),
+      _ => acc.push(tag),
+    }
+  }
+}
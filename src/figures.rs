@@ -0,0 +1,47 @@
+use std::ops::Range;
+
+use crate::{
+  ast::{Tag, TagKind},
+  tree::get_string_content,
+};
+
+/// A captioned figure, see [`Document::figures`](crate::Document::figures).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FigureEntry {
+  /// Figures are numbered from 1 in document order.
+  pub number: usize,
+  /// The `id` attribute of the figure, or `figure-N`.
+  pub id: String,
+  /// The text of the caption.
+  pub caption: String,
+  pub span: Range<usize>,
+}
+
+pub(crate) fn entries(children: &[Tag]) -> Vec<FigureEntry> {
+  let mut acc = Vec::new();
+  collect(children, &mut acc);
+  acc
+}
+
+pub(crate) fn figure_id(tag: &Tag, number: usize) -> String {
+  match tag.get_attr("id") {
+    Some(id) => id.to_string(),
+    None => format!("figure-{number}"),
+  }
+}
+
+fn collect(tags: &[Tag], acc: &mut Vec<FigureEntry>) {
+  for tag in tags {
+    if let TagKind::Figure(_) = tag.kind {
+      let number = acc.len() + 1;
+      let caption = tag.children.iter().find(|it| matches!(it.kind, TagKind::Caption(_)));
+      acc.push(FigureEntry {
+        number,
+        id: figure_id(tag, number),
+        caption: caption.map(get_string_content).unwrap_or_default(),
+        span: tag.span.clone(),
+      })
+    }
+    collect(&tag.children, acc)
+  }
+}
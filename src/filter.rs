@@ -0,0 +1,82 @@
+//! Transformations of the AST between parsing and rendering, like the
+//! filters of djot.lua.
+//!
+//! ```
+//! use djot::{ast::{Tag, TagKind}, filter::{Action, Filter}};
+//!
+//! struct StaticImages;
+//!
+//! impl Filter for StaticImages {
+//!   fn exit(&mut self, tag: &mut Tag) -> Action {
+//!     if let TagKind::Image(image) = &mut tag.kind {
+//!       image.destination = image.destination.take().map(|it| format!("/static/{it}"));
+//!     }
+//!     Action::Keep
+//!   }
+//! }
+//!
+//! let mut doc = djot::Document::parse("![cat](cat.png)");
+//! doc.apply_filter(&mut StaticImages);
+//! # #[cfg(feature = "html")]
+//! assert_eq!(doc.to_html(), "<p><img alt=\"cat\" src=\"/static/cat.png\"></p>\n");
+//! ```
+
+use crate::ast::Tag;
+
+/// What becomes of a node after a callback of a [`Filter`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+  /// Keeps the node, including any changes made by the callback.
+  Keep,
+  /// Keeps the node without visiting its children. Only meaningful when
+  /// returned from [`Filter::enter`], otherwise the same as `Keep`.
+  SkipChildren,
+  /// Removes the node with all its children.
+  Delete,
+  /// Replaces the node with any number of nodes, which are not visited.
+  Replace(Vec<Tag>),
+}
+
+/// Callbacks for every node of a document, see
+/// [`Document::apply_filter`](crate::Document::apply_filter).
+///
+/// Filters typically match on the [`TagKind`](crate::ast::TagKind) of the
+/// node and return [`Action::Keep`] for the kinds they don't handle.
+pub trait Filter {
+  /// Called before the children of `tag` are visited.
+  fn enter(&mut self, _tag: &mut Tag) -> Action {
+    Action::Keep
+  }
+
+  /// Called after the children of `tag` were visited.
+  fn exit(&mut self, _tag: &mut Tag) -> Action {
+    Action::Keep
+  }
+}
+
+/// A closure is called when leaving each node, so children are filtered
+/// before their parents.
+impl<F: FnMut(&mut Tag) -> Action> Filter for F {
+  fn exit(&mut self, tag: &mut Tag) -> Action {
+    self(tag)
+  }
+}
+
+pub(crate) fn apply(tags: &mut Vec<Tag>, filter: &mut impl Filter) {
+  for mut tag in std::mem::take(tags) {
+    match filter.enter(&mut tag) {
+      Action::Keep => apply(&mut tag.children, filter),
+      Action::SkipChildren => (),
+      Action::Delete => continue,
+      Action::Replace(new) => {
+        tags.extend(new);
+        continue;
+      }
+    }
+    match filter.exit(&mut tag) {
+      Action::Keep | Action::SkipChildren => tags.push(tag),
+      Action::Delete => (),
+      Action::Replace(new) => tags.extend(new),
+    }
+  }
+}
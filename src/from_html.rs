@@ -0,0 +1,546 @@
+//! Conversion of HTML to djot, for migrating existing content, enabled by
+//! the `from-html` feature.
+//!
+//! ```
+//! let html = "<h1>Title</h1><p>Some <em>text</em> and <a href=\"/x\">a link</a>.</p>";
+//! assert_eq!(djot::from_html::to_djot(html), "# Title\n\nSome _text_ and [a link](/x).\n");
+//! ```
+//!
+//! Only the elements with a djot equivalent are understood: paragraphs,
+//! headings, emphasis, links, images, code, block quotes, lists and tables,
+//! among a few others. Any other element is replaced by its content, except
+//! for `head`, `script` and `style`, which are dropped along with comments.
+//! The HTML doesn't have to be well formed, unclosed elements are closed at
+//! the end of their parent.
+
+use crate::Document;
+
+/// Converts `html` to djot source.
+pub fn to_djot(html: &str) -> String {
+  let blocks = blocks(&parse(html));
+  if blocks.is_empty() {
+    return String::new();
+  }
+  format!("{}\n", blocks.join("\n\n"))
+}
+
+/// Converts `html` to a document, by parsing the result of [`to_djot`].
+pub fn to_document(html: &str) -> Document {
+  Document::parse(&to_djot(html))
+}
+
+#[derive(Debug)]
+enum Node {
+  Element(Element),
+  Text(String),
+}
+
+#[derive(Debug)]
+struct Element {
+  name: String,
+  attrs: Vec<(String, String)>,
+  children: Vec<Node>,
+}
+
+impl Element {
+  fn attr(&self, name: &str) -> Option<&str> {
+    self.attrs.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+  }
+}
+
+const VOID: &[&str] = &[
+  "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+  "wbr",
+];
+const DROPPED: &[&str] = &["head", "script", "style", "template", "title"];
+// starting one of these closes an open paragraph
+const BLOCKS: &[&str] = &[
+  "address",
+  "article",
+  "aside",
+  "blockquote",
+  "div",
+  "dl",
+  "figure",
+  "footer",
+  "form",
+  "h1",
+  "h2",
+  "h3",
+  "h4",
+  "h5",
+  "h6",
+  "header",
+  "hr",
+  "main",
+  "nav",
+  "ol",
+  "p",
+  "pre",
+  "section",
+  "table",
+  "ul",
+];
+
+fn parse(html: &str) -> Vec<Node> {
+  let mut root = Element { name: String::new(), attrs: Vec::new(), children: Vec::new() };
+  let mut stack: Vec<Element> = Vec::new();
+  let mut pos = 0;
+  while pos < html.len() {
+    let rest = &html[pos..];
+    let parent = stack.last_mut().unwrap_or(&mut root);
+    if let Some(comment) = rest.strip_prefix("<!--") {
+      pos += 4 + comment.find("-->").map_or(comment.len(), |it| it + 3);
+    } else if rest.starts_with("<!") || rest.starts_with("<?") {
+      pos += rest.find('>').map_or(rest.len(), |it| it + 1);
+    } else if let Some(end_tag) = rest.strip_prefix("</") {
+      let len = end_tag.find('>').map_or(end_tag.len(), |it| it + 1);
+      let name = tag_name(end_tag);
+      if stack.iter().any(|it| it.name == name) {
+        while let Some(element) = stack.pop() {
+          let done = element.name == name;
+          stack.last_mut().unwrap_or(&mut root).children.push(Node::Element(element));
+          if done {
+            break;
+          }
+        }
+      }
+      pos += 2 + len;
+    } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+      let (element, len, self_closing) = start_tag(&rest[1..]);
+      pos += 1 + len;
+      // elements which are implicitly closed by their next sibling
+      let closes = |open: &str| match element.name.as_str() {
+        "li" | "tr" | "td" | "th" | "dt" | "dd" => open == element.name,
+        name => open == "p" && BLOCKS.contains(&name),
+      };
+      if stack.last().is_some_and(|it| closes(&it.name)) {
+        let open = stack.pop().unwrap();
+        stack.last_mut().unwrap_or(&mut root).children.push(Node::Element(open));
+      }
+      if DROPPED.contains(&element.name.as_str()) && !self_closing {
+        // skip the content, which may not be HTML at all
+        let end = format!("</{}", element.name);
+        let lower = html[pos..].to_ascii_lowercase();
+        pos += lower.find(&end).unwrap_or(lower.len());
+        continue;
+      }
+      let parent = stack.last_mut().unwrap_or(&mut root);
+      if self_closing || VOID.contains(&element.name.as_str()) {
+        parent.children.push(Node::Element(element))
+      } else {
+        stack.push(element)
+      }
+    } else {
+      let len = rest[1..].find('<').map_or(rest.len(), |it| it + 1);
+      let text = decode_entities(&rest[..len]);
+      match parent.children.last_mut() {
+        Some(Node::Text(prev)) => prev.push_str(&text),
+        _ => parent.children.push(Node::Text(text)),
+      }
+      pos += len;
+    }
+  }
+  while let Some(element) = stack.pop() {
+    stack.last_mut().unwrap_or(&mut root).children.push(Node::Element(element));
+  }
+  root.children
+}
+
+fn tag_name(s: &str) -> String {
+  let end = s.find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/').unwrap_or(s.len());
+  s[..end].to_ascii_lowercase()
+}
+
+/// Parses `name attr="value" ...>` after a `<`, returning the element, the
+/// length of the tag and whether it ended with `/>`.
+fn start_tag(s: &str) -> (Element, usize, bool) {
+  let name = tag_name(s);
+  let mut attrs = Vec::new();
+  let mut pos = name.len();
+  let bytes = s.as_bytes();
+  let skip_space = |pos: &mut usize| {
+    while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+      *pos += 1
+    }
+  };
+  let mut self_closing = false;
+  loop {
+    skip_space(&mut pos);
+    match bytes.get(pos) {
+      None => break,
+      Some(b'>') => {
+        pos += 1;
+        break;
+      }
+      Some(b'/') => {
+        self_closing = bytes.get(pos + 1) == Some(&b'>');
+        pos += 1;
+        continue;
+      }
+      Some(_) => (),
+    }
+    let start = pos;
+    while pos < bytes.len() && !matches!(bytes[pos], b'=' | b'>' | b'/') {
+      if bytes[pos].is_ascii_whitespace() {
+        break;
+      }
+      pos += 1
+    }
+    let key = s[start..pos].to_ascii_lowercase();
+    skip_space(&mut pos);
+    let mut value = String::new();
+    if bytes.get(pos) == Some(&b'=') {
+      pos += 1;
+      skip_space(&mut pos);
+      let start = pos;
+      match bytes.get(pos) {
+        Some(&quote @ (b'"' | b'\'')) => {
+          let len = s[pos + 1..].find(quote as char).unwrap_or(s.len() - pos - 1);
+          value = decode_entities(&s[pos + 1..pos + 1 + len]);
+          pos = (pos + 2 + len).min(s.len());
+        }
+        _ => {
+          while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && bytes[pos] != b'>' {
+            pos += 1
+          }
+          value = decode_entities(&s[start..pos]);
+        }
+      }
+    }
+    if !key.is_empty() {
+      attrs.push((key, value))
+    } else if pos == start {
+      pos += 1
+    }
+  }
+  (Element { name, attrs, children: Vec::new() }, pos, self_closing)
+}
+
+fn decode_entities(text: &str) -> String {
+  let mut res = String::with_capacity(text.len());
+  let mut rest = text;
+  while let Some(idx) = rest.find('&') {
+    res.push_str(&rest[..idx]);
+    rest = &rest[idx..];
+    let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]).filter(|it| it.len() <= 8);
+    let decoded = entity.and_then(|entity| match entity {
+      "amp" => Some('&'),
+      "lt" => Some('<'),
+      "gt" => Some('>'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      "nbsp" => Some('\u{a0}'),
+      "ndash" => Some('–'),
+      "mdash" => Some('—'),
+      "hellip" => Some('…'),
+      "copy" => Some('©'),
+      _ => {
+        let number = entity.strip_prefix('#')?;
+        let code = match number.strip_prefix(['x', 'X']) {
+          Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+          None => number.parse().ok()?,
+        };
+        char::from_u32(code)
+      }
+    });
+    match (entity, decoded) {
+      (Some(entity), Some(c)) => {
+        res.push(c);
+        rest = &rest[entity.len() + 2..];
+      }
+      _ => {
+        res.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  res.push_str(rest);
+  res
+}
+
+fn is_block(node: &Node) -> bool {
+  match node {
+    Node::Element(element) => {
+      BLOCKS.contains(&element.name.as_str())
+        || matches!(element.name.as_str(), "body" | "html" | "li" | "tr" | "dt" | "dd")
+    }
+    Node::Text(_) => false,
+  }
+}
+
+/// Renders the nodes as djot blocks, wrapping runs of inline content into
+/// paragraphs.
+fn blocks<'a>(nodes: impl IntoIterator<Item = &'a Node>) -> Vec<String> {
+  let mut res = Vec::new();
+  let mut inlines: Vec<&Node> = Vec::new();
+  let flush = |inlines: &mut Vec<&Node>, res: &mut Vec<String>| {
+    let para = paragraph(inlines.drain(..));
+    if !para.is_empty() {
+      res.push(para)
+    }
+  };
+  for node in nodes {
+    let Node::Element(element) = node else {
+      inlines.push(node);
+      continue;
+    };
+    if !is_block(node) {
+      inlines.push(node);
+      continue;
+    }
+    flush(&mut inlines, &mut res);
+    match element.name.as_str() {
+      "p" => res.extend(Some(paragraph(element.children.iter())).filter(|it| !it.is_empty())),
+      "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+        let level = usize::from(element.name.as_bytes()[1] - b'0');
+        let text = paragraph(element.children.iter()).replace('\n', " ");
+        res.push(format!("{} {text}", "#".repeat(level)))
+      }
+      "blockquote" => {
+        let content = blocks(&element.children).join("\n\n");
+        res.push(prefix_lines(&content, "> ", ">"))
+      }
+      "pre" => res.push(code_block(element)),
+      "hr" => res.push("* * *".to_string()),
+      "ul" | "ol" => res.push(list(element)),
+      "table" => res.extend(table(element)),
+      _ => res.extend(blocks(&element.children)),
+    }
+  }
+  flush(&mut inlines, &mut res);
+  res
+}
+
+fn prefix_lines(text: &str, prefix: &str, empty_prefix: &str) -> String {
+  let lines: Vec<String> = text
+    .lines()
+    .map(|line| if line.is_empty() { empty_prefix.to_string() } else { format!("{prefix}{line}") })
+    .collect();
+  lines.join("\n")
+}
+
+fn code_block(pre: &Element) -> String {
+  let code = match pre.children.as_slice() {
+    [Node::Element(code)] if code.name == "code" => code,
+    _ => pre,
+  };
+  let lang = [code, pre].into_iter().find_map(|it| {
+    let class = it.attr("class")?;
+    class.split_whitespace().find_map(|it| it.strip_prefix("language-"))
+  });
+  let mut text = text_content(&code.children);
+  if text.starts_with('\n') {
+    text.remove(0);
+  }
+  if !text.ends_with('\n') {
+    text.push('\n')
+  }
+  let fence = "`".repeat(longest_run(&text, '`').max(2) + 1);
+  match lang {
+    Some(lang) => format!("{fence} {lang}\n{text}{fence}"),
+    None => format!("{fence}\n{text}{fence}"),
+  }
+}
+
+fn list(element: &Element) -> String {
+  let ordered = element.name == "ol";
+  let mut number = element.attr("start").and_then(|it| it.parse().ok()).unwrap_or(1);
+  let mut items = Vec::new();
+  let mut loose = false;
+  for node in &element.children {
+    let Node::Element(item) = node else { continue };
+    if item.name != "li" {
+      continue;
+    }
+    let mut children = item.children.iter().skip_while(|it| is_blank(it)).peekable();
+    let mut marker = if ordered { format!("{number}.") } else { "-".to_string() };
+    number += 1;
+    if let Some(Node::Element(input)) = children.peek() {
+      if input.name == "input" && input.attr("type") == Some("checkbox") {
+        let checked = input.attrs.iter().any(|(key, _)| key == "checked");
+        marker.push_str(if checked { " [x]" } else { " [ ]" });
+        children.next();
+      }
+    }
+    let content = blocks(children);
+    loose |= content.len() > 1;
+    let content = content.join("\n\n");
+    let indent = " ".repeat(marker.len() + 1);
+    let content = prefix_lines(&content, &indent, "");
+    items.push(format!("{marker} {}", content.trim_start()))
+  }
+  items.join(if loose { "\n\n" } else { "\n" })
+}
+
+fn table(element: &Element) -> Vec<String> {
+  let mut rows = Vec::new();
+  collect_rows(&element.children, &mut rows);
+  if rows.is_empty() {
+    return Vec::new();
+  }
+  let mut lines = Vec::new();
+  for (i, (cells, header)) in rows.iter().enumerate() {
+    let cells: Vec<String> = cells
+      .iter()
+      .map(|it| paragraph(it.children.iter()).replace('\n', " ").replace('|', "\\|"))
+      .collect();
+    lines.push(format!("| {} |", cells.join(" | ")));
+    if i == 0 && *header {
+      let rule: Vec<&str> = cells.iter().map(|_| "---").collect();
+      lines.push(format!("|{}|", rule.join("|")));
+    }
+  }
+  vec![lines.join("\n")]
+}
+
+fn collect_rows<'a>(nodes: &'a [Node], rows: &mut Vec<(Vec<&'a Element>, bool)>) {
+  for node in nodes {
+    let Node::Element(element) = node else { continue };
+    match element.name.as_str() {
+      "tr" => {
+        let cells: Vec<&Element> = element
+          .children
+          .iter()
+          .filter_map(|it| match it {
+            Node::Element(cell) if cell.name == "td" || cell.name == "th" => Some(cell),
+            _ => None,
+          })
+          .collect();
+        let header = !cells.is_empty() && cells.iter().all(|it| it.name == "th");
+        rows.push((cells, header))
+      }
+      "thead" | "tbody" | "tfoot" => collect_rows(&element.children, rows),
+      _ => (),
+    }
+  }
+}
+
+fn is_blank(node: &Node) -> bool {
+  matches!(node, Node::Text(text) if text.trim().is_empty())
+}
+
+/// Inline content, with whitespace at the start of lines removed.
+fn paragraph<'a>(nodes: impl Iterator<Item = &'a Node>) -> String {
+  let mut out = String::new();
+  for node in nodes {
+    inline(node, &mut out)
+  }
+  let lines: Vec<&str> = out.trim().lines().map(str::trim_start).collect();
+  let mut res = lines.join("\n");
+  // text which would be read as a block marker
+  if res.starts_with(['#', '>', '-', '+']) {
+    res.insert(0, '\\')
+  }
+  res
+}
+
+fn inline(node: &Node, out: &mut String) {
+  let element = match node {
+    Node::Text(text) => return escape_text(text, out),
+    Node::Element(element) => element,
+  };
+  let delimited = |out: &mut String, open: &str, close: &str| {
+    let mut content = String::new();
+    for child in &element.children {
+      inline(child, &mut content)
+    }
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+      return out.push_str(&content);
+    }
+    // whitespace next to the delimiters would keep them from closing
+    if content.starts_with(char::is_whitespace) {
+      out.push(' ')
+    }
+    out.push_str(open);
+    out.push_str(trimmed);
+    out.push_str(close);
+    if content.ends_with(char::is_whitespace) {
+      out.push(' ')
+    }
+  };
+  match element.name.as_str() {
+    "em" | "i" => delimited(out, "_", "_"),
+    "strong" | "b" => delimited(out, "*", "*"),
+    "sub" => delimited(out, "~", "~"),
+    "sup" => delimited(out, "^", "^"),
+    "mark" => delimited(out, "{=", "=}"),
+    "del" | "s" => delimited(out, "{-", "-}"),
+    "ins" | "u" => delimited(out, "{+", "+}"),
+    "code" | "kbd" | "samp" | "tt" => {
+      let text = text_content(&element.children).replace('\n', " ");
+      let fence = "`".repeat(longest_run(&text, '`') + 1);
+      let pad = if text.starts_with('`') || text.ends_with('`') { " " } else { "" };
+      out.push_str(&format!("{fence}{pad}{text}{pad}{fence}"))
+    }
+    "br" => out.push_str("\\\n"),
+    "img" => {
+      let alt = element.attr("alt").unwrap_or_default();
+      let mut alt_text = String::new();
+      escape_text(alt, &mut alt_text);
+      let src = destination(element.attr("src").unwrap_or_default());
+      out.push_str(&format!("![{alt_text}]({src})"))
+    }
+    "a" => {
+      let Some(href) = element.attr("href") else {
+        return element.children.iter().for_each(|it| inline(it, out));
+      };
+      if text_content(&element.children) == href && href.contains(':') && !href.contains('>') {
+        return out.push_str(&format!("<{href}>"));
+      }
+      delimited(out, "[", &format!("]({})", destination(href)))
+    }
+    "input" => (),
+    _ => element.children.iter().for_each(|it| inline(it, out)),
+  }
+}
+
+fn destination(url: &str) -> String {
+  url.replace(' ', "%20").replace('(', "%28").replace(')', "%29")
+}
+
+fn text_content(nodes: &[Node]) -> String {
+  let mut res = String::new();
+  for node in nodes {
+    match node {
+      Node::Text(text) => res.push_str(text),
+      Node::Element(element) => res.push_str(&text_content(&element.children)),
+    }
+  }
+  res
+}
+
+fn escape_text(text: &str, out: &mut String) {
+  let mut prev_space = out.ends_with(char::is_whitespace);
+  for (idx, c) in text.char_indices() {
+    if c.is_whitespace() && c != '\u{a0}' {
+      if !prev_space {
+        out.push(if c == '\n' { '\n' } else { ' ' })
+      }
+      prev_space = true;
+      continue;
+    }
+    prev_space = false;
+    let escape = match c {
+      '\\' | '*' | '_' | '`' | '[' | ']' | '{' | '}' | '<' | '~' | '^' | '"' => true,
+      // the start of a `:symbol:`
+      ':' => text[idx + 1..].split(char::is_whitespace).next().is_some_and(|it| it.contains(':')),
+      _ => false,
+    };
+    if escape {
+      out.push('\\')
+    }
+    out.push(c)
+  }
+}
+
+fn longest_run(text: &str, c: char) -> usize {
+  let mut res = 0;
+  let mut run = 0;
+  for it in text.chars() {
+    run = if it == c { run + 1 } else { 0 };
+    res = res.max(run)
+  }
+  res
+}
@@ -0,0 +1,87 @@
+//! Code block highlighting with [syntect], enabled by the `syntect` feature.
+//!
+//! ```
+//! use djot::highlight::SyntectHighlighter;
+//!
+//! let highlighter = SyntectHighlighter::with_theme("InspiredGitHub").unwrap();
+//! let opts = djot::HtmlOpts {
+//!   highlighter: Some(highlighter.into_highlighter()),
+//!   ..djot::HtmlOpts::default()
+//! };
+//! let html = djot::Document::parse("``` rust\nfn main() {}\n```\n").to_html_opts(&opts);
+//! assert!(html.starts_with("<pre style="));
+//! ```
+
+use std::sync::Arc;
+
+use syntect::{
+  highlighting::{Theme, ThemeSet},
+  html::{
+    css_for_theme_with_class_style, highlighted_html_for_string, ClassStyle, ClassedHTMLGenerator,
+  },
+  parsing::{SyntaxReference, SyntaxSet},
+  util::LinesWithEndings,
+};
+
+use crate::CodeHighlighter;
+
+pub struct SyntectHighlighter {
+  syntaxes: SyntaxSet,
+  theme: Option<Theme>,
+}
+
+impl SyntectHighlighter {
+  /// Highlights with inline styles, using one of syntect's bundled themes
+  /// (`InspiredGitHub`, `base16-ocean.dark`, `Solarized (light)`, ...).
+  pub fn with_theme(name: &str) -> Option<SyntectHighlighter> {
+    let theme = ThemeSet::load_defaults().themes.remove(name)?;
+    Some(SyntectHighlighter { syntaxes: SyntaxSet::load_defaults_newlines(), theme: Some(theme) })
+  }
+
+  /// Highlights with CSS classes, pair with a stylesheet from
+  /// [`SyntectHighlighter::css`].
+  pub fn with_classes() -> SyntectHighlighter {
+    SyntectHighlighter { syntaxes: SyntaxSet::load_defaults_newlines(), theme: None }
+  }
+
+  /// Stylesheet for the class based output in one of the bundled themes.
+  pub fn css(theme: &str) -> Option<String> {
+    let theme = ThemeSet::load_defaults().themes.remove(theme)?;
+    css_for_theme_with_class_style(&theme, ClassStyle::Spaced).ok()
+  }
+
+  /// Highlights a code block, `None` if the language is not known.
+  ///
+  /// Without a language, the syntax is guessed from the first line (e.g. a
+  /// shebang).
+  pub fn highlight(&self, code: &str, lang: Option<&str>) -> Option<String> {
+    let syntax = self.find_syntax(code, lang)?;
+    match &self.theme {
+      Some(theme) => {
+        let html = highlighted_html_for_string(code, &self.syntaxes, syntax, theme).ok()?;
+        Some(html.trim_end().to_string())
+      }
+      None => {
+        let mut generator =
+          ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntaxes, ClassStyle::Spaced);
+        for line in LinesWithEndings::from(code) {
+          generator.parse_html_for_line_which_includes_newline(line).ok()?;
+        }
+        let class = lang.map(|it| format!(" class=\"language-{it}\"")).unwrap_or_default();
+        Some(format!("<pre><code{class}>{}</code></pre>", generator.finalize()))
+      }
+    }
+  }
+
+  /// Wraps the highlighter into a hook for [`HtmlOpts::highlighter`](crate::HtmlOpts::highlighter).
+  pub fn into_highlighter(self) -> CodeHighlighter {
+    Arc::new(move |code, lang| self.highlight(code, lang))
+  }
+
+  fn find_syntax(&self, code: &str, lang: Option<&str>) -> Option<&SyntaxReference> {
+    match lang {
+      Some(lang) => self.syntaxes.find_syntax_by_token(lang),
+      None => self.syntaxes.find_syntax_by_first_line(code.lines().next()?),
+    }
+  }
+}
@@ -1,67 +1,693 @@
+use std::{borrow::Cow, collections::HashMap, fmt, ops::RangeInclusive};
+
 use crate::{
-  ast::{Attrs, Tag, TagKind},
-  tree::get_string_content,
-  Document, HtmlOpts,
+  ast::{Alignment, Attrs, DisplayMath, Heading, InlineMath, Tag, TagKind},
+  bidi::first_strong,
+  escape::{escape_html_attr, escape_html_text, replace_invisible},
+  figures,
+  index::{self, is_index_term, term_id},
+  line_index::LineIndex,
+  lint::has_alt_text,
+  lists,
+  numbering::HeadingNumbers,
+  standalone,
+  tree::{
+    citation_keys, get_string_content, parse_inlines, references, resolve_references, References,
+  },
+  Document, HtmlOpts, TextDirection,
 };
 
 pub(crate) fn convert(opts: &HtmlOpts, doc: &Document) -> String {
-  let mut ctx = Ctx { opts, res: String::new() };
+  let mut res = String::new();
+  write_html(opts, doc, &mut res).expect("str format can't fail");
+  res
+}
+
+fn write_html(opts: &HtmlOpts, doc: &Document, out: &mut dyn fmt::Write) -> fmt::Result {
+  #[cfg(feature = "tracing")]
+  let _span = tracing::info_span!("render_html").entered();
+  if opts.standalone {
+    standalone::write_head(opts, doc, out)?;
+  }
+  let mut ctx = Ctx::new(opts, out);
   ctx.render_doc(doc);
-  ctx.res
+  ctx.res?;
+  if opts.standalone {
+    standalone::write_foot(out)?;
+  }
+  Ok(())
+}
+
+pub(crate) fn convert_tag(opts: &HtmlOpts, tag: &Tag) -> String {
+  let mut res = String::new();
+  let mut ctx = Ctx::new(opts, &mut res);
+  ctx.render(tag);
+  ctx.res.expect("str format can't fail");
+  res
+}
+
+/// A document paired with rendering options, rendered to HTML on demand by
+/// its [`fmt::Display`] implementation.
+///
+/// Created by [`Document::html`].
+pub struct Html<'a> {
+  pub(crate) doc: &'a Document,
+  pub(crate) opts: &'a HtmlOpts,
+}
+
+impl fmt::Display for Html<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write_html(self.opts, self.doc, f)
+  }
+}
+
+/// Renders a document piece by piece, a few top level blocks at a time, for
+/// sending it before all of it is rendered.
+#[cfg(any(feature = "axum", feature = "actix"))]
+pub(crate) struct Chunks {
+  doc: Document,
+  opts: HtmlOpts,
+  state: Option<State>,
+  // the next top level block, past the end once the rest was rendered
+  next: usize,
+}
+
+#[cfg(any(feature = "axum", feature = "actix"))]
+impl Chunks {
+  /// The size from which a chunk is complete.
+  const LEN: usize = 8 * 1024;
+
+  pub(crate) fn new(doc: Document, opts: HtmlOpts) -> Chunks {
+    Chunks { doc, opts, state: None, next: 0 }
+  }
+}
+
+#[cfg(any(feature = "axum", feature = "actix"))]
+impl Iterator for Chunks {
+  type Item = String;
+
+  fn next(&mut self) -> Option<String> {
+    let Chunks { doc, opts, state, next } = self;
+    let doc = &*doc;
+    if *next > doc.children.len() {
+      return None;
+    }
+    let mut res = String::new();
+    while res.len() < Chunks::LEN && *next <= doc.children.len() {
+      if state.is_none() && opts.standalone {
+        standalone::write_head(opts, doc, &mut res).expect("str format can't fail");
+      }
+      let mut ctx = Ctx::new(opts, &mut res);
+      match state.take() {
+        Some(state) => ctx.restore(doc, state),
+        None => ctx.begin_doc(doc),
+      }
+      let child = doc.children.get(*next);
+      match child {
+        Some(child) => ctx.render(child),
+        None => ctx.end_doc(doc),
+      }
+      ctx.res.expect("str format can't fail");
+      *state = Some(ctx.save());
+      if child.is_none() && opts.standalone {
+        standalone::write_foot(&mut res).expect("str format can't fail");
+      }
+      *next += 1
+    }
+    Some(res)
+  }
+}
+
+// the part of `Ctx` which carries over from one chunk to the next
+#[cfg(any(feature = "axum", feature = "actix"))]
+struct State {
+  heading_numbers: HeadingNumbers,
+  index_terms: usize,
+  targets: HashMap<String, String>,
+  figures: usize,
+  footnotes: Vec<String>,
+  references: References,
 }
 
 struct Ctx<'a> {
-  #[allow(unused)]
   opts: &'a HtmlOpts,
-  res: String,
+  out: &'a mut dyn fmt::Write,
+  res: fmt::Result,
+  heading_numbers: HeadingNumbers,
+  // direction of the enclosing block, when known
+  dir: Option<TextDirection>,
+  // marked index terms rendered so far
+  index_terms: usize,
+  // text of cross references by identifier
+  targets: HashMap<String, String>,
+  // figures rendered so far
+  figures: usize,
+  // labels of the footnotes in the order of their first reference
+  footnotes: Vec<String>,
+  // number of the footnote whose last paragraph ends with the backlink
+  backlink: Option<usize>,
+  // for reference links in content left unparsed
+  references: References,
+  line_index: Option<&'a LineIndex>,
+  // `data-pos` of the next element
+  pos: Option<String>,
 }
 impl<'a> Ctx<'a> {
-  fn render_doc(&mut self, doc: &Document) {
+  fn new(opts: &'a HtmlOpts, out: &'a mut dyn fmt::Write) -> Ctx<'a> {
+    Ctx {
+      opts,
+      out,
+      res: Ok(()),
+      heading_numbers: HeadingNumbers::default(),
+      dir: opts.dir,
+      index_terms: 0,
+      targets: HashMap::new(),
+      figures: 0,
+      footnotes: Vec::new(),
+      backlink: None,
+      references: References::new(),
+      line_index: None,
+      pos: None,
+    }
+  }
+
+  #[cfg(any(feature = "axum", feature = "actix"))]
+  fn save(self) -> State {
+    State {
+      heading_numbers: self.heading_numbers,
+      index_terms: self.index_terms,
+      targets: self.targets,
+      figures: self.figures,
+      footnotes: self.footnotes,
+      references: self.references,
+    }
+  }
+
+  /// Continues rendering `doc` where a previous `Ctx` left off.
+  #[cfg(any(feature = "axum", feature = "actix"))]
+  fn restore(&mut self, doc: &'a Document, state: State) {
+    self.line_index = doc.line_index.as_ref().filter(|_| self.opts.include_positions);
+    self.heading_numbers = state.heading_numbers;
+    self.index_terms = state.index_terms;
+    self.targets = state.targets;
+    self.figures = state.figures;
+    self.footnotes = state.footnotes;
+    self.references = state.references;
+  }
+
+  fn render_doc(&mut self, doc: &'a Document) {
+    self.begin_doc(doc);
     for child in &doc.children {
       self.render(child)
     }
+    self.end_doc(doc);
+  }
+
+  fn begin_doc(&mut self, doc: &'a Document) {
+    self.line_index = doc.line_index.as_ref().filter(|_| self.opts.include_positions);
+    self.collect_targets(&doc.children, None, &mut HeadingNumbers::default());
+    self.references = references(&doc.children);
+    self.figures = 0;
+    if self.opts.list_of_figures {
+      self.render_list_of_figures(&figures::entries(&doc.children))
+    }
+  }
+
+  fn end_doc(&mut self, doc: &Document) {
+    self.render_footnotes(doc);
+    if let Some(bibliography) = &self.opts.bibliography {
+      let mut keys = Vec::new();
+      citation_keys(&doc.children, &mut keys);
+      let entries: Vec<_> =
+        keys.into_iter().filter_map(|key| Some((bibliography(&key)?, key))).collect();
+      if !entries.is_empty() {
+        self.out("<div id=\"refs\" role=\"doc-bibliography\">");
+        self.newline();
+        for (html, key) in entries {
+          self.out(&format!("<div id=\"ref-{}\">", escape_html_attr(&key)));
+          self.out(&html);
+          self.out("</div>");
+          self.newline();
+        }
+        self.out("</div>");
+        self.newline();
+      }
+    }
+    if self.opts.index {
+      self.render_index(&index::entries(&doc.children))
+    }
+  }
+
+  /// Sections are referred to by their number or, without numbering, by
+  /// their title, anything else by its text.
+  fn collect_targets(&mut self, tags: &[Tag], section: Option<&str>, numbers: &mut HeadingNumbers) {
+    for tag in tags {
+      match &tag.kind {
+        TagKind::Section(_) => self.collect_targets(&tag.children, tag.id(), numbers),
+        TagKind::Figure(_) if self.opts.list_of_figures => {
+          self.figures += 1;
+          let id = figures::figure_id(tag, self.figures);
+          let text = format!("{} {}", self.opts.labels.figure, self.figures);
+          self.targets.entry(id).or_insert(text);
+          self.collect_targets(&tag.children, None, numbers)
+        }
+        TagKind::Heading(heading) => {
+          let mut number = heading.number.clone();
+          if self.opts.number_headings {
+            number = number.or(numbers.next(&tag.attrs, heading.level));
+          }
+          let Some(id) = tag.id().or(section) else { continue };
+          let text = match number {
+            Some(number) => format!("{} {number}", self.opts.labels.section),
+            None => tag.text(),
+          };
+          self.targets.entry(id.to_string()).or_insert(text);
+        }
+        _ => {
+          if let Some(id) = tag.id() {
+            self.targets.entry(id.to_string()).or_insert_with(|| tag.text());
+          }
+          self.collect_targets(&tag.children, None, numbers)
+        }
+      }
+    }
+  }
+
+  fn render_list_of_figures(&mut self, entries: &[figures::FigureEntry]) {
+    if entries.is_empty() {
+      return;
+    }
+    self.out("<nav class=\"list-of-figures\">");
+    self.newline();
+    self.out("<h2>");
+    self.out_escape_html(&self.opts.labels.figures);
+    self.out("</h2>");
+    self.newline();
+    self.out("<ol>");
+    self.newline();
+    for entry in entries {
+      self.out(&format!("<li><a href=\"#{}\">", escape_html_attr(&entry.id)));
+      self.out_escape_html(&format!(
+        "{} {}: {}",
+        self.opts.labels.figure, entry.number, entry.caption
+      ));
+      self.out("</a></li>");
+      self.newline();
+    }
+    self.out("</ol>");
+    self.newline();
+    self.out("</nav>");
+    self.newline();
+  }
+
+  fn render_footnotes(&mut self, doc: &Document) {
+    if self.footnotes.is_empty() {
+      return;
+    }
+    if self.opts.label_footnotes {
+      let label = escape_html_attr(&self.opts.labels.footnotes);
+      self.out(&format!("<section role=\"doc-endnotes\" aria-label=\"{label}\">"));
+    } else {
+      self.out("<section role=\"doc-endnotes\">");
+    }
+    self.newline();
+    self.out("<hr>");
+    self.newline();
+    self.out("<ol>");
+    self.newline();
+    // notes may refer to further notes
+    let mut i = 0;
+    while i < self.footnotes.len() {
+      let number = i + 1;
+      self.out(&format!("<li id=\"fn{number}\">"));
+      self.newline();
+      let note = find_footnote(&doc.children, &self.footnotes[i]);
+      let children = note.map_or(&[][..], |it| &it.children);
+      let last_para = matches!(children.last().map(|it| &it.kind), Some(TagKind::Para(_)));
+      for (j, child) in children.iter().enumerate() {
+        if last_para && j + 1 == children.len() {
+          self.backlink = Some(number)
+        }
+        self.render(child)
+      }
+      if !last_para {
+        self.out(&format!("<p>{}</p>", backlink(number)));
+        self.newline()
+      }
+      self.out("</li>");
+      self.newline();
+      i += 1
+    }
+    self.out("</ol>");
+    self.newline();
+    self.out("</section>");
+    self.newline();
+  }
+
+  fn render_index(&mut self, entries: &[index::IndexEntry]) {
+    if entries.is_empty() {
+      return;
+    }
+    self.out("<section class=\"index\" role=\"doc-index\">");
+    self.newline();
+    self.out("<h2>");
+    self.out_escape_html(&self.opts.labels.index);
+    self.out("</h2>");
+    self.newline();
+    self.out("<ul>");
+    self.newline();
+    for entry in entries {
+      self.out("<li>");
+      self.out_escape_html(&entry.term);
+      for (i, location) in entry.locations.iter().enumerate() {
+        self.out(if i == 0 { " " } else { ", " });
+        self.out(&format!("<a href=\"#{}\">{}</a>", escape_html_attr(&location.id), i + 1));
+      }
+      self.out("</li>");
+      self.newline();
+    }
+    self.out("</ul>");
+    self.newline();
+    self.out("</section>");
+    self.newline();
   }
   fn render(&mut self, tag: &Tag) {
+    self.pos = self.position(tag);
+    self.render_node(tag);
+    self.pos = None;
+  }
+
+  fn render_node(&mut self, tag: &Tag) {
     match &tag.kind {
       TagKind::Doc(_doc) => self.render_children(tag),
-      TagKind::Heading(_) => todo!(),
+      TagKind::Section(_) => {
+        if !self.opts.sections {
+          let id = tag.attrs.get("id").filter(|_| !self.opts.no_heading_ids);
+          for child in &tag.children {
+            match &child.kind {
+              TagKind::Heading(heading) if child.id().is_none() => {
+                self.render_heading(child, heading, id.map(String::as_str))
+              }
+              _ => self.render(child),
+            }
+          }
+          return;
+        }
+        let heading_id = tag.attrs.get("id").map(|id| format!("{id}-heading"));
+        let mut attrs = tag.attrs.clone();
+        if let Some(heading_id) = &heading_id {
+          attrs.insert("aria-labelledby".to_string(), heading_id.clone());
+        }
+        self.render_tag("section", &attrs);
+        self.newline();
+        for child in &tag.children {
+          match &child.kind {
+            TagKind::Heading(heading) => self.render_heading(child, heading, heading_id.as_deref()),
+            _ => self.render(child),
+          }
+        }
+        self.out("</section>");
+        self.newline();
+      }
+      TagKind::Heading(heading) => self.render_heading(tag, heading, None),
+      TagKind::ThematicBreak(_) => {
+        self.render_tag("hr", &tag.attrs);
+        self.newline()
+      }
+      TagKind::BlockQuote(_) => {
+        self.render_tag("blockquote", &tag.attrs);
+        self.newline();
+        self.render_children(tag);
+        self.out("</blockquote>");
+        self.newline()
+      }
+      TagKind::Div(_) => {
+        self.render_tag("div", &tag.attrs);
+        self.newline();
+        self.render_children(tag);
+        self.out("</div>");
+        self.newline()
+      }
+      TagKind::List(list) => {
+        let mut attrs = tag.attrs.clone();
+        if tag.children.first().is_some_and(|it| matches!(it.kind, TagKind::TaskListItem(_))) {
+          let class = match attrs.get("class") {
+            Some(class) => format!("task-list {class}"),
+            None => "task-list".to_string(),
+          };
+          attrs.insert("class".to_string(), class);
+        }
+        if let Some(start) = list.start.filter(|&it| it != 1) {
+          attrs.insert("start".to_string(), start.to_string());
+        }
+        let list_type = lists::html_type(&list.style);
+        if list.start.is_some() && list_type != "1" {
+          attrs.insert("type".to_string(), list_type.to_string());
+        }
+        let tag_name = if list.start.is_some() { "ol" } else { "ul" };
+        self.render_tag(tag_name, &attrs);
+        self.newline();
+        for item in &tag.children {
+          self.render_list_item(item, list.tight)
+        }
+        self.out(&format!("</{tag_name}>"));
+        self.newline()
+      }
+      TagKind::ListItem(_) | TagKind::TaskListItem(_) => self.render_list_item(tag, false),
+      TagKind::DefinitionList(list) => {
+        self.render_tag("dl", &tag.attrs);
+        self.newline();
+        for item in &tag.children {
+          for child in &item.children {
+            match child.kind {
+              TagKind::Definition(_) => self.render_definition(child, list.tight),
+              _ => self.render(child),
+            }
+          }
+        }
+        self.out("</dl>");
+        self.newline()
+      }
+      TagKind::DefinitionListItem(_) => self.render_children(tag),
+      TagKind::Term(_) => {
+        self.render_tag("dt", &tag.attrs);
+        self.render_children(tag);
+        self.out("</dt>");
+        self.newline()
+      }
+      TagKind::Definition(_) => self.render_definition(tag, false),
+      TagKind::Figure(_) => {
+        self.figures += 1;
+        let mut attrs = tag.attrs.clone();
+        if self.opts.list_of_figures {
+          attrs.insert("id".to_string(), figures::figure_id(tag, self.figures));
+        }
+        self.render_tag("figure", &attrs);
+        self.newline();
+        for child in &tag.children {
+          match &child.kind {
+            TagKind::Caption(_) => {
+              self.pos = self.position(child);
+              self.render_tag("figcaption", &child.attrs);
+              if self.opts.list_of_figures {
+                self.out_escape_html(&format!("{} {}: ", self.opts.labels.figure, self.figures));
+              }
+              self.render_children(child);
+              self.out("</figcaption>");
+              self.newline()
+            }
+            // the image itself, rather than a paragraph around it
+            TagKind::Para(_) if child.attrs.is_empty() => {
+              self.render_children(child);
+              self.newline()
+            }
+            _ => self.render(child),
+          }
+        }
+        self.out("</figure>");
+        self.newline()
+      }
+      TagKind::Caption(_) => {
+        let mut attrs = tag.attrs.clone();
+        attrs.insert("class".to_string(), "caption".to_string());
+        self.render_tag("p", &attrs);
+        self.render_children(tag);
+        self.out("</p>");
+        self.newline()
+      }
+      TagKind::Table(_) => {
+        self.render_tag("table", &tag.attrs);
+        self.newline();
+        for child in &tag.children {
+          match &child.kind {
+            TagKind::Caption(_) => {
+              self.pos = self.position(child);
+              self.render_tag("caption", &child.attrs);
+              self.render_children(child);
+              self.out("</caption>");
+              self.newline()
+            }
+            _ => self.render(child),
+          }
+        }
+        self.out("</table>");
+        self.newline()
+      }
+      TagKind::Row(_) => {
+        self.render_tag("tr", &tag.attrs);
+        self.newline();
+        self.render_children(tag);
+        self.out("</tr>");
+        self.newline()
+      }
+      TagKind::Cell(cell) => {
+        let tag_name = if cell.head { "th" } else { "td" };
+        let mut attrs = tag.attrs.clone();
+        if cell.align != Alignment::Default {
+          attrs.insert("style".to_string(), format!("text-align: {};", cell.align.as_str()));
+        }
+        self.render_tag(tag_name, &attrs);
+        self.render_children(tag);
+        self.out(&format!("</{tag_name}>"));
+        self.newline()
+      }
+      TagKind::Callout(callout) => {
+        let mut attrs = tag.attrs.clone();
+        let class = match attrs.get("class") {
+          Some(class) => format!("callout {} {class}", callout.kind),
+          None => format!("callout {}", callout.kind),
+        };
+        attrs.insert("class".to_string(), class);
+        self.render_tag("div", &attrs);
+        self.newline();
+        let labels = &self.opts.labels;
+        let title = match callout.kind.as_str() {
+          "note" => &labels.note,
+          "tip" => &labels.tip,
+          "important" => &labels.important,
+          "warning" => &labels.warning,
+          "caution" => &labels.caution,
+          kind => kind,
+        };
+        self.out("<p class=\"callout-title\">");
+        self.out_escape_html(title);
+        self.out("</p>");
+        self.newline();
+        self.render_children(tag);
+        self.out("</div>");
+        self.newline()
+      }
       TagKind::Para(_para) => {
-        self.render_tag("p", &tag.attrs);
+        let outer = self.dir;
+        let attrs = self.directed_attrs(tag);
+        self.render_tag("p", &attrs);
         self.render_children(tag);
+        if let Some(number) = self.backlink.take() {
+          self.out(&backlink(number))
+        }
         self.out("</p>");
-        self.out("\n")
+        self.newline();
+        self.dir = outer;
       }
-      TagKind::Link(image) => {
+      TagKind::Link(link) => {
         let mut attrs = Attrs::new();
-        attrs.insert(
-          "href".to_string(),
-          image.destination.clone().unwrap_or_else(|| "url".to_string()),
-        );
+        // unresolved references go without a destination
+        if let Some(destination) = &link.destination {
+          attrs.insert("href".to_string(), destination.clone());
+        }
+        for (key, value) in &tag.attrs {
+          attrs.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        if self.opts.a11y_placeholders && get_string_content(tag).trim().is_empty() {
+          let class = match attrs.get("class") {
+            Some(class) => format!("empty-link {class}"),
+            None => "empty-link".to_string(),
+          };
+          attrs.insert("class".to_string(), class);
+          self.render_tag("a", &attrs);
+          self.out_escape_html(&self.opts.labels.empty_link);
+          return self.out("</a>");
+        }
         self.render_tag("a", &attrs);
         self.render_children(tag);
         self.out("</a>");
       }
       TagKind::Image(image) => {
         let mut attrs = Attrs::new();
-        let alt_text = get_string_content(tag);
+        let mut alt_text = get_string_content(tag);
+        if alt_text.is_empty() {
+          alt_text = tag.get_attr("alt").unwrap_or_default().to_string();
+        }
+        if self.opts.a11y_placeholders && !has_alt_text(tag) {
+          let class = match tag.get_attr("class") {
+            Some(class) => format!("missing-alt-text {class}"),
+            None => "missing-alt-text".to_string(),
+          };
+          attrs.insert("class".to_string(), class);
+          alt_text = self.opts.labels.missing_alt_text.clone();
+        }
         if !alt_text.is_empty() {
           attrs.insert("alt".to_string(), alt_text);
         }
-        attrs.insert(
-          "src".to_string(),
-          image.destination.clone().unwrap_or_else(|| "url".to_string()),
-        );
+        if let Some(destination) = &image.destination {
+          attrs.insert("src".to_string(), destination.clone());
+        }
+        for (key, value) in &tag.attrs {
+          attrs.entry(key.clone()).or_insert_with(|| value.clone());
+        }
         self.render_tag("img", &attrs)
       }
+      TagKind::RawBlock(raw) => {
+        if raw.format == "html" {
+          self.out(&raw.text)
+        }
+      }
       TagKind::CodeBlock(code_block) => {
-        self.render_tag("pre", &tag.attrs);
+        let lang = code_block.lang.as_deref();
+        if let Some(html) = self.opts.highlighter.as_ref().and_then(|it| it(&code_block.text, lang))
+        {
+          self.out(&html);
+          return self.newline();
+        }
+        let start = tag.get_attr("start").and_then(|it| it.parse::<usize>().ok());
+        let highlight = tag.get_attr("highlight").map(line_ranges);
+        let numbered = self.opts.line_numbers || start.is_some();
+        let mut pre_attrs = self.code_attrs(&tag.attrs).into_owned();
+        pre_attrs.shift_remove("start");
+        pre_attrs.shift_remove("highlight");
+        if numbered {
+          let class = match pre_attrs.get("class") {
+            Some(class) => format!("{class} line-numbers"),
+            None => "line-numbers".to_string(),
+          };
+          pre_attrs.insert("class".to_string(), class);
+        }
+        self.render_tag("pre", &pre_attrs);
         let mut attrs = Attrs::default();
         if let Some(lang) = &code_block.lang {
           attrs.insert("class".to_string(), format!("language-{lang}"));
         }
         self.render_tag("code", &attrs);
-        self.out_escape_html(&code_block.text);
-        self.out("</code></pre>\n");
+        if !numbered && highlight.is_none() {
+          self.out_escape_html(&code_block.text);
+        } else {
+          for (i, line) in code_block.text.split_inclusive('\n').enumerate() {
+            let highlighted = highlight.iter().flatten().any(|it| it.contains(&(i + 1)));
+            self.out(if highlighted {
+              "<span class=\"line highlighted\">"
+            } else {
+              "<span class=\"line\">"
+            });
+            if numbered {
+              let number = start.unwrap_or(1) + i;
+              self.out(&format!("<span class=\"line-number\">{number}</span>"));
+            }
+            self.out_escape_html(line);
+            self.out("</span>");
+          }
+        }
+        self.out("</code></pre>");
+        self.newline();
       }
       TagKind::Strong(_) => {
         self.render_tag("strong", &tag.attrs);
@@ -73,32 +699,302 @@ impl<'a> Ctx<'a> {
         self.render_children(tag);
         self.out("</em>");
       }
+      TagKind::Subscript(_) => {
+        self.render_tag("sub", &tag.attrs);
+        self.render_children(tag);
+        self.out("</sub>");
+      }
+      TagKind::Superscript(_) => {
+        self.render_tag("sup", &tag.attrs);
+        self.render_children(tag);
+        self.out("</sup>");
+      }
+      TagKind::Insert(_) => {
+        self.render_tag("ins", &tag.attrs);
+        self.render_children(tag);
+        self.out("</ins>");
+      }
+      TagKind::Delete(_) => {
+        self.render_tag("del", &tag.attrs);
+        self.render_children(tag);
+        self.out("</del>");
+      }
+      TagKind::Highlight(_) => {
+        self.render_tag("mark", &tag.attrs);
+        self.render_children(tag);
+        self.out("</mark>");
+      }
+      TagKind::SingleQuoted(_) => {
+        self.out("&lsquo;");
+        self.render_children(tag);
+        self.out("&rsquo;");
+      }
       TagKind::DoubleQuoted(_) => {
         self.out("&ldquo;");
         self.render_children(tag);
         self.out("&rdquo;");
       }
       TagKind::Softbreak(_) => self.out("\n"),
+      TagKind::HardBreak(_) => self.out("<br>\n"),
+      TagKind::NonBreakingSpace(_) => self.out("&nbsp;"),
+      TagKind::SmartPunctuation(punctuation) => self.out(match punctuation.kind.as_str() {
+        "left_single_quote" => "&lsquo;",
+        "right_single_quote" => "&rsquo;",
+        "left_double_quote" => "&ldquo;",
+        "right_double_quote" => "&rdquo;",
+        "en_dash" => "&ndash;",
+        "em_dash" => "&mdash;",
+        "ellipses" => "&hellip;",
+        _ => punctuation.text(),
+      }),
+      TagKind::Symbol(symbol) => {
+        match self.opts.symbol_resolver.as_ref().and_then(|resolve| resolve(&symbol.alias)) {
+          Some(html) => self.out(&html),
+          None if self.opts.symbols.contains_key(&symbol.alias) => {
+            self.out_escape_html(&self.opts.symbols[&symbol.alias])
+          }
+          None => {
+            let mut attrs = tag.attrs.clone();
+            let class = match attrs.get("class") {
+              Some(class) => format!("symbol {class}"),
+              None => "symbol".to_string(),
+            };
+            attrs.insert("class".to_string(), class);
+            self.render_tag("span", &attrs);
+            self.out(":");
+            self.out_escape_html(&symbol.alias);
+            self.out(":</span>");
+          }
+        }
+      }
       TagKind::Url(url) => {
         let mut attrs = Attrs::new();
         attrs.insert("href".to_string(), url.destination.clone());
-        self.render_tag("a", &attrs);
+        self.render_tag("a", &self.code_attrs(&attrs));
         self.render_children(tag);
         self.out("</a>");
       }
       TagKind::Str(str) => self.out_escape_html(&str.text),
+      TagKind::Unparsed(_) => {
+        let mut tags = vec![tag.clone()];
+        parse_inlines(&mut tags);
+        resolve_references(&mut tags, &self.references);
+        for tag in &tags {
+          self.render(tag)
+        }
+      }
+      TagKind::RawInline(raw) => {
+        if raw.format == "html" {
+          self.out(&raw.text)
+        }
+      }
       TagKind::Verbatim(verbatim) => {
-        self.render_tag("code", &tag.attrs);
+        self.render_tag("code", &self.code_attrs(&tag.attrs));
         self.out_escape_html(&verbatim.text);
         self.out("</code>");
       }
+      TagKind::InlineMath(InlineMath { text }) | TagKind::DisplayMath(DisplayMath { text }) => {
+        let (kind, open, close) = match tag.kind {
+          TagKind::InlineMath(_) => ("inline", "\\(", "\\)"),
+          _ => ("display", "\\[", "\\]"),
+        };
+        let mut attrs = tag.attrs.clone();
+        let class = match attrs.get("class") {
+          Some(class) => format!("math {kind} {class}"),
+          None => format!("math {kind}"),
+        };
+        attrs.insert("class".to_string(), class);
+        self.render_tag("span", &attrs);
+        self.out(open);
+        self.out_escape_html(text);
+        self.out(close);
+        self.out("</span>");
+      }
+      TagKind::Span(_) if self.opts.index && is_index_term(tag) => {
+        self.index_terms += 1;
+        let mut attrs = tag.attrs.clone();
+        attrs.shift_remove("index");
+        attrs.insert("id".to_string(), term_id(tag, self.index_terms));
+        self.render_tag("span", &attrs);
+        self.render_children(tag);
+        self.out("</span>");
+      }
       TagKind::Span(_) => {
         self.render_tag("span", &tag.attrs);
         self.render_children(tag);
         self.out("</span>");
       }
-      TagKind::ReferenceDefinition(_) | TagKind::ReferenceKey(_) | TagKind::ReferenceValue(_) => (),
+      TagKind::Abbreviation(abbreviation) => {
+        let mut attrs = tag.attrs.clone();
+        attrs.insert("title".to_string(), abbreviation.title.clone());
+        self.render_tag("abbr", &attrs);
+        self.render_children(tag);
+        self.out("</abbr>");
+      }
+      TagKind::Citation(citation) => {
+        if let Some(html) = self.opts.citation_resolver.as_ref().and_then(|it| it(citation)) {
+          return self.out(&html);
+        }
+        self.render_tag("cite", &tag.attrs);
+        if citation.bracketed {
+          self.out("[");
+        }
+        self.out(&format!(
+          "<a href=\"#ref-{}\" role=\"doc-biblioref\">",
+          escape_html_attr(&citation.key)
+        ));
+        self.out_escape_html(&citation.key);
+        self.out("</a>");
+        if let Some(locator) = &citation.locator {
+          self.out(", ");
+          self.out_escape_html(locator);
+        }
+        if citation.bracketed {
+          self.out("]");
+        }
+        self.out("</cite>");
+      }
+      TagKind::CrossReference(reference) => {
+        let mut attrs = tag.attrs.clone();
+        attrs.insert("href".to_string(), format!("#{}", reference.target));
+        let class = match attrs.get("class") {
+          Some(class) => format!("cross-reference {class}"),
+          None => "cross-reference".to_string(),
+        };
+        attrs.insert("class".to_string(), class);
+        self.render_tag("a", &attrs);
+        let text = self.targets.get(&reference.target).unwrap_or(&reference.target).clone();
+        self.out_escape_html(&text);
+        self.out("</a>");
+      }
+      TagKind::FootnoteReference(reference) => {
+        let number = match self.footnotes.iter().position(|it| *it == reference.label) {
+          Some(i) => i + 1,
+          None => {
+            self.footnotes.push(reference.label.clone());
+            self.footnotes.len()
+          }
+        };
+        self.out(&format!(
+          "<a id=\"fnref{number}\" href=\"#fn{number}\" role=\"doc-noteref\"><sup>{number}</sup></a>"
+        ));
+      }
+      TagKind::Footnote(_)
+      | TagKind::ReferenceDefinition(_)
+      | TagKind::ReferenceKey(_)
+      | TagKind::ReferenceValue(_)
+      | TagKind::AbbreviationDefinition(_) => (),
+    }
+  }
+
+  fn render_list_item(&mut self, tag: &Tag, tight: bool) {
+    self.pos = self.position(tag);
+    self.render_tag("li", &tag.attrs);
+    self.newline();
+    if let TagKind::TaskListItem(task) = &tag.kind {
+      let mut attrs = Attrs::new();
+      if !self.opts.interactive_tasks {
+        attrs.insert("disabled".to_string(), String::new());
+      }
+      attrs.insert("type".to_string(), "checkbox".to_string());
+      if task.checked {
+        attrs.insert("checked".to_string(), String::new());
+      }
+      if self.opts.interactive_tasks {
+        attrs.insert("data-task-index".to_string(), tag.span.start.to_string());
+      }
+      self.render_tag("input", &attrs);
+      self.newline();
+    }
+    for child in &tag.children {
+      match child.kind {
+        // the paragraphs of tight lists go without `<p>`
+        TagKind::Para(_) if tight && child.attrs.is_empty() => {
+          self.render_children(child);
+          self.newline()
+        }
+        _ => self.render(child),
+      }
+    }
+    self.out("</li>");
+    self.newline()
+  }
+
+  fn render_definition(&mut self, tag: &Tag, tight: bool) {
+    self.pos = self.position(tag);
+    self.render_tag("dd", &tag.attrs);
+    self.newline();
+    for child in &tag.children {
+      match child.kind {
+        TagKind::Para(_) if tight && child.attrs.is_empty() => {
+          self.render_children(child);
+          self.newline()
+        }
+        _ => self.render(child),
+      }
+    }
+    self.out("</dd>");
+    self.newline()
+  }
+
+  fn render_heading(&mut self, tag: &Tag, heading: &Heading, id: Option<&str>) {
+    self.pos = self.position(tag);
+    let level = (heading.level as i32 + self.opts.heading_offset).clamp(1, 6);
+    let tag_name = format!("h{level}");
+    let outer = self.dir;
+    let mut attrs = self.directed_attrs(tag).into_owned();
+    if let Some(id) = id {
+      attrs.insert("id".to_string(), id.to_string());
+    }
+    self.render_tag(&tag_name, &attrs);
+    if self.opts.number_headings {
+      let number = self.heading_numbers.next(&tag.attrs, heading.level);
+      if let Some(number) = heading.number.as_ref().or(number.as_ref()) {
+        self.out("<span class=\"section-number\">");
+        self.out_escape_html(number);
+        self.out("</span> ");
+      }
     }
+    self.render_children(tag);
+    self.out(&format!("</{tag_name}>"));
+    self.newline();
+    self.dir = outer;
+  }
+
+  /// Adds a `dir` attribute to blocks starting with text running against the
+  /// direction of the enclosing block, and enters the block's direction.
+  fn directed_attrs<'t>(&mut self, tag: &'t Tag) -> Cow<'t, Attrs> {
+    let outer = self.dir;
+    if let Some(dir) = tag.attrs.get("dir") {
+      self.dir = match dir.as_str() {
+        "ltr" => Some(TextDirection::Ltr),
+        "rtl" => Some(TextDirection::Rtl),
+        _ => None,
+      };
+      return Cow::Borrowed(&tag.attrs);
+    }
+    if outer.is_none() {
+      return Cow::Borrowed(&tag.attrs);
+    }
+    match first_strong(tag) {
+      Some(dir) if Some(dir) != outer => {
+        self.dir = Some(dir);
+        let mut attrs = tag.attrs.clone();
+        attrs.insert("dir".to_string(), dir.as_str().to_string());
+        Cow::Owned(attrs)
+      }
+      _ => Cow::Borrowed(&tag.attrs),
+    }
+  }
+
+  /// Code reads left to right, also within right-to-left text.
+  fn code_attrs<'t>(&self, attrs: &'t Attrs) -> Cow<'t, Attrs> {
+    if self.dir != Some(TextDirection::Rtl) || attrs.contains_key("dir") {
+      return Cow::Borrowed(attrs);
+    }
+    let mut attrs = attrs.clone();
+    attrs.insert("dir".to_string(), "ltr".to_string());
+    Cow::Owned(attrs)
   }
 
   fn render_children(&mut self, tag: &Tag) {
@@ -107,22 +1003,68 @@ impl<'a> Ctx<'a> {
     }
   }
 
+  fn position(&self, tag: &Tag) -> Option<String> {
+    self.line_index?.format_range(tag.span.clone())
+  }
+
   fn render_tag(&mut self, tag_name: &str, attrs: &Attrs) {
     self.out("<");
     self.out(tag_name);
+    if let Some(pos) = self.pos.take() {
+      self.out(" data-pos=\"");
+      self.out(&pos);
+      self.out("\"");
+    }
     for (k, v) in attrs {
       self.out(" ");
       self.out(k);
-      self.out("=");
-      self.out(&format!("{v:?}"));
+      self.out("=\"");
+      self.out_escape_attr(v);
+      self.out("\"");
     }
     self.out(">");
   }
 
+  // Cosmetic line break between block level elements.
+  fn newline(&mut self) {
+    if !self.opts.compact {
+      self.out("\n")
+    }
+  }
+
   fn out(&mut self, s: &str) {
-    self.res.push_str(s)
+    if self.res.is_ok() {
+      self.res = self.out.write_str(s)
+    }
   }
   fn out_escape_html(&mut self, s: &str) {
-    self.res.push_str(s)
+    let s = replace_invisible(s, self.opts.invisible_chars);
+    self.out(&escape_html_text(&s))
+  }
+  fn out_escape_attr(&mut self, s: &str) {
+    let s = replace_invisible(s, self.opts.invisible_chars);
+    self.out(&escape_html_attr(&s))
   }
 }
+
+/// Parses `3,5-7` into line ranges, skipping anything else.
+fn line_ranges(spec: &str) -> Vec<RangeInclusive<usize>> {
+  let line = |it: &str| it.trim().parse::<usize>().ok();
+  let range = |it: &str| match it.split_once('-') {
+    Some((start, end)) => Some(line(start)?..=line(end)?),
+    None => line(it).map(|it| it..=it),
+  };
+  spec.split(',').filter_map(range).collect()
+}
+
+fn backlink(number: usize) -> String {
+  format!("<a href=\"#fnref{number}\" role=\"doc-backlink\">\u{21a9}\u{fe0e}\u{fe0e}</a>")
+}
+
+/// The first definition of the footnote with the given label.
+fn find_footnote<'t>(tags: &'t [Tag], label: &str) -> Option<&'t Tag> {
+  tags.iter().find_map(|tag| match &tag.kind {
+    TagKind::Footnote(note) if note.label == label => Some(tag),
+    _ => find_footnote(&tag.children, label),
+  })
+}
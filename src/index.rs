@@ -0,0 +1,80 @@
+use std::ops::Range;
+
+use crate::{
+  ast::{Tag, TagKind},
+  tree::{get_string_content, parse_inlines},
+};
+
+/// A term marked for the index by a span with an `index` attribute, like
+/// `[TeX]{index=""}` or `[typesetting]{index="TeX"}`, with all places where
+/// it is marked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+  /// The value of the attribute, or the text of the span when it is empty.
+  pub term: String,
+  pub locations: Vec<IndexLocation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexLocation {
+  /// Identifier of the span: its `id` attribute, or `index-term-N` for the
+  /// Nth marked span in the document.
+  pub id: String,
+  /// Identifier of the enclosing section, `None` before the first heading.
+  pub section: Option<String>,
+  /// Byte range of the span in the source.
+  pub span: Range<usize>,
+}
+
+/// The entries sorted by term, ignoring case. Marked spans without any text
+/// or term are left out.
+pub(crate) fn entries(children: &[Tag]) -> Vec<IndexEntry> {
+  let mut acc: Vec<IndexEntry> = Vec::new();
+  collect(children, None, &mut 0, &mut acc);
+  acc.sort_by_cached_key(|it| (it.term.to_lowercase(), it.term.clone()));
+  acc
+}
+
+pub(crate) fn is_index_term(tag: &Tag) -> bool {
+  matches!(tag.kind, TagKind::Span(_)) && tag.attrs.contains_key("index")
+}
+
+pub(crate) fn term_id(tag: &Tag, n: usize) -> String {
+  match tag.get_attr("id") {
+    Some(id) => id.to_string(),
+    None => format!("index-term-{n}"),
+  }
+}
+
+fn collect(tags: &[Tag], section: Option<&str>, n: &mut usize, acc: &mut Vec<IndexEntry>) {
+  for tag in tags {
+    match &tag.kind {
+      TagKind::Section(_) => collect(&tag.children, tag.get_attr("id"), n, acc),
+      TagKind::Unparsed(_) => {
+        let mut tags = vec![tag.clone()];
+        parse_inlines(&mut tags);
+        collect(&tags, section, n, acc)
+      }
+      _ if is_index_term(tag) => {
+        *n += 1;
+        let term = match tag.get_attr("index").unwrap_or_default() {
+          "" => get_string_content(tag),
+          term => term.to_string(),
+        };
+        if !term.trim().is_empty() {
+          let location = IndexLocation {
+            id: term_id(tag, *n),
+            section: section.map(|it| it.to_string()),
+            span: tag.span.clone(),
+          };
+          match acc.iter_mut().find(|it| it.term == term) {
+            Some(entry) => entry.locations.push(location),
+            None => acc.push(IndexEntry { term, locations: vec![location] }),
+          }
+        }
+        collect(&tag.children, section, n, acc)
+      }
+      _ => collect(&tag.children, section, n, acc),
+    }
+  }
+}
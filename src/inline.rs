@@ -1,36 +1,66 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{
+  collections::{BTreeMap, HashMap},
+  ops::Range,
+};
 
 use crate::{
   annot::{Annot, Atom, Comp},
+  attributes::{AttributeParser, Status},
+  budget::Budget,
   patterns::{find_at, is_space, PatMatch},
-  Match, ParseOpts,
+  Diagnostic, Match, ParseOpts,
 };
 
 #[derive(Default)]
 pub struct Parser {
   opts: ParseOpts,
-  subject: String,
-  matches: BTreeMap<usize, Match>,
-  openers: HashMap<u8, Vec<Opener>>,
+  matches: BTreeMap<u32, Match>,
+  // by marker and whether it was given with a brace, so that `{_` is only
+  // closed by `_}` and `_` only by `_`
+  openers: HashMap<(u8, bool), Vec<Opener>>,
   verbatim: usize,
   verbatim_type: Comp,
+  verbatim_start: usize,
   destination: bool,
   firstpos: usize,
   lastpos: usize,
+  // where `ParseOpts::max_nesting` was first exceeded
+  too_deep: Option<usize>,
+  // attributes which may continue on the next line, starting at
+  // `attribute_start` and fed in `attribute_slices` so far
+  attribute_parser: Option<AttributeParser>,
+  attribute_start: usize,
+  attribute_slices: Vec<Range<usize>>,
+  // the `[` and `]` of a bracketed span whose attributes are still open
+  pending_span: Option<(usize, usize)>,
+  // set while feeding text which failed to parse as attributes again
+  reparsing: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
 struct Opener {
-  spos: usize,
-  epos: usize,
+  spos: u32,
+  epos: u32,
   annot: &'static str,
-  subspos: usize,
-  subepos: usize,
+  subspos: u32,
+  subepos: u32,
 }
 
 impl Opener {
   fn new(spos: usize, epos: usize) -> Self {
-    Self { spos, epos, annot: "", subspos: 0, subepos: 0 }
+    Self { spos: spos as u32, epos: epos as u32, annot: "", subspos: 0, subepos: 0 }
+  }
+  fn spos(&self) -> usize {
+    self.spos as usize
+  }
+  fn epos(&self) -> usize {
+    self.epos as usize
+  }
+  fn subspos(&self) -> usize {
+    self.subspos as usize
+  }
+  fn subepos(&self) -> usize {
+    self.subepos as usize
   }
 }
 
@@ -48,49 +78,84 @@ fn bounded_find(
   m
 }
 
+/// Whether the attributes starting at `pos` don't fail on this line.
 impl Parser {
-  pub fn new(subject: String, opts: ParseOpts) -> Parser {
-    let mut res = Parser::default();
-    res.subject = subject;
-    res.opts = opts;
-    res
+  /// Prepares the parser for a new block, keeping allocated buffers.
+  pub fn reset(&mut self, opts: &ParseOpts) {
+    self.opts.clone_from(opts);
+    self.matches.clear();
+    for openers in self.openers.values_mut() {
+      openers.clear()
+    }
+    self.verbatim = 0;
+    self.verbatim_type = Comp::default();
+    self.verbatim_start = 0;
+    self.destination = false;
+    self.firstpos = 0;
+    self.lastpos = 0;
+    self.too_deep = None;
+    self.attribute_parser = None;
+    self.attribute_slices.clear();
+    self.pending_span = None;
+    self.reparsing = false;
   }
 
   fn add_match(&mut self, startpos: usize, endpos: usize, annotation: impl Into<Annot>) {
     let m = Match::new(startpos..endpos, annotation);
-    self.matches.insert(startpos, m);
+    self.matches.insert(startpos as u32, m);
   }
 
-  fn add_opener(&mut self, name: u8, opener: Opener) {
+  fn add_opener(&mut self, name: (u8, bool), opener: Opener) {
     self.openers.entry(name).or_default().push(opener)
   }
 
+  /// Checks whether another opener would exceed the nesting limit.
+  fn too_deep(&mut self, pos: usize) -> bool {
+    let Some(max) = self.opts.max_nesting else { return false };
+    if self.openers.values().map(|it| it.len()).sum::<usize>() < max {
+      return false;
+    }
+    self.too_deep.get_or_insert(pos);
+    true
+  }
+
   fn clear_openers(&mut self, startpos: usize, endpos: usize) {
     for v in self.openers.values_mut() {
-      v.retain(|it| !(startpos <= it.spos && it.epos <= endpos))
+      v.retain(|it| !(startpos <= it.spos() && it.epos() <= endpos))
     }
   }
 
   fn str_matches(&mut self, startpos: usize, endpos: usize) {
-    for i in startpos..endpos {
-      if let Some(m) = self.matches.get_mut(&i) {
-        if m.is_not(Atom::Str) && m.is_not(Atom::Escape) {
-          m.a = Atom::Str.into();
-        }
+    if startpos >= endpos {
+      return;
+    }
+    for m in self.matches.range_mut(startpos as u32..endpos as u32).map(|(_, m)| m) {
+      if m.is_not(Atom::Str) && m.is_not(Atom::Escape) {
+        m.a = Atom::Str.into();
       }
     }
   }
 
-  fn between_matched(&mut self, pos: usize, c: u8, annotation: Comp, defaultmatch: Atom) -> usize {
-    let mut can_open = find_at(&self.subject, "^%S", pos + 1).is_match;
-    let mut can_close = !self.subject[..pos].ends_with(is_space);
+  fn between_matched(
+    &mut self,
+    subject: &str,
+    pos: usize,
+    c: u8,
+    annotation: Comp,
+    mut defaultmatch: Atom,
+    opentest: Option<fn(&Self, &str, usize) -> bool>,
+  ) -> usize {
+    let mut can_open = find_at(subject, "^%S", pos + 1).is_match;
+    let mut can_close = !subject[..pos].ends_with(is_space);
     let has_open_marker =
-      pos != 0 && self.matches.get(&(pos - 1)).map_or(false, |it| it.is(Atom::OpenMarker));
-    let hash_close_marker = self.subject.as_bytes()[pos + 1] == b'}';
+      pos != 0 && self.matches.get(&(pos as u32 - 1)).map_or(false, |it| it.is(Atom::OpenMarker));
+    let hash_close_marker = subject.as_bytes().get(pos + 1) == Some(&b'}');
     let mut endcloser = pos;
     let mut startopener = pos;
 
-    // TODO: opentest
+    if let Some(opentest) = opentest {
+      can_open = can_open && opentest(self, subject, pos);
+    }
 
     // allow explicit open/close markers to override:
     if has_open_marker {
@@ -104,23 +169,35 @@ impl Parser {
       endcloser = pos + 1;
     }
 
-    // TODO: defaultmatch
+    if has_open_marker {
+      defaultmatch = match defaultmatch {
+        Atom::RightSingleQuote => Atom::LeftSingleQuote,
+        Atom::RightDoubleQuote => Atom::LeftDoubleQuote,
+        it => it,
+      }
+    } else if hash_close_marker {
+      defaultmatch = match defaultmatch {
+        Atom::LeftSingleQuote => Atom::RightSingleQuote,
+        Atom::LeftDoubleQuote => Atom::RightDoubleQuote,
+        it => it,
+      }
+    }
 
-    let openers = self.openers.entry(c).or_default();
+    let openers = self.openers.entry((c, hash_close_marker)).or_default();
     if can_close && openers.len() > 0 {
       // check openers for a match
       let opener = *openers.last().unwrap();
-      if opener.epos != pos - 1 {
+      if opener.epos() != pos - 1 {
         // exclude empty emph
-        self.clear_openers(opener.spos, pos);
-        self.add_match(opener.spos, opener.epos, Annot::Add(annotation));
-        self.add_match(pos, endcloser, Annot::Sub(annotation));
+        self.clear_openers(opener.spos(), pos);
+        self.add_match(opener.spos(), opener.epos() + 1, Annot::Add(annotation));
+        self.add_match(pos, endcloser + 1, Annot::Sub(annotation));
         return endcloser + 1;
       }
     }
     // if we get here, we didn't match an opener
-    if can_open {
-      self.add_opener(c, Opener::new(startopener, pos));
+    if can_open && !self.too_deep(pos) {
+      self.add_opener((c, has_open_marker), Opener::new(startopener, pos));
       self.add_match(startopener, pos + 1, defaultmatch);
       pos + 1
     } else {
@@ -129,51 +206,75 @@ impl Parser {
     }
   }
 
-  fn matchers(&mut self, c: u8, pos: usize, endpos: usize) -> Option<usize> {
+  /// A single quote only opens after whitespace or an opening
+  /// punctuation mark, otherwise it's an apostrophe: `don't`.
+  fn single_quote_opens(&self, subject: &str, pos: usize) -> bool {
+    pos <= self.firstpos
+      || subject[..pos].ends_with(|c: char| c.is_whitespace() || "\"'-([".contains(c))
+  }
+
+  fn has_brace(&self, subject: &str, pos: usize) -> bool {
+    subject[..pos].ends_with('{') || subject.as_bytes().get(pos + 1) == Some(&b'}')
+  }
+
+  fn matchers(&mut self, subject: &str, c: u8, pos: usize, endpos: usize) -> Option<usize> {
     match c {
       b'`' => {
-        let m = bounded_find(&self.subject, "^`*", pos, endpos);
+        let m = bounded_find(subject, "^`*", pos, endpos);
         if !m.is_match {
           return None;
         }
-        // TODO: display/inline math
-
-        self.add_match(pos, m.end, Annot::Add(Comp::Verbatim));
-        self.verbatim_type = Comp::Verbatim;
+        // `$` and `$$` right before the backticks make it math
+        let before = &subject[self.firstpos.min(pos)..pos];
+        let (start, comp) = if before.ends_with("$$") && !before.ends_with("\\$$") {
+          (pos - 2, Comp::DisplayMath)
+        } else if before.ends_with('$') && !before.ends_with("\\$") {
+          (pos - 1, Comp::InlineMath)
+        } else {
+          (pos, Comp::Verbatim)
+        };
+        for dollar in start..pos {
+          self.matches.remove(&(dollar as u32));
+        }
+        self.add_match(start, m.end, Annot::Add(comp));
+        self.verbatim_type = comp;
 
         self.verbatim = m.end - pos;
+        self.verbatim_start = start;
         return Some(m.end);
       }
       b'\\' => {
-        let m = bounded_find(&self.subject, "^[ \t]*\r?\n", pos + 1, endpos);
-        self.add_match(pos, pos + 1, Atom::Escape);
-
+        let m = bounded_find(subject, "^[ \t]*\r?\n", pos + 1, endpos);
         if m.is_match {
-          // see f there were preceding spaces
-          if let Some((_, &mm)) = self.matches.iter().rev().next() {
-            let sp = mm.s;
-            let mut ep = mm.e;
+          // see if there were preceding spaces
+          if let Some((_, &mm)) = self.matches.iter().next_back() {
+            let sp = mm.start();
+            let mut ep = mm.end();
             if mm.is(Atom::Str) {
-              while self.subject.as_bytes()[ep] == b' ' || self.subject.as_bytes()[ep] == b'\t' {
-                ep = ep - 1
+              while ep > sp && matches!(subject.as_bytes()[ep - 1], b' ' | b'\t') {
+                ep -= 1
               }
               if sp == ep {
-                self.matches.remove(&sp);
+                self.matches.remove(&(sp as u32));
               } else {
                 self.add_match(sp, ep, Atom::Str)
               }
             }
           }
+        }
+        self.add_match(pos, pos + 1, Atom::Escape);
+
+        if m.is_match {
           self.add_match(pos + 1, m.end, Atom::Hardbreak);
           return Some(m.end);
         } else {
-          let m = bounded_find(&self.subject, "^[%p ]", pos + 1, endpos);
+          let m = bounded_find(subject, "^[%p ]", pos + 1, endpos);
           if !m.is_match {
             self.add_match(pos, pos + 1, Atom::Str);
             return Some(pos + 1);
           } else {
             self.add_match(pos, pos + 1, Atom::Escape);
-            if find_at(&self.subject, "^ ", pos + 1).is_match {
+            if find_at(subject, "^ ", pos + 1).is_match {
               self.add_match(pos + 1, m.end, Atom::Nbsp)
             } else {
               self.add_match(pos + 1, m.end, Atom::Str)
@@ -183,10 +284,10 @@ impl Parser {
         }
       }
       b'<' => {
-        let url = bounded_find(&self.subject, "^%<[^<>%s]+%>", pos, endpos);
+        let url = bounded_find(subject, "^%<[^<>%s]+%>", pos, endpos);
         if url.is_match {
-          let is_url = bounded_find(&self.subject, "^%a+:", pos + 1, url.end).is_match;
-          let is_email = bounded_find(&self.subject, "^[^:]+%@", pos + 1, url.end).is_match;
+          let is_url = bounded_find(subject, "^%a+:", pos + 1, url.end).is_match;
+          let is_email = bounded_find(subject, "^[^:]+%@", pos + 1, url.end).is_match;
           if is_email {
             self.add_match(url.start, url.start + 1, Comp::Email.add());
             self.add_match(url.start + 1, url.end - 1, Atom::Str);
@@ -201,55 +302,75 @@ impl Parser {
         }
         return None;
       }
-      b'~' => Some(self.between_matched(pos, b'~', Comp::Subscript, Atom::Str)),
-      b'^' => Some(self.between_matched(pos, b'^', Comp::Superscript, Atom::Str)),
+      b'~' => Some(self.between_matched(subject, pos, b'~', Comp::Subscript, Atom::Str, None)),
+      b'^' => Some(self.between_matched(subject, pos, b'^', Comp::Superscript, Atom::Str, None)),
       b'[' => {
-        let m = bounded_find(&self.subject, "^%^([^]]+)%]", pos + 1, endpos);
+        let m = bounded_find(subject, "^%^([^%]]+)%]", pos + 1, endpos);
         if m.is_match {
           self.add_match(pos, m.end, Atom::FootnoteReference);
           return Some(m.end);
+        } else if self.too_deep(pos) {
+          self.add_match(pos, pos + 1, Atom::Str);
+          return Some(pos + 1);
         } else {
-          self.add_opener(b'[', Opener::new(pos, pos + 1));
+          self.add_opener((b'[', false), Opener::new(pos, pos + 1));
           self.add_match(pos, pos + 1, Atom::Str);
           return Some(pos + 1);
         }
       }
       b']' => {
-        let openers = self.openers.entry(b'[').or_default();
+        let openers = self.openers.entry((b'[', false)).or_default();
         if openers.len() > 0 {
           let opener = openers.last_mut().unwrap();
           if opener.annot == "reference_link" {
             let opener = *opener;
             // found a reference link
             // add the matches
-            let is_image = self.subject[..opener.spos].ends_with('!')
-              && !self.subject[..opener.spos].ends_with("[]");
+            let is_image =
+              subject[..opener.spos()].ends_with('!') && !subject[..opener.spos()].ends_with("[]");
             if is_image {
-              self.add_match(opener.spos - 1, opener.spos, Atom::ImageMarker);
-              self.add_match(opener.spos, opener.epos, Comp::Imagetext.add());
-              self.add_match(opener.subspos, opener.subepos, Comp::Imagetext.sub());
+              self.add_match(opener.spos() - 1, opener.spos(), Atom::ImageMarker);
+              self.add_match(opener.spos(), opener.epos(), Comp::Imagetext.add());
+              self.add_match(opener.subspos(), opener.subepos(), Comp::Imagetext.sub());
             } else {
-              self.add_match(opener.spos, opener.epos, Comp::Linktext.add());
-              self.add_match(opener.subspos, opener.subepos, Comp::Linktext.sub());
+              self.add_match(opener.spos(), opener.epos(), Comp::Linktext.add());
+              self.add_match(opener.subspos(), opener.subepos(), Comp::Linktext.sub());
             }
-            self.add_match(opener.subepos - 1, opener.subepos, Comp::Reference.add());
+            self.add_match(opener.subepos() - 1, opener.subepos(), Comp::Reference.add());
             self.add_match(pos, pos, Comp::Reference.sub());
             // convert all matches to str
-            self.str_matches(opener.subepos + 1, pos);
+            self.str_matches(opener.subepos() + 1, pos);
             // remove from openers
-            self.clear_openers(opener.spos, pos);
+            self.clear_openers(opener.spos(), pos);
             return Some(pos + 1);
-          } else if bounded_find(&self.subject, "^[%[]", pos + 1, endpos).is_match {
+          } else if bounded_find(subject, "^[%[]", pos + 1, endpos).is_match {
             opener.annot = "reference_link";
-            opener.subspos = pos; // intermediate ]
-            opener.subepos = pos + 2; // intermediate [
+            opener.subspos = pos as u32; // intermediate ]
+            opener.subepos = (pos + 2) as u32; // intermediate [
             self.add_match(pos, pos + 2, Atom::Str);
             return Some(pos + 2);
-          } else if bounded_find(&self.subject, "^[(]", pos + 1, endpos).is_match {
+          } else if subject[pos + 1..endpos].starts_with('{') {
+            // a bracketed span, if followed by its attributes
+            let opener = *opener;
+            match AttributeParser::default().feed(subject, pos + 1..endpos) {
+              Status::Done(_) => {
+                self.add_match(opener.spos(), opener.epos(), Comp::Span.add());
+                self.add_match(pos, pos + 1, Comp::Span.sub());
+              }
+              Status::Continue if !self.reparsing => {
+                // decided once the attributes end on a later line
+                self.pending_span = Some((opener.spos(), pos));
+                self.add_match(pos, pos + 1, Atom::Str);
+              }
+              _ => return None,
+            }
+            self.clear_openers(opener.spos(), pos);
+            return Some(pos + 1);
+          } else if bounded_find(subject, "^[(]", pos + 1, endpos).is_match {
             opener.annot = "explicit_link";
-            opener.subspos = pos; // intermediate ]
-            opener.subepos = pos + 2; // intermediate (
-            self.openers.remove(&b'('); // clear ( openers
+            opener.subspos = pos as u32; // intermediate ]
+            opener.subepos = (pos + 2) as u32; // intermediate (
+            self.openers.remove(&(b'(', false)); // clear ( openers
             self.destination = true;
             self.add_match(pos, pos + 2, Atom::Str);
             return Some(pos + 2);
@@ -261,7 +382,7 @@ impl Parser {
         if !self.destination {
           return None;
         }
-        self.add_opener(b'(', Opener::new(pos, pos + 1));
+        self.add_opener((b'(', false), Opener::new(pos, pos + 1));
         self.add_match(pos, pos + 1, Atom::Str);
         return Some(pos + 1);
       }
@@ -269,55 +390,59 @@ impl Parser {
         if !self.destination {
           return None;
         }
-        let parens = self.openers.entry(b'(').or_default();
+        let parens = self.openers.entry((b'(', false)).or_default();
         if parens.len() > 0 {
           // TODO?
           parens.pop();
           self.add_match(pos, pos + 1, Atom::Str);
           return Some(pos + 1);
         } else {
-          let openers = &self.openers.entry(b'[').or_default().clone();
+          let openers = &self.openers.entry((b'[', false)).or_default().clone();
           if let Some(&opener) = openers.last() {
             if opener.annot == "explicit_link" {
-              let (startdest, enddest) = (opener.subepos - 1, pos);
+              let (startdest, enddest) = (opener.subepos() - 1, pos);
               // we have inline link
-              let is_image = self.subject[..opener.spos].ends_with('!')
-                && !self.subject[..opener.spos].ends_with("[]");
+              let is_image = subject[..opener.spos()].ends_with('!')
+                && !subject[..opener.spos()].ends_with("[]");
               if is_image {
-                self.add_match(opener.spos - 1, opener.spos, Atom::ImageMarker);
-                self.add_match(opener.spos, opener.epos, Comp::Imagetext.add());
-                self.add_match(opener.subspos, opener.subepos, Comp::Imagetext.sub());
+                self.add_match(opener.spos() - 1, opener.spos(), Atom::ImageMarker);
+                self.add_match(opener.spos(), opener.epos(), Comp::Imagetext.add());
+                self.add_match(opener.subspos(), opener.subepos(), Comp::Imagetext.sub());
               } else {
-                self.add_match(opener.spos, opener.epos, Comp::Linktext.add());
-                self.add_match(opener.subspos, opener.subepos, Comp::Linktext.sub());
+                self.add_match(opener.spos(), opener.epos(), Comp::Linktext.add());
+                self.add_match(opener.subspos(), opener.subepos(), Comp::Linktext.sub());
               }
               self.add_match(startdest, startdest + 1, Comp::Destination.add());
               self.add_match(enddest, enddest + 1, Comp::Destination.sub());
               self.destination = false;
               // convert all matches to str
-              self.str_matches(opener.subepos + 1, pos);
+              self.str_matches(opener.subepos() + 1, pos);
               // remove from openers
-              self.clear_openers(opener.spos, pos);
+              self.clear_openers(opener.spos(), pos);
               return Some(enddest + 1);
             }
           }
           return None;
         }
       }
-      b'_' => Some(self.between_matched(pos, b'_', Comp::Emph, Atom::Str)),
-      b'*' => Some(self.between_matched(pos, b'*', Comp::Strong, Atom::Str)),
+      b'_' => Some(self.between_matched(subject, pos, b'_', Comp::Emph, Atom::Str, None)),
+      b'*' => Some(self.between_matched(subject, pos, b'*', Comp::Strong, Atom::Str, None)),
       b'{' => {
-        if self.subject[pos + 1..endpos].starts_with(|c: char| "_*^+='\"-".contains(c)) {
+        if subject[pos + 1..endpos].starts_with(|c: char| "_*~^+='\"-".contains(c)) {
           self.add_match(pos, pos + 1, Atom::OpenMarker);
           return Some(pos + 1);
+        } else if !self.reparsing {
+          self.attribute_parser = Some(AttributeParser::default());
+          self.attribute_start = pos;
+          self.attribute_slices.clear();
+          return Some(pos);
         } else {
-          // TODO: attributes
           self.add_match(pos, pos + 1, Atom::Str);
           return Some(pos + 1);
         }
       }
       b':' => {
-        let m = bounded_find(&self.subject, "^%:[%w_+-]%:", pos, endpos);
+        let m = bounded_find(subject, "^%:[%w_+-]+%:", pos, endpos);
         if m.is_match {
           self.add_match(m.start, m.end, Atom::Emoji);
           return Some(m.end);
@@ -326,13 +451,72 @@ impl Parser {
           return Some(pos + 1);
         }
       }
-      b'+' => todo!(),
-      b'=' => todo!(),
-      b'\'' => todo!(),
-      b'"' => Some(self.between_matched(pos, b'"', Comp::DoubleQuoted, Atom::LeftDoubleQuote)),
-      b'-' => todo!(),
+      // only with explicit braces: `{+insert+}` and `{=mark=}`
+      b'+' | b'=' if !self.has_brace(subject, pos) => None,
+      b'+' => Some(self.between_matched(subject, pos, b'+', Comp::Insert, Atom::Str, None)),
+      b'=' => Some(self.between_matched(subject, pos, b'=', Comp::Mark, Atom::Str, None)),
+      b'\'' => Some(self.between_matched(
+        subject,
+        pos,
+        b'\'',
+        Comp::SingleQuoted,
+        Atom::RightSingleQuote,
+        Some(Self::single_quote_opens),
+      )),
+      b'"' => Some(self.between_matched(
+        subject,
+        pos,
+        b'"',
+        Comp::DoubleQuoted,
+        Atom::LeftDoubleQuote,
+        None,
+      )),
+      b'-' if self.has_brace(subject, pos) => {
+        Some(self.between_matched(subject, pos, b'-', Comp::Delete, Atom::Str, None))
+      }
+      b'-' => {
+        // didn't match a del, try for smart hyphens:
+        let mut pos = pos;
+        let ep = bounded_find(subject, "^%-*", pos, endpos).end_or(endpos);
+        let mut hyphens = ep - pos;
+        if subject.as_bytes().get(ep) == Some(&b'}') {
+          hyphens -= 1 // last hyphen is close del
+        }
+        if hyphens == 0 {
+          // this means we have '-}'
+          self.add_match(pos, pos + 1, Atom::Str);
+          return Some(pos + 1);
+        }
+        // Try to construct a homogeneous sequence of dashes
+        let all_em = hyphens.is_multiple_of(3);
+        let all_en = hyphens.is_multiple_of(2);
+        while hyphens > 0 {
+          if all_em {
+            self.add_match(pos, pos + 3, Atom::EmDash);
+            pos += 3;
+            hyphens -= 3
+          } else if all_en {
+            self.add_match(pos, pos + 2, Atom::EnDash);
+            pos += 2;
+            hyphens -= 2
+          } else if hyphens >= 3 && (!hyphens.is_multiple_of(2) || hyphens > 4) {
+            self.add_match(pos, pos + 3, Atom::EmDash);
+            pos += 3;
+            hyphens -= 3
+          } else if hyphens >= 2 {
+            self.add_match(pos, pos + 2, Atom::EnDash);
+            pos += 2;
+            hyphens -= 2
+          } else {
+            self.add_match(pos, pos + 1, Atom::Str);
+            pos += 1;
+            hyphens -= 1
+          }
+        }
+        return Some(pos);
+      }
       b'.' => {
-        if bounded_find(&self.subject, "^%.%.", pos + 1, endpos).is_match {
+        if bounded_find(subject, "^%.%.", pos + 1, endpos).is_match {
           self.add_match(pos, pos + 3, Atom::Ellipses);
           return Some(pos + 3);
         }
@@ -346,10 +530,17 @@ impl Parser {
     pos + 1
   }
 
+  /// Whether the text fed so far ends within verbatim, where `|` doesn't
+  /// end a table cell.
+  pub(crate) fn in_verbatim(&self) -> bool {
+    self.verbatim > 0
+  }
+
   // Feed a slice to the parser, updating state.
-  pub fn feed(&mut self, spos: usize, endpos: usize) {
+  pub fn feed(&mut self, subject: &str, spos: usize, endpos: usize, budget: &mut Budget) {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("parse_inline", bytes = endpos - spos).entered();
     let special = "[%]%[\\`{}_*()!<>~^:=+$\r\n'\".-]";
-    let subject = self.subject.clone();
     if spos < self.firstpos {
       self.firstpos = spos
     }
@@ -357,16 +548,45 @@ impl Parser {
       self.lastpos = endpos
     }
     let mut pos = spos;
-    while pos < endpos {
-      if false {
-        // TODO: attributes
+    while pos < endpos && budget.step() {
+      if let Some(parser) = &mut self.attribute_parser {
+        let sp = pos;
+        let ep = bounded_find(subject, "[\r\n]", pos, endpos).end_or(endpos);
+        match parser.feed(subject, sp..ep) {
+          Status::Done(end) => {
+            let parser = self.attribute_parser.take().unwrap();
+            let start = self.attribute_start;
+            if let Some((open, close)) = self.pending_span.take().filter(|s| s.1 + 1 == start) {
+              self.add_match(open, open + 1, Comp::Span.add());
+              self.add_match(close, close + 1, Comp::Span.sub());
+            }
+            self.add_match(start, start + 1, Comp::Attributes.add());
+            for (range, atom) in parser.matches {
+              self.add_match(range.start, range.end, atom)
+            }
+            self.add_match(end, end + 1, Comp::Attributes.sub());
+            self.attribute_slices.clear();
+            pos = end + 1
+          }
+          Status::Fail(end) => {
+            // only the text up to the failure wasn't attributes, what
+            // follows may well be
+            self.attribute_slices.push(sp..end);
+            self.reparse_attributes(subject, budget);
+            pos = end
+          }
+          Status::Continue => {
+            self.attribute_slices.push(sp..ep);
+            pos = ep
+          }
+        }
       } else {
         // find next interesting character:
-        let newpos = bounded_find(&subject, special, pos, endpos).or(endpos);
+        let newpos = bounded_find(subject, special, pos, endpos).or(endpos);
         if newpos > pos {
           self.add_match(pos, newpos, Atom::Str);
           pos = newpos;
-          if pos > endpos {
+          if pos >= endpos {
             break; // otherwise, fall through:
           }
         }
@@ -374,7 +594,7 @@ impl Parser {
         // i.e. we have something interesting at pos
         let c = subject.as_bytes()[pos];
         if c == b'\r' || c == b'\n' {
-          if c == b'\r' && bounded_find(&subject, "^[%n]", pos + 1, endpos).is_match {
+          if c == b'\r' && bounded_find(subject, "^[%n]", pos + 1, endpos).is_match {
             self.add_match(pos, pos + 2, Atom::Softbreak);
             pos = pos + 2
           } else {
@@ -383,11 +603,18 @@ impl Parser {
           }
         } else if self.verbatim > 0 {
           if c == b'`' {
-            let m = bounded_find(&subject, "^`+", pos, endpos);
+            let m = bounded_find(subject, "^`+", pos, endpos);
             if m.is_match && m.end - pos == self.verbatim {
-              // TODO: Check for raw attributes
               self.add_match(pos, m.end, self.verbatim_type.sub());
               pos = m.end;
+              if self.verbatim_type == Comp::Verbatim {
+                // `{=html}` makes it raw content for that format
+                let raw = bounded_find(subject, "^{=[^%s{}`]+}", pos, endpos);
+                if raw.is_match {
+                  self.add_match(raw.start, raw.end, Atom::RawFormat);
+                  pos = raw.end
+                }
+              }
               self.verbatim = 0;
               self.verbatim_type = Comp::default();
             } else {
@@ -396,41 +623,71 @@ impl Parser {
               pos = endchar
             }
           } else {
-            self.add_match(pos, pos + 1, Atom::Str);
-            pos = pos + 1
+            // everything up to the next backtick or line end is verbatim
+            let endchar = bounded_find(subject, "[`\r\n]", pos, endpos).or(endpos);
+            self.add_match(pos, endchar, Atom::Str);
+            pos = endchar
           }
         } else {
-          pos = self.matchers(c, pos, endpos).unwrap_or_else(|| self.single_char(pos))
+          pos = self.matchers(subject, c, pos, endpos).unwrap_or_else(|| self.single_char(pos))
         }
       }
     }
   }
 
-  pub(crate) fn get_matches(&mut self) -> Vec<Match> {
-    let mut sorted: Vec<Match> = Vec::new();
+  /// Feeds the text which turned out not to be attributes again, as
+  /// regular inline content.
+  fn reparse_attributes(&mut self, subject: &str, budget: &mut Budget) {
+    self.attribute_parser = None;
+    self.pending_span = None;
+    let mut slices = std::mem::take(&mut self.attribute_slices);
+    self.reparsing = true;
+    for slice in &slices {
+      self.feed(subject, slice.start, slice.end, budget)
+    }
+    self.reparsing = false;
+    // keep the buffer
+    slices.clear();
+    self.attribute_slices = slices;
+  }
+
+  pub(crate) fn get_matches(
+    &mut self,
+    subject: &str,
+    sorted: &mut Vec<Match>,
+    warnings: &mut Vec<Diagnostic>,
+  ) {
+    if self.attribute_parser.is_some() {
+      // unclosed attributes
+      self.reparse_attributes(subject, &mut Budget::default())
+    }
+    let start = sorted.len();
     let mut m_last = Match::new(0..0, Atom::Ellipses); // TODO
-    for i in self.firstpos..=self.lastpos {
-      if let Some(&m) = self.matches.get(&i) {
-        if m.is(Atom::Str) && m_last.is(Atom::Str) && m_last.e == m.s {
-          (*sorted.last_mut().unwrap()).e = m.e;
-          m_last.e = m.e;
-        } else {
-          sorted.push(m);
-          m_last = m
-        }
+    let range = self.firstpos as u32..=self.lastpos as u32;
+    for (_, &m) in self.matches.range(range) {
+      if m.is(Atom::Str) && m_last.is(Atom::Str) && m_last.e == m.s {
+        (*sorted.last_mut().unwrap()).e = m.e;
+        m_last.e = m.e;
+      } else {
+        sorted.push(m);
+        m_last = m
       }
     }
-    if sorted.len() > 0 {
+    if sorted.len() > start {
       if sorted.last().unwrap().is(Atom::Softbreak) {
         // remove final softbreak
         sorted.pop();
       }
       if self.verbatim > 0 {
         // unclosed verbatim
-        let e = sorted.last().unwrap().e;
+        let e = sorted.last().unwrap().end();
+        warnings.push(Diagnostic::warning(self.verbatim_start..e, "unclosed verbatim"));
         sorted.push(Match::new(e..e, self.verbatim_type.sub()))
       }
     }
-    sorted
+    if let (Some(pos), Some(max)) = (self.too_deep, self.opts.max_nesting) {
+      let message = format!("nesting limit of {max} exceeded");
+      warnings.push(Diagnostic::warning(pos..pos + 1, message));
+    }
   }
 }
@@ -0,0 +1,282 @@
+//! The JSON AST of djot.lua: lists are split into `bullet_list`,
+//! `ordered_list` and `task_list`, attributes are stored under `attributes`,
+//! and reference definitions and footnotes are moved out of the content
+//! into the `references` and `footnotes` of the document.
+//!
+//! Reading the JSON back moves them to the end of the document.
+
+use indexmap::IndexMap;
+use serde::{
+  de::Error as _,
+  ser::{Serialize, SerializeMap, Serializer},
+};
+use serde_json::{Map, Value};
+
+use crate::{
+  ast::{Attrs, ReferenceDefinition, ReferenceKey, ReferenceValue, Tag, TagKind},
+  line_index::LineIndex,
+  Document,
+};
+
+/// The kinds of [`SmartPunctuation`](crate::ast::SmartPunctuation), which
+/// djot.lua uses as tags of their own.
+const SMART_PUNCTUATION: &[&str] = &[
+  "left_single_quote",
+  "right_single_quote",
+  "left_double_quote",
+  "right_double_quote",
+  "en_dash",
+  "em_dash",
+  "ellipses",
+];
+
+pub(crate) fn to_json(doc: &Document) -> String {
+  let mut children = Vec::new();
+  let mut references = Vec::new();
+  let mut footnotes = Vec::new();
+  let line_index = doc.line_index.as_ref();
+  split_notes(&doc.children, line_index, &mut children, &mut references, &mut footnotes);
+  let json = DocJson { children, references, footnotes };
+  serde_json::to_string_pretty(&json).expect("the AST serializes to JSON")
+}
+
+pub(crate) fn from_json(json: &str) -> Result<Document, serde_json::Error> {
+  let mut doc = match serde_json::from_str(json)? {
+    Value::Object(doc) => doc,
+    _ => return Err(serde_json::Error::custom("expected a `doc` object")),
+  };
+  let mut children = tags(doc.remove("children"))?;
+  if let Some(Value::Object(references)) = doc.remove("references") {
+    for (_, node) in references {
+      children.push(reference_definition(node)?)
+    }
+  }
+  if let Some(Value::Object(footnotes)) = doc.remove("footnotes") {
+    for (_, node) in footnotes {
+      children.push(tag(node)?)
+    }
+  }
+  Ok(Document::new(children))
+}
+
+fn tags(value: Option<Value>) -> Result<Vec<Tag>, serde_json::Error> {
+  match value {
+    Some(Value::Array(nodes)) => nodes.into_iter().map(tag).collect(),
+    Some(_) => Err(serde_json::Error::custom("expected an array of children")),
+    None => Ok(Vec::new()),
+  }
+}
+
+/// Turns a djot.lua node into the serde representation of [`TagKind`] and
+/// deserializes that.
+fn tag(value: Value) -> Result<Tag, serde_json::Error> {
+  let mut fields = match value {
+    Value::Object(fields) => fields,
+    _ => return Err(serde_json::Error::custom("expected a node object")),
+  };
+  let name = match fields.remove("tag") {
+    Some(Value::String(name)) => name,
+    _ => return Err(serde_json::Error::custom("node without a `tag`")),
+  };
+  let attrs: Attrs = match fields.remove("attributes") {
+    Some(attrs) => serde_json::from_value(attrs)?,
+    None => Attrs::new(),
+  };
+  let children = tags(fields.remove("children"))?;
+  fields.remove("pos");
+  let kind = match name.as_str() {
+    "bullet_list" | "ordered_list" => "list",
+    "task_list" => {
+      fields.entry("style").or_insert_with(|| "-".into());
+      "list"
+    }
+    "task_list_item" => {
+      let checked = fields.remove("checkbox").is_some_and(|it| it == "checked");
+      fields.insert("checked".to_string(), checked.into());
+      "task_list_item"
+    }
+    "mark" => "highlight",
+    name if SMART_PUNCTUATION.contains(&name) => {
+      fields.insert("kind".to_string(), name.into());
+      "smart_punctuation"
+    }
+    name => name,
+  };
+  fields.insert("tag".to_string(), kind.replace('_', "").into());
+  let kind: TagKind = serde_json::from_value(Value::Object(fields))?;
+  Ok(Tag::new(kind).with_attrs(attrs).with_children(children))
+}
+
+fn reference_definition(value: Value) -> Result<Tag, serde_json::Error> {
+  let field = |key: &str| match value.get(key) {
+    Some(Value::String(it)) => Ok(it.clone()),
+    _ => Err(serde_json::Error::custom(format!("reference without a `{key}`"))),
+  };
+  let attrs: Attrs = match value.get("attributes") {
+    Some(attrs) => serde_json::from_value(attrs.clone())?,
+    None => Attrs::new(),
+  };
+  let children = vec![
+    Tag::new(ReferenceKey::new(field("label")?)),
+    Tag::new(ReferenceValue::new(field("destination")?)),
+  ];
+  Ok(Tag::new(ReferenceDefinition {}).with_attrs(attrs).with_children(children))
+}
+
+struct DocJson<'a> {
+  children: Vec<Node<'a>>,
+  references: Vec<&'a Tag>,
+  footnotes: Vec<Node<'a>>,
+}
+
+/// A tag with the reference definitions and footnotes taken out of its
+/// children.
+struct Node<'a> {
+  tag: &'a Tag,
+  /// Like `1:1-2:5`, for documents parsed with source positions.
+  pos: Option<String>,
+  children: Vec<Node<'a>>,
+}
+
+fn split_notes<'a>(
+  tags: &'a [Tag],
+  line_index: Option<&LineIndex>,
+  children: &mut Vec<Node<'a>>,
+  references: &mut Vec<&'a Tag>,
+  footnotes: &mut Vec<Node<'a>>,
+) {
+  for tag in tags {
+    if let TagKind::ReferenceDefinition(_) = tag.kind {
+      references.push(tag);
+      continue;
+    }
+    let mut node = Node::new(tag, line_index);
+    split_notes(&tag.children, line_index, &mut node.children, references, footnotes);
+    match tag.kind {
+      TagKind::Footnote(_) => footnotes.push(node),
+      _ => children.push(node),
+    }
+  }
+}
+
+impl Serialize for DocJson<'_> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(None)?;
+    map.serialize_entry("tag", "doc")?;
+    map.serialize_entry("children", &self.children)?;
+    let references: IndexMap<_, _> = self.references.iter().map(|&tag| reference(tag)).collect();
+    map.serialize_entry("references", &references)?;
+    let footnotes: IndexMap<_, _> = self
+      .footnotes
+      .iter()
+      .filter_map(|node| match &node.tag.kind {
+        TagKind::Footnote(footnote) => Some((footnote.label.as_str(), node)),
+        _ => None,
+      })
+      .collect();
+    map.serialize_entry("footnotes", &footnotes)?;
+    map.end()
+  }
+}
+
+impl<'a> Node<'a> {
+  fn new(tag: &'a Tag, line_index: Option<&LineIndex>) -> Node<'a> {
+    let pos = line_index.and_then(|it| it.format_range(tag.span.clone()));
+    Node { tag, pos, children: Vec::new() }
+  }
+}
+
+impl Serialize for Node<'_> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let tag = self.tag;
+    let mut fields = match serde_json::to_value(&tag.kind).map_err(ser_error)? {
+      Value::Object(fields) => fields,
+      _ => unreachable!("tags serialize to objects"),
+    };
+    fields.remove("tag");
+    let name = tag_name(tag);
+    let mut map = serializer.serialize_map(None)?;
+    map.serialize_entry("tag", &name)?;
+    match &tag.kind {
+      TagKind::TaskListItem(item) => {
+        fields.remove("checked");
+        map.serialize_entry("checkbox", if item.checked { "checked" } else { "unchecked" })?;
+      }
+      TagKind::SmartPunctuation(_) => {
+        fields.remove("kind");
+      }
+      TagKind::List(_) if name == "task_list" => {
+        fields.remove("style");
+      }
+      _ => (),
+    }
+    for (key, value) in &fields {
+      map.serialize_entry(key, value)?;
+    }
+    if !tag.attrs.is_empty() {
+      map.serialize_entry("attributes", &tag.attrs)?;
+    }
+    if let Some(pos) = &self.pos {
+      map.serialize_entry("pos", pos)?;
+    }
+    // leaves like `code_block` keep their content in `text` alone
+    if !self.children.is_empty() && !fields.contains_key("text") {
+      map.serialize_entry("children", &self.children)?;
+    }
+    map.end()
+  }
+}
+
+/// The label and the `reference` node of a reference definition.
+fn reference(tag: &Tag) -> (String, IndexMap<&'static str, Value>) {
+  let mut label = String::new();
+  let mut destination = String::new();
+  for child in &tag.children {
+    match &child.kind {
+      TagKind::ReferenceKey(key) => label = key.text.clone(),
+      TagKind::ReferenceValue(value) => destination.push_str(&value.text),
+      _ => (),
+    }
+  }
+  let mut node = IndexMap::new();
+  node.insert("tag", "reference".into());
+  node.insert("label", label.clone().into());
+  node.insert("destination", destination.into());
+  if !tag.attrs.is_empty() {
+    let attrs = tag.attrs.iter().map(|(k, v)| (k.clone(), Value::from(v.as_str())));
+    node.insert("attributes", attrs.collect::<Map<_, _>>().into());
+  }
+  (label, node)
+}
+
+/// The name djot.lua uses for the kind of `tag`.
+fn tag_name(tag: &Tag) -> String {
+  match &tag.kind {
+    TagKind::List(_)
+      if tag.children.iter().any(|it| matches!(it.kind, TagKind::TaskListItem(_))) =>
+    {
+      "task_list".to_string()
+    }
+    TagKind::List(list) if list.start.is_some() => "ordered_list".to_string(),
+    TagKind::List(_) => "bullet_list".to_string(),
+    TagKind::BlockQuote(_) => "blockquote".to_string(),
+    TagKind::Highlight(_) => "mark".to_string(),
+    TagKind::SmartPunctuation(it) => it.kind.clone(),
+    kind => snake_case(kind.name()),
+  }
+}
+
+fn snake_case(name: &str) -> String {
+  let mut res = String::new();
+  for (i, c) in name.char_indices() {
+    if c.is_ascii_uppercase() && i > 0 {
+      res.push('_');
+    }
+    res.push(c.to_ascii_lowercase());
+  }
+  res
+}
+
+fn ser_error<E: serde::ser::Error>(err: serde_json::Error) -> E {
+  E::custom(err)
+}
@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+
+use crate::{
+  ast::{Alignment, Cell, Tag, TagKind},
+  Document, LatexOpts,
+};
+
+const PREAMBLE: &str = "\\documentclass{article}
+\\usepackage[T1]{fontenc}
+\\usepackage[utf8]{inputenc}
+\\usepackage{amssymb}
+\\usepackage{graphicx}
+\\usepackage[normalem]{ulem}
+\\usepackage{xcolor}
+\\usepackage{hyperref}
+\\usepackage{alltt}
+\\begin{document}
+";
+
+const SECTIONS: [&str; 5] = ["section", "subsection", "subsubsection", "paragraph", "subparagraph"];
+
+const ENUM_COUNTERS: [&str; 4] = ["enumi", "enumii", "enumiii", "enumiv"];
+
+pub(crate) fn convert(opts: &LatexOpts, doc: &Document) -> String {
+  let mut ctx = Ctx {
+    out: String::new(),
+    footnotes: HashMap::new(),
+    in_footnotes: Vec::new(),
+    section_id: None,
+    enum_depth: 0,
+  };
+  collect_footnotes(&doc.children, &mut ctx.footnotes);
+  if opts.standalone {
+    ctx.out.push_str(PREAMBLE);
+  }
+  ctx.render_blocks(&doc.children);
+  if opts.standalone {
+    ctx.out.push_str("\\end{document}\n");
+  }
+  ctx.out
+}
+
+fn collect_footnotes<'a>(tags: &'a [Tag], acc: &mut HashMap<&'a str, &'a Tag>) {
+  for tag in tags {
+    match &tag.kind {
+      TagKind::Footnote(note) => {
+        acc.entry(note.label.as_str()).or_insert(tag);
+      }
+      _ => collect_footnotes(&tag.children, acc),
+    }
+  }
+}
+
+struct Ctx<'a> {
+  out: String,
+  footnotes: HashMap<&'a str, &'a Tag>,
+  // labels of the footnotes being rendered, which can't refer to themselves
+  in_footnotes: Vec<&'a str>,
+  // identifier of the section whose heading comes next
+  section_id: Option<&'a str>,
+  // nesting of `enumerate` environments, which have a counter each
+  enum_depth: usize,
+}
+
+impl<'a> Ctx<'a> {
+  fn render_blocks(&mut self, tags: &'a [Tag]) {
+    self.render_block_list(tags, &mut true)
+  }
+
+  /// Renders the blocks separated by blank lines. Sections and divs have no
+  /// LaTeX equivalent, their children are rendered in their place.
+  fn render_block_list(&mut self, tags: &'a [Tag], first: &mut bool) {
+    for tag in tags {
+      match &tag.kind {
+        TagKind::Section(_) => {
+          self.section_id = tag.id();
+          self.render_block_list(&tag.children, first);
+          continue;
+        }
+        TagKind::Div(_) => {
+          self.render_block_list(&tag.children, first);
+          continue;
+        }
+        TagKind::Footnote(_)
+        | TagKind::ReferenceDefinition(_)
+        | TagKind::AbbreviationDefinition(_) => continue,
+        TagKind::RawBlock(raw) if raw.format != "latex" => continue,
+        _ => (),
+      }
+      if !*first {
+        self.out.push('\n');
+      }
+      *first = false;
+      self.render_block(tag);
+    }
+  }
+
+  fn render_block(&mut self, tag: &'a Tag) {
+    match &tag.kind {
+      TagKind::Heading(heading) => {
+        let level = (heading.level as usize).clamp(1, SECTIONS.len());
+        self.out.push('\\');
+        self.out.push_str(SECTIONS[level - 1]);
+        self.out.push('{');
+        self.render_inlines(&tag.children);
+        self.out.push('}');
+        if let Some(id) = tag.id().or(self.section_id.take()) {
+          self.out.push_str("\\label{");
+          self.out.push_str(id);
+          self.out.push('}');
+        }
+        self.out.push('\n')
+      }
+      TagKind::Para(_) => {
+        self.render_inlines(&tag.children);
+        self.out.push('\n')
+      }
+      TagKind::ThematicBreak(_) => {
+        self.out.push_str("\\begin{center}\\rule{0.5\\linewidth}{0.5pt}\\end{center}\n")
+      }
+      TagKind::BlockQuote(_) | TagKind::Callout(_) => {
+        self.out.push_str("\\begin{quote}\n");
+        self.render_blocks(&tag.children);
+        self.out.push_str("\\end{quote}\n")
+      }
+      TagKind::List(list) => {
+        let enumerate = list.start.is_some() && self.enum_depth < ENUM_COUNTERS.len();
+        let env = if enumerate { "enumerate" } else { "itemize" };
+        self.out.push_str(&format!("\\begin{{{env}}}\n"));
+        if enumerate {
+          let start = list.start.unwrap_or(1);
+          if start != 1 {
+            let counter = ENUM_COUNTERS[self.enum_depth];
+            self.out.push_str(&format!("\\setcounter{{{counter}}}{{{}}}\n", start - 1));
+          }
+          self.enum_depth += 1;
+        }
+        for item in &tag.children {
+          match &item.kind {
+            TagKind::TaskListItem(task) if task.checked => {
+              self.out.push_str("\\item[$\\boxtimes$]")
+            }
+            TagKind::TaskListItem(_) => self.out.push_str("\\item[$\\square$]"),
+            _ => self.out.push_str("\\item"),
+          }
+          self.render_item(&item.children)
+        }
+        if enumerate {
+          self.enum_depth -= 1;
+        }
+        self.out.push_str(&format!("\\end{{{env}}}\n"))
+      }
+      TagKind::DefinitionList(_) => {
+        self.out.push_str("\\begin{description}\n");
+        for item in &tag.children {
+          for child in &item.children {
+            match child.kind {
+              TagKind::Term(_) => {
+                self.out.push_str("\\item[");
+                self.render_inlines(&child.children);
+                self.out.push(']');
+              }
+              _ => self.render_item(&child.children),
+            }
+          }
+        }
+        self.out.push_str("\\end{description}\n")
+      }
+      TagKind::Figure(_) => {
+        self.out.push_str("\\begin{figure}\n\\centering\n");
+        for child in &tag.children {
+          match child.kind {
+            TagKind::Caption(_) => self.render_caption(child, tag.id()),
+            _ => self.render_block(child),
+          }
+        }
+        self.out.push_str("\\end{figure}\n")
+      }
+      TagKind::Table(_) => self.render_table(tag),
+      TagKind::CodeBlock(code) => {
+        // `verbatim` can't contain its own end, `alltt` takes escapes
+        let env = if code.text.contains("\\end{verbatim}") { "alltt" } else { "verbatim" };
+        self.out.push_str(&format!("\\begin{{{env}}}\n"));
+        if env == "alltt" {
+          self.out_alltt(&code.text)
+        } else {
+          self.out.push_str(&code.text)
+        }
+        if !code.text.ends_with('\n') {
+          self.out.push('\n');
+        }
+        self.out.push_str(&format!("\\end{{{env}}}\n"))
+      }
+      TagKind::RawBlock(raw) => {
+        self.out.push_str(&raw.text);
+        if !raw.text.ends_with('\n') {
+          self.out.push('\n');
+        }
+      }
+      _ => {
+        self.render_inline(tag);
+        self.out.push('\n')
+      }
+    }
+  }
+
+  /// The blocks of a list item after its `\item`. The first paragraph goes
+  /// on the same line.
+  fn render_item(&mut self, blocks: &'a [Tag]) {
+    match blocks.first() {
+      Some(para) if matches!(para.kind, TagKind::Para(_)) => self.out.push(' '),
+      _ => self.out.push('\n'),
+    }
+    self.render_blocks(blocks)
+  }
+
+  fn render_caption(&mut self, caption: &'a Tag, id: Option<&str>) {
+    self.out.push_str("\\caption{");
+    self.render_inlines(&caption.children);
+    self.out.push('}');
+    if let Some(id) = id {
+      self.out.push_str("\\label{");
+      self.out.push_str(id);
+      self.out.push('}');
+    }
+    self.out.push('\n')
+  }
+
+  /// A `tabular`, in a floating `table` when it has a caption. The rules
+  /// separate the header rows from the body.
+  fn render_table(&mut self, tag: &'a Tag) {
+    let caption = tag.children.iter().find(|it| matches!(it.kind, TagKind::Caption(_)));
+    let rows: Vec<&Tag> =
+      tag.children.iter().filter(|it| matches!(it.kind, TagKind::Row(_))).collect();
+    if let Some(caption) = caption {
+      self.out.push_str("\\begin{table}\n\\centering\n");
+      self.render_caption(caption, tag.id());
+    }
+    let columns: String = rows
+      .last()
+      .map(|row| {
+        row.children.iter().map(|cell| match cell.kind {
+          TagKind::Cell(Cell { align: Alignment::Center, .. }) => 'c',
+          TagKind::Cell(Cell { align: Alignment::Right, .. }) => 'r',
+          _ => 'l',
+        })
+      })
+      .into_iter()
+      .flatten()
+      .collect();
+    self.out.push_str(&format!("\\begin{{tabular}}{{{columns}}}\n"));
+    for (i, row) in rows.iter().enumerate() {
+      for (j, cell) in row.children.iter().enumerate() {
+        if j > 0 {
+          self.out.push_str(" & ");
+        }
+        self.render_inlines(&cell.children);
+      }
+      self.out.push_str(" \\\\\n");
+      let head = |row: &Tag| matches!(row.kind, TagKind::Row(ref it) if it.head);
+      if head(row) && !rows.get(i + 1).is_some_and(|it| head(it)) {
+        self.out.push_str("\\hline\n");
+      }
+    }
+    self.out.push_str("\\end{tabular}\n");
+    if caption.is_some() {
+      self.out.push_str("\\end{table}\n");
+    }
+  }
+
+  fn render_inlines(&mut self, tags: &'a [Tag]) {
+    for tag in tags {
+      self.render_inline(tag)
+    }
+  }
+
+  fn render_command(&mut self, command: &str, tag: &'a Tag) {
+    self.out.push('\\');
+    self.out.push_str(command);
+    self.out.push('{');
+    self.render_inlines(&tag.children);
+    self.out.push('}');
+  }
+
+  fn render_inline(&mut self, tag: &'a Tag) {
+    match &tag.kind {
+      TagKind::Str(str) => self.out_escaped(&str.text),
+      TagKind::Unparsed(unparsed) => self.out_escaped(&unparsed.text),
+      TagKind::Softbreak(_) => self.out.push('\n'),
+      TagKind::HardBreak(_) => self.out.push_str("\\\\\n"),
+      TagKind::NonBreakingSpace(_) => self.out.push('~'),
+      TagKind::Emph(_) => self.render_command("emph", tag),
+      TagKind::Strong(_) => self.render_command("textbf", tag),
+      TagKind::Subscript(_) => self.render_command("textsubscript", tag),
+      TagKind::Superscript(_) => self.render_command("textsuperscript", tag),
+      TagKind::Insert(_) => self.render_command("uline", tag),
+      TagKind::Delete(_) => self.render_command("sout", tag),
+      TagKind::Highlight(_) => {
+        self.out.push_str("\\colorbox{yellow}{");
+        self.render_inlines(&tag.children);
+        self.out.push('}');
+      }
+      TagKind::SingleQuoted(_) | TagKind::DoubleQuoted(_) => {
+        let double = matches!(tag.kind, TagKind::DoubleQuoted(_));
+        self.out.push_str(if double { "``" } else { "`" });
+        self.render_inlines(&tag.children);
+        self.out.push_str(if double { "''" } else { "'" });
+      }
+      TagKind::Verbatim(verbatim) => {
+        self.out.push_str("\\texttt{");
+        self.out_escaped(&verbatim.text);
+        self.out.push('}');
+      }
+      TagKind::RawInline(raw) if raw.format == "latex" => self.out.push_str(&raw.text),
+      TagKind::RawInline(_) => (),
+      TagKind::InlineMath(math) => {
+        self.out.push_str("\\(");
+        self.out.push_str(&math.text);
+        self.out.push_str("\\)");
+      }
+      TagKind::DisplayMath(math) => {
+        self.out.push_str("\\[");
+        self.out.push_str(&math.text);
+        self.out.push_str("\\]");
+      }
+      TagKind::SmartPunctuation(punctuation) => {
+        self.out.push_str(match punctuation.kind.as_str() {
+          "left_single_quote" => "`",
+          "right_single_quote" => "'",
+          "left_double_quote" => "``",
+          "right_double_quote" => "''",
+          "en_dash" => "--",
+          "em_dash" => "---",
+          "ellipses" => "\\ldots{}",
+          _ => "",
+        })
+      }
+      TagKind::Symbol(symbol) => self.out_escaped(&format!(":{}:", symbol.alias)),
+      TagKind::Citation(citation) => {
+        self.out.push_str("\\cite");
+        if let Some(locator) = &citation.locator {
+          self.out.push('[');
+          self.out_escaped(locator);
+          self.out.push(']');
+        }
+        self.out.push('{');
+        self.out.push_str(&citation.key);
+        self.out.push('}');
+      }
+      TagKind::CrossReference(reference) => {
+        self.out.push_str("\\ref{");
+        self.out.push_str(&reference.target);
+        self.out.push('}');
+      }
+      TagKind::FootnoteReference(reference) => self.render_footnote(&reference.label),
+      TagKind::Url(url) => {
+        self.out.push_str("\\url{");
+        self.out_url(&url.destination);
+        self.out.push('}');
+      }
+      TagKind::Link(link) => match link.destination.as_deref() {
+        Some(destination) if destination.starts_with('#') => {
+          self.out.push_str("\\hyperref[");
+          self.out.push_str(&destination[1..]);
+          self.out.push_str("]{");
+          self.render_inlines(&tag.children);
+          self.out.push('}');
+        }
+        Some(destination) => {
+          self.out.push_str("\\href{");
+          self.out_url(destination);
+          self.out.push_str("}{");
+          self.render_inlines(&tag.children);
+          self.out.push('}');
+        }
+        None => self.render_inlines(&tag.children),
+      },
+      TagKind::Image(image) => match image.destination.as_deref() {
+        Some(destination) => {
+          self.out.push_str("\\includegraphics{");
+          self.out_url(destination);
+          self.out.push('}');
+        }
+        None => self.render_inlines(&tag.children),
+      },
+      _ => self.render_inlines(&tag.children),
+    }
+  }
+
+  /// Renders the content of the footnote in place of the reference, as
+  /// LaTeX numbers footnotes by itself.
+  fn render_footnote(&mut self, label: &'a str) {
+    let Some(note) = self.footnotes.get(label).copied() else {
+      return;
+    };
+    if self.in_footnotes.contains(&label) {
+      return;
+    }
+    self.in_footnotes.push(label);
+    self.out.push_str("\\footnote{");
+    match note.children.as_slice() {
+      [para] if matches!(para.kind, TagKind::Para(_)) => self.render_inlines(&para.children),
+      blocks => {
+        self.render_blocks(blocks);
+        if self.out.ends_with('\n') {
+          self.out.pop();
+        }
+      }
+    }
+    self.out.push('}');
+    self.in_footnotes.pop();
+  }
+
+  fn out_escaped(&mut self, text: &str) {
+    for c in text.chars() {
+      match c {
+        '\\' => self.out.push_str("\\textbackslash{}"),
+        '{' | '}' | '$' | '&' | '#' | '_' | '%' => {
+          self.out.push('\\');
+          self.out.push(c)
+        }
+        '^' => self.out.push_str("\\textasciicircum{}"),
+        '~' => self.out.push_str("\\textasciitilde{}"),
+        '<' => self.out.push_str("\\textless{}"),
+        '>' => self.out.push_str("\\textgreater{}"),
+        '`' => self.out.push_str("\\textasciigrave{}"),
+        '\u{a0}' => self.out.push('~'),
+        // `--` would be a dash
+        '-' if self.out.ends_with('-') => self.out.push_str("{}-"),
+        _ => self.out.push(c),
+      }
+    }
+  }
+
+  /// Only `\`, `{` and `}` keep their meaning in `alltt`.
+  fn out_alltt(&mut self, text: &str) {
+    for c in text.chars() {
+      match c {
+        '\\' => self.out.push_str("\\textbackslash{}"),
+        '{' | '}' => {
+          self.out.push('\\');
+          self.out.push(c)
+        }
+        _ => self.out.push(c),
+      }
+    }
+  }
+
+  /// Destinations are taken verbatim by `\url` and `\href`, except for the
+  /// characters which end the argument or start a comment.
+  fn out_url(&mut self, url: &str) {
+    for c in url.chars() {
+      if matches!(c, '%' | '#' | '{' | '}' | '\\') {
+        self.out.push('\\');
+      }
+      self.out.push(c)
+    }
+  }
+}
@@ -1,70 +1,1132 @@
 pub mod ast;
+pub mod events;
+pub mod filter;
+pub mod lint;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "syntect")]
+pub mod highlight;
+#[cfg(any(feature = "axum", feature = "actix"))]
+pub mod web;
+#[cfg(any(feature = "minijinja", feature = "tera"))]
+pub mod template;
+#[cfg(feature = "rhai")]
+pub mod script;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "from-html")]
+pub mod from_html;
+
+mod macros;
 
 mod annot;
+mod attributes;
 mod patterns;
 mod block;
 mod inline;
 mod tree;
+#[cfg(feature = "html")]
 mod html;
+mod numbering;
+mod line_index;
+mod diagnostic;
+mod diff;
+#[cfg(feature = "serde")]
+mod dump;
+#[cfg(feature = "serde")]
+mod json;
+mod excerpt;
+mod budget;
+mod concat;
+mod error;
+mod escape;
+#[cfg(feature = "html")]
+mod bidi;
+mod prose;
+mod search;
+mod index;
+mod figures;
+mod lists;
+#[cfg(feature = "html")]
+mod session;
+#[cfg(feature = "html")]
+mod standalone;
+mod stats;
+mod text;
+#[cfg(feature = "latex")]
+mod latex;
+mod typography;
+mod writer;
 
-use std::ops::Range;
+#[cfg(feature = "timing")]
+use std::time::Instant;
+use std::{
+  borrow::Cow,
+  collections::HashMap,
+  io::{self, BufRead},
+  ops::Range,
+  str,
+  sync::Arc,
+  time::Duration,
+};
 
 use annot::Annot;
 
-#[derive(Debug, Clone)]
+pub use diagnostic::{Diagnostic, Severity};
+pub use diff::{diff, Change};
+pub use error::ParseError;
+pub use figures::FigureEntry;
+#[cfg(feature = "html")]
+pub use html::Html;
+pub use index::{IndexEntry, IndexLocation};
+pub use line_index::{LineCol, LineIndex};
+pub use prose::ProseSegment;
+pub use search::SearchRecord;
+#[cfg(feature = "html")]
+pub use session::Session;
+pub use stats::Stats;
+pub use writer::RoundtripError;
+
+/// A parsed djot document.
+///
+/// Documents (as well as [`ParseOpts`] and [`HtmlOpts`]) are `Send + Sync`,
+/// so they can be parsed once and rendered from many threads.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[non_exhaustive]
 pub struct Document {
   pub children: Vec<ast::Tag>,
   pub debug: String,
+  /// Collected when [`ParseOpts::stats`] is set.
+  pub stats: Option<Stats>,
+  /// The lines of the source, kept when [`ParseOpts::source_positions`] is
+  /// set. See [`Document::position`].
+  pub line_index: Option<LineIndex>,
+}
+
+impl From<Vec<ast::Tag>> for Document {
+  fn from(children: Vec<ast::Tag>) -> Document {
+    Document::new(children)
+  }
 }
 
 impl Document {
+  /// A document with the given content, e.g. built with [`djot_ast!`].
+  pub fn new(children: Vec<ast::Tag>) -> Document {
+    Document { children, ..Document::default() }
+  }
+
   pub fn parse(text: &str) -> Document {
     Document::parse_opts(ParseOpts::default(), text)
   }
 
+  /// Parses with [`Parser::parse`], see there for what happens when the
+  /// input exceeds the limits of `opts`.
   pub fn parse_opts(opts: ParseOpts, text: &str) -> Document {
-    let mut p = block::Parser::new(text.to_string(), opts);
-    p.parse();
-    tree::build(p)
+    Parser::new(opts).parse(text)
+  }
+
+  pub fn try_parse_opts(opts: ParseOpts, text: &str) -> Result<Document, ParseError> {
+    Parser::new(opts).try_parse(text)
   }
 
+  /// Parses `text`, also returning the warnings produced on the way, see
+  /// [`Parser::parse_with_warnings`].
+  pub fn parse_with_warnings(opts: ParseOpts, text: &str) -> Result<ParseResult, ParseError> {
+    Parser::new(opts).parse_with_warnings(text)
+  }
+
+  /// Parses UTF-8 encoded bytes, see [`Parser::parse_bytes`].
+  pub fn parse_bytes(
+    bytes: &[u8],
+    opts: ParseOpts,
+    validation: Utf8Validation,
+  ) -> Result<Document, ParseError> {
+    Parser::new(opts).parse_bytes(bytes, validation)
+  }
+
+  /// Parses bytes which may not be valid UTF-8, replacing invalid sequences
+  /// with `U+FFFD` and reporting them as warnings, see
+  /// [`Parser::parse_lossy`].
+  pub fn parse_lossy(bytes: &[u8]) -> (Document, Vec<Diagnostic>) {
+    Parser::new(ParseOpts::default()).parse_lossy(bytes, Utf8Validation::Lossy)
+  }
+
+  /// Parses the input as it is read, see [`Parser::parse_reader`].
+  pub fn parse_from_reader(reader: impl BufRead, opts: ParseOpts) -> io::Result<Document> {
+    Parser::new(opts).parse_reader(reader)
+  }
+
+  /// Parses a document given as lines, see [`Parser::parse_lines`].
+  pub fn parse_lines(lines: impl IntoIterator<Item = impl AsRef<str>>) -> Document {
+    Parser::new(ParseOpts::default()).parse_lines(lines)
+  }
+
+  /// Parses an edited version of this document's source.
+  ///
+  /// Nodes which are unaffected by the edit keep their [`ast::NodeId`]s, so
+  /// that consumers like live previews can update only the changed parts.
+  pub fn reparse(&self, opts: ParseOpts, text: &str) -> Document {
+    let mut res = Document::parse_opts(opts, text);
+    diff::reconcile_ids(&self.children, &mut res.children);
+    res
+  }
+
+  #[cfg(feature = "html")]
   pub fn to_html(&self) -> String {
     self.to_html_opts(&HtmlOpts::default())
   }
 
+  #[cfg(feature = "html")]
   pub fn to_html_opts(&self, opts: &HtmlOpts) -> String {
     html::convert(opts, self)
   }
 
+  /// Renders only the section (or any other node) with the given
+  /// identifier, e.g. to serve a single chapter. See [`ast::Tag::to_html`].
+  #[cfg(feature = "html")]
+  pub fn render_section(&self, id: &str, opts: &HtmlOpts) -> Option<String> {
+    Some(self.find_by_id(id)?.to_html(opts))
+  }
+
+  /// The first node with the given `id` attribute.
+  pub fn find_by_id(&self, id: &str) -> Option<&ast::Tag> {
+    self.children.iter().find_map(|it| it.find_by_id(id))
+  }
+
+  /// Renders the document back to djot source.
+  pub fn to_djot(&self) -> String {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!("render_djot").entered();
+    writer::convert(self)
+  }
+
+  /// Renders the plain text of the document, with blocks separated by
+  /// blank lines.
+  pub fn to_text(&self, opts: &TextOpts) -> String {
+    text::convert(self, opts)
+  }
+
+  /// Renders the document as LaTeX, e.g. to typeset it with `pdflatex`.
+  #[cfg(feature = "latex")]
+  pub fn to_latex(&self, opts: &LatexOpts) -> String {
+    latex::convert(opts, self)
+  }
+
+  /// Returns a value which renders the document as HTML when formatted, e.g.
+  /// `write!(w, "<body>{}</body>", doc.html(&opts))`.
+  #[cfg(feature = "html")]
+  pub fn html<'a>(&'a self, opts: &'a HtmlOpts) -> Html<'a> {
+    Html { doc: self, opts }
+  }
+
+  /// The sections, and the headings outside of them, by their identifier.
+  /// `[Section title][]` links to the section with that heading.
+  pub fn sections_by_id(&self) -> HashMap<&str, &ast::Tag> {
+    fn collect<'a>(tags: &'a [ast::Tag], acc: &mut HashMap<&'a str, &'a ast::Tag>) {
+      for tag in tags {
+        if let (ast::TagKind::Section(_) | ast::TagKind::Heading(_), Some(id)) =
+          (&tag.kind, tag.id())
+        {
+          acc.entry(id).or_insert(tag);
+        }
+        collect(&tag.children, acc)
+      }
+    }
+    let mut res = HashMap::new();
+    collect(&self.children, &mut res);
+    res
+  }
+
+  /// Parses all inline content left unparsed by
+  /// [`ParseOpts::lazy_inlines`] and renumbers the nodes. Use
+  /// [`ast::Tag::parse_inlines`] to only parse a part of the document.
+  pub fn parse_inlines(&mut self) {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("parse_inlines").entered();
+    tree::parse_inlines(&mut self.children);
+    let references = tree::references(&self.children);
+    tree::resolve_references(&mut self.children, &references);
+    let mut next_id = 1;
+    for child in &mut self.children {
+      diff::assign_ids(child, &mut next_id)
+    }
+  }
+
+  /// Applies typographic substitutions to the text of the document, like
+  /// [`ParseOpts::typography`] does while parsing.
+  pub fn substitute_typography(&mut self, typography: &Typography) {
+    typography::substitute(&mut self.children, typography)
+  }
+
+  /// Appends the content of `other`, see [`Document::concat`].
+  pub fn append(&mut self, other: Document) {
+    concat::append(self, other)
+  }
+
+  /// Joins documents into one, like the chapters of a book. The spans of
+  /// each document are moved past the end of the previous one, sections
+  /// whose identifier is already taken get a new one (and the links to them
+  /// follow), and reference definitions are shared, with labels defined
+  /// differently in several documents renamed. Footnotes keep their own
+  /// definitions, renamed when the label is taken.
+  pub fn concat(docs: impl IntoIterator<Item = Document>) -> Document {
+    let mut res = Document::default();
+    for doc in docs {
+      res.append(doc)
+    }
+    res
+  }
+
+  /// Runs `filter` over all nodes of the document, see [`filter`](crate::filter).
+  pub fn apply_filter(&mut self, filter: &mut impl filter::Filter) {
+    filter::apply(&mut self.children, filter)
+  }
+
+  /// The zero-based lines and byte columns of the start and the end of
+  /// `tag` in the source, when the document was parsed with
+  /// [`ParseOpts::source_positions`].
+  pub fn position(&self, tag: &ast::Tag) -> Option<Range<LineCol>> {
+    self.line_index.as_ref()?.range(tag.span.clone())
+  }
+
+  /// Assigns hierarchical numbers (`1`, `1.1`, `1.2`, `2`, ...) to all
+  /// headings, skipping the ones with an `unnumbered` class.
+  pub fn number_headings(&mut self) {
+    numbering::number_headings(&mut self.children, &mut numbering::HeadingNumbers::default())
+  }
+
+  /// Returns an indented, human readable outline of the AST, one node per
+  /// line, suitable for bug reports and snapshot tests.
+  #[cfg(feature = "serde")]
+  pub fn dump(&self) -> String {
+    dump::dump(self)
+  }
+
+  /// Returns the keys of all [`ast::Citation`]s, in order of first
+  /// citation, e.g. to look them up in a bibliography.
+  pub fn citation_keys(&self) -> Vec<String> {
+    let mut res = Vec::new();
+    tree::citation_keys(&self.children, &mut res);
+    res
+  }
+
+  /// The text of the first heading.
+  pub fn title(&self) -> Option<String> {
+    fn find(tags: &[ast::Tag]) -> Option<String> {
+      tags.iter().find_map(|tag| match tag.kind {
+        ast::TagKind::Heading(_) => Some(tag.text()),
+        ast::TagKind::Section(_) => find(&tag.children),
+        _ => None,
+      })
+    }
+    find(&self.children)
+  }
+
+  /// Returns the captioned figures, in document order.
+  pub fn figures(&self) -> Vec<FigureEntry> {
+    figures::entries(&self.children)
+  }
+
+  /// Returns the terms marked for the index, see [`IndexEntry`].
+  pub fn index_entries(&self) -> Vec<IndexEntry> {
+    index::entries(&self.children)
+  }
+
+  /// Returns the runs of human-language text in the document, skipping
+  /// code, verbatim and URLs, for spellcheckers and grammar tools.
+  pub fn prose(&self) -> Vec<ProseSegment> {
+    prose::segments(&self.children)
+  }
+
+  /// Returns one record per section, in document order, for building
+  /// client or server side search indexes.
+  pub fn search_records(&self) -> Vec<SearchRecord> {
+    search::records(&self.children)
+  }
+
+  /// Returns the content up to the first block with the
+  /// [`ExcerptOpts::marker`] class or, without such a block, the first
+  /// paragraph. Typically used for post listings and summaries.
+  pub fn excerpt(&self, opts: &ExcerptOpts) -> String {
+    excerpt::excerpt(self, opts)
+  }
+
+  /// Serializes the AST in the JSON format of djot.lua's `--ast` output, so
+  /// that it can be compared with the reference implementation.
+  #[cfg(feature = "serde")]
   pub fn to_json(&self) -> String {
-    #[derive(serde::Serialize)]
-    struct DocRepr<'a> {
-      tag: &'static str,
-      children: &'a [ast::Tag],
+    json::to_json(self)
+  }
+
+  /// Reads a document from the JSON produced by [`Document::to_json`] or
+  /// djot.lua, e.g. after it was transformed by an external tool.
+  #[cfg(feature = "serde")]
+  pub fn from_json(json: &str) -> Result<Document, serde_json::Error> {
+    json::from_json(json)
+  }
+}
+
+/// Checks the input for problems like unclosed code blocks or verbatim
+/// spans. This is considerably cheaper than parsing a [`Document`], as no
+/// tree is built, which makes it suitable for checking large corpora.
+pub fn validate(text: &str, opts: ParseOpts) -> Vec<Diagnostic> {
+  Parser::new(opts).validate(text)
+}
+
+/// Parses `text` into a flat sequence of [`events::Event`]s, without building
+/// the [`Document`] tree. See [`Parser::events`].
+///
+/// Input longer than [`MAX_INPUT_LEN`] is truncated, see [`Parser::parse`].
+pub fn parse_events(text: &str) -> impl Iterator<Item = events::Event> {
+  let mut parser = Parser::new(ParseOpts::default());
+  parser.block.reset("");
+  parser.push_truncated(text);
+  parser.take_events()
+}
+
+/// Parses an attribute block like `{#id .class key="value"}` on its own,
+/// the way the parser reads the attributes of elements. Classes accumulate,
+/// `id` and other keys given several times keep the last value.
+pub fn parse_attributes(text: &str) -> Result<ast::Attrs, Diagnostic> {
+  attributes::parse(text)
+}
+
+/// Parses `text`, renders it back to djot with [`Document::to_djot`] and
+/// parses the result again, reporting any differences between the two
+/// documents. Useful to check that reformatting a document is safe.
+///
+/// Text split up differently, e.g. by escapes, is no difference: adjacent
+/// [`ast::Str`] nodes are joined before comparing, and the paths of the
+/// changes refer to the documents with joined text.
+pub fn roundtrip_check(text: &str) -> Result<(), RoundtripError> {
+  let mut doc = Document::parse(text);
+  let djot = doc.to_djot();
+  let mut reparsed = Document::parse(&djot);
+  tree::join_text(&mut doc.children);
+  tree::join_text(&mut reparsed.children);
+  let changes = diff(&doc, &reparsed);
+  if !changes.is_empty() {
+    return Err(RoundtripError { djot, changes });
+  }
+  Ok(())
+}
+
+/// A document together with the warnings produced while parsing it, see
+/// [`Parser::parse_with_warnings`].
+#[derive(Debug, Clone)]
+pub struct ParseResult {
+  pub document: Document,
+  pub warnings: Vec<Diagnostic>,
+}
+
+/// A reusable parser.
+///
+/// Parsing many small documents with the same `Parser` avoids reallocating
+/// its internal buffers for every document.
+pub struct Parser {
+  block: block::Parser<'static>,
+}
+
+impl Parser {
+  pub fn new(opts: ParseOpts) -> Parser {
+    Parser { block: block::Parser::new(opts) }
+  }
+
+  /// Parses `text` as well as possible, ignoring [`ParseOpts::strict`].
+  /// Where [`Parser::try_parse`] fails, this returns what it could parse:
+  /// input longer than [`ParseOpts::max_input_bytes`] is truncated as with
+  /// [`ParseOpts::truncate_input`], and when the budget runs out, the
+  /// document parsed up to that point is returned.
+  pub fn parse(&mut self, text: &str) -> Document {
+    self.block.reset("");
+    self.push_truncated(text);
+    self.finish_partial()
+  }
+
+  pub fn try_parse(&mut self, text: &str) -> Result<Document, ParseError> {
+    self.block.reset("");
+    self.push_limited(text)?;
+    let strict = self.block.opts.strict;
+    finish(&mut self.block, strict)
+  }
+
+  /// Like [`Parser::try_parse`], but keeps the warnings about problems like
+  /// unclosed code blocks, which the input was parsed in spite of. With
+  /// [`ParseOpts::strict`], any warning fails the parse instead.
+  pub fn parse_with_warnings(&mut self, text: &str) -> Result<ParseResult, ParseError> {
+    let document = self.try_parse(text)?;
+    Ok(ParseResult { document, warnings: std::mem::take(&mut self.block.warnings) })
+  }
+
+  /// Parses UTF-8 encoded bytes, e.g. a memory mapped file. Valid input is
+  /// parsed where it is, without copying it, unless it lacks a final
+  /// newline, which has to be appended to a copy.
+  pub fn parse_bytes(
+    &mut self,
+    bytes: &[u8],
+    validation: Utf8Validation,
+  ) -> Result<Document, ParseError> {
+    let text = match str::from_utf8(bytes) {
+      Ok(text) => return self.parse_borrowed(text),
+      Err(err) if validation == Utf8Validation::Strict => return Err(ParseError::Utf8(err)),
+      Err(_) => decode_invalid(bytes, validation),
+    };
+    self.try_parse(&text)
+  }
+
+  /// Like [`Parser::try_parse`], but parses `text` where it is rather than
+  /// in the buffer of this parser.
+  fn parse_borrowed(&mut self, text: &str) -> Result<Document, ParseError> {
+    let limit = self.input_limit();
+    if text.len() > limit && !self.block.opts.truncate_input {
+      return Err(ParseError::InputTooLarge { len: text.len(), limit });
+    }
+    let end = char_boundary_below(text, limit);
+    let mut block = block::Parser::borrowed(self.block.opts.clone(), &text[..end]);
+    if end < text.len() {
+      block.warnings.push(truncated(end..text.len(), limit));
     }
-    serde_json::to_string_pretty(&DocRepr { tag: "doc", children: self.children.as_slice() })
-      .unwrap()
+    let strict = block.opts.strict;
+    finish(&mut block, strict)
+  }
+
+  /// Parses bytes which may not be valid UTF-8, e.g. legacy files in a batch
+  /// conversion. Invalid input is decoded according to `validation`, with
+  /// `Strict` acting like `Lossy`, and each invalid sequence is reported as
+  /// a warning. The ranges of the warnings are offsets into `bytes`. Like
+  /// [`Parser::parse`], this truncates input which is too long.
+  pub fn parse_lossy(
+    &mut self,
+    bytes: &[u8],
+    validation: Utf8Validation,
+  ) -> (Document, Vec<Diagnostic>) {
+    let message = match validation {
+      Utf8Validation::Latin1 => "invalid UTF-8, decoded the input as Latin-1",
+      _ => "invalid UTF-8, replaced with U+FFFD",
+    };
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut offset = 0;
+    for chunk in bytes.utf8_chunks() {
+      offset += chunk.valid().len();
+      let len = chunk.invalid().len();
+      match diagnostics.last_mut() {
+        _ if len == 0 => (),
+        Some(last) if last.range.end == offset => last.range.end += len,
+        _ => diagnostics
+          .push(Diagnostic::warning(offset..offset + len, message).with_code("invalid-utf8")),
+      }
+      offset += len;
+    }
+    let text = match str::from_utf8(bytes) {
+      Ok(text) => Cow::Borrowed(text),
+      Err(_) => decode_invalid(bytes, validation),
+    };
+    (self.parse(&text), diagnostics)
+  }
+
+  /// Reads all of `reader`, e.g. stdin, line by line and parses it as it
+  /// goes. Only the source of the blocks which later lines could still
+  /// change is kept in memory, e.g. of a list up to its end, the rest is
+  /// dropped once it is built into the document. With
+  /// [`ParseOpts::source_positions`], [`ParseOpts::cross_references`] or
+  /// [`ParseOpts::stats`], which need all of it, the whole input is kept.
+  pub fn parse_reader(&mut self, mut reader: impl BufRead) -> io::Result<Document> {
+    self.block.reset("");
+    let mut line = String::new();
+    while reader.read_line(&mut line)? != 0 {
+      let complete =
+        self.push_limited(&line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+      if !complete {
+        break;
+      }
+      // the last line may lack a newline, it is completed by `finish`
+      if line.ends_with('\n') {
+        self.block.parse_lines();
+        if self.block.can_drop_parsed() {
+          tree::build_parsed(&mut self.block)
+        }
+      }
+      line.clear();
+    }
+    let strict = self.block.opts.strict;
+    finish(&mut self.block, strict).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+  }
+
+  /// Parses a document given as lines, each with or without its line
+  /// terminator, e.g. straight from an editor's buffer. Like
+  /// [`Parser::parse`], this ignores [`ParseOpts::strict`], truncates input
+  /// which is too long and returns the partial document when the budget
+  /// runs out.
+  pub fn parse_lines<I>(&mut self, lines: I) -> Document
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    self.block.reset("");
+    for line in lines {
+      let line = line.as_ref();
+      if !self.push_truncated(line) {
+        break;
+      }
+      if !line.ends_with('\n') {
+        self.block.push_str("\n");
+      }
+      self.block.parse_lines();
+    }
+    self.finish_partial()
+  }
+
+  /// Finishes without [`ParseOpts::strict`], keeping the partial document
+  /// when the budget ran out.
+  fn finish_partial(&mut self) -> Document {
+    match finish(&mut self.block, false) {
+      Ok(doc) => doc,
+      Err(ParseError::BudgetExceeded { partial }) => *partial,
+      Err(err) => unreachable!("{err}"),
+    }
+  }
+
+  /// Runs the parser over the input without building the document, only
+  /// reporting problems like unclosed code blocks. See [`validate`].
+  pub fn validate(&mut self, text: &str) -> Vec<Diagnostic> {
+    self.block.reset("");
+    if let Err(err) = self.push_limited(text) {
+      return vec![Diagnostic::error(0..0, err.to_string())];
+    }
+    self.block.parse();
+    if self.block.budget.exceeded {
+      let message = ParseError::BudgetExceeded { partial: Box::default() }.to_string();
+      self.block.warnings.push(Diagnostic::error(0..0, message));
+    }
+    if self.block.opts.strict {
+      return strict_warnings(&mut self.block);
+    }
+    std::mem::take(&mut self.block.warnings)
+  }
+
+  /// Runs the parser over the input and returns its matches as events, the
+  /// opening and closing of containers and the leaf elements in between,
+  /// each with its range in `text`.
+  pub fn events(&mut self, text: &str) -> Result<events::Events, ParseError> {
+    self.block.reset("");
+    self.push_limited(text)?;
+    Ok(self.take_events())
+  }
+
+  fn take_events(&mut self) -> events::Events {
+    self.block.parse();
+    events::Events::new(std::mem::take(&mut self.block.matches))
+  }
+
+  /// Appends `text` to the input, as far as the length limit allows.
+  /// Returns whether all of it was appended.
+  fn push_limited(&mut self, text: &str) -> Result<bool, ParseError> {
+    let limit = self.input_limit();
+    let len = self.block.offset + self.block.subject.len() + text.len();
+    if len > limit && !self.block.opts.truncate_input {
+      return Err(ParseError::InputTooLarge { len, limit });
+    }
+    Ok(self.push_truncated(text))
+  }
+
+  /// Appends `text` to the input, truncating it to the length limit. Returns
+  /// whether all of it was appended.
+  fn push_truncated(&mut self, text: &str) -> bool {
+    let limit = self.input_limit();
+    let start = self.block.subject.len();
+    let len = self.block.offset + start + text.len();
+    if len <= limit {
+      self.block.push_str(text);
+      return true;
+    }
+    let end = char_boundary_below(text, limit.saturating_sub(self.block.offset + start));
+    self.block.push_str(&text[..end]);
+    // within the subject, like the warnings of the parser
+    self.block.warnings.push(truncated(start + end..start + text.len(), limit));
+    false
+  }
+
+  fn input_limit(&self) -> usize {
+    self.block.opts.max_input_bytes.map_or(MAX_INPUT_LEN, |it| it.min(MAX_INPUT_LEN))
+  }
+
+  /// Clears the state left over from the previous document, keeping the
+  /// allocated buffers around for the next one.
+  pub fn reset(&mut self) {
+    self.block.reset("")
+  }
+}
+
+fn finish(block: &mut block::Parser, strict: bool) -> Result<Document, ParseError> {
+  #[cfg(feature = "tracing")]
+  let span = tracing::info_span!(
+    "parse",
+    bytes = block.offset + block.subject.len(),
+    blocks = tracing::field::Empty
+  )
+  .entered();
+  #[cfg(feature = "timing")]
+  let start = Instant::now();
+  #[cfg(feature = "tracing")]
+  let block_span = tracing::debug_span!("parse_blocks").entered();
+  block.parse();
+  #[cfg(feature = "tracing")]
+  drop(block_span);
+  let mut stats = block.opts.stats.then(|| Stats {
+    bytes: block.subject.len(),
+    matches: block.matches.len(),
+    warnings: block.warnings.len(),
+    ..Stats::default()
+  });
+  #[cfg(feature = "timing")]
+  let parsed = Instant::now();
+  let mut doc = tree::build(block);
+  let mut next_id = 1;
+  for child in &mut doc.children {
+    diff::assign_ids(child, &mut next_id)
+  }
+  #[cfg(feature = "tracing")]
+  span.record("blocks", count_blocks(&doc.children));
+  if let Some(stats) = &mut stats {
+    #[cfg(feature = "timing")]
+    {
+      stats.parse_time = parsed - start;
+      stats.build_time = parsed.elapsed();
+    }
+    stats.count_nodes(&doc.children);
+  }
+  doc.stats = stats;
+  if block.opts.source_positions {
+    doc.line_index = Some(LineIndex::new(&block.subject));
+  }
+  if block.budget.exceeded {
+    return Err(ParseError::BudgetExceeded { partial: Box::new(doc) });
+  }
+  if strict && !block.warnings.is_empty() {
+    return Err(ParseError::Warnings(strict_warnings(block)));
+  }
+  Ok(doc)
+}
+
+/// The warnings, as errors.
+fn strict_warnings(block: &mut block::Parser) -> Vec<Diagnostic> {
+  let mut res = std::mem::take(&mut block.warnings);
+  for it in &mut res {
+    it.severity = Severity::Error
+  }
+  res
+}
+
+/// The end of the longest prefix of `text` no longer than `len`.
+fn char_boundary_below(text: &str, len: usize) -> usize {
+  let mut end = len.min(text.len());
+  while !text.is_char_boundary(end) {
+    end -= 1
+  }
+  end
+}
+
+fn truncated(range: Range<usize>, limit: usize) -> Diagnostic {
+  let message = format!("input truncated to {limit} bytes");
+  Diagnostic::warning(range, message).with_code("truncated")
+}
+
+fn decode_invalid(bytes: &[u8], validation: Utf8Validation) -> Cow<'_, str> {
+  match validation {
+    Utf8Validation::Latin1 => Cow::Owned(bytes.iter().map(|&b| b as char).collect()),
+    _ => String::from_utf8_lossy(bytes),
   }
 }
 
+/// The longest input accepted by the parser, in bytes. Source positions are
+/// stored as `u32`, which keeps the parser's buffers small, and a final
+/// newline may need to be added.
+pub const MAX_INPUT_LEN: usize = u32::MAX as usize - 1;
+
+/// How to treat input which is not valid UTF-8.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Utf8Validation {
+  /// Reject the input.
+  #[default]
+  Strict,
+  /// Replace invalid sequences with `U+FFFD`.
+  Lossy,
+  /// Decode the whole input as Latin-1 (ISO 8859-1), which is what most
+  /// legacy files that aren't UTF-8 turn out to be.
+  Latin1,
+}
+
 #[derive(Default, Clone)]
 pub struct ParseOpts {
   pub debug_matches: bool,
+  /// Only parse the block structure, leaving the content of paragraphs and
+  /// headings as [`ast::Unparsed`] nodes. They are parsed when rendered, or
+  /// up front with [`Document::parse_inlines`].
+  pub lazy_inlines: bool,
+  /// Fill in [`Document::stats`]. Phase timings additionally require the
+  /// `timing` feature.
+  pub stats: bool,
+  /// Reject input longer than this many bytes, e.g. to bound the memory
+  /// used for user-submitted documents. Always capped at [`MAX_INPUT_LEN`].
+  /// Only the `try_*` methods reject it, the others truncate it.
+  pub max_input_bytes: Option<usize>,
+  /// Instead of rejecting input which is too long, only parse the part
+  /// which fits and report the rest with a warning. [`Parser::parse`] and
+  /// the other methods which can't fail always do this.
+  pub truncate_input: bool,
+  /// Give up after roughly this many steps of the block and inline parsers,
+  /// so that hostile input cannot keep a worker busy. Each line and each
+  /// inline token takes a step. See [`ParseError::BudgetExceeded`].
+  pub max_steps: Option<u64>,
+  /// Give up when parsing takes longer than this.
+  pub time_limit: Option<Duration>,
+  /// Limits how deeply block containers (like block quotes) and inline
+  /// containers (like emphasis or links) can nest. Markers beyond the limit
+  /// are treated as text, and reported with a warning.
+  pub max_nesting: Option<usize>,
+  /// Turn block quotes starting with a GitHub style `[!NOTE]`, `[!TIP]`,
+  /// `[!IMPORTANT]`, `[!WARNING]` or `[!CAUTION]` line into
+  /// [`ast::Callout`]s, for content migrated from Markdown.
+  pub github_alerts: bool,
+  /// Parse `*[HTML]: HyperText Markup Language` lines as abbreviation
+  /// definitions, marking up every occurrence of the term as an
+  /// [`ast::Abbreviation`].
+  pub abbreviations: bool,
+  /// Parse `[@key]`, `[@key, locator]` and `@key` as [`ast::Citation`]s.
+  /// See [`HtmlOpts::citation_resolver`] and [`HtmlOpts::bibliography`] for
+  /// rendering them.
+  pub citations: bool,
+  /// Parse citations of identifiers in the document, like `[@intro]` for
+  /// the section titled "intro", as [`ast::CrossReference`]s, rendered as
+  /// the section number (see [`HtmlOpts::number_headings`]) or title.
+  pub cross_references: bool,
+  /// Replace sequences like `->` or `(c)` in text with the characters they
+  /// stand for.
+  pub typography: Typography,
+  /// Fail with [`ParseError::Warnings`] when the input produces warnings,
+  /// like an unclosed code block, rather than parsing it as well as
+  /// possible. Only the methods returning a `Result`, like
+  /// [`Document::try_parse_opts`], fail; [`Document::parse_opts`] and the
+  /// other panicking ones ignore this. [`validate`] reports the warnings as
+  /// errors.
+  pub strict: bool,
+  /// Keep the lines of the source in [`Document::line_index`], to map the
+  /// [`ast::Tag::span`]s to lines and columns, e.g. for editor tooling or
+  /// the `data-pos` attributes of [`HtmlOpts::include_positions`].
+  pub source_positions: bool,
 }
 
+/// Classes of typographic substitutions, see [`ParseOpts::typography`] and
+/// [`Document::substitute_typography`]. Code, verbatim text and URLs are
+/// left alone.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Typography {
+  /// `->`, `<-`, `<->`, `=>` and `<=>` as arrows.
+  pub arrows: bool,
+  /// `<=`, `>=`, `!=` and `+-` as `≤`, `≥`, `≠` and `±`.
+  pub math: bool,
+  /// `1/2`, `1/3`, `2/3`, `1/4` and `3/4` as fractions, when not part of a
+  /// longer number.
+  pub fractions: bool,
+  /// `(c)`, `(r)` and `(tm)` as `©`, `®` and `™`.
+  pub symbols: bool,
+}
+
+impl Typography {
+  /// All substitutions.
+  pub fn all() -> Typography {
+    Typography { arrows: true, math: true, fractions: true, symbols: true }
+  }
+}
+
+#[cfg(feature = "html")]
 #[derive(Default, Clone)]
-pub struct HtmlOpts {}
+pub struct HtmlOpts {
+  /// Prefix headings with their section number, see
+  /// [`Document::number_headings`].
+  pub number_headings: bool,
+  /// Wrap each section in a `<section>` element labelled by its heading.
+  pub sections: bool,
+  /// Leave out the identifier each heading gets from its section (generated
+  /// from the heading text unless the heading has its own) when not
+  /// rendering [`HtmlOpts::sections`]. Links to the heading, like
+  /// `[Intro][]`, then have no target.
+  pub no_heading_ids: bool,
+  /// Shift all rendered heading levels by this amount, clamping the result
+  /// to `h1`..`h6`. Useful when embedding output under an existing `<h1>`.
+  pub heading_offset: i32,
+  /// Omit the cosmetic newlines between block level elements.
+  pub compact: bool,
+  /// Custom rendering for `:alias:` symbols.
+  pub symbol_resolver: Option<SymbolResolver>,
+  /// Text for `:alias:` symbols, e.g. `😄` for `smile`, used when the
+  /// [`HtmlOpts::symbol_resolver`] gives nothing.
+  pub symbols: HashMap<String, String>,
+  /// Custom rendering for code blocks, typically syntax highlighting.
+  pub highlighter: Option<CodeHighlighter>,
+  /// Number the lines of code blocks. Blocks with a `start="10"` attribute
+  /// are numbered from that line regardless, and the lines listed in a
+  /// `highlight="3,5-7"` attribute (counting from the first line of the
+  /// block) are marked up as highlighted.
+  pub line_numbers: bool,
+  /// What to do with bidi control and other invisible characters, which
+  /// can make the rendered text read differently from the source.
+  pub invisible_chars: InvisibleChars,
+  /// Text generated by the renderer itself, English by default.
+  pub labels: Labels,
+  /// Give the footnotes section an `aria-label` from [`Labels::footnotes`].
+  /// Off by default, like in djot.lua.
+  pub label_footnotes: bool,
+  /// Base direction of the text, for right-to-left documents. Blocks
+  /// starting with text in the other direction get a `dir` attribute (unless
+  /// they already have one), and so does code in right-to-left text.
+  pub dir: Option<TextDirection>,
+  /// Render images without alt text and links without text with a
+  /// placeholder from [`Labels`], so that they stand out in previews. See
+  /// [`lint::MissingAltText`] and [`lint::EmptyLinks`].
+  pub a11y_placeholders: bool,
+  /// Custom rendering for citations, typically looking the key up in a
+  /// bibliography.
+  pub citation_resolver: Option<CitationResolver>,
+  /// Append a `<div id="refs">` listing the entries for the keys cited in
+  /// the document, in order of first citation. Keys without an entry are
+  /// left out.
+  pub bibliography: Option<BibliographyResolver>,
+  /// Give the terms marked for the index (see [`IndexEntry`]) their
+  /// identifiers and append an index linking back to them.
+  pub index: bool,
+  /// Number the captions of figures and start the document with a list of
+  /// the figures linking to them.
+  pub list_of_figures: bool,
+  /// Render the checkboxes of task list items enabled rather than
+  /// `disabled`, with a `data-task-index` attribute holding the byte offset
+  /// of the item in the source, so that an editor can toggle the task and
+  /// write the change back.
+  pub interactive_tasks: bool,
+  /// Render a complete HTML page rather than a fragment, titled by the
+  /// first heading.
+  pub standalone: bool,
+  /// Add metadata for search engines and link previews to the head of
+  /// [`HtmlOpts::standalone`] pages.
+  pub seo: Option<SeoOpts>,
+  /// Give elements a `data-pos` attribute like `1:1-2:5`, the one-based
+  /// lines and columns of their first and last character, for documents
+  /// parsed with [`ParseOpts::source_positions`].
+  pub include_positions: bool,
+}
+
+/// See [`HtmlOpts::seo`]. The description and image default to the
+/// [excerpt](Document::excerpt) and the first image of the document.
+#[cfg(feature = "html")]
+#[derive(Debug, Default, Clone)]
+pub struct SeoOpts {
+  pub description: Option<String>,
+  pub image: Option<String>,
+  /// Canonical URL of the page.
+  pub url: Option<String>,
+  pub site_name: Option<String>,
+  /// `@username` of the site on Twitter.
+  pub twitter_site: Option<String>,
+}
+
+/// See [`HtmlOpts::dir`].
+#[cfg(feature = "html")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextDirection {
+  Ltr,
+  Rtl,
+}
+
+#[cfg(feature = "html")]
+impl TextDirection {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      TextDirection::Ltr => "ltr",
+      TextDirection::Rtl => "rtl",
+    }
+  }
+}
+
+/// Options for [`Document::to_text`].
+#[derive(Default, Clone)]
+pub struct TextOpts {
+  /// Wrap lines longer than this many characters. Code blocks are left as
+  /// they are.
+  pub width: Option<usize>,
+  /// Break words which don't fit on a line at soft hyphens (`U+00AD`),
+  /// hyphens, and wherever [`TextOpts::hyphenator`] allows, rather than
+  /// moving them to the next line whole.
+  pub hyphenate: bool,
+  /// Language-aware hyphenation, see [`Hyphenator`].
+  pub hyphenator: Option<Hyphenator>,
+}
+
+/// Options for [`Document::to_latex`].
+///
+/// Fragments use commands from the `graphicx`, `hyperref`, `ulem`
+/// (with `normalem`), `xcolor`, `amssymb` and `alltt` packages, which
+/// complete documents load.
+#[cfg(feature = "latex")]
+#[derive(Debug, Default, Clone)]
+pub struct LatexOpts {
+  /// Render a complete document with a `\documentclass` and the packages
+  /// rather than a fragment to `\input`.
+  pub standalone: bool,
+}
+
+/// Returns the byte offsets at which a word may be hyphenated, e.g. using
+/// a dictionary for the document's language.
+pub type Hyphenator = Arc<dyn Fn(&str) -> Vec<usize> + Send + Sync>;
+
+/// Headings and titles generated by the HTML renderer, see
+/// [`HtmlOpts::labels`]. Translate them for documents which aren't in
+/// English:
+///
+/// ```
+/// let labels = djot::Labels { footnotes: "Notes".to_string(), ..djot::Labels::default() };
+/// ```
+#[cfg(feature = "html")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Labels {
+  /// Label of the footnotes section at the end of the document, see
+  /// [`HtmlOpts::label_footnotes`].
+  pub footnotes: String,
+  /// Prefix of the numbers of referenced sections, see
+  /// [`ParseOpts::cross_references`].
+  pub section: String,
+  /// Prefix of figure numbers and title of the list of figures, see
+  /// [`HtmlOpts::list_of_figures`].
+  pub figure: String,
+  pub figures: String,
+  /// Heading of the index, see [`HtmlOpts::index`].
+  pub index: String,
+  /// Titles of the `NOTE`, `TIP`, `IMPORTANT`, `WARNING` and `CAUTION`
+  /// callouts.
+  pub note: String,
+  pub tip: String,
+  pub important: String,
+  pub warning: String,
+  pub caution: String,
+  /// Placeholders for [`HtmlOpts::a11y_placeholders`].
+  pub missing_alt_text: String,
+  pub empty_link: String,
+}
+
+#[cfg(feature = "html")]
+impl Default for Labels {
+  fn default() -> Labels {
+    Labels {
+      footnotes: "Footnotes".to_string(),
+      section: "Section".to_string(),
+      figure: "Figure".to_string(),
+      figures: "Figures".to_string(),
+      index: "Index".to_string(),
+      note: "Note".to_string(),
+      tip: "Tip".to_string(),
+      important: "Important".to_string(),
+      warning: "Warning".to_string(),
+      caution: "Caution".to_string(),
+      missing_alt_text: "Missing alt text".to_string(),
+      empty_link: "Empty link".to_string(),
+    }
+  }
+}
+
+/// See [`HtmlOpts::invisible_chars`] and [`lint::InvisibleCharacters`].
+#[cfg(feature = "html")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvisibleChars {
+  #[default]
+  Keep,
+  Strip,
+  /// Show them as `<U+202E>`.
+  Escape,
+}
+
+#[derive(Clone)]
+pub struct ExcerptOpts {
+  pub format: ExcerptFormat,
+  /// Class marking the end of the excerpt, `more` by default.
+  pub marker: String,
+}
+
+impl Default for ExcerptOpts {
+  fn default() -> ExcerptOpts {
+    ExcerptOpts { format: ExcerptFormat::Text, marker: "more".to_string() }
+  }
+}
+
+#[derive(Clone)]
+#[non_exhaustive]
+#[allow(clippy::large_enum_variant)]
+pub enum ExcerptFormat {
+  /// Plain text, with blocks separated by blank lines.
+  Text,
+  /// An HTML fragment rendered with the given options.
+  #[cfg(feature = "html")]
+  Html(HtmlOpts),
+}
+
+/// Maps a symbol alias (without the surrounding colons) to the HTML to emit
+/// in its place. Returning `None` falls back to the default
+/// `<span class="symbol">:alias:</span>` rendering.
+#[cfg(feature = "html")]
+pub type SymbolResolver = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// Renders a code block given its text and language, returning the HTML to
+/// emit instead of the default `<pre><code>` block. Returning `None` falls
+/// back to the default rendering.
+#[cfg(feature = "html")]
+pub type CodeHighlighter = Arc<dyn Fn(&str, Option<&str>) -> Option<String> + Send + Sync>;
+
+/// Renders a citation, returning the HTML to emit in its place. Returning
+/// `None` falls back to the default rendering, a link to the key's entry in
+/// the bibliography.
+#[cfg(feature = "html")]
+pub type CitationResolver = Arc<dyn Fn(&ast::Citation) -> Option<String> + Send + Sync>;
+
+/// Maps a citation key to the HTML of its bibliography entry.
+#[cfg(feature = "html")]
+pub type BibliographyResolver = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+// Public types are shared across worker threads, make sure they stay so.
+const _: () = {
+  fn assert_send_sync<T: Send + Sync>() {}
+  #[allow(unused)]
+  fn assert_all() {
+    assert_send_sync::<Document>();
+    assert_send_sync::<ast::Tag>();
+    assert_send_sync::<ParseOpts>();
+    #[cfg(feature = "html")]
+    assert_send_sync::<HtmlOpts>();
+    #[cfg(feature = "html")]
+    assert_send_sync::<Html<'_>>();
+    assert_send_sync::<Parser>();
+  }
+};
 
 #[derive(Debug, Clone, Copy)]
 struct Match {
-  s: usize,
-  e: usize,
+  s: u32,
+  e: u32,
   a: Annot,
 }
 
 impl Match {
   fn new(range: Range<usize>, a: impl Into<Annot>) -> Match {
-    Match { s: range.start, e: range.end, a: a.into() }
+    // inputs longer than `MAX_INPUT_LEN` are rejected before parsing
+    Match { s: range.start as u32, e: range.end as u32, a: a.into() }
+  }
+  fn start(&self) -> usize {
+    self.s as usize
+  }
+  fn end(&self) -> usize {
+    self.e as usize
+  }
+  fn range(&self) -> Range<usize> {
+    self.start()..self.end()
   }
   fn is(&self, annot: impl Into<Annot>) -> bool {
     self.a == annot.into()
@@ -73,3 +1135,33 @@ impl Match {
     !self.is(annot)
   }
 }
+
+#[cfg(feature = "tracing")]
+fn count_blocks(tags: &[ast::Tag]) -> usize {
+  let count = |tag: &ast::Tag| match tag.kind {
+    ast::TagKind::Section(_)
+    | ast::TagKind::BlockQuote(_)
+    | ast::TagKind::Callout(_)
+    | ast::TagKind::List(_)
+    | ast::TagKind::ListItem(_)
+    | ast::TagKind::TaskListItem(_)
+    | ast::TagKind::DefinitionList(_)
+    | ast::TagKind::DefinitionListItem(_)
+    | ast::TagKind::Definition(_)
+    | ast::TagKind::Footnote(_)
+    | ast::TagKind::Div(_)
+    | ast::TagKind::Figure(_) => 1 + count_blocks(&tag.children),
+    ast::TagKind::Heading(_)
+    | ast::TagKind::Para(_)
+    | ast::TagKind::Term(_)
+    | ast::TagKind::CodeBlock(_)
+    | ast::TagKind::RawBlock(_)
+    | ast::TagKind::ThematicBreak(_)
+    | ast::TagKind::Caption(_)
+    | ast::TagKind::Table(_)
+    | ast::TagKind::ReferenceDefinition(_)
+    | ast::TagKind::AbbreviationDefinition(_) => 1,
+    _ => 0,
+  };
+  tags.iter().map(count).sum()
+}
@@ -0,0 +1,126 @@
+use std::{collections::HashMap, ops::Range};
+
+/// Zero-based line and column.
+///
+/// Whether `col` counts UTF-8 bytes or UTF-16 code units depends on the
+/// [`LineIndex`] method which produced (or consumes) it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+  pub line: u32,
+  pub col: u32,
+}
+
+/// Maps byte offsets in a source text to line/column positions and back.
+///
+/// Built once per source, lookups are logarithmic in the number of lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineIndex {
+  len: usize,
+  line_starts: Vec<usize>,
+  /// Multi-byte characters per line, used to convert columns to UTF-16.
+  wide_chars: HashMap<u32, Vec<WideChar>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct WideChar {
+  start: u32,
+  end: u32,
+}
+
+impl WideChar {
+  fn len(&self) -> u32 {
+    self.end - self.start
+  }
+  fn len_utf16(&self) -> u32 {
+    if self.len() == 4 {
+      2
+    } else {
+      1
+    }
+  }
+}
+
+impl LineIndex {
+  pub fn new(text: &str) -> LineIndex {
+    let mut line_starts = vec![0];
+    let mut wide_chars: HashMap<u32, Vec<WideChar>> = HashMap::new();
+    for (i, c) in text.char_indices() {
+      if c == '\n' {
+        line_starts.push(i + 1);
+      } else if c.len_utf8() > 1 {
+        let line = line_starts.len() as u32 - 1;
+        let start = (i - line_starts.last().unwrap()) as u32;
+        let end = start + c.len_utf8() as u32;
+        wide_chars.entry(line).or_default().push(WideChar { start, end });
+      }
+    }
+    LineIndex { len: text.len(), line_starts, wide_chars }
+  }
+
+  /// The positions of the start and the end of a byte range, `None` for
+  /// empty ranges and ones past the end of the text.
+  pub(crate) fn range(&self, range: Range<usize>) -> Option<Range<LineCol>> {
+    if range.is_empty() || range.end > self.len {
+      return None;
+    }
+    Some(self.line_col(range.start)..self.line_col(range.end))
+  }
+
+  /// Formats a byte range like `1:1-2:5`, with one-based lines and columns
+  /// from the first to the last character, not counting final line breaks.
+  #[cfg(any(feature = "html", feature = "serde"))]
+  pub(crate) fn format_range(&self, range: Range<usize>) -> Option<String> {
+    let start = self.range(range.clone())?.start;
+    let mut end = range.end;
+    while end > range.start + 1 && self.line_col(end).col == 0 {
+      end -= 1
+    }
+    let end = self.line_col(end);
+    Some(format!("{}:{}-{}:{}", start.line + 1, start.col + 1, end.line + 1, end.col))
+  }
+
+  /// Converts a byte offset to a line and a byte column.
+  ///
+  /// Panics if `offset` is past the end of the text.
+  pub fn line_col(&self, offset: usize) -> LineCol {
+    assert!(offset <= self.len, "offset {offset} out of bounds (len {})", self.len);
+    let line = self.line_starts.partition_point(|&it| it <= offset) - 1;
+    LineCol { line: line as u32, col: (offset - self.line_starts[line]) as u32 }
+  }
+
+  /// Converts a byte offset to a line and a UTF-16 column, as used by LSP
+  /// and JavaScript based editors.
+  pub fn line_col_utf16(&self, offset: usize) -> LineCol {
+    let LineCol { line, col } = self.line_col(offset);
+    let mut res = col;
+    for c in self.wide_chars.get(&line).map_or(&[][..], |it| it.as_slice()) {
+      if c.end > col {
+        break;
+      }
+      res -= c.len() - c.len_utf16();
+    }
+    LineCol { line, col: res }
+  }
+
+  /// Converts a line and a byte column back to a byte offset, `None` if the
+  /// position is outside of the text.
+  pub fn offset(&self, line_col: LineCol) -> Option<usize> {
+    let line = line_col.line as usize;
+    let start = *self.line_starts.get(line)?;
+    let end = self.line_starts.get(line + 1).copied().unwrap_or(self.len);
+    let offset = start + line_col.col as usize;
+    (offset <= end).then_some(offset)
+  }
+
+  /// Converts a line and a UTF-16 column back to a byte offset.
+  pub fn offset_utf16(&self, line_col: LineCol) -> Option<usize> {
+    let LineCol { line, mut col } = line_col;
+    for c in self.wide_chars.get(&line).map_or(&[][..], |it| it.as_slice()) {
+      if c.start >= col {
+        break;
+      }
+      col += c.len() - c.len_utf16();
+    }
+    self.offset(LineCol { line, col })
+  }
+}
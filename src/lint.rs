@@ -0,0 +1,326 @@
+//! Style checks over djot sources.
+//!
+//! ```
+//! let diagnostics = djot::lint::lint("# One\n\n### Three\n");
+//! assert_eq!(diagnostics[0].code, Some("heading-increment"));
+//! ```
+
+use std::{collections::HashSet, ops::Range};
+
+use crate::{
+  ast::{Tag, TagKind},
+  escape::invisible_char_kind,
+  tree::get_string_content,
+  Diagnostic, Document,
+};
+
+/// A single check, producing diagnostics for a parsed document.
+pub trait Rule: Send + Sync {
+  /// Kebab-case name, used as the code of the produced diagnostics.
+  fn name(&self) -> &'static str;
+  fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<Diagnostic>);
+}
+
+/// The document being linted together with its source.
+pub struct Context<'a> {
+  pub source: &'a str,
+  pub doc: &'a Document,
+}
+
+impl Context<'_> {
+  /// Calls `f` for every node of the document, parents before children.
+  pub fn walk(&self, f: &mut dyn FnMut(&Tag)) {
+    fn go(tag: &Tag, f: &mut dyn FnMut(&Tag)) {
+      f(tag);
+      for child in &tag.children {
+        go(child, f)
+      }
+    }
+    for tag in &self.doc.children {
+      go(tag, f)
+    }
+  }
+
+  /// Source lines outside of code blocks, with their byte ranges (excluding
+  /// the line terminator).
+  pub fn prose_lines(&self) -> Vec<Range<usize>> {
+    let mut code = Vec::new();
+    self.walk(&mut |tag| {
+      if let TagKind::CodeBlock(_) | TagKind::RawBlock(_) = tag.kind {
+        code.push(tag.span.clone())
+      }
+    });
+    let mut res = Vec::new();
+    let mut start = 0;
+    for line in self.source.split_inclusive('\n') {
+      let range = start..start + line.trim_end_matches(['\r', '\n']).len();
+      start += line.len();
+      if !code.iter().any(|it| it.start <= range.start && range.end <= it.end) {
+        res.push(range)
+      }
+    }
+    res
+  }
+}
+
+/// A set of rules to run.
+pub struct Linter {
+  rules: Vec<Box<dyn Rule>>,
+}
+
+impl Default for Linter {
+  /// All built-in rules with their default settings.
+  fn default() -> Linter {
+    Linter::new()
+      .rule(HeadingIncrement)
+      .rule(DuplicateIds)
+      .rule(BareUrls)
+      .rule(LongLines::default())
+      .rule(MissingAltText)
+      .rule(EmptyLinks)
+      .rule(BrokenAnchors::default())
+      .rule(TrailingWhitespace)
+      .rule(InvisibleCharacters)
+  }
+}
+
+impl Linter {
+  /// A linter without any rules.
+  pub fn new() -> Linter {
+    Linter { rules: Vec::new() }
+  }
+
+  pub fn rule(mut self, rule: impl Rule + 'static) -> Linter {
+    self.rules.push(Box::new(rule));
+    self
+  }
+
+  pub fn check(&self, source: &str) -> Vec<Diagnostic> {
+    self.check_document(source, &Document::parse(source))
+  }
+
+  /// Checks an already parsed document, `doc` must be parsed from `source`.
+  pub fn check_document(&self, source: &str, doc: &Document) -> Vec<Diagnostic> {
+    let cx = Context { source, doc };
+    let mut res = Vec::new();
+    for rule in &self.rules {
+      let start = res.len();
+      rule.check(&cx, &mut res);
+      for diagnostic in &mut res[start..] {
+        diagnostic.code.get_or_insert(rule.name());
+      }
+    }
+    res.sort_by_key(|it| (it.range.start, it.range.end));
+    res
+  }
+}
+
+/// Checks the source with all built-in rules.
+pub fn lint(source: &str) -> Vec<Diagnostic> {
+  Linter::default().check(source)
+}
+
+/// Heading levels should only increase one at a time.
+pub struct HeadingIncrement;
+
+impl Rule for HeadingIncrement {
+  fn name(&self) -> &'static str {
+    "heading-increment"
+  }
+  fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    let mut prev = 0;
+    cx.walk(&mut |tag| {
+      if let TagKind::Heading(heading) = &tag.kind {
+        if heading.level > prev + 1 {
+          let message = format!("heading level jumps from {prev} to {}", heading.level);
+          diagnostics.push(Diagnostic::warning(tag.span.clone(), message))
+        }
+        prev = heading.level
+      }
+    })
+  }
+}
+
+/// Identifiers must be unique within the document.
+pub struct DuplicateIds;
+
+impl Rule for DuplicateIds {
+  fn name(&self) -> &'static str {
+    "duplicate-ids"
+  }
+  fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    let mut seen = HashSet::new();
+    cx.walk(&mut |tag| {
+      if let Some(id) = tag.get_attr("id") {
+        if !seen.insert(id.to_string()) {
+          let message = format!("duplicate identifier `{id}`");
+          diagnostics.push(Diagnostic::error(tag.span.clone(), message))
+        }
+      }
+    })
+  }
+}
+
+/// URLs in plain text should be written as `<https://...>` autolinks.
+pub struct BareUrls;
+
+impl Rule for BareUrls {
+  fn name(&self) -> &'static str {
+    "bare-urls"
+  }
+  fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    cx.walk(&mut |tag| {
+      let TagKind::Str(_) = tag.kind else { return };
+      let Some(text) = cx.source.get(tag.span.clone()) else { return };
+      for scheme in ["http://", "https://"] {
+        for (offset, _) in text.match_indices(scheme) {
+          let len = text[offset..].find(char::is_whitespace).unwrap_or(text.len() - offset);
+          let start = tag.span.start + offset;
+          let message = "bare URL, use an autolink (`<url>`) instead";
+          diagnostics.push(Diagnostic::warning(start..start + len, message))
+        }
+      }
+    })
+  }
+}
+
+/// Lines (outside of code blocks) should not exceed `max` characters.
+pub struct LongLines {
+  pub max: usize,
+}
+
+impl Default for LongLines {
+  fn default() -> LongLines {
+    LongLines { max: 120 }
+  }
+}
+
+impl Rule for LongLines {
+  fn name(&self) -> &'static str {
+    "long-lines"
+  }
+  fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    for line in cx.prose_lines() {
+      let len = cx.source[line.clone()].chars().count();
+      if len > self.max {
+        let message = format!("line is {len} characters long, the maximum is {}", self.max);
+        diagnostics.push(Diagnostic::warning(line, message))
+      }
+    }
+  }
+}
+
+/// Images need a description for readers who can't see them, either as
+/// their text or as an `alt` attribute. See also
+/// [`HtmlOpts::a11y_placeholders`](crate::HtmlOpts::a11y_placeholders).
+pub struct MissingAltText;
+
+impl Rule for MissingAltText {
+  fn name(&self) -> &'static str {
+    "missing-alt-text"
+  }
+  fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    cx.walk(&mut |tag| {
+      if let TagKind::Image(_) = tag.kind {
+        if !has_alt_text(tag) {
+          diagnostics.push(Diagnostic::warning(tag.span.clone(), "image without alt text"))
+        }
+      }
+    })
+  }
+}
+
+pub(crate) fn has_alt_text(image: &Tag) -> bool {
+  !get_string_content(image).trim().is_empty()
+    || image.get_attr("alt").is_some_and(|it| !it.trim().is_empty())
+}
+
+/// Links without text give screen reader users nothing to go by.
+pub struct EmptyLinks;
+
+impl Rule for EmptyLinks {
+  fn name(&self) -> &'static str {
+    "empty-links"
+  }
+  fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    cx.walk(&mut |tag| {
+      if let TagKind::Link(_) = tag.kind {
+        if get_string_content(tag).trim().is_empty() {
+          diagnostics.push(Diagnostic::warning(tag.span.clone(), "link without text"))
+        }
+      }
+    })
+  }
+}
+
+/// Links to `#fragment`s must point at an identifier in the document,
+/// like the one of a section, or at one of `known_ids`, e.g. identifiers
+/// provided by the page template.
+#[derive(Default)]
+pub struct BrokenAnchors {
+  pub known_ids: HashSet<String>,
+}
+
+impl Rule for BrokenAnchors {
+  fn name(&self) -> &'static str {
+    "broken-anchors"
+  }
+  fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    let mut ids = HashSet::new();
+    cx.walk(&mut |tag| {
+      if let Some(id) = tag.id() {
+        ids.insert(id.to_string());
+      }
+    });
+    cx.walk(&mut |tag| {
+      let TagKind::Link(link) = &tag.kind else { return };
+      let Some(id) = link.destination.as_deref().and_then(|it| it.strip_prefix('#')) else {
+        return;
+      };
+      // `#` alone links to the top of the page
+      if !id.is_empty() && !ids.contains(id) && !self.known_ids.contains(id) {
+        let message = format!("link to missing identifier `{id}`");
+        diagnostics.push(Diagnostic::warning(tag.span.clone(), message))
+      }
+    })
+  }
+}
+
+/// Trailing whitespace does not produce a hard break in djot (unlike
+/// Markdown), a backslash at the end of the line does.
+pub struct TrailingWhitespace;
+
+impl Rule for TrailingWhitespace {
+  fn name(&self) -> &'static str {
+    "trailing-whitespace"
+  }
+  fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    for line in cx.prose_lines() {
+      let text = &cx.source[line.clone()];
+      let trimmed = text.trim_end_matches([' ', '\t']);
+      if !trimmed.is_empty() && trimmed.len() < text.len() {
+        let message = "trailing whitespace, use `\\` at the end of the line for a hard break";
+        diagnostics.push(Diagnostic::warning(line.start + trimmed.len()..line.end, message))
+      }
+    }
+  }
+}
+
+/// Bidi control characters and invisible characters, which can make the
+/// rendered text (or code) read differently from the source. See also
+/// [`HtmlOpts::invisible_chars`](crate::HtmlOpts::invisible_chars).
+pub struct InvisibleCharacters;
+
+impl Rule for InvisibleCharacters {
+  fn name(&self) -> &'static str {
+    "invisible-characters"
+  }
+  fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    for (idx, c) in cx.source.char_indices() {
+      if let Some(kind) = invisible_char_kind(c) {
+        let message = format!("{kind} U+{:04X}", c as u32);
+        diagnostics.push(Diagnostic::warning(idx..idx + c.len_utf8(), message))
+      }
+    }
+  }
+}
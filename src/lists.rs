@@ -0,0 +1,122 @@
+//! List marker styles: `-`, `*` and `+` for bullet lists, `1.`, `a)`,
+//! `(I)` and so on for ordered ones.
+
+const ROMAN: &[(u64, &str)] = &[
+  (1000, "m"),
+  (900, "cm"),
+  (500, "d"),
+  (400, "cd"),
+  (100, "c"),
+  (90, "xc"),
+  (50, "l"),
+  (40, "xl"),
+  (10, "x"),
+  (9, "ix"),
+  (5, "v"),
+  (4, "iv"),
+  (1, "i"),
+];
+
+/// The styles a list item marker may belong to, two for ambiguous markers
+/// like `i.`, which is either roman or alphabetic.
+pub(crate) fn styles(marker: &str) -> Vec<String> {
+  if !is_ordered(marker) {
+    return vec![marker.to_string()];
+  }
+  let body = number(marker);
+  let style = |it: &str| marker.replacen(body, it, 1);
+  let is_roman = |c: char| "ivxlcdm".contains(c.to_ascii_lowercase());
+  let upper = body.starts_with(|c: char| c.is_ascii_uppercase());
+  let (roman, alpha) = if upper { ("I", "A") } else { ("i", "a") };
+  if body.chars().all(|c| c.is_ascii_digit()) {
+    vec![style("1")]
+  } else if body.chars().all(is_roman) && body.len() == 1 {
+    vec![style(roman), style(alpha)]
+  } else if body.chars().all(is_roman) {
+    vec![style(roman)]
+  } else {
+    vec![style(alpha)]
+  }
+}
+
+/// Whether `marker` starts an ordered list item, like `1.`, `a)` or `(iv)`.
+pub(crate) fn is_ordered(marker: &str) -> bool {
+  let body = number(marker);
+  let wrapped = [format!("{body}."), format!("{body})"), format!("({body})")];
+  let is_roman = |c: char| "ivxlcdmIVXLCDM".contains(c);
+  wrapped.contains(&marker.to_string())
+    && (!body.is_empty() && body.chars().all(|c| c.is_ascii_digit())
+      || !body.is_empty() && body.chars().all(is_roman)
+      || body.len() == 1 && body.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+/// The number of the item with the given marker in a list of `style`, or
+/// `None` for bullets.
+pub(crate) fn start(marker: &str, style: &str) -> Option<u64> {
+  let body = number(marker);
+  match number(style) {
+    "1" => body.parse().ok(),
+    "a" | "A" => Some(u64::from(body.to_ascii_lowercase().as_bytes()[0] - b'a') + 1),
+    "i" | "I" => Some(roman_value(&body.to_ascii_lowercase())),
+    _ => None,
+  }
+}
+
+/// The marker of the item numbered `n` in a list of `style`.
+pub(crate) fn marker(style: &str, n: u64) -> String {
+  let digits = match number(style) {
+    "1" => n.to_string(),
+    "a" => alpha(n),
+    "A" => alpha(n).to_ascii_uppercase(),
+    "i" => roman(n),
+    "I" => roman(n).to_ascii_uppercase(),
+    _ => return style.to_string(),
+  };
+  style.replacen(number(style), &digits, 1)
+}
+
+/// The `type` attribute of an HTML ordered list, `1` for decimal numbers.
+#[cfg(feature = "html")]
+pub(crate) fn html_type(style: &str) -> &str {
+  number(style)
+}
+
+fn number(marker: &str) -> &str {
+  marker.trim_start_matches('(').trim_end_matches(['.', ')'])
+}
+
+fn roman_value(s: &str) -> u64 {
+  let value = |c: char| ROMAN.iter().find(|(_, it)| it.len() == 1 && it.starts_with(c)).unwrap().0;
+  let mut res = 0;
+  let mut chars = s.chars().peekable();
+  while let Some(c) = chars.next() {
+    match chars.peek() {
+      Some(&next) if value(next) > value(c) => res -= value(c) as i64,
+      _ => res += value(c) as i64,
+    }
+  }
+  res.max(0) as u64
+}
+
+fn roman(mut n: u64) -> String {
+  let mut res = String::new();
+  for &(value, digits) in ROMAN {
+    while n >= value {
+      res.push_str(digits);
+      n -= value;
+    }
+  }
+  res
+}
+
+/// `a`..`z`, then `aa`, `ab` and so on.
+fn alpha(mut n: u64) -> String {
+  let mut res = Vec::new();
+  while n > 0 {
+    n -= 1;
+    res.push(b'a' + (n % 26) as u8);
+    n /= 26;
+  }
+  res.reverse();
+  String::from_utf8(res).unwrap()
+}
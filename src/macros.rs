@@ -0,0 +1,124 @@
+/// Builds an AST fragment, mostly useful in tests and generators:
+///
+/// ```
+/// use djot::djot_ast;
+///
+/// let para = djot_ast! { para ["Hello ", emph { class = "x" } ["world"], softbreak, "!"] };
+/// let doc = djot::Document::new(vec![para]);
+/// # #[cfg(feature = "html")]
+/// assert_eq!(doc.to_html(), "<p>Hello <em class=\"x\">world</em>\n!</p>\n");
+/// ```
+///
+/// A node is written as its kind in `snake_case`, followed by optional
+/// arguments for kinds carrying data (`heading(2)`, `link("url")`,
+/// `verbatim("code")`, ...), optional attributes in braces and optional
+/// children in brackets. String literals stand for `str` nodes and
+/// parenthesized expressions are spliced in as they are.
+#[macro_export]
+macro_rules! djot_ast {
+  ($text:literal) => {
+    $crate::ast::Tag::new($crate::ast::Str::new($text))
+  };
+  (($tag:expr)) => {
+    $tag
+  };
+  ($kind:ident $(($($args:tt)*))? $({ $($attrs:tt)* })? $([ $($children:tt)* ])?) => {{
+    #[allow(unused_mut)]
+    let mut attrs = $crate::ast::Attrs::new();
+    $($crate::djot_ast!(@attrs attrs $($attrs)*);)?
+    $crate::ast::Tag::new($crate::djot_ast!(@kind $kind $(($($args)*))?))
+      .with_attrs(attrs)
+      .with_children($crate::djot_ast!(@list [] $($($children)*)?))
+  }};
+
+  (@list [$($acc:expr,)*]) => {
+    ::std::vec![$($acc),*]
+  };
+  (@list [$($acc:expr,)*] $text:literal $(, $($rest:tt)*)?) => {
+    $crate::djot_ast!(@list [$($acc,)* $crate::djot_ast!($text),] $($($rest)*)?)
+  };
+  (@list [$($acc:expr,)*] ($tag:expr) $(, $($rest:tt)*)?) => {
+    $crate::djot_ast!(@list [$($acc,)* $tag,] $($($rest)*)?)
+  };
+  (@list [$($acc:expr,)*]
+    $kind:ident $(($($args:tt)*))? $({ $($attrs:tt)* })? $([ $($children:tt)* ])?
+    $(, $($rest:tt)*)?
+  ) => {
+    $crate::djot_ast!(@list [
+      $($acc,)*
+      $crate::djot_ast!($kind $(($($args)*))? $({ $($attrs)* })? $([ $($children)* ])?),
+    ] $($($rest)*)?)
+  };
+
+  (@attrs $attrs:ident $($key:tt = $value:expr),* $(,)?) => {
+    $($attrs.insert($crate::djot_ast!(@key $key).to_string(), ($value).to_string());)*
+  };
+  (@key $key:ident) => { stringify!($key) };
+  (@key $key:literal) => { $key };
+
+  (@kind doc) => { $crate::ast::Doc::default() };
+  (@kind section) => { $crate::ast::Section::default() };
+  (@kind heading($level:expr)) => { $crate::ast::Heading::new($level) };
+  (@kind para) => { $crate::ast::Para::default() };
+  (@kind thematic_break) => { $crate::ast::ThematicBreak::default() };
+  (@kind block_quote) => { $crate::ast::BlockQuote::default() };
+  (@kind callout($kind:expr)) => { $crate::ast::Callout::new(($kind).to_string()) };
+  (@kind list($style:expr)) => { $crate::ast::List::new(($style).to_string()) };
+  (@kind list_item) => { $crate::ast::ListItem::default() };
+  (@kind task_list_item($checked:expr)) => { $crate::ast::TaskListItem::new($checked) };
+  (@kind definition_list) => { $crate::ast::DefinitionList::new(true) };
+  (@kind definition_list($tight:expr)) => { $crate::ast::DefinitionList::new($tight) };
+  (@kind definition_list_item) => { $crate::ast::DefinitionListItem::default() };
+  (@kind term) => { $crate::ast::Term::default() };
+  (@kind definition) => { $crate::ast::Definition::default() };
+  (@kind figure) => { $crate::ast::Figure::default() };
+  (@kind caption) => { $crate::ast::Caption::default() };
+  (@kind table) => { $crate::ast::Table::default() };
+  (@kind row) => { $crate::ast::Row::default() };
+  (@kind row($head:expr)) => { $crate::ast::Row::new($head) };
+  (@kind cell) => { $crate::ast::Cell::default() };
+  (@kind cell($head:expr, $align:expr)) => { $crate::ast::Cell::new($head, $align) };
+  (@kind footnote($label:expr)) => { $crate::ast::Footnote::new(($label).to_string()) };
+  (@kind div) => { $crate::ast::Div::default() };
+  (@kind link($destination:expr)) => { $crate::ast::Link::new(($destination).to_string()) };
+  (@kind image($destination:expr)) => { $crate::ast::Image::new(($destination).to_string()) };
+  (@kind code_block($text:expr)) => { $crate::ast::CodeBlock::new(($text).to_string()) };
+  (@kind code_block($lang:expr, $text:expr)) => {
+    $crate::ast::CodeBlock::new(($text).to_string()).with_lang(($lang).to_string())
+  };
+  (@kind raw_block($format:expr, $text:expr)) => {
+    $crate::ast::RawBlock::new(($format).to_string(), ($text).to_string())
+  };
+  (@kind strong) => { $crate::ast::Strong::default() };
+  (@kind emph) => { $crate::ast::Emph::default() };
+  (@kind subscript) => { $crate::ast::Subscript::default() };
+  (@kind superscript) => { $crate::ast::Superscript::default() };
+  (@kind insert) => { $crate::ast::Insert::default() };
+  (@kind delete) => { $crate::ast::Delete::default() };
+  (@kind highlight) => { $crate::ast::Highlight::default() };
+  (@kind span) => { $crate::ast::Span::default() };
+  (@kind single_quoted) => { $crate::ast::SingleQuoted::default() };
+  (@kind double_quoted) => { $crate::ast::DoubleQuoted::default() };
+  (@kind verbatim($text:expr)) => { $crate::ast::Verbatim::new(($text).to_string()) };
+  (@kind raw_inline($format:expr, $text:expr)) => {
+    $crate::ast::RawInline::new(($format).to_string(), ($text).to_string())
+  };
+  (@kind inline_math($text:expr)) => { $crate::ast::InlineMath::new(($text).to_string()) };
+  (@kind display_math($text:expr)) => { $crate::ast::DisplayMath::new(($text).to_string()) };
+  (@kind softbreak) => { $crate::ast::Softbreak::default() };
+  (@kind hard_break) => { $crate::ast::HardBreak::default() };
+  (@kind non_breaking_space) => { $crate::ast::NonBreakingSpace::default() };
+  (@kind citation($key:expr)) => { $crate::ast::Citation::new(($key).to_string()) };
+  (@kind cross_reference($target:expr)) => {
+    $crate::ast::CrossReference::new(($target).to_string())
+  };
+  (@kind footnote_reference($label:expr)) => {
+    $crate::ast::FootnoteReference::new(($label).to_string())
+  };
+  (@kind smart_punctuation($kind:expr)) => {
+    $crate::ast::SmartPunctuation::new(($kind).to_string())
+  };
+  (@kind symbol($alias:expr)) => { $crate::ast::Symbol::new(($alias).to_string()) };
+  (@kind url($destination:expr)) => { $crate::ast::Url::new(($destination).to_string()) };
+  (@kind str($text:expr)) => { $crate::ast::Str::new($text) };
+}
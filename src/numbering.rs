@@ -0,0 +1,32 @@
+use crate::ast::{has_class, Attrs, Tag, TagKind};
+
+/// Running section counters, advanced once per heading in document order.
+#[derive(Default)]
+pub(crate) struct HeadingNumbers {
+  counts: Vec<u32>,
+}
+
+impl HeadingNumbers {
+  /// Returns the number for the next heading, or `None` if the heading opts
+  /// out with the `unnumbered` class.
+  pub(crate) fn next(&mut self, attrs: &Attrs, level: u32) -> Option<String> {
+    if has_class(attrs, "unnumbered") {
+      return None;
+    }
+    let level = level.max(1) as usize;
+    self.counts.resize(level, 0);
+    self.counts[level - 1] += 1;
+    let first = self.counts.iter().position(|&it| it != 0).unwrap_or(0);
+    let parts: Vec<String> = self.counts[first..].iter().map(|it| it.to_string()).collect();
+    Some(parts.join("."))
+  }
+}
+
+pub(crate) fn number_headings(tags: &mut [Tag], numbers: &mut HeadingNumbers) {
+  for tag in tags {
+    if let TagKind::Heading(heading) = &mut tag.kind {
+      heading.number = numbers.next(&tag.attrs, heading.level);
+    }
+    number_headings(&mut tag.children, numbers)
+  }
+}
@@ -32,14 +32,16 @@ pub fn find(subject: &str, pat: &'static str) -> PatMatch {
 }
 
 pub fn find_at(subject: &str, pat: &'static str, start: usize) -> PatMatch {
-  let mut pat = lua_patterns::LuaPattern::new(pat);
+  let pat = terminated(pat);
+  let mut pat = lua_patterns::LuaPattern::from_bytes(&pat[..pat.len() - 1]);
   let is_match = pat.matches(&subject[start..]);
   let range = pat.range();
   PatMatch { start: range.start + start, end: range.end + start, is_match, cap1: 0..0, cap2: 0..0 }
 }
 
 pub fn capture_at<'a>(subject: &'a str, pat: &'static str, start: usize) -> PatMatch {
-  let mut pat = lua_patterns::LuaPattern::new(pat);
+  let pat = terminated(pat);
+  let mut pat = lua_patterns::LuaPattern::from_bytes(&pat[..pat.len() - 1]);
   let is_match = pat.matches(&subject[start..]);
   let range = pat.range();
   let cap1 = pat.capture(1);
@@ -50,3 +52,12 @@ pub fn capture_at<'a>(subject: &'a str, pat: &'static str, start: usize) -> PatM
 pub(crate) fn is_space(c: char) -> bool {
   " \n\t".contains(c)
 }
+
+// `lua_patterns` looks one byte past the end of the pattern for a `*`, `+`,
+// `-` or `?` suffix, so the pattern must end with a NUL like in C.
+fn terminated(pat: &str) -> Vec<u8> {
+  let mut res = Vec::with_capacity(pat.len() + 1);
+  res.extend_from_slice(pat.as_bytes());
+  res.push(0);
+  res
+}
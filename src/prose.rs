@@ -0,0 +1,148 @@
+use std::ops::Range;
+
+use crate::{
+  ast::{Tag, TagKind},
+  tree::parse_inlines,
+};
+
+/// A run of human-language text, e.g. for spellchecking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProseSegment {
+  /// The text with markup removed.
+  pub text: String,
+  /// Byte range of the source text spanned by the segment, including any
+  /// markup within it.
+  pub range: Range<usize>,
+  // (offset in `text`, offset in source) for each contiguous piece
+  pieces: Vec<(usize, usize)>,
+}
+
+impl ProseSegment {
+  /// Maps a byte offset in [`ProseSegment::text`] to the corresponding
+  /// offset in the source text.
+  pub fn source_offset(&self, offset: usize) -> usize {
+    let idx = self.pieces.partition_point(|&(text, _)| text <= offset).saturating_sub(1);
+    match self.pieces.get(idx) {
+      Some(&(text, source)) => source + (offset - text),
+      None => self.range.start,
+    }
+  }
+
+  fn push(&mut self, text: &str, source: usize) {
+    if self.pieces.is_empty() {
+      self.range = source..source;
+    }
+    self.pieces.push((self.text.len(), source));
+    self.text.push_str(text);
+    self.range.end = source + text.len();
+  }
+}
+
+pub(crate) fn segments(tags: &[Tag]) -> Vec<ProseSegment> {
+  let mut ctx = Ctx { res: Vec::new(), current: None };
+  for tag in tags {
+    ctx.visit(tag)
+  }
+  ctx.flush();
+  ctx.res
+}
+
+struct Ctx {
+  res: Vec<ProseSegment>,
+  current: Option<ProseSegment>,
+}
+
+impl Ctx {
+  fn visit(&mut self, tag: &Tag) {
+    match &tag.kind {
+      TagKind::Str(str) => self.push(&str.text, tag.span.start),
+      TagKind::Softbreak(_) | TagKind::HardBreak(_) => self.push("\n", tag.span.start),
+      TagKind::NonBreakingSpace(_) => self.push("\u{a0}", tag.span.start),
+      TagKind::SmartPunctuation(punctuation) => self.push(punctuation.text(), tag.span.start),
+      TagKind::Unparsed(_) => {
+        let mut tags = vec![tag.clone()];
+        parse_inlines(&mut tags);
+        for tag in &tags {
+          self.visit(tag)
+        }
+      }
+      // not prose, and breaks up the surrounding text
+      TagKind::Verbatim(_)
+      | TagKind::RawInline(_)
+      | TagKind::InlineMath(_)
+      | TagKind::DisplayMath(_)
+      | TagKind::Url(_)
+      | TagKind::Symbol(_)
+      | TagKind::Citation(_)
+      | TagKind::CrossReference(_)
+      | TagKind::FootnoteReference(_)
+      | TagKind::CodeBlock(_)
+      | TagKind::RawBlock(_)
+      | TagKind::ThematicBreak(_)
+      | TagKind::ReferenceDefinition(_)
+      | TagKind::AbbreviationDefinition(_)
+      | TagKind::ReferenceKey(_)
+      | TagKind::ReferenceValue(_) => self.flush(),
+      TagKind::Doc(_)
+      | TagKind::Section(_)
+      | TagKind::Heading(_)
+      | TagKind::Para(_)
+      | TagKind::BlockQuote(_)
+      | TagKind::Callout(_)
+      | TagKind::List(_)
+      | TagKind::ListItem(_)
+      | TagKind::TaskListItem(_)
+      | TagKind::DefinitionList(_)
+      | TagKind::DefinitionListItem(_)
+      | TagKind::Term(_)
+      | TagKind::Definition(_)
+      | TagKind::Figure(_)
+      | TagKind::Caption(_)
+      | TagKind::Table(_)
+      | TagKind::Row(_)
+      | TagKind::Cell(_)
+      | TagKind::Footnote(_)
+      | TagKind::Div(_) => {
+        self.flush();
+        self.visit_children(tag);
+        self.flush()
+      }
+      TagKind::Link(_)
+      | TagKind::Image(_)
+      | TagKind::Strong(_)
+      | TagKind::Emph(_)
+      | TagKind::Subscript(_)
+      | TagKind::Superscript(_)
+      | TagKind::Insert(_)
+      | TagKind::Delete(_)
+      | TagKind::Highlight(_)
+      | TagKind::Span(_)
+      | TagKind::Abbreviation(_)
+      | TagKind::SingleQuoted(_)
+      | TagKind::DoubleQuoted(_) => self.visit_children(tag),
+    }
+  }
+
+  fn visit_children(&mut self, tag: &Tag) {
+    for child in &tag.children {
+      self.visit(child)
+    }
+  }
+
+  fn push(&mut self, text: &str, source: usize) {
+    let current = self.current.get_or_insert_with(|| ProseSegment {
+      text: String::new(),
+      range: 0..0,
+      pieces: Vec::new(),
+    });
+    current.push(text, source)
+  }
+
+  fn flush(&mut self) {
+    if let Some(segment) = self.current.take() {
+      if !segment.text.trim().is_empty() {
+        self.res.push(segment)
+      }
+    }
+  }
+}
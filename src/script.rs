@@ -0,0 +1,99 @@
+//! Filters written in [Rhai](https://rhai.rs), enabled by the `rhai` feature.
+//!
+//! A filter script defines functions named after node kinds (`str`, `para`,
+//! `link`, `codeblock`, ...), which are called bottom-up on every node of
+//! that kind between parsing and rendering. Nodes are passed as object maps
+//! with the fields of the serialized [`Tag`]:
+//!
+//! ```
+//! let filter = djot::script::ScriptFilter::new(r#"
+//!   fn image(node) {
+//!     node.destination = "/static/" + node.destination;
+//!     node
+//!   }
+//! "#).unwrap();
+//! let mut doc = djot::Document::parse("![cat](cat.png)");
+//! filter.apply(&mut doc).unwrap();
+//! assert_eq!(doc.to_html(), "<p><img alt=\"cat\" src=\"/static/cat.png\"></p>\n");
+//! ```
+//!
+//! Returning a node replaces the original one, returning an array replaces
+//! it with any number of nodes (so `[]` deletes it), and returning `()`
+//! keeps it unchanged.
+
+use std::{error, fmt};
+
+use rhai::{Dynamic, Engine, Scope, AST};
+
+use crate::{ast::Tag, Document};
+
+/// A compiled filter script.
+pub struct ScriptFilter {
+  engine: Engine,
+  ast: AST,
+}
+
+/// A script which failed to compile or run.
+#[derive(Debug, Clone)]
+pub struct ScriptError {
+  message: String,
+}
+
+impl fmt::Display for ScriptError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+
+impl error::Error for ScriptError {}
+
+impl From<rhai::ParseError> for ScriptError {
+  fn from(err: rhai::ParseError) -> ScriptError {
+    ScriptError { message: err.to_string() }
+  }
+}
+
+impl From<Box<rhai::EvalAltResult>> for ScriptError {
+  fn from(err: Box<rhai::EvalAltResult>) -> ScriptError {
+    ScriptError { message: err.to_string() }
+  }
+}
+
+impl ScriptFilter {
+  pub fn new(script: &str) -> Result<ScriptFilter, ScriptError> {
+    let engine = Engine::new();
+    let ast = engine.compile(script)?;
+    Ok(ScriptFilter { engine, ast })
+  }
+
+  pub fn apply(&self, doc: &mut Document) -> Result<(), ScriptError> {
+    self.apply_children(&mut doc.children)
+  }
+
+  fn apply_children(&self, tags: &mut Vec<Tag>) -> Result<(), ScriptError> {
+    for mut tag in std::mem::take(tags) {
+      self.apply_children(&mut tag.children)?;
+      let name = tag.kind.name().to_lowercase();
+      if !self.ast.iter_functions().any(|it| it.name == name && it.params.len() == 1) {
+        tags.push(tag);
+        continue;
+      }
+      let node = rhai::serde::to_dynamic(&tag)?;
+      let res: Dynamic = self.engine.call_fn(&mut Scope::new(), &self.ast, &name, (node,))?;
+      if res.is_unit() {
+        tags.push(tag)
+      } else if res.is_array() {
+        for node in res.into_array().unwrap_or_default() {
+          tags.push(rhai::serde::from_dynamic(&node)?)
+        }
+      } else {
+        let mut new: Tag = rhai::serde::from_dynamic(&res)?;
+        // a modified node still stands for the same source
+        new.id = tag.id;
+        new.span = tag.span;
+        tags.push(new)
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,65 @@
+use crate::{
+  ast::{Tag, TagKind},
+  tree::get_string_content,
+};
+
+/// The plain text of one section, ready to be fed to a full text search
+/// index. Serializes to a flat JSON object.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SearchRecord {
+  /// Identifier of the section, `None` for content before the first
+  /// heading.
+  pub id: Option<String>,
+  /// Titles of the enclosing headings, outermost first, ending with the
+  /// section's own title.
+  pub path: Vec<String>,
+  /// Text of the section, excluding its heading and subsections.
+  pub text: String,
+  /// Relative importance, taken from a `search-weight` attribute on the
+  /// section's heading.
+  #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+  pub weight: Option<f32>,
+}
+
+pub(crate) fn records(children: &[Tag]) -> Vec<SearchRecord> {
+  let mut res = Vec::new();
+  collect(None, &mut Vec::new(), None, children, &mut res);
+  res
+}
+
+fn collect(
+  id: Option<&str>,
+  path: &mut Vec<String>,
+  weight: Option<f32>,
+  children: &[Tag],
+  acc: &mut Vec<SearchRecord>,
+) {
+  let idx = acc.len();
+  let mut text = String::new();
+  for child in children {
+    match &child.kind {
+      TagKind::Heading(_) => (),
+      TagKind::Section(_) => {
+        let heading = child.children.iter().find(|it| matches!(it.kind, TagKind::Heading(_)));
+        let weight = heading.and_then(|it| it.get_attr("search-weight")?.parse().ok());
+        path.push(heading.map(get_string_content).unwrap_or_default());
+        collect(child.get_attr("id"), path, weight, &child.children, acc);
+        path.pop();
+      }
+      _ => {
+        let block = get_string_content(child);
+        if !block.trim().is_empty() {
+          if !text.is_empty() {
+            text.push('\n');
+          }
+          text.push_str(block.trim_end())
+        }
+      }
+    }
+  }
+  if id.is_some() || !text.is_empty() {
+    let record = SearchRecord { id: id.map(|it| it.to_string()), path: path.clone(), text, weight };
+    acc.insert(idx, record)
+  }
+}
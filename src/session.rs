@@ -0,0 +1,38 @@
+use std::fmt::Write;
+
+use crate::{Document, HtmlOpts, ParseError, ParseOpts, Parser};
+
+/// Converts many documents in a row, e.g. all pages of a static site.
+///
+/// The parser's buffers and the HTML output string are kept between
+/// documents, so after the first few documents converting another one
+/// rarely allocates more than the tree itself.
+pub struct Session {
+  parser: Parser,
+  opts: HtmlOpts,
+  out: String,
+}
+
+impl Session {
+  pub fn new(parse_opts: ParseOpts, html_opts: HtmlOpts) -> Session {
+    Session { parser: Parser::new(parse_opts), opts: html_opts, out: String::new() }
+  }
+
+  pub fn parse(&mut self, text: &str) -> Result<Document, ParseError> {
+    self.parser.try_parse(text)
+  }
+
+  /// Converts `text` to HTML. The returned string is overwritten by the
+  /// next call.
+  pub fn to_html(&mut self, text: &str) -> Result<&str, ParseError> {
+    let doc = self.parser.try_parse(text)?;
+    Ok(self.render(&doc))
+  }
+
+  /// Renders an already parsed document, like [`Session::to_html`].
+  pub fn render(&mut self, doc: &Document) -> &str {
+    self.out.clear();
+    write!(self.out, "{}", doc.html(&self.opts)).unwrap();
+    &self.out
+  }
+}
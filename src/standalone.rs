@@ -0,0 +1,79 @@
+use std::fmt::{self, Write};
+
+use crate::{
+  ast::{Tag, TagKind},
+  escape::{escape_html_attr, escape_html_text},
+  Document, ExcerptOpts, HtmlOpts,
+};
+
+// Search engines cut descriptions off at about this many characters.
+const MAX_DESCRIPTION_LEN: usize = 160;
+
+/// The document head, up to and including `<body>`.
+pub(crate) fn write_head(opts: &HtmlOpts, doc: &Document, out: &mut dyn Write) -> fmt::Result {
+  out.write_str("<!DOCTYPE html>\n")?;
+  match opts.dir {
+    Some(dir) => writeln!(out, "<html dir=\"{}\">", dir.as_str())?,
+    None => out.write_str("<html>\n")?,
+  }
+  out.write_str("<head>\n<meta charset=\"utf-8\">\n")?;
+  let title = doc.title();
+  if let Some(title) = &title {
+    writeln!(out, "<title>{}</title>", escape_html_text(title))?;
+  }
+  if let Some(seo) = &opts.seo {
+    let description = seo.description.clone().unwrap_or_else(|| description(doc));
+    let image = seo.image.clone().or_else(|| find_image(&doc.children));
+    let mut meta = |attr: &str, key: &str, value: &str| {
+      writeln!(out, "<meta {attr}=\"{key}\" content=\"{}\">", escape_html_attr(value))
+    };
+    if !description.is_empty() {
+      meta("name", "description", &description)?;
+    }
+    meta("property", "og:type", "article")?;
+    if let Some(title) = &title {
+      meta("property", "og:title", title)?;
+    }
+    if !description.is_empty() {
+      meta("property", "og:description", &description)?;
+    }
+    if let Some(image) = &image {
+      meta("property", "og:image", image)?;
+    }
+    if let Some(url) = &seo.url {
+      meta("property", "og:url", url)?;
+    }
+    if let Some(site_name) = &seo.site_name {
+      meta("property", "og:site_name", site_name)?;
+    }
+    let card = if image.is_some() { "summary_large_image" } else { "summary" };
+    meta("name", "twitter:card", card)?;
+    if let Some(twitter_site) = &seo.twitter_site {
+      meta("name", "twitter:site", twitter_site)?;
+    }
+  }
+  out.write_str("</head>\n<body>\n")
+}
+
+pub(crate) fn write_foot(out: &mut dyn Write) -> fmt::Result {
+  out.write_str("</body>\n</html>\n")
+}
+
+/// The excerpt on a single line, shortened at a word boundary.
+fn description(doc: &Document) -> String {
+  let text = doc.excerpt(&ExcerptOpts::default());
+  let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+  if text.chars().count() <= MAX_DESCRIPTION_LEN {
+    return text;
+  }
+  let end = text.char_indices().nth(MAX_DESCRIPTION_LEN).map_or(text.len(), |(i, _)| i);
+  let end = text[..end].rfind(' ').unwrap_or(end);
+  format!("{}…", text[..end].trim_end_matches(|c: char| c.is_ascii_punctuation()))
+}
+
+fn find_image(tags: &[Tag]) -> Option<String> {
+  tags.iter().find_map(|tag| match &tag.kind {
+    TagKind::Image(image) => image.destination.clone(),
+    _ => find_image(&tag.children),
+  })
+}
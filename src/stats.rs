@@ -0,0 +1,33 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "timing")]
+use std::time::Duration;
+
+use crate::ast::Tag;
+
+/// Statistics about a parse, see [`ParseOpts::stats`](crate::ParseOpts::stats).
+#[derive(Debug, Default, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Stats {
+  /// Length of the input in bytes.
+  pub bytes: usize,
+  /// Number of matches produced by the block and inline parsers.
+  pub matches: usize,
+  pub warnings: usize,
+  /// Number of nodes in the tree by kind, see [`TagKind::name`](crate::ast::TagKind::name).
+  pub nodes: BTreeMap<&'static str, usize>,
+  /// Time spent producing matches.
+  #[cfg(feature = "timing")]
+  pub parse_time: Duration,
+  /// Time spent building the tree from the matches.
+  #[cfg(feature = "timing")]
+  pub build_time: Duration,
+}
+
+impl Stats {
+  pub(crate) fn count_nodes(&mut self, tags: &[Tag]) {
+    for tag in tags {
+      *self.nodes.entry(tag.kind.name()).or_default() += 1;
+      self.count_nodes(&tag.children)
+    }
+  }
+}
@@ -0,0 +1,70 @@
+//! A `djot` filter rendering markup to HTML in templates, enabled by the
+//! `minijinja` and `tera` features.
+//!
+//! ```ignore
+//! let mut env = minijinja::Environment::new();
+//! djot::template::register_minijinja(&mut env, djot::HtmlOpts::default());
+//! let html = env.render_str("{{ body | djot }}", minijinja::context! { body => "*hi*" })?;
+//! ```
+
+use crate::{Document, HtmlOpts};
+
+fn render(text: &str, opts: &HtmlOpts) -> String {
+  Document::parse(text).to_html_opts(opts)
+}
+
+/// Registers the `djot` filter and function with a MiniJinja environment.
+/// The output is marked safe, so it is not escaped again.
+#[cfg(feature = "minijinja")]
+pub fn register_minijinja(env: &mut minijinja::Environment<'_>, opts: HtmlOpts) {
+  use minijinja::value::Value;
+
+  let filter_opts = opts.clone();
+  env.add_filter("djot", move |text: &str| Value::from_safe_string(render(text, &filter_opts)));
+  env.add_function("djot", move |text: &str| Value::from_safe_string(render(text, &opts)));
+}
+
+/// Registers the `djot` filter and function with a Tera instance. The
+/// output is marked safe, so it is not escaped again.
+#[cfg(feature = "tera")]
+pub fn register_tera(tera: &mut tera::Tera, opts: HtmlOpts) {
+  tera.register_filter("djot", TeraDjot(opts.clone()));
+  tera.register_function("djot", TeraDjot(opts));
+}
+
+#[cfg(feature = "tera")]
+struct TeraDjot(HtmlOpts);
+
+#[cfg(feature = "tera")]
+impl tera::Filter for TeraDjot {
+  fn filter(
+    &self,
+    value: &tera::Value,
+    _args: &std::collections::HashMap<String, tera::Value>,
+  ) -> tera::Result<tera::Value> {
+    let text = value.as_str().ok_or("djot filter expects a string")?;
+    Ok(render(text, &self.0).into())
+  }
+
+  fn is_safe(&self) -> bool {
+    true
+  }
+}
+
+#[cfg(feature = "tera")]
+impl tera::Function for TeraDjot {
+  fn call(
+    &self,
+    args: &std::collections::HashMap<String, tera::Value>,
+  ) -> tera::Result<tera::Value> {
+    let text = args
+      .get("text")
+      .and_then(|it| it.as_str())
+      .ok_or("djot function expects a `text` string argument")?;
+    Ok(render(text, &self.0).into())
+  }
+
+  fn is_safe(&self) -> bool {
+    true
+  }
+}
@@ -0,0 +1,115 @@
+//! Generators of valid documents for property testing, enabled by the
+//! `testing` feature.
+//!
+//! ```
+//! use arbitrary::{Arbitrary, Unstructured};
+//!
+//! let mut u = Unstructured::new(&[7, 1, 3, 3, 7, 42, 0, 1, 2, 3]);
+//! let doc = djot::Document::arbitrary(&mut u).unwrap();
+//! let _html = doc.to_html();
+//! ```
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{
+  ast::{
+    CodeBlock, Delete, Emph, Heading, Highlight, Insert, Link, Para, Softbreak, Str, Strong,
+    Subscript, Superscript, Symbol, Tag, Verbatim,
+  },
+  Document,
+};
+
+/// Maximum nesting of inline containers.
+const MAX_DEPTH: u32 = 3;
+
+impl<'a> Arbitrary<'a> for Document {
+  fn arbitrary(u: &mut Unstructured<'a>) -> Result<Document> {
+    let mut children = Vec::new();
+    for _ in 0..u.int_in_range(0..=8)? {
+      children.push(block(u)?);
+    }
+    Ok(Document { children, ..Document::default() })
+  }
+}
+
+/// A non-empty sequence of inline nodes, as found in a paragraph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inlines(pub Vec<Tag>);
+
+impl<'a> Arbitrary<'a> for Inlines {
+  fn arbitrary(u: &mut Unstructured<'a>) -> Result<Inlines> {
+    Ok(Inlines(inlines(u, MAX_DEPTH)?))
+  }
+}
+
+fn block(u: &mut Unstructured<'_>) -> Result<Tag> {
+  let res = match u.int_in_range(0..=4)? {
+    0 => {
+      let level = u.int_in_range(1..=6)?;
+      Tag::new(Heading { level, number: None }).with_children(inlines(u, MAX_DEPTH)?)
+    }
+    1 => {
+      let lang = if u.arbitrary()? { Some(word(u)?) } else { None };
+      let mut text = String::new();
+      for _ in 0..u.int_in_range(0..=3)? {
+        text.push_str(&words(u)?);
+        text.push('\n');
+      }
+      Tag::new(CodeBlock { lang, text })
+    }
+    _ => Tag::new(Para {}).with_children(inlines(u, MAX_DEPTH)?),
+  };
+  Ok(res)
+}
+
+fn inlines(u: &mut Unstructured<'_>, depth: u32) -> Result<Vec<Tag>> {
+  let mut res = vec![inline(u, depth)?];
+  for _ in 0..u.int_in_range(0..=4)? {
+    res.push(inline(u, depth)?);
+  }
+  Ok(res)
+}
+
+fn inline(u: &mut Unstructured<'_>, depth: u32) -> Result<Tag> {
+  if depth == 0 {
+    return Ok(Tag::new(Str::new(words(u)?)));
+  }
+  let children = |u: &mut Unstructured<'_>| inlines(u, depth - 1);
+  let res = match u.int_in_range(0..=11)? {
+    0 => Tag::new(Emph {}).with_children(children(u)?),
+    1 => Tag::new(Strong {}).with_children(children(u)?),
+    2 => Tag::new(Subscript {}).with_children(children(u)?),
+    3 => Tag::new(Superscript {}).with_children(children(u)?),
+    4 => Tag::new(Insert {}).with_children(children(u)?),
+    5 => Tag::new(Delete {}).with_children(children(u)?),
+    6 => Tag::new(Highlight {}).with_children(children(u)?),
+    7 => Tag::new(Verbatim { text: words(u)? }),
+    8 => Tag::new(Symbol { alias: word(u)? }),
+    9 => {
+      let destination = Some(format!("https://example.com/{}", word(u)?));
+      // links can't nest
+      Tag::new(Link { destination, reference: None }).with_children(vec![inline(u, 0)?])
+    }
+    10 => Tag::new(Softbreak {}),
+    _ => Tag::new(Str::new(words(u)?)),
+  };
+  Ok(res)
+}
+
+fn words(u: &mut Unstructured<'_>) -> Result<String> {
+  let mut res = word(u)?;
+  for _ in 0..u.int_in_range(0..=3)? {
+    res.push(' ');
+    res.push_str(&word(u)?);
+  }
+  Ok(res)
+}
+
+fn word(u: &mut Unstructured<'_>) -> Result<String> {
+  const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+  let mut res = String::new();
+  for _ in 0..u.int_in_range(1..=8)? {
+    res.push(*u.choose(ALPHABET)? as char);
+  }
+  Ok(res)
+}
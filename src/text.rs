@@ -0,0 +1,107 @@
+use crate::{
+  ast::{Tag, TagKind},
+  Document, TextOpts,
+};
+
+const SOFT_HYPHEN: char = '\u{ad}';
+
+pub(crate) fn convert(doc: &Document, opts: &TextOpts) -> String {
+  let mut blocks = Vec::new();
+  collect_blocks(&doc.children, opts, &mut blocks);
+  blocks.iter().map(|it| format!("{}\n", it.trim_end())).collect::<Vec<_>>().join("\n")
+}
+
+fn collect_blocks(tags: &[Tag], opts: &TextOpts, acc: &mut Vec<String>) {
+  for tag in tags {
+    match tag.kind {
+      TagKind::Section(_) | TagKind::Figure(_) => collect_blocks(&tag.children, opts, acc),
+      TagKind::ReferenceDefinition(_)
+      | TagKind::AbbreviationDefinition(_)
+      | TagKind::RawBlock(_)
+      | TagKind::ThematicBreak(_) => (),
+      TagKind::CodeBlock(_) => acc.push(tag.text()),
+      _ => match opts.width {
+        Some(width) => acc.push(wrap(&tag.text(), width, opts)),
+        None => acc.push(tag.text().replace(SOFT_HYPHEN, "")),
+      },
+    }
+  }
+}
+
+/// Fills lines up to `width` characters, breaking words which don't fit
+/// when hyphenating.
+fn wrap(text: &str, width: usize, opts: &TextOpts) -> String {
+  let mut lines = Vec::new();
+  let mut line = String::new();
+  let mut line_len = 0;
+  for word in text.split_whitespace() {
+    let (mut word, mut breaks) = hyphenate(word, opts);
+    loop {
+      let sep = usize::from(line_len > 0);
+      let word_len = word.chars().count();
+      if line_len + sep + word_len <= width {
+        if sep == 1 {
+          line.push(' ')
+        }
+        line.push_str(&word);
+        line_len += sep + word_len;
+        break;
+      }
+      // the longest start of the word which fits, with its hyphen
+      let fits = breaks.iter().rev().find(|&&(offset, hyphen)| {
+        line_len + sep + word[..offset].chars().count() + usize::from(hyphen) <= width
+      });
+      if let Some(&(offset, hyphen)) = fits {
+        if sep == 1 {
+          line.push(' ')
+        }
+        line.push_str(&word[..offset]);
+        if hyphen {
+          line.push('-')
+        }
+        word = word[offset..].to_string();
+        breaks =
+          breaks.iter().filter(|it| it.0 > offset).map(|&(it, h)| (it - offset, h)).collect();
+      } else if line_len == 0 {
+        // too long, and no way to break it
+        line.push_str(&word);
+        line_len = word_len;
+        break;
+      }
+      lines.push(std::mem::take(&mut line));
+      line_len = 0;
+    }
+  }
+  if !line.is_empty() {
+    lines.push(line)
+  }
+  lines.join("\n")
+}
+
+/// Removes the soft hyphens from `word`, returning the byte offsets where
+/// it may be broken and whether a hyphen has to be added there.
+fn hyphenate(word: &str, opts: &TextOpts) -> (String, Vec<(usize, bool)>) {
+  let mut res = String::new();
+  let mut breaks = Vec::new();
+  for c in word.chars() {
+    match c {
+      SOFT_HYPHEN => breaks.push((res.len(), true)),
+      '-' => {
+        res.push(c);
+        breaks.push((res.len(), false))
+      }
+      _ => res.push(c),
+    }
+  }
+  if !opts.hyphenate {
+    return (res, Vec::new());
+  }
+  if let Some(hyphenator) = &opts.hyphenator {
+    let points = hyphenator(&res).into_iter().filter(|&it| res.is_char_boundary(it));
+    breaks.extend(points.map(|it| (it, true)))
+  }
+  breaks.retain(|&(it, _)| 0 < it && it < res.len());
+  breaks.sort();
+  breaks.dedup_by_key(|it| it.0);
+  (res, breaks)
+}
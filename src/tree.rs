@@ -1,65 +1,748 @@
 use crate::{
   annot::{Annot, Atom, Comp},
   ast::{
-    CodeBlock, Doc, DoubleQuoted, Emph, Image, Link, Para, ReferenceDefinition, ReferenceKey,
-    ReferenceValue, Softbreak, Span, Str, Strong, Tag, TagKind, Url, Verbatim,
+    Abbreviation, AbbreviationDefinition, Alignment, Attrs, BlockQuote, Callout, Caption, Cell,
+    Citation, CodeBlock, CrossReference, Definition, DefinitionList, DefinitionListItem, Delete,
+    DisplayMath, Div, Doc, DoubleQuoted, Emph, Figure, Footnote, FootnoteReference, HardBreak,
+    Heading, Highlight, Image, InlineMath, Insert, Link, List, ListItem, NonBreakingSpace, Para,
+    RawBlock, RawInline, ReferenceDefinition, ReferenceKey, ReferenceValue, Row, Section,
+    SingleQuoted, SmartPunctuation, Softbreak, Span, Str, Strong, Subscript, Superscript, Symbol,
+    Table, Tag, TagKind, TaskListItem, Term, ThematicBreak, Unparsed, Url, Verbatim,
   },
-  block,
+  attributes, block,
+  budget::Budget,
+  inline, lists,
   patterns::find,
-  Document, Match,
+  typography, Document, Match, ParseOpts,
 };
 
-pub(crate) fn build(p: block::Parser) -> Document {
-  let tag = Ctx { subject: p.subject, matches: p.matches, idx: 0 }.get_node(Comp::Doc);
-  Document { children: tag.children, debug: p.debug }
+use std::{
+  collections::{HashMap, HashSet},
+  ops::Range,
+};
+
+pub(crate) fn build(p: &mut block::Parser) -> Document {
+  #[cfg(feature = "tracing")]
+  let _span = tracing::debug_span!("resolve", matches = p.matches.len()).entered();
+  let mut children = std::mem::take(&mut p.blocks);
+  children.extend(build_blocks(p));
+  convert_definition_lists(&mut children);
+  attach_captions(&mut children);
+  if p.opts.github_alerts {
+    convert_alerts(&mut children)
+  }
+  if p.opts.abbreviations {
+    let mut definitions = Vec::new();
+    collect_abbreviations(&children, &mut definitions);
+    // longer terms win over their prefixes
+    definitions.sort_by_key(|(term, _)| std::cmp::Reverse(term.len()));
+    if !definitions.is_empty() {
+      mark_abbreviations(&mut children, &definitions)
+    }
+  }
+  if p.opts.citations || p.opts.cross_references {
+    mark_citations(&mut children)
+  }
+  typography::substitute(&mut children, &p.opts.typography);
+  // explicit identifiers are taken before any is generated
+  let mut identifiers = HashSet::new();
+  collect_ids(&children, &mut identifiers);
+  let mut children = sectionize(children, &mut identifiers);
+  assign_heading_ids(&mut children, &mut identifiers);
+  let references = references(&children);
+  resolve_references(&mut children, &references);
+  if p.opts.cross_references {
+    resolve_cross_references(&mut children, &identifiers, &p.subject, p.opts.citations)
+  }
+  Document { children, debug: std::mem::take(&mut p.debug), stats: None, line_index: None }
+}
+
+/// Builds the top level blocks parsed so far and drops their source, so
+/// that input read line by line isn't kept in memory as a whole. See
+/// [`block::Parser::can_drop_parsed`].
+pub(crate) fn build_parsed(p: &mut block::Parser) {
+  let blocks = build_blocks(p);
+  p.blocks.extend(blocks);
+  p.drop_parsed()
+}
+
+/// The blocks of the subject, their spans counting from the start of the
+/// input.
+fn build_blocks(p: &block::Parser) -> Vec<Tag> {
+  let tag = Ctx {
+    subject: &p.subject,
+    matches: &p.matches,
+    idx: 0,
+    blank: false,
+    loose: false,
+    separators: Vec::new(),
+    block_attrs: Attrs::new(),
+  }
+  .get_node(Comp::Doc, 0);
+  let mut children = tag.children;
+  if p.offset > 0 {
+    for child in &mut children {
+      shift_spans(child, p.offset)
+    }
+  }
+  children
+}
+
+/// Wraps images (paragraphs containing nothing else), block quotes and code
+/// blocks followed by a caption into figures. Captions of tables become
+/// their first child instead.
+fn attach_captions(tags: &mut Vec<Tag>) {
+  let mut i = 0;
+  while i < tags.len() {
+    attach_captions(&mut tags[i].children);
+    let is_caption =
+      |tag: Option<&Tag>| tag.is_some_and(|it| matches!(it.kind, TagKind::Caption(_)));
+    if matches!(tags[i].kind, TagKind::Table(_)) && is_caption(tags.get(i + 1)) {
+      let caption = tags.remove(i + 1);
+      tags[i].span.end = caption.span.end;
+      tags[i].children.insert(0, caption);
+    }
+    let captioned = i + 1 < tags.len()
+      && matches!(tags[i + 1].kind, TagKind::Caption(_))
+      && match &tags[i].kind {
+        TagKind::Para(_) => {
+          matches!(tags[i].children.as_slice(), [it] if matches!(it.kind, TagKind::Image(_)))
+        }
+        TagKind::BlockQuote(_) | TagKind::CodeBlock(_) => true,
+        _ => false,
+      };
+    if captioned {
+      let children: Vec<Tag> = tags.drain(i..i + 2).collect();
+      let mut figure = Tag::new(Figure {});
+      figure.span = children[0].span.start..children[1].span.end;
+      tags.insert(i, figure.with_children(children))
+    }
+    i += 1
+  }
+}
+
+/// Turns lists of `: term` items into definition lists, the first paragraph
+/// of each item being the term and the rest its definition.
+fn convert_definition_lists(tags: &mut [Tag]) {
+  for tag in tags {
+    convert_definition_lists(&mut tag.children);
+    let TagKind::List(list) = &tag.kind else { continue };
+    if list.style != ":" {
+      continue;
+    }
+    tag.kind = DefinitionList { tight: list.tight }.into();
+    for item in &mut tag.children {
+      let mut blocks = std::mem::take(&mut item.children).into_iter().peekable();
+      let mut term = Tag::new(Term {});
+      term.span = item.span.start..item.span.start;
+      if let Some(para) = blocks.next_if(|it| matches!(it.kind, TagKind::Para(_))) {
+        term.span = para.span.clone();
+        term.attrs = para.attrs;
+        term.children = para.children;
+      }
+      let mut definition = Tag::new(Definition {}).with_children(blocks.collect());
+      definition.span = match (definition.children.first(), definition.children.last()) {
+        (Some(first), Some(last)) => first.span.start..last.span.end,
+        _ => item.span.end..item.span.end,
+      };
+      item.kind = DefinitionListItem {}.into();
+      item.children = vec![term, definition];
+    }
+  }
+}
+
+/// Turns block quotes starting with a `[!NOTE]` line into callouts.
+fn convert_alerts(tags: &mut [Tag]) {
+  for tag in tags {
+    convert_alerts(&mut tag.children);
+    if !matches!(tag.kind, TagKind::BlockQuote(_)) {
+      continue;
+    }
+    let Some(para) = tag.children.first_mut().filter(|it| matches!(it.kind, TagKind::Para(_)))
+    else {
+      continue;
+    };
+    let end = para.children.iter().position(|it| !matches!(it.kind, TagKind::Str(_)));
+    let end = end.unwrap_or(para.children.len());
+    let leader: String = para.children[..end].iter().map(get_string_content).collect();
+    let kind = match leader.trim().to_ascii_lowercase().as_str() {
+      "[!note]" => "note",
+      "[!tip]" => "tip",
+      "[!important]" => "important",
+      "[!warning]" => "warning",
+      "[!caution]" => "caution",
+      _ => continue,
+    };
+    if end < para.children.len() && !matches!(para.children[end].kind, TagKind::Softbreak(_)) {
+      continue;
+    }
+    // the leader line and the line break after it
+    para.children.drain(..(end + 1).min(para.children.len()));
+    if para.children.is_empty() {
+      tag.children.remove(0);
+    }
+    tag.kind = Callout { kind: kind.to_string() }.into();
+  }
+}
+
+/// The destinations and attributes of reference definitions by their
+/// normalized label.
+pub(crate) type References = HashMap<String, (String, Attrs)>;
+
+/// The reference definitions of the document, and the headings, which can
+/// be referred to by their text unless a definition has the same label.
+pub(crate) fn references(tags: &[Tag]) -> References {
+  let mut res = References::new();
+  collect_references(tags, &mut res);
+  collect_heading_references(tags, &mut res);
+  res
+}
+
+fn collect_heading_references(tags: &[Tag], acc: &mut References) {
+  for tag in tags {
+    let heading = match &tag.kind {
+      TagKind::Section(_) => tag.children.first(),
+      TagKind::Heading(_) => Some(tag),
+      _ => None,
+    };
+    if let (Some(heading), Some(id)) = (heading, tag.id()) {
+      let label = normalize_label(&get_string_content(heading));
+      acc.entry(label).or_insert_with(|| (format!("#{id}"), Attrs::new()));
+    }
+    collect_heading_references(&tag.children, acc)
+  }
+}
+
+/// Later definitions win over earlier ones with the same label.
+fn collect_references(tags: &[Tag], acc: &mut References) {
+  for tag in tags {
+    if !matches!(tag.kind, TagKind::ReferenceDefinition(_)) {
+      collect_references(&tag.children, acc);
+      continue;
+    }
+    let mut label = None;
+    let mut destination = String::new();
+    for child in &tag.children {
+      match &child.kind {
+        TagKind::ReferenceKey(key) => label = Some(normalize_label(&key.text)),
+        TagKind::ReferenceValue(value) => destination.push_str(&value.text),
+        _ => (),
+      }
+    }
+    if let Some(label) = label {
+      acc.insert(label, (destination, tag.attrs.clone()));
+    }
+  }
+}
+
+/// Fills in the destinations of reference links and images, which also
+/// get the attributes of the definition they don't set themselves.
+pub(crate) fn resolve_references(tags: &mut [Tag], definitions: &References) {
+  for tag in tags {
+    resolve_references(&mut tag.children, definitions);
+    let reference = match &tag.kind {
+      TagKind::Link(link) => link.reference.as_deref(),
+      TagKind::Image(image) => image.reference.as_deref(),
+      _ => continue,
+    };
+    // `[text][]` refers to its own text
+    let label = match reference {
+      Some("") => normalize_label(&get_string_content(tag)),
+      Some(reference) => normalize_label(reference),
+      None => continue,
+    };
+    let Some((target, attrs)) = definitions.get(&label) else { continue };
+    match &mut tag.kind {
+      TagKind::Link(link) => link.destination = Some(target.clone()),
+      TagKind::Image(image) => image.destination = Some(target.clone()),
+      _ => unreachable!(),
+    }
+    for (key, value) in attrs.iter() {
+      match tag.attrs.get_mut(key) {
+        Some(classes) if key == "class" => {
+          let missing: Vec<&str> = value
+            .split_whitespace()
+            .filter(|it| !classes.split_whitespace().any(|c| c == *it))
+            .collect();
+          if !missing.is_empty() {
+            *classes = format!("{} {classes}", missing.join(" "))
+          }
+        }
+        Some(_) => (),
+        None => {
+          tag.attrs.insert(key.clone(), value.clone());
+        }
+      }
+    }
+  }
+}
+
+/// Labels match with any whitespace, including line breaks, collapsed.
+fn normalize_label(label: &str) -> String {
+  label.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn collect_abbreviations(tags: &[Tag], acc: &mut Vec<(String, String)>) {
+  for tag in tags {
+    match &tag.kind {
+      TagKind::AbbreviationDefinition(it) => acc.push((it.term.clone(), it.title.clone())),
+      _ => collect_abbreviations(&tag.children, acc),
+    }
+  }
+}
+
+/// Wraps the whole-word occurrences of the terms in text into
+/// `Abbreviation` nodes.
+fn mark_abbreviations(tags: &mut Vec<Tag>, definitions: &[(String, String)]) {
+  let mut i = 0;
+  while i < tags.len() {
+    match &tags[i].kind {
+      TagKind::Str(str) => {
+        let pieces = split_abbreviations(&str.text, tags[i].span.start, definitions);
+        let len = pieces.len();
+        if len > 1 {
+          tags.splice(i..i + 1, pieces);
+        }
+        i += len;
+      }
+      TagKind::CodeBlock(_)
+      | TagKind::RawBlock(_)
+      | TagKind::Verbatim(_)
+      | TagKind::RawInline(_)
+      | TagKind::InlineMath(_)
+      | TagKind::DisplayMath(_)
+      | TagKind::Url(_)
+      | TagKind::Abbreviation(_)
+      | TagKind::ReferenceDefinition(_) => i += 1,
+      _ => {
+        mark_abbreviations(&mut tags[i].children, definitions);
+        i += 1
+      }
+    }
+  }
+}
+
+fn split_abbreviations(text: &str, offset: usize, definitions: &[(String, String)]) -> Vec<Tag> {
+  let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+  let str = |start: usize, end: usize| {
+    let mut tag = Tag::new(Str::new(&text[start..end]));
+    tag.span = offset + start..offset + end;
+    tag
+  };
+  let mut res = Vec::new();
+  let mut last = 0;
+  let mut pos = 0;
+  while pos < text.len() {
+    let found = definitions.iter().find(|(term, _)| {
+      text[pos..].starts_with(term.as_str())
+        && !is_word(text[..pos].chars().next_back())
+        && !is_word(text[pos + term.len()..].chars().next())
+    });
+    match found {
+      Some((term, title)) => {
+        if last < pos {
+          res.push(str(last, pos))
+        }
+        let mut abbreviation = Tag::new(Abbreviation { title: title.clone() });
+        abbreviation.span = offset + pos..offset + pos + term.len();
+        abbreviation.children.push(str(pos, pos + term.len()));
+        res.push(abbreviation);
+        pos += term.len();
+        last = pos;
+      }
+      None => pos += text[pos..].chars().next().map_or(1, char::len_utf8),
+    }
+  }
+  if last < text.len() || res.is_empty() {
+    res.push(str(last, text.len()))
+  }
+  res
+}
+
+/// Replaces `[@key, locator]` and `@key` in text with `Citation` nodes.
+fn mark_citations(tags: &mut Vec<Tag>) {
+  let mut i = 0;
+  while i < tags.len() {
+    match &tags[i].kind {
+      TagKind::Str(str) => {
+        let pieces = split_citations(&str.text, tags[i].span.start);
+        let len = pieces.len();
+        if len > 1 || !matches!(pieces[0].kind, TagKind::Str(_)) {
+          tags.splice(i..i + 1, pieces);
+        }
+        i += len;
+      }
+      TagKind::CodeBlock(_)
+      | TagKind::RawBlock(_)
+      | TagKind::Verbatim(_)
+      | TagKind::RawInline(_)
+      | TagKind::InlineMath(_)
+      | TagKind::DisplayMath(_)
+      | TagKind::Url(_)
+      | TagKind::Link(_)
+      | TagKind::Image(_)
+      | TagKind::ReferenceDefinition(_) => i += 1,
+      _ => {
+        mark_citations(&mut tags[i].children);
+        i += 1
+      }
+    }
+  }
+}
+
+fn split_citations(text: &str, offset: usize) -> Vec<Tag> {
+  let str = |start: usize, end: usize| {
+    let mut tag = Tag::new(Str::new(&text[start..end]));
+    tag.span = offset + start..offset + end;
+    tag
+  };
+  let mut res = Vec::new();
+  let mut last = 0;
+  let mut pos = 0;
+  while let Some(at) = text[pos..].find('@').map(|it| pos + it) {
+    pos = at + 1;
+    let key_len = citation_key_len(&text[at + 1..]);
+    if key_len == 0 {
+      continue;
+    }
+    let key = &text[at + 1..at + 1 + key_len];
+    let mut citation = Citation::new(key);
+    let (mut start, mut end) = (at, at + 1 + key_len);
+    if at > 0 && text[..at].ends_with('[') {
+      let rest = &text[end..];
+      let Some(close) = rest.find(']') else { continue };
+      let locator = rest[..close].trim_start_matches(',').trim();
+      if rest[..close].contains('[') || !(locator.is_empty() || rest.starts_with([',', ' '])) {
+        continue;
+      }
+      citation.locator = Some(locator.to_string()).filter(|it| !it.is_empty());
+      citation.bracketed = true;
+      (start, end) = (at - 1, end + close + 1);
+    } else if text[..at].chars().next_back().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+      // an email address
+      continue;
+    }
+    if last < start {
+      res.push(str(last, start))
+    }
+    let mut tag = Tag::new(citation);
+    tag.span = offset + start..offset + end;
+    res.push(tag);
+    (pos, last) = (end, end);
+  }
+  if last < text.len() || res.is_empty() {
+    res.push(str(last, text.len()))
+  }
+  res
+}
+
+/// Keys start with a letter, digit or `_`, and may contain `:`, `.`, `-`
+/// and `/` when followed by one of those, so that trailing punctuation is
+/// not part of the key.
+fn citation_key_len(text: &str) -> usize {
+  let is_key = |c: char| c.is_alphanumeric() || c == '_';
+  let mut len = 0;
+  let mut chars = text.char_indices().peekable();
+  while let Some((i, c)) = chars.next() {
+    let next_is_key = chars.peek().is_some_and(|&(_, c)| is_key(c));
+    if is_key(c) || (len > 0 && matches!(c, ':' | '.' | '-' | '/') && next_is_key) {
+      len = i + c.len_utf8()
+    } else {
+      break;
+    }
+  }
+  len
+}
+
+fn collect_ids(tags: &[Tag], acc: &mut HashSet<String>) {
+  for tag in tags {
+    if let Some(id) = tag.id() {
+      acc.insert(id.to_string());
+    }
+    collect_ids(&tag.children, acc)
+  }
+}
+
+/// Turns citations of identifiers in the document into cross references,
+/// and the remaining ones back into text unless citations are enabled.
+fn resolve_cross_references(
+  tags: &mut Vec<Tag>,
+  ids: &HashSet<String>,
+  subject: &str,
+  citations: bool,
+) {
+  let mut reverted = false;
+  for tag in tags.iter_mut() {
+    resolve_cross_references(&mut tag.children, ids, subject, citations);
+    let TagKind::Citation(citation) = &tag.kind else { continue };
+    if ids.contains(&citation.key) && citation.locator.is_none() {
+      let target = citation.key.clone();
+      tag.kind = CrossReference { target, bracketed: citation.bracketed }.into();
+    } else if !citations {
+      tag.kind = Str::new(&subject[tag.span.clone()]).into();
+      reverted = true;
+    }
+  }
+  if reverted {
+    // join the text split up around the citation again
+    tags.dedup_by(|next, prev| match (&mut prev.kind, &next.kind) {
+      (TagKind::Str(prev_str), TagKind::Str(next_str)) if prev.span.end == next.span.start => {
+        prev_str.text.push_str(&next_str.text);
+        prev.span.end = next.span.end;
+        true
+      }
+      _ => false,
+    })
+  }
+}
+
+/// The keys of all citations, in order of first citation.
+pub(crate) fn citation_keys(tags: &[Tag], acc: &mut Vec<String>) {
+  for tag in tags {
+    match &tag.kind {
+      TagKind::Citation(citation) if !acc.contains(&citation.key) => acc.push(citation.key.clone()),
+      _ => citation_keys(&tag.children, acc),
+    }
+  }
 }
 
-struct Ctx {
-  subject: String,
-  matches: Vec<Match>,
+/// Wraps each top-level heading together with the blocks following it (up
+/// to the next heading of the same or higher level) into a `Section` node
+/// carrying an identifier derived from the heading text.
+fn sectionize(children: Vec<Tag>, identifiers: &mut HashSet<String>) -> Vec<Tag> {
+  let mut res = Vec::new();
+  let mut sections: Vec<(u32, Tag)> = Vec::new();
+  for child in children {
+    if let TagKind::Heading(heading) = &child.kind {
+      let level = heading.level;
+      while matches!(sections.last(), Some((l, _)) if *l >= level) {
+        let (_, section) = sections.pop().unwrap();
+        if let Some((_, parent)) = sections.last_mut() {
+          parent.span.end = section.span.end
+        }
+        sections.last_mut().map_or(&mut res, |(_, it)| &mut it.children).push(section);
+      }
+      // an explicit identifier wins over the generated one
+      let id = match child.id() {
+        Some(id) => {
+          identifiers.insert(id.to_string());
+          id.to_string()
+        }
+        None => get_identifier(&get_string_content(&child), identifiers),
+      };
+      let mut section = Tag::new(Section {});
+      section.span = child.span.clone();
+      section.attrs.insert("id".to_string(), id);
+      section.children.push(child);
+      sections.push((level, section));
+    } else {
+      if let Some((_, section)) = sections.last_mut() {
+        section.span.end = child.span.end
+      }
+      sections.last_mut().map_or(&mut res, |(_, it)| &mut it.children).push(child)
+    }
+  }
+  while let Some((_, section)) = sections.pop() {
+    if let Some((_, parent)) = sections.last_mut() {
+      parent.span.end = section.span.end
+    }
+    sections.last_mut().map_or(&mut res, |(_, it)| &mut it.children).push(section);
+  }
+  res
+}
+
+/// Gives the headings which don't start a section, like those in block
+/// quotes, identifiers of their own.
+fn assign_heading_ids(tags: &mut [Tag], identifiers: &mut HashSet<String>) {
+  for tag in tags {
+    match tag.kind {
+      TagKind::Section(_) => {
+        // the heading shares the identifier of the section
+        let heading = tag.children.first().is_some_and(|it| matches!(it.kind, TagKind::Heading(_)));
+        let start = usize::from(heading);
+        assign_heading_ids(&mut tag.children[start..], identifiers)
+      }
+      TagKind::Heading(_) if tag.id().is_none() => {
+        let id = get_identifier(&get_string_content(tag), identifiers);
+        tag.set_id(id)
+      }
+      _ => assign_heading_ids(&mut tag.children, identifiers),
+    }
+  }
+}
+
+/// Joins adjacent [`Str`] nodes without attributes, like the text on both
+/// sides of an escape.
+pub(crate) fn join_text(tags: &mut Vec<Tag>) {
+  tags.dedup_by(|next, prev| match (&mut prev.kind, &next.kind) {
+    (TagKind::Str(prev_str), TagKind::Str(next_str))
+      if prev.attrs.is_empty() && next.attrs.is_empty() =>
+    {
+      prev_str.text.push_str(&next_str.text);
+      prev.span.end = next.span.end;
+      true
+    }
+    _ => false,
+  });
+  for tag in tags {
+    join_text(&mut tag.children)
+  }
+}
+
+pub(crate) fn get_identifier(s: &str, identifiers: &mut HashSet<String>) -> String {
+  let base: String =
+    s.chars().filter(|&c| !"][~!@#$%^&*(){}`,.<>\\|=+/?'\"‘’“”".contains(c)).collect();
+  let mut base = base.split_whitespace().collect::<Vec<_>>().join("-");
+  let mut ident = base.clone();
+  // generate unique id
+  let mut i = 0;
+  while ident.is_empty() || identifiers.contains(&ident) {
+    i += 1;
+    if base.is_empty() {
+      base = "s".to_string();
+    }
+    ident = format!("{base}-{i}");
+  }
+  identifiers.insert(ident.clone());
+  ident
+}
+
+struct Ctx<'a> {
+  subject: &'a str,
+  matches: &'a [Match],
   idx: usize,
+  // a blank line since the last block, for tight and loose lists
+  blank: bool,
+  // the list item contains blocks separated by a blank line
+  loose: bool,
+  // alignments of the separator line being parsed as a table row
+  separators: Vec<Alignment>,
+  // attributes on the lines before the next block
+  block_attrs: Attrs,
 }
 
-impl Ctx {
-  fn get_node(&mut self, maintag: Comp) -> Tag {
+impl<'a> Ctx<'a> {
+  fn get_node(&mut self, maintag: Comp, start: usize) -> Tag {
     let mut node = Tag::new(match maintag {
       Comp::Doc => TagKind::Doc(Doc {}),
       Comp::Para => Para {}.into(),
+      Comp::BlockQuote => BlockQuote {}.into(),
+      Comp::ListItem => ListItem {}.into(),
+      Comp::Caption => Caption {}.into(),
+      Comp::Table => Table {}.into(),
+      Comp::Footnote => Footnote { label: String::new() }.into(),
+      Comp::Div => Div {}.into(),
+      Comp::Row => Row { head: false }.into(),
+      Comp::Cell => Cell { head: false, align: Alignment::Default }.into(),
+      Comp::Heading => Heading { level: 0, number: None }.into(),
       Comp::Imagetext => Image { destination: None, reference: None }.into(),
       Comp::Linktext => Link { destination: None, reference: None }.into(),
       Comp::CodeBlock => CodeBlock { text: String::new(), lang: None }.into(),
       Comp::Destination => Doc {}.into(),
       Comp::Strong => Strong {}.into(),
       Comp::Emph => Emph {}.into(),
+      Comp::Insert => Insert {}.into(),
+      Comp::Delete => Delete {}.into(),
+      Comp::Mark => Highlight {}.into(),
+      Comp::Subscript => Subscript {}.into(),
+      Comp::Superscript => Superscript {}.into(),
+      Comp::SingleQuoted => SingleQuoted {}.into(),
       Comp::DoubleQuoted => DoubleQuoted {}.into(),
       Comp::Verbatim => Verbatim { text: String::new() }.into(),
-      Comp::Reference => Span {}.into(),
+      Comp::InlineMath => InlineMath { text: String::new() }.into(),
+      Comp::DisplayMath => DisplayMath { text: String::new() }.into(),
+      Comp::Reference | Comp::Span => Span {}.into(),
       Comp::ReferenceDefinition => ReferenceDefinition {}.into(),
+      Comp::AbbreviationDefinition => {
+        AbbreviationDefinition { term: String::new(), title: String::new() }.into()
+      }
       Comp::Url => Url { destination: String::new() }.into(),
       _ => panic!("unhandled {maintag}"),
     });
+    // the styles the list at the end of the children may still have
+    let mut list_styles = Vec::new();
+    // the alignments of the columns of a table
+    let mut aligns = Vec::new();
     while self.idx < self.matches.len() {
       let m = self.matches[self.idx];
 
-      if m.is(Atom::Blankline) || m.is(Atom::ImageMarker) || m.is(Atom::Escape) {
+      if m.is(Atom::Blankline) {
+        self.blank = true;
+        self.idx += 1;
+        continue;
+      }
+      if m.is(Atom::ImageMarker) || m.is(Atom::Escape) {
         self.idx += 1;
         continue;
       }
 
       if m.is(maintag.sub()) {
         self.idx += 1;
+        node.span = start..m.end();
+        // attributes at the end of a container don't apply to anything
+        self.block_attrs.clear();
         return node;
       } else {
         match m.a {
+          Annot::Add(Comp::BlockAttributes) => {
+            let end = self.matches[self.idx..]
+              .iter()
+              .position(|it| it.is(Comp::BlockAttributes.sub()))
+              .map_or(self.matches.len(), |it| self.idx + it);
+            let parts: Vec<_> = self.matches[self.idx + 1..end]
+              .iter()
+              .filter_map(|it| match it.a {
+                Annot::Atom(atom) => Some((it.range(), atom)),
+                _ => None,
+              })
+              .collect();
+            attributes::to_attrs(self.subject, &parts, &mut self.block_attrs);
+            self.idx = end + 1;
+          }
+          Annot::Add(Comp::Attributes) => {
+            let end = self.matches[self.idx..]
+              .iter()
+              .position(|it| it.is(Comp::Attributes.sub()))
+              .map_or(self.matches.len(), |it| self.idx + it);
+            let parts: Vec<_> = self.matches[self.idx + 1..end]
+              .iter()
+              .filter_map(|it| match it.a {
+                Annot::Atom(atom) => Some((it.range(), atom)),
+                _ => None,
+              })
+              .collect();
+            let mut attrs = Attrs::new();
+            attributes::to_attrs(self.subject, &parts, &mut attrs);
+            let attrs_end = self.matches.get(end).map_or(m.end(), |it| it.end());
+            attach_attributes(&mut node.children, attrs, m.start()..attrs_end);
+            self.idx = end + 1;
+          }
           Annot::Add(tag) => {
             let _startidx = self.idx;
+            let start = match self.idx.checked_sub(1).map(|i| self.matches[i]) {
+              Some(marker) if marker.is(Atom::ImageMarker) => marker.start(),
+              _ => m.start(),
+            };
+            let block_attrs = std::mem::take(&mut self.block_attrs);
+            let blank = std::mem::take(&mut self.blank);
+            let loose = std::mem::take(&mut self.loose);
             self.idx += 1;
-            let mut result = self.get_node(tag);
+            let mut result = self.get_node(tag, start);
+            let item_loose = std::mem::replace(&mut self.loose, loose);
+            // only list items end with the blank lines after them
+            let trailing_blank = std::mem::take(&mut self.blank) && tag == Comp::ListItem;
+            let in_item = matches!(node.kind, TagKind::ListItem(_) | TagKind::TaskListItem(_));
+            if in_item && blank && !node.children.is_empty() && tag != Comp::ListItem {
+              // blocks separated by a blank line, which is fine before a
+              // sublist
+              self.loose = true
+            }
             match tag {
               Comp::Imagetext | Comp::Linktext => {
                 if self.matches[self.idx].is(Comp::Destination.add()) {
                   self.idx += 1;
-                  let dest = self.get_node(Comp::Destination);
+                  let dest = self.get_node(Comp::Destination, self.matches[self.idx - 1].start());
+                  result.span.end = dest.span.end;
 
                   let destination = match tag {
                     Comp::Imagetext => &mut result.cast::<Image>().destination,
@@ -69,7 +752,8 @@ impl Ctx {
                   *destination = Some(get_string_content(&dest));
                 } else if self.matches[self.idx].is(Comp::Reference.add()) {
                   self.idx += 1;
-                  let span = self.get_node(Comp::Reference);
+                  let span = self.get_node(Comp::Reference, self.matches[self.idx - 1].start());
+                  result.span.end = span.span.end;
 
                   let reference = match tag {
                     Comp::Imagetext => &mut result.cast::<Image>().reference,
@@ -79,8 +763,18 @@ impl Ctx {
                   *reference = Some(get_string_content(&span));
                 }
               }
-              Comp::CodeBlock => result.cast::<CodeBlock>().text = get_string_content(&result),
-              Comp::Verbatim => {
+              Comp::Heading => result.cast::<Heading>().level = (m.end() - m.start()) as u32,
+              Comp::CodeBlock => {
+                let text = get_string_content(&result);
+                let code_block = result.cast::<CodeBlock>();
+                match code_block.lang.as_deref().and_then(|it| it.strip_prefix('=')) {
+                  Some(format) => {
+                    result.kind = RawBlock { format: format.to_string(), text }.into()
+                  }
+                  None => code_block.text = text,
+                }
+              }
+              Comp::Verbatim | Comp::InlineMath | Comp::DisplayMath => {
                 let mut text = get_string_content(&result);
                 if find(text.as_str(), "^ +`").is_match {
                   text.remove(0);
@@ -88,50 +782,345 @@ impl Ctx {
                 if find(text.as_str(), "` +$").is_match {
                   text.pop();
                 }
-                result.cast::<Verbatim>().text = text;
+                match &mut result.kind {
+                  TagKind::InlineMath(math) => math.text = text,
+                  TagKind::DisplayMath(math) => math.text = text,
+                  _ => result.cast::<Verbatim>().text = text,
+                }
               }
               Comp::Url => result.cast::<Url>().destination = get_string_content(&result),
               _ => (),
             }
-            node.children.push(result)
+            match tag {
+              Comp::ListItem => {
+                let marker = &self.subject[m.range()];
+                let (subject, styles) = (self.subject, &mut list_styles);
+                add_list_item(
+                  &mut node.children,
+                  styles,
+                  result,
+                  subject,
+                  marker,
+                  blank,
+                  item_loose,
+                );
+                self.blank = trailing_blank;
+              }
+              Comp::Row => {
+                let separators = std::mem::take(&mut self.separators);
+                add_table_row(&mut node.children, &mut aligns, result, separators)
+              }
+              _ => {
+                list_styles.clear();
+                node.children.push(result)
+              }
+            }
+            if let Some(mut last) = node.children.last_mut().filter(|_| !block_attrs.is_empty()) {
+              if tag == Comp::ListItem && last.children.len() > 1 {
+                // attributes before a later item of the list
+                last = last.children.last_mut().unwrap();
+              }
+              let attrs = std::mem::replace(&mut last.attrs, block_attrs);
+              last.merge_attrs(&attrs);
+            }
           }
           Annot::Sub(_) => panic!("unexpected {}", m.a),
           Annot::Atom(atom) => {
-            let tag = match atom {
-              Atom::Str => Tag::new(Str::new(&self.subject[m.s..m.e])),
+            let mut tag = match atom {
+              Atom::Str => Tag::new(Str::new(&self.subject[m.range()])),
               Atom::ReferenceKey => {
-                Tag::new(ReferenceKey { text: self.subject[m.s..m.e].to_string() })
+                Tag::new(ReferenceKey { text: self.subject[m.range()].to_string() })
               }
               Atom::ReferenceValue => {
-                Tag::new(ReferenceValue { text: self.subject[m.s..m.e].to_string() })
+                Tag::new(ReferenceValue { text: self.subject[m.range()].to_string() })
               }
               Atom::Softbreak => Tag::new(Softbreak {}),
+              Atom::Hardbreak => Tag::new(HardBreak {}),
+              Atom::Nbsp => Tag::new(NonBreakingSpace {}),
+              Atom::ThematicBreak => {
+                Tag::new(ThematicBreak {}).with_attrs(std::mem::take(&mut self.block_attrs))
+              }
+              Atom::FootnoteReference => {
+                let label = &self.subject[m.start() + 2..m.end() - 1];
+                Tag::new(FootnoteReference { label: label.to_string() })
+              }
+              Atom::LeftSingleQuote
+              | Atom::RightSingleQuote
+              | Atom::LeftDoubleQuote
+              | Atom::RightDoubleQuote
+              | Atom::Ellipses
+              | Atom::EnDash
+              | Atom::EmDash => Tag::new(SmartPunctuation::new(atom.to_string())),
+              Atom::Unparsed => {
+                let text = &self.subject[m.range()];
+                if let Some(TagKind::Unparsed(last)) =
+                  node.children.last_mut().map(|it| &mut it.kind)
+                {
+                  // lines of the same block
+                  last.text.push('\n');
+                  last.text.push_str(text);
+                  node.children.last_mut().unwrap().span.end = m.end();
+                  self.idx += 1;
+                  continue;
+                }
+                Tag::new(Unparsed { text: text.to_string() })
+              }
+              Atom::Emoji => {
+                Tag::new(Symbol { alias: self.subject[m.start() + 1..m.end() - 1].to_string() })
+              }
+              Atom::AbbreviationTerm | Atom::AbbreviationTitle => {
+                let text = self.subject[m.range()].to_string();
+                let definition = node.cast::<AbbreviationDefinition>();
+                match atom {
+                  Atom::AbbreviationTerm => definition.term = text,
+                  _ => definition.title = text,
+                }
+                self.idx += 1;
+                continue;
+              }
+              Atom::CheckboxChecked | Atom::CheckboxUnchecked => {
+                node.kind = TaskListItem { checked: atom == Atom::CheckboxChecked }.into();
+                self.idx += 1;
+                continue;
+              }
+              Atom::SeparatorDefault
+              | Atom::SeparatorLeft
+              | Atom::SeparatorRight
+              | Atom::SeparatorCenter => {
+                self.separators.push(match atom {
+                  Atom::SeparatorLeft => Alignment::Left,
+                  Atom::SeparatorRight => Alignment::Right,
+                  Atom::SeparatorCenter => Alignment::Center,
+                  _ => Alignment::Default,
+                });
+                self.idx += 1;
+                continue;
+              }
+              Atom::NoteLabel => {
+                node.cast::<Footnote>().label = self.subject[m.range()].to_string();
+                self.idx += 1;
+                continue;
+              }
+              Atom::Id | Atom::Class | Atom::Key | Atom::Value => {
+                // the name and attributes on the opening fence of a div
+                let end = self.matches[self.idx..]
+                  .iter()
+                  .position(|it| {
+                    !matches!(it.a, Annot::Atom(Atom::Id | Atom::Class | Atom::Key | Atom::Value))
+                  })
+                  .map_or(self.matches.len(), |it| self.idx + it);
+                let parts: Vec<_> = self.matches[self.idx..end]
+                  .iter()
+                  .filter_map(|it| match it.a {
+                    Annot::Atom(atom) => Some((it.range(), atom)),
+                    _ => None,
+                  })
+                  .collect();
+                attributes::to_attrs(self.subject, &parts, &mut node.attrs);
+                self.idx = end;
+                continue;
+              }
+              Atom::RawFormat => {
+                let format = self.subject[m.start() + 2..m.end() - 1].to_string();
+                if let Some(last) = node.children.last_mut() {
+                  if let TagKind::Verbatim(verbatim) = &mut last.kind {
+                    let text = std::mem::take(&mut verbatim.text);
+                    last.kind = RawInline { format, text }.into();
+                    last.span.end = m.end();
+                  }
+                }
+                self.idx += 1;
+                continue;
+              }
               Atom::CodeLanguage => {
-                node.cast::<CodeBlock>().lang = Some(self.subject[m.s..m.e].to_string());
+                node.cast::<CodeBlock>().lang = Some(self.subject[m.range()].to_string());
                 self.idx += 1;
                 continue;
               }
               _ => todo!("todo atom: {atom}"),
             };
+            tag.span = m.range();
             node.children.push(tag);
             self.idx += 1;
           }
         }
       }
     }
+    node.span = start..self.subject.len();
     node
   }
 }
 
+/// Adds the item to the list at the end of `children` if its marker fits
+/// the `styles` the list may still have, or starts a new list.
+fn add_list_item(
+  children: &mut Vec<Tag>,
+  styles: &mut Vec<String>,
+  item: Tag,
+  subject: &str,
+  marker: &str,
+  separated: bool,
+  loose: bool,
+) {
+  let task = matches!(item.kind, TagKind::TaskListItem(_));
+  let item_styles = lists::styles(marker);
+  let list = children.last_mut().filter(|list| {
+    matches!(list.kind, TagKind::List(_))
+      && list.children.first().is_some_and(|it| matches!(it.kind, TagKind::TaskListItem(_))) == task
+      && styles.iter().any(|it| item_styles.contains(it))
+  });
+  let Some(list) = list else {
+    let style = item_styles[0].clone();
+    let start = lists::start(marker, &style);
+    let mut list = Tag::new(List { style, start, tight: !loose });
+    list.span = item.span.clone();
+    list.children.push(item);
+    children.push(list);
+    *styles = item_styles;
+    return;
+  };
+  styles.retain(|it| item_styles.contains(it));
+  list.span.end = item.span.end;
+  list.children.push(item);
+  let TagKind::List(kind) = &mut list.kind else { unreachable!() };
+  // a blank line between items makes the list loose
+  kind.tight &= !separated && !loose;
+  if kind.style != styles[0] {
+    // `i.` turned out to be alphabetic
+    let first = &subject[list.children[0].span.start..];
+    let first = first.split(char::is_whitespace).next().unwrap_or_default();
+    kind.start = lists::start(first, &styles[0]);
+    kind.style = styles[0].clone()
+  }
+}
+
+/// Adds the row to the table, or for a separator line, makes the row before
+/// it a header and sets the alignments of the columns from there on.
+fn add_table_row(
+  rows: &mut Vec<Tag>,
+  aligns: &mut Vec<Alignment>,
+  mut row: Tag,
+  separators: Vec<Alignment>,
+) {
+  if separators.is_empty() {
+    for (cell, &align) in row.children.iter_mut().zip(aligns.iter()) {
+      cell.cast::<Cell>().align = align
+    }
+    rows.push(row);
+    return;
+  }
+  if let Some(prev) = rows.last_mut() {
+    prev.cast::<Row>().head = true;
+    for (i, cell) in prev.children.iter_mut().enumerate() {
+      let cell = cell.cast::<Cell>();
+      cell.head = true;
+      cell.align = separators.get(i).copied().unwrap_or_default()
+    }
+  }
+  *aligns = separators
+}
+
+/// Gives the attributes to the element right before them, or to the last
+/// word of text.
+fn attach_attributes(children: &mut Vec<Tag>, attrs: Attrs, range: Range<usize>) {
+  let Some(prev) = children.last_mut().filter(|it| it.span.end == range.start) else {
+    // separated by whitespace, or at the start
+    return;
+  };
+  let TagKind::Str(str) = &mut prev.kind else {
+    prev.merge_attrs(&attrs);
+    prev.span.end = range.end;
+    return;
+  };
+  let word_len = str.text.len() - str.text.trim_end_matches(|c: char| !c.is_whitespace()).len();
+  if word_len == 0 {
+    return;
+  }
+  let word = str.text.split_off(str.text.len() - word_len);
+  let mut word = Tag::new(Str::new(word));
+  word.span = prev.span.end - word_len..prev.span.end;
+  let mut span = Tag::new(Span {}).with_attrs(attrs);
+  span.span = word.span.start..range.end;
+  span.children.push(word);
+  if str.text.is_empty() {
+    *prev = span
+  } else {
+    prev.span.end = span.span.start;
+    children.push(span)
+  }
+}
+
+/// Replaces `Unparsed` nodes with their parsed content.
+pub(crate) fn parse_inlines(tags: &mut Vec<Tag>) {
+  let mut i = 0;
+  while i < tags.len() {
+    if let TagKind::Unparsed(unparsed) = &tags[i].kind {
+      let children = parse_unparsed(&unparsed.text, tags[i].span.start);
+      let len = children.len();
+      tags.splice(i..i + 1, children);
+      i += len;
+    } else {
+      parse_inlines(&mut tags[i].children);
+      i += 1;
+    }
+  }
+}
+
+fn parse_unparsed(text: &str, offset: usize) -> Vec<Tag> {
+  // the final newline keeps lookahead in bounds, like for regular blocks
+  let subject = format!("{text}\n");
+  let mut parser = inline::Parser::default();
+  parser.reset(&ParseOpts::default());
+  parser.feed(&subject, 0, subject.len(), &mut Budget::default());
+  let mut matches = vec![Match::new(0..0, Comp::Para.add())];
+  parser.get_matches(&subject, &mut matches, &mut Vec::new());
+  matches.push(Match::new(text.len()..text.len(), Comp::Para.sub()));
+  let mut para = Ctx {
+    subject: &subject,
+    matches: &matches,
+    idx: 1,
+    blank: false,
+    loose: false,
+    separators: Vec::new(),
+    block_attrs: Attrs::new(),
+  }
+  .get_node(Comp::Para, 0);
+  for child in &mut para.children {
+    shift_spans(child, offset)
+  }
+  para.children
+}
+
+fn shift_spans(tag: &mut Tag, offset: usize) {
+  tag.span = tag.span.start + offset..tag.span.end + offset;
+  for child in &mut tag.children {
+    shift_spans(child, offset)
+  }
+}
+
 pub(crate) fn get_string_content(dest: &Tag) -> String {
   let mut res = String::new();
   match &dest.kind {
-    TagKind::Softbreak(_) => res.push('\n'),
+    TagKind::Softbreak(_) | TagKind::HardBreak(_) => res.push('\n'),
+    TagKind::NonBreakingSpace(_) => res.push('\u{a0}'),
     TagKind::Str(str) => res.push_str(&str.text),
+    TagKind::SmartPunctuation(punctuation) => res.push_str(punctuation.text()),
+    TagKind::Unparsed(unparsed) => {
+      for tag in parse_unparsed(&unparsed.text, 0) {
+        res.push_str(&get_string_content(&tag))
+      }
+    }
+    TagKind::SingleQuoted(_) => res.push('‘'),
+    TagKind::DoubleQuoted(_) => res.push('“'),
     _ => (),
   }
   for c in &dest.children {
     res.push_str(&get_string_content(c))
   }
+  match &dest.kind {
+    TagKind::SingleQuoted(_) => res.push('’'),
+    TagKind::DoubleQuoted(_) => res.push('”'),
+    _ => (),
+  }
   res
 }
@@ -0,0 +1,85 @@
+use crate::{
+  ast::{Tag, TagKind},
+  Typography,
+};
+
+// Longer sequences come first, so that `<->` wins over `<-`.
+const ARROWS: &[(&str, &str)] =
+  &[("<=>", "⇔"), ("<->", "↔"), ("->", "→"), ("<-", "←"), ("=>", "⇒")];
+const MATH: &[(&str, &str)] = &[("<=", "≤"), (">=", "≥"), ("!=", "≠"), ("+-", "±")];
+const SYMBOLS: &[(&str, &str)] =
+  &[("(c)", "©"), ("(C)", "©"), ("(r)", "®"), ("(R)", "®"), ("(tm)", "™"), ("(TM)", "™")];
+const FRACTIONS: &[(&str, &str)] =
+  &[("1/2", "½"), ("1/4", "¼"), ("3/4", "¾"), ("1/3", "⅓"), ("2/3", "⅔")];
+
+pub(crate) fn substitute(tags: &mut [Tag], typography: &Typography) {
+  let mut table = Vec::new();
+  if typography.arrows {
+    table.extend_from_slice(ARROWS)
+  }
+  if typography.math {
+    table.extend_from_slice(MATH)
+  }
+  if typography.symbols {
+    table.extend_from_slice(SYMBOLS)
+  }
+  if table.is_empty() && !typography.fractions {
+    return;
+  }
+  // `<=>` is an arrow, not `<=` followed by `>`
+  table.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+  substitute_tags(tags, &table, typography.fractions)
+}
+
+fn substitute_tags(tags: &mut [Tag], table: &[(&str, &str)], fractions: bool) {
+  for tag in tags {
+    match &mut tag.kind {
+      TagKind::Str(str) => {
+        if let Some(text) = substitute_text(&str.text, table, fractions) {
+          str.text = text
+        }
+      }
+      TagKind::CodeBlock(_)
+      | TagKind::RawBlock(_)
+      | TagKind::Verbatim(_)
+      | TagKind::RawInline(_)
+      | TagKind::InlineMath(_)
+      | TagKind::DisplayMath(_)
+      | TagKind::Url(_) => (),
+      _ => substitute_tags(&mut tag.children, table, fractions),
+    }
+  }
+}
+
+fn substitute_text(text: &str, table: &[(&str, &str)], fractions: bool) -> Option<String> {
+  let mut res = String::new();
+  let mut last = 0;
+  let mut pos = 0;
+  while pos < text.len() {
+    let rest = &text[pos..];
+    let mut found = table.iter().find(|(from, _)| rest.starts_with(from));
+    if fractions && found.is_none() {
+      // only whole numbers, so that `11/2` and `1/25` stay as they are
+      let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit() || c == '/');
+      found = FRACTIONS.iter().find(|(from, _)| {
+        rest.starts_with(from)
+          && !is_digit(text[..pos].chars().next_back())
+          && !is_digit(rest[from.len()..].chars().next())
+      });
+    }
+    match found {
+      Some((from, to)) => {
+        res.push_str(&text[last..pos]);
+        res.push_str(to);
+        pos += from.len();
+        last = pos;
+      }
+      None => pos += rest.chars().next().map_or(1, char::len_utf8),
+    }
+  }
+  if last == 0 {
+    return None;
+  }
+  res.push_str(&text[last..]);
+  Some(res)
+}
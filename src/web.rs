@@ -0,0 +1,85 @@
+//! Serving rendered documents from web frameworks, enabled by the `axum` and
+//! `actix` features.
+//!
+//! ```ignore
+//! async fn page() -> djot::web::DjotHtml {
+//!   let doc = djot::Document::parse("# Hello\n");
+//!   djot::web::DjotHtml(doc, djot::HtmlOpts::default())
+//! }
+//! ```
+
+use std::{
+  convert::Infallible,
+  pin::Pin,
+  task::{Context, Poll},
+};
+
+use crate::{html::Chunks, Document, HtmlOpts};
+
+/// A response rendering the document as an HTML page body, with a
+/// `text/html; charset=utf-8` content type. The body is streamed: it is
+/// rendered a few blocks at a time, as the client reads it.
+pub struct DjotHtml(pub Document, pub HtmlOpts);
+
+impl DjotHtml {
+  fn into_body(self) -> HtmlBody {
+    HtmlBody(Chunks::new(self.0, self.1))
+  }
+}
+
+/// The body of a [`DjotHtml`] response.
+pub struct HtmlBody(Chunks);
+
+impl HtmlBody {
+  fn poll_chunk(self: Pin<&mut Self>) -> Poll<Option<Result<String, Infallible>>> {
+    Poll::Ready(self.get_mut().0.next().map(Ok))
+  }
+}
+
+#[cfg(feature = "axum")]
+impl futures_core::Stream for HtmlBody {
+  type Item = Result<String, Infallible>;
+
+  fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    self.poll_chunk()
+  }
+}
+
+#[cfg(feature = "axum")]
+impl axum::response::IntoResponse for DjotHtml {
+  fn into_response(self) -> axum::response::Response {
+    let content_type = [(axum::http::header::CONTENT_TYPE, "text/html; charset=utf-8")];
+    (content_type, axum::body::Body::from_stream(self.into_body())).into_response()
+  }
+}
+
+#[cfg(feature = "actix")]
+impl actix_web::body::MessageBody for HtmlBody {
+  type Error = Infallible;
+
+  fn size(&self) -> actix_web::body::BodySize {
+    actix_web::body::BodySize::Stream
+  }
+
+  fn poll_next(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+  ) -> Poll<Option<Result<actix_web::web::Bytes, Infallible>>> {
+    self.poll_chunk().map(|it| it.map(|it| it.map(Into::into)))
+  }
+}
+
+#[cfg(feature = "actix")]
+impl actix_web::Responder for DjotHtml {
+  type Body = HtmlBody;
+
+  fn respond_to(self, _req: &actix_web::HttpRequest) -> actix_web::HttpResponse<HtmlBody> {
+    let mut res =
+      actix_web::HttpResponse::with_body(actix_web::http::StatusCode::OK, self.into_body());
+    res.headers_mut().insert(
+      actix_web::http::header::CONTENT_TYPE,
+      actix_web::http::header::HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    res
+  }
+}
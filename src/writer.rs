@@ -0,0 +1,798 @@
+use std::{collections::HashSet, error, fmt};
+
+use crate::{
+  ast::{
+    Alignment, Attrs, CodeBlock, DisplayMath, InlineMath, ListItem, Para, RawBlock, RawInline, Tag,
+    TagKind, Verbatim,
+  },
+  attributes::{AttributeParser, Status},
+  lists,
+  tree::{get_identifier, get_string_content, join_text},
+  Change, Document,
+};
+
+pub(crate) fn convert(doc: &Document) -> String {
+  let mut ctx = Ctx {
+    out: String::new(),
+    prefix: String::new(),
+    line_start: true,
+    in_cell: false,
+    section_heading: false,
+    in_brackets: 0,
+    delimiters: Vec::new(),
+  };
+  // text split up by escapes is escaped anew, as a whole
+  let mut children = doc.children.clone();
+  join_text(&mut children);
+  ctx.render_blocks(&children);
+  ctx.out
+}
+
+/// The differences found by [`roundtrip_check`](crate::roundtrip_check).
+#[derive(Debug, Clone)]
+pub struct RoundtripError {
+  /// The document rendered back to djot.
+  pub djot: String,
+  /// Changes from the original document to the reparsed one.
+  pub changes: Vec<Change>,
+}
+
+impl fmt::Display for RoundtripError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "document changed after a round trip through djot:")?;
+    for change in &self.changes {
+      match change {
+        Change::Insert { path, .. } => write!(f, "\n  inserted {path:?}")?,
+        Change::Delete { path } => write!(f, "\n  deleted {path:?}")?,
+        Change::Replace { path, .. } => write!(f, "\n  replaced {path:?}")?,
+      }
+    }
+    Ok(())
+  }
+}
+
+impl error::Error for RoundtripError {}
+
+struct Ctx {
+  out: String,
+  // written at the start of every line, for container blocks
+  prefix: String,
+  line_start: bool,
+  // a `|` would end the table cell
+  in_cell: bool,
+  // the next block is the heading of a section, with only explicit
+  // identifiers
+  section_heading: bool,
+  // within the text of a link, image or span
+  in_brackets: usize,
+  // the emphasis-like delimiters which could pair up in the current block
+  delimiters: Vec<char>,
+}
+
+impl Ctx {
+  fn render_blocks(&mut self, tags: &[Tag]) {
+    self.render_block_list(tags, &mut true)
+  }
+
+  fn render_block_list(&mut self, tags: &[Tag], first: &mut bool) {
+    for tag in tags {
+      if let TagKind::Section(_) = tag.kind {
+        // sections are implied by headings
+        self.section_heading = true;
+        self.render_block_list(&tag.children, first);
+        continue;
+      }
+      if !*first {
+        self.newline();
+      }
+      *first = false;
+      self.render_block(tag);
+    }
+  }
+
+  fn render_block(&mut self, tag: &Tag) {
+    let section_heading = std::mem::take(&mut self.section_heading);
+    self.delimiters = delimiters(&tag.children);
+    match tag.kind {
+      TagKind::Div(_) => (),
+      TagKind::Heading(_) if !section_heading => {
+        // other headings get the same identifier again
+        let mut attrs = tag.attrs.clone();
+        let generated = get_identifier(&get_string_content(tag), &mut HashSet::new());
+        if tag.id() == Some(generated.as_str()) {
+          attrs.shift_remove("id");
+        }
+        self.render_block_attrs(&attrs)
+      }
+      _ => self.render_block_attrs(&tag.attrs),
+    }
+    match &tag.kind {
+      TagKind::Doc(_) | TagKind::Section(_) => self.render_blocks(&tag.children),
+      TagKind::Heading(heading) => {
+        let marker = "#".repeat(heading.level as usize);
+        self.out(&marker);
+        self.out(" ");
+        for child in &tag.children {
+          match child.kind {
+            TagKind::Softbreak(_) => {
+              self.newline();
+              self.out(&marker);
+              self.out(" ");
+            }
+            _ => self.render_inline(child),
+          }
+        }
+        self.newline()
+      }
+      TagKind::Para(_) => {
+        self.render_inlines(&tag.children);
+        match tag.children.last().map(|it| &it.kind) {
+          // a trailing line break is only kept before empty attributes
+          Some(TagKind::Softbreak(_)) => {
+            self.out("{}");
+            self.newline()
+          }
+          // a hard one already ended the line
+          Some(TagKind::HardBreak(_)) => (),
+          _ => self.newline(),
+        }
+      }
+      TagKind::Figure(_) => self.render_blocks(&tag.children),
+      TagKind::Caption(_) => {
+        self.out("^ ");
+        self.render_inlines(&tag.children);
+        self.newline()
+      }
+      TagKind::ThematicBreak(_) => {
+        self.out("* * *");
+        self.newline()
+      }
+      TagKind::BlockQuote(_) => self.render_quoted(|ctx| {
+        if tag.children.is_empty() {
+          // a lone `>`
+          ctx.newline()
+        }
+        ctx.render_blocks(&tag.children)
+      }),
+      TagKind::List(list) => {
+        for (i, item) in tag.children.iter().enumerate() {
+          if i > 0 && !list.tight {
+            self.newline()
+          }
+          let mut marker = match list.start {
+            Some(start) => lists::marker(&list.style, start + i as u64),
+            None => list.style.clone(),
+          };
+          if let TagKind::TaskListItem(task) = &item.kind {
+            marker.push_str(if task.checked { " [x]" } else { " [ ]" })
+          }
+          self.render_list_item(item, &marker)
+        }
+      }
+      TagKind::ListItem(_) => self.render_list_item(tag, "-"),
+      TagKind::DefinitionList(list) => {
+        for (i, item) in tag.children.iter().enumerate() {
+          if i > 0 && !list.tight {
+            self.newline()
+          }
+          self.render_definition_list_item(item)
+        }
+      }
+      TagKind::DefinitionListItem(_) => self.render_definition_list_item(tag),
+      TagKind::Term(_) => {
+        self.render_inlines(&tag.children);
+        self.newline()
+      }
+      TagKind::Definition(_) => self.render_blocks(&tag.children),
+      TagKind::TaskListItem(task) => {
+        self.render_list_item(tag, if task.checked { "- [x]" } else { "- [ ]" })
+      }
+      TagKind::Table(_) => {
+        let mut caption = None;
+        for (i, child) in tag.children.iter().enumerate() {
+          let TagKind::Row(row) = &child.kind else {
+            caption = Some(child);
+            continue;
+          };
+          let aligned = child.children.iter().any(|it| cell_align(it) != Alignment::Default);
+          if i == 0 && !row.head && aligned {
+            // alignments without a header
+            self.render_separator(child)
+          }
+          self.render_block_attrs(&child.attrs);
+          self.out("|");
+          for cell in &child.children {
+            self.render_block_attrs(&cell.attrs);
+            self.out(" ");
+            self.in_cell = true;
+            self.render_inlines(&cell.children);
+            self.in_cell = false;
+            self.out(" |");
+          }
+          self.newline();
+          if row.head {
+            self.render_separator(child)
+          }
+        }
+        if let Some(caption) = caption {
+          self.newline();
+          self.render_block(caption)
+        }
+      }
+      TagKind::Footnote(note) => {
+        self.out("[^");
+        self.out(&note.label);
+        self.out("]: ");
+        let len = self.prefix.len();
+        self.prefix.push_str("    ");
+        if tag.children.is_empty() {
+          self.newline()
+        }
+        self.render_blocks(&tag.children);
+        self.prefix.truncate(len);
+      }
+      TagKind::Div(_) => {
+        // enclosing divs need longer fences than the ones inside
+        let fence = ":".repeat(3 + div_depth(&tag.children));
+        self.out(&fence);
+        let mut attrs = tag.attrs.clone();
+        let class = attrs.get("class").cloned().unwrap_or_default();
+        let mut classes = class.split_whitespace();
+        if let Some(name) = classes.next() {
+          self.out(" ");
+          self.out(name);
+          let rest: Vec<&str> = classes.collect();
+          if rest.is_empty() {
+            attrs.remove("class");
+          } else {
+            attrs.insert("class".to_string(), rest.join(" "));
+          }
+        }
+        if !attrs.is_empty() {
+          self.out(" ");
+          self.render_attrs(&attrs)
+        }
+        self.newline();
+        self.render_blocks(&tag.children);
+        self.out(&fence);
+        self.newline()
+      }
+      TagKind::Callout(callout) => self.render_quoted(|ctx| {
+        ctx.out("[!");
+        ctx.out(&callout.kind.to_uppercase());
+        ctx.out("]");
+        ctx.newline();
+        ctx.render_blocks(&tag.children)
+      }),
+      TagKind::CodeBlock(CodeBlock { text, .. }) | TagKind::RawBlock(RawBlock { text, .. }) => {
+        let fence = "`".repeat(longest_run(text, '`').max(2) + 1);
+        self.out(&fence);
+        match &tag.kind {
+          TagKind::CodeBlock(CodeBlock { lang: Some(lang), .. }) => {
+            self.out(" ");
+            self.out(lang);
+          }
+          TagKind::RawBlock(raw) => {
+            self.out(" =");
+            self.out(&raw.format);
+          }
+          _ => (),
+        }
+        self.newline();
+        for line in text.lines() {
+          self.out(line);
+          self.newline()
+        }
+        self.out(&fence);
+        self.newline()
+      }
+      TagKind::AbbreviationDefinition(definition) => {
+        self.out("*[");
+        self.out(&definition.term);
+        self.out("]: ");
+        self.out(&definition.title);
+        self.newline()
+      }
+      TagKind::ReferenceDefinition(_) => {
+        let mut values = 0;
+        for child in &tag.children {
+          match &child.kind {
+            TagKind::ReferenceKey(key) => {
+              self.out("[");
+              self.out(&key.text);
+              self.out("]:");
+            }
+            TagKind::ReferenceValue(value) => {
+              // a long destination continues on indented lines
+              if values > 0 {
+                self.newline();
+                self.out(" ");
+              }
+              values += 1;
+              self.out(" ");
+              self.out(&value.text);
+            }
+            _ => (),
+          }
+        }
+        self.newline()
+      }
+      _ => {
+        self.render_inline(tag);
+        self.newline()
+      }
+    }
+  }
+
+  fn render_list_item(&mut self, tag: &Tag, marker: &str) {
+    self.render_block_attrs(&tag.attrs);
+    self.out(marker);
+    self.out(" ");
+    // continuation lines are indented past the marker
+    let len = self.prefix.len();
+    self.prefix.push_str(&" ".repeat(marker.len() + 1));
+    if tag.children.is_empty() {
+      self.newline()
+    }
+    self.render_blocks(&tag.children);
+    self.prefix.truncate(len);
+  }
+
+  fn render_definition_list_item(&mut self, tag: &Tag) {
+    // the term is the first paragraph of the item
+    let mut item = Tag::new(ListItem {});
+    item.attrs = tag.attrs.clone();
+    for child in &tag.children {
+      match child.kind {
+        TagKind::Term(_) => {
+          let mut para = Tag::new(Para {}).with_children(child.children.clone());
+          para.attrs = child.attrs.clone();
+          item.children.push(para)
+        }
+        _ => item.children.extend(child.children.iter().cloned()),
+      }
+    }
+    self.render_list_item(&item, ":")
+  }
+
+  fn render_separator(&mut self, row: &Tag) {
+    self.out("|");
+    for cell in &row.children {
+      self.out(match cell_align(cell) {
+        Alignment::Default => "---|",
+        Alignment::Left => ":--|",
+        Alignment::Right => "--:|",
+        Alignment::Center => ":-:|",
+      });
+    }
+    self.newline()
+  }
+
+  fn render_quoted(&mut self, f: impl FnOnce(&mut Ctx)) {
+    if !self.line_start {
+      // right after a list marker
+      self.out("> ")
+    }
+    let len = self.prefix.len();
+    self.prefix.push_str("> ");
+    f(self);
+    self.prefix.truncate(len);
+  }
+
+  fn render_inlines(&mut self, tags: &[Tag]) {
+    for tag in tags {
+      self.render_inline(tag)
+    }
+  }
+
+  fn render_inline(&mut self, tag: &Tag) {
+    match &tag.kind {
+      TagKind::Str(str) => self.out_escaped(&str.text),
+      TagKind::Unparsed(unparsed) => self.out(&unparsed.text),
+      TagKind::Softbreak(_) => self.newline(),
+      TagKind::HardBreak(_) => {
+        self.out("\\");
+        self.newline()
+      }
+      TagKind::NonBreakingSpace(_) => self.out("\\ "),
+      TagKind::Strong(_) => self.render_delimited(tag, '*'),
+      TagKind::Emph(_) => self.render_delimited(tag, '_'),
+      TagKind::Subscript(_) => self.render_delimited(tag, '~'),
+      TagKind::Superscript(_) => self.render_delimited(tag, '^'),
+      TagKind::Insert(_) => self.render_braced(tag, '+'),
+      TagKind::Delete(_) => self.render_braced(tag, '-'),
+      TagKind::Highlight(_) => self.render_braced(tag, '='),
+      TagKind::SingleQuoted(_) => self.render_delimited(tag, '\''),
+      TagKind::DoubleQuoted(_) => self.render_delimited(tag, '"'),
+      TagKind::Verbatim(Verbatim { text })
+      | TagKind::RawInline(RawInline { text, .. })
+      | TagKind::InlineMath(InlineMath { text })
+      | TagKind::DisplayMath(DisplayMath { text }) => {
+        if self.out.ends_with('$') && !self.out.ends_with("\\$") {
+          // not math
+          self.out.insert(self.out.len() - 1, '\\');
+        }
+        match tag.kind {
+          TagKind::InlineMath(_) => self.out("$"),
+          TagKind::DisplayMath(_) => self.out("$$"),
+          _ => (),
+        }
+        let fence = "`".repeat(longest_run(text, '`') + 1);
+        let pad = if text.starts_with('`') || text.ends_with('`') { " " } else { "" };
+        self.out(&fence);
+        self.out(pad);
+        self.out(text);
+        self.out(pad);
+        self.out(&fence);
+        if let TagKind::RawInline(raw) = &tag.kind {
+          self.out("{=");
+          self.out(&raw.format);
+          self.out("}");
+        }
+      }
+      TagKind::SmartPunctuation(punctuation) => match punctuation.kind.as_str() {
+        "left_single_quote" => self.out("{'"),
+        // an apostrophe
+        "right_single_quote" if self.out.ends_with(char::is_alphanumeric) => self.out("'"),
+        "right_single_quote" => self.out("'}"),
+        "left_double_quote" => self.out("{\""),
+        "right_double_quote" => self.out("\"}"),
+        "en_dash" => self.out("--"),
+        "em_dash" => self.out("---"),
+        "ellipses" => self.out("..."),
+        _ => (),
+      },
+      TagKind::Symbol(symbol) => {
+        self.out(":");
+        self.out(&symbol.alias);
+        self.out(":");
+      }
+      TagKind::Citation(citation) => {
+        if citation.bracketed {
+          self.out("[");
+        }
+        self.out("@");
+        self.out(&citation.key);
+        if let Some(locator) = &citation.locator {
+          self.out(", ");
+          self.out(locator);
+        }
+        if citation.bracketed {
+          self.out("]");
+        }
+      }
+      TagKind::CrossReference(reference) => {
+        self.out(if reference.bracketed { "[@" } else { "@" });
+        self.out(&reference.target);
+        if reference.bracketed {
+          self.out("]");
+        }
+      }
+      TagKind::FootnoteReference(reference) => {
+        self.out("[^");
+        self.out(&reference.label);
+        self.out("]");
+      }
+      TagKind::Url(url) => {
+        self.out("<");
+        self.out(&url.destination);
+        self.out(">");
+      }
+      TagKind::Link(link) => {
+        if self.out.ends_with('!') && !self.out.ends_with("\\!") {
+          // not an image
+          self.out.insert(self.out.len() - 1, '\\');
+        }
+        self.out("[");
+        self.render_bracketed(&tag.children);
+        self.out("]");
+        self.render_target(link.destination.as_deref(), link.reference.as_deref());
+      }
+      TagKind::Image(image) => {
+        self.out("![");
+        self.render_bracketed(&tag.children);
+        self.out("]");
+        self.render_target(image.destination.as_deref(), image.reference.as_deref());
+      }
+      TagKind::Span(_) => {
+        self.out("[");
+        self.render_bracketed(&tag.children);
+        self.out("]");
+        if tag.attrs.is_empty() {
+          // a span needs attributes to be parsed as such
+          self.out("{}");
+        }
+      }
+      TagKind::Doc(_)
+      | TagKind::Section(_)
+      | TagKind::Heading(_)
+      | TagKind::Para(_)
+      | TagKind::ThematicBreak(_)
+      | TagKind::BlockQuote(_)
+      | TagKind::Callout(_)
+      | TagKind::List(_)
+      | TagKind::ListItem(_)
+      | TagKind::TaskListItem(_)
+      | TagKind::DefinitionList(_)
+      | TagKind::DefinitionListItem(_)
+      | TagKind::Term(_)
+      | TagKind::Definition(_)
+      | TagKind::Figure(_)
+      | TagKind::Caption(_)
+      | TagKind::Table(_)
+      | TagKind::Row(_)
+      | TagKind::Cell(_)
+      | TagKind::Footnote(_)
+      | TagKind::Div(_)
+      | TagKind::CodeBlock(_)
+      | TagKind::RawBlock(_)
+      | TagKind::ReferenceDefinition(_)
+      | TagKind::AbbreviationDefinition(_)
+      | TagKind::Abbreviation(_)
+      | TagKind::ReferenceKey(_)
+      | TagKind::ReferenceValue(_) => self.render_inlines(&tag.children),
+    }
+    self.render_inline_attrs(&tag.attrs);
+  }
+
+  fn render_delimited(&mut self, tag: &Tag, c: char) {
+    // explicit `{*`/`*}` markers are needed next to whitespace
+    let first = tag.children.first().and_then(first_char);
+    let last = tag.children.last().and_then(last_char);
+    let mut braced =
+      first.is_some_and(char::is_whitespace) || last.is_some_and(char::is_whitespace);
+    if c == '\'' && self.out.ends_with(|c: char| !c.is_whitespace() && !"\"'-([".contains(c)) {
+      // would be an apostrophe
+      braced = true
+    }
+    if braced {
+      self.out("{")
+    }
+    self.out_char(c);
+    if tag.children.is_empty() {
+      // `**` is no emphasis, but `*{}*` is
+      self.out("{}")
+    }
+    self.render_inlines(&tag.children);
+    self.out_char(c);
+    if braced {
+      self.out("}")
+    }
+  }
+
+  fn render_bracketed(&mut self, tags: &[Tag]) {
+    self.in_brackets += 1;
+    self.render_inlines(tags);
+    self.in_brackets -= 1;
+  }
+
+  fn render_braced(&mut self, tag: &Tag, c: char) {
+    self.out("{");
+    self.out_char(c);
+    self.render_inlines(&tag.children);
+    self.out_char(c);
+    self.out("}");
+  }
+
+  fn render_target(&mut self, destination: Option<&str>, reference: Option<&str>) {
+    match (destination, reference) {
+      (_, Some(reference)) => {
+        self.out("[");
+        self.out(reference);
+        self.out("]");
+      }
+      (destination, None) => {
+        self.out("(");
+        self.out(destination.unwrap_or_default());
+        self.out(")");
+      }
+    }
+  }
+
+  fn render_block_attrs(&mut self, attrs: &Attrs) {
+    if !attrs.is_empty() {
+      self.render_attrs(attrs);
+      self.newline()
+    }
+  }
+
+  fn render_inline_attrs(&mut self, attrs: &Attrs) {
+    if !attrs.is_empty() {
+      self.render_attrs(attrs)
+    }
+  }
+
+  fn render_attrs(&mut self, attrs: &Attrs) {
+    self.out("{");
+    for (idx, (k, v)) in attrs.iter().enumerate() {
+      if idx > 0 {
+        self.out(" ");
+      }
+      match k.as_str() {
+        "id" => {
+          self.out("#");
+          self.out(v);
+        }
+        "class" => {
+          let classes: Vec<String> = v.split_whitespace().map(|it| format!(".{it}")).collect();
+          self.out(&classes.join(" "));
+        }
+        _ => {
+          self.out(k);
+          self.out("=\"");
+          self.out(&v.replace('\\', "\\\\").replace('"', "\\\""));
+          self.out("\"");
+        }
+      }
+    }
+    self.out("}");
+  }
+
+  fn newline(&mut self) {
+    if self.line_start {
+      // a blank line within a block quote
+      self.out.push_str(self.prefix.trim_end());
+    }
+    self.out.push('\n');
+    self.line_start = true;
+  }
+
+  fn out(&mut self, s: &str) {
+    if s.is_empty() {
+      return;
+    }
+    if self.line_start {
+      self.out.push_str(&self.prefix);
+      self.line_start = false;
+    }
+    self.out.push_str(s)
+  }
+
+  fn out_char(&mut self, c: char) {
+    self.out(c.encode_utf8(&mut [0; 4]))
+  }
+
+  /// Escapes the characters of `text` which would be read as markup here.
+  /// Where the next character is in another node, this errs on the side of
+  /// escaping.
+  fn out_escaped(&mut self, text: &str) {
+    let mut res = String::with_capacity(text.len());
+    if self.line_start && text.starts_with(char::is_whitespace) {
+      // whitespace at the start of a line is only kept after empty attributes
+      res.push_str("{}")
+    }
+    // `1. ` at the start of a line would start an ordered list
+    let word = text.split(char::is_whitespace).next().unwrap_or_default();
+    let ordered = self.line_start && lists::is_ordered(word);
+    let mut prev = if self.line_start { Some('\n') } else { self.out.chars().next_back() };
+    for (idx, c) in text.char_indices() {
+      let rest = &text[idx + c.len_utf8()..];
+      let next = rest.chars().next();
+      let escape = match c {
+        '.' | ')' if ordered && idx == word.len() - 1 => true,
+        '\\' | '`' | '"' | '\'' => true,
+        // a footnote reference
+        '^' if prev == Some('[') => true,
+        // a list item or thematic break
+        '*' if self.line_start && idx == 0 => true,
+        // a caption or definition
+        '^' | ':' if self.line_start && idx == 0 && next.is_none_or(char::is_whitespace) => true,
+        // can only open or close next to non-whitespace, and only pair up
+        // with the same delimiter elsewhere in the block
+        '*' | '_' | '~' | '^' => {
+          self.delimiters.contains(&c)
+            && !(prev.is_some_and(char::is_whitespace) && next.is_some_and(char::is_whitespace))
+        }
+        '[' => self.in_brackets > 0 || matches!(next, None | Some('^')),
+        ']' => self.in_brackets > 0 || matches!(next, None | Some('(' | '[' | '{')),
+        // explicit emphasis markers or attributes
+        '{' => {
+          matches!(next, None | Some('_' | '*' | '~' | '^' | '+' | '=' | '\'' | '"' | '-'))
+            || !matches!(AttributeParser::default().feed(text, idx..text.len()), Status::Fail(_))
+        }
+        '}' => matches!(prev, None | Some('_' | '*' | '~' | '^' | '+' | '=' | '\'' | '"' | '-')),
+        // even after an escaped brace, these are explicit markers
+        '+' | '=' | '-' if prev == Some('{') => true,
+        // an autolink
+        '<' => {
+          let url = rest.split(char::is_whitespace).next().unwrap_or_default();
+          !url.is_empty() && (url.contains('>') || url.len() == rest.len())
+        }
+        // block markers at the start of a line
+        '#' | '>' | '+' => self.line_start && idx == 0,
+        '|' => self.line_start && idx == 0 || self.in_cell,
+        // dashes and ellipses
+        '-' => self.line_start && idx == 0 || rest.starts_with('-'),
+        '.' => text[idx..].starts_with("..."),
+        // a div fence, a reference definition, or the start of a `:symbol:`
+        ':' => {
+          self.line_start && idx == 0 && text.starts_with(":::")
+            || prev == Some(']')
+            || rest.split(char::is_whitespace).next().is_some_and(|it| it.contains(':'))
+        }
+        _ => false,
+      };
+      if escape {
+        res.push('\\');
+      }
+      res.push(c);
+      prev = Some(c)
+    }
+    self.out(&res)
+  }
+}
+
+/// The delimiters of strong, emphasis, subscript and superscript.
+const DELIMITERS: [char; 4] = ['*', '_', '~', '^'];
+
+/// The delimiters of emphasis and the like which occur in the text of
+/// `tags` more than once, or are also used by the markup itself.
+fn delimiters(tags: &[Tag]) -> Vec<char> {
+  fn count(tags: &[Tag], acc: &mut [usize; 4]) {
+    for tag in tags {
+      let markup = match &tag.kind {
+        TagKind::Str(str) => {
+          for c in str.text.chars() {
+            if let Some(i) = DELIMITERS.iter().position(|&it| it == c) {
+              acc[i] += 1
+            }
+          }
+          None
+        }
+        TagKind::Strong(_) => Some(0),
+        TagKind::Emph(_) => Some(1),
+        TagKind::Subscript(_) => Some(2),
+        TagKind::Superscript(_) => Some(3),
+        _ => None,
+      };
+      if let Some(i) = markup {
+        acc[i] += 2
+      }
+      count(&tag.children, acc)
+    }
+  }
+  let mut counts = [0; 4];
+  count(tags, &mut counts);
+  DELIMITERS.into_iter().zip(counts).filter(|(_, n)| *n > 1).map(|(c, _)| c).collect()
+}
+
+fn div_depth(tags: &[Tag]) -> usize {
+  let depth = |tag: &Tag| match tag.kind {
+    TagKind::Div(_) => 1 + div_depth(&tag.children),
+    _ => div_depth(&tag.children),
+  };
+  tags.iter().map(depth).max().unwrap_or(0)
+}
+
+fn cell_align(tag: &Tag) -> Alignment {
+  match &tag.kind {
+    TagKind::Cell(cell) => cell.align,
+    _ => Alignment::Default,
+  }
+}
+
+fn longest_run(text: &str, c: char) -> usize {
+  let mut res = 0;
+  let mut run = 0;
+  for it in text.chars() {
+    run = if it == c { run + 1 } else { 0 };
+    res = res.max(run)
+  }
+  res
+}
+
+fn first_char(tag: &Tag) -> Option<char> {
+  match &tag.kind {
+    TagKind::Str(str) => str.text.chars().next(),
+    TagKind::Softbreak(_) | TagKind::HardBreak(_) => Some('\n'),
+    _ => tag.children.first().and_then(first_char),
+  }
+}
+
+fn last_char(tag: &Tag) -> Option<char> {
+  match &tag.kind {
+    TagKind::Str(str) => str.text.chars().next_back(),
+    TagKind::Softbreak(_) | TagKind::HardBreak(_) => Some('\n'),
+    _ => tag.children.last().and_then(last_char),
+  }
+}
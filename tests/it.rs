@@ -27,7 +27,7 @@ fn ref_tests() {
   let opts = TestOpts {
     debug_ast: false,
     ref_matches: true,
-    parse: djot::ParseOpts { debug_matches: true },
+    parse: djot::ParseOpts { debug_matches: true, ..djot::ParseOpts::default() },
   };
 
   let mut last_fail = LastFail::load();
@@ -77,6 +77,1844 @@ fn ref_tests() {
   eprintln!("total tests: {total}");
 }
 
+#[test]
+fn number_headings() {
+  let source = "# One\n\n## Sub\n\n## Sub\n\n# Two\n";
+  let opts = djot::HtmlOpts { number_headings: true, ..djot::HtmlOpts::default() };
+  let html = djot::Document::parse(source).to_html_opts(&opts);
+  assert_eq!(
+    html,
+    "<h1 id=\"One\"><span class=\"section-number\">1</span> One</h1>
+<h2 id=\"Sub\"><span class=\"section-number\">1.1</span> Sub</h2>
+<h2 id=\"Sub-1\"><span class=\"section-number\">1.2</span> Sub</h2>
+<h1 id=\"Two\"><span class=\"section-number\">2</span> Two</h1>
+"
+  );
+
+  let mut doc = djot::Document::parse(source);
+  doc.children[0].children[2].children[0].add_class("unnumbered");
+  doc.number_headings();
+  let mut numbers = Vec::new();
+  collect_heading_numbers(&doc.children, &mut numbers);
+  assert_eq!(numbers, [Some("1".into()), Some("1.1".into()), None, Some("2".into())]);
+}
+
+fn collect_heading_numbers(tags: &[djot::ast::Tag], acc: &mut Vec<Option<String>>) {
+  for tag in tags {
+    if let djot::ast::TagKind::Heading(heading) = &tag.kind {
+      acc.push(heading.number.clone())
+    }
+    collect_heading_numbers(&tag.children, acc)
+  }
+}
+
+#[test]
+fn sections() {
+  let source = "# One\n\ntext\n\n## Sub\n\n# One\n";
+  let opts = djot::HtmlOpts { sections: true, ..djot::HtmlOpts::default() };
+  let html = djot::Document::parse(source).to_html_opts(&opts);
+  assert_eq!(
+    html,
+    "<section id=\"One\" aria-labelledby=\"One-heading\">
+<h1 id=\"One-heading\">One</h1>
+<p>text</p>
+<section id=\"Sub\" aria-labelledby=\"Sub-heading\">
+<h2 id=\"Sub-heading\">Sub</h2>
+</section>
+</section>
+<section id=\"One-1\" aria-labelledby=\"One-1-heading\">
+<h1 id=\"One-1-heading\">One</h1>
+</section>
+"
+  );
+}
+
+#[test]
+fn heading_offset() {
+  let source = "# One\n\n##### Five\n";
+  let opts = djot::HtmlOpts { heading_offset: 2, ..djot::HtmlOpts::default() };
+  let html = djot::Document::parse(source).to_html_opts(&opts);
+  assert_eq!(html, "<h3 id=\"One\">One</h3>\n<h6 id=\"Five\">Five</h6>\n");
+
+  let opts = djot::HtmlOpts { heading_offset: -3, ..djot::HtmlOpts::default() };
+  let html = djot::Document::parse(source).to_html_opts(&opts);
+  assert_eq!(html, "<h1 id=\"One\">One</h1>\n<h2 id=\"Five\">Five</h2>\n");
+  // `h6` is the deepest level even without an offset
+  let html = djot::Document::parse("####### Seven\n").to_html();
+  assert_eq!(html, "<h6 id=\"Seven\">Seven</h6>\n");
+}
+
+#[test]
+fn heading_ids() {
+  let mut doc = djot::Document::parse("# One *two*\n\n## One two\n\n# Three\n");
+  assert_eq!(
+    doc.to_html(),
+    "<h1 id=\"One-two\">One <strong>two</strong></h1>\n<h2 id=\"One-two-1\">One two</h2>\n\
+     <h1 id=\"Three\">Three</h1>\n"
+  );
+  let opts = djot::HtmlOpts { no_heading_ids: true, ..djot::HtmlOpts::default() };
+  assert_eq!(
+    doc.to_html_opts(&opts),
+    "<h1>One <strong>two</strong></h1>\n<h2>One two</h2>\n<h1>Three</h1>\n"
+  );
+  // an explicit identifier on the heading wins
+  doc.children[1].children[0].set_id("third");
+  assert!(doc.to_html().ends_with("<h1 id=\"third\">Three</h1>\n"));
+  assert!(doc.to_html_opts(&opts).ends_with("<h1 id=\"third\">Three</h1>\n"));
+}
+
+#[test]
+fn compact() {
+  let source = "# Title\n\nsome\ntext\n\n```\ncode\n```\n";
+  let opts = djot::HtmlOpts { compact: true, sections: true, ..djot::HtmlOpts::default() };
+  let html = djot::Document::parse(source).to_html_opts(&opts);
+  assert_eq!(
+    html,
+    "<section id=\"Title\" aria-labelledby=\"Title-heading\"><h1 id=\"Title-heading\">Title</h1>\
+     <p>some\ntext</p><pre><code>code\n</code></pre></section>"
+  );
+}
+
+#[test]
+fn insert_delete() {
+  use djot::ast::{Attrs, Delete, Insert, Para, Str, Tag};
+
+  let mut attrs = Attrs::new();
+  attrs.insert("class".to_string(), "edit".to_string());
+  let para = Tag::new(Para::default()).with_children(vec![
+    Tag::new(Delete::default()).with_children(vec![Tag::new(Str::new("old"))]),
+    Tag::new(Insert::default()).with_attrs(attrs).with_children(vec![Tag::new(Str::new("new"))]),
+  ]);
+  let doc = djot::Document::new(vec![para]);
+  assert_eq!(doc.to_html(), "<p><del>old</del><ins class=\"edit\">new</ins></p>\n");
+}
+
+#[test]
+fn highlight() {
+  use djot::ast::{Attrs, Highlight, Para, Str, Tag};
+
+  let mut attrs = Attrs::new();
+  attrs.insert("id".to_string(), "hl".to_string());
+  let para = Tag::new(Para::default()).with_children(vec![Tag::new(Highlight::default())
+    .with_attrs(attrs)
+    .with_children(vec![Tag::new(Str::new("marked"))])]);
+  let doc = djot::Document::new(vec![para]);
+  assert_eq!(doc.to_html(), "<p><mark id=\"hl\">marked</mark></p>\n");
+}
+
+#[test]
+fn symbol_resolver() {
+  let doc = djot::Document::parse("a :+: b :x:\n");
+  assert_eq!(
+    doc.to_html(),
+    "<p>a <span class=\"symbol\">:+:</span> b <span class=\"symbol\">:x:</span></p>\n"
+  );
+
+  let resolver: djot::SymbolResolver =
+    std::sync::Arc::new(|alias| (alias == "+").then(|| "&#x1F44D;".to_string()));
+  let opts = djot::HtmlOpts { symbol_resolver: Some(resolver), ..djot::HtmlOpts::default() };
+  assert_eq!(doc.to_html_opts(&opts), "<p>a &#x1F44D; b <span class=\"symbol\">:x:</span></p>\n");
+
+  let doc = djot::Document::parse(":x:{.big}\n");
+  assert_eq!(doc.to_html(), "<p><span class=\"symbol big\">:x:</span></p>\n");
+}
+
+#[test]
+fn symbol_table() {
+  let doc = djot::Document::parse("It's :smile: o'clock, :heavy_check_mark:\n");
+  let mut opts = djot::HtmlOpts::default();
+  opts.symbols.insert("smile".to_string(), "😄".to_string());
+  opts.symbols.insert("heavy_check_mark".to_string(), "<✔>".to_string());
+  assert_eq!(doc.to_html_opts(&opts), "<p>It&rsquo;s 😄 o&rsquo;clock, &lt;✔&gt;</p>\n");
+}
+
+#[test]
+fn html_display() {
+  let doc = djot::Document::parse("# Hi\n\n*there*\n");
+  let opts = djot::HtmlOpts::default();
+  assert_eq!(
+    format!("<body>{}</body>", doc.html(&opts)),
+    format!("<body>{}</body>", doc.to_html())
+  );
+}
+
+#[test]
+fn render_across_threads() {
+  let doc = std::sync::Arc::new(djot::Document::parse("*shared*\n"));
+  let opts = std::sync::Arc::new(djot::HtmlOpts::default());
+  let handles: Vec<_> = (0..4)
+    .map(|_| {
+      let (doc, opts) = (doc.clone(), opts.clone());
+      std::thread::spawn(move || doc.to_html_opts(&opts))
+    })
+    .collect();
+  for handle in handles {
+    assert_eq!(handle.join().unwrap(), "<p><strong>shared</strong></p>\n");
+  }
+}
+
+#[test]
+fn reusable_parser() {
+  let mut parser = djot::Parser::new(djot::ParseOpts::default());
+  for source in ["*one*\n", "# two", "```\nthree\n```\n", "*one*\n"] {
+    assert_eq!(parser.parse(source).to_html(), djot::Document::parse(source).to_html());
+  }
+  parser.reset();
+  assert_eq!(parser.parse("").to_html(), "");
+}
+
+#[test]
+fn line_index() {
+  use djot::{LineCol, LineIndex};
+
+  let text = "ab\nя😀c\n\nd";
+  let index = LineIndex::new(text);
+  let c = text.find('c').unwrap();
+  assert_eq!(index.line_col(0), LineCol { line: 0, col: 0 });
+  assert_eq!(index.line_col(c), LineCol { line: 1, col: 6 });
+  assert_eq!(index.line_col_utf16(c), LineCol { line: 1, col: 3 });
+  assert_eq!(index.line_col(text.len()), LineCol { line: 3, col: 1 });
+  assert_eq!(index.offset(LineCol { line: 1, col: 6 }), Some(c));
+  assert_eq!(index.offset_utf16(LineCol { line: 1, col: 3 }), Some(c));
+  assert_eq!(index.offset(LineCol { line: 2, col: 0 }), Some(text.find("\n\n").unwrap() + 1));
+  assert_eq!(index.offset(LineCol { line: 0, col: 7 }), None);
+  assert_eq!(index.offset(LineCol { line: 4, col: 0 }), None);
+}
+
+#[test]
+fn diagnostic_render() {
+  let source = "first\n\tfoo `bar\n";
+  let index = djot::LineIndex::new(source);
+  let start = source.find('`').unwrap();
+  let diagnostic = djot::Diagnostic::warning(start..start + 4, "unclosed verbatim");
+  assert_eq!(
+    diagnostic.render(source, &index, false),
+    "warning: unclosed verbatim
+ --> 2:6
+  |
+2 | \tfoo `bar
+  | \t    ^^^^
+"
+  );
+  assert!(diagnostic.render(source, &index, true).contains("\x1b[1;33mwarning\x1b[0m"));
+}
+
+#[test]
+fn diff_documents() {
+  use djot::{ast::Tag, Change};
+
+  fn apply(children: &mut Vec<Tag>, change: &Change) {
+    let (path, tag) = match change {
+      Change::Insert { path, tag } | Change::Replace { path, tag } => (path, Some(tag)),
+      Change::Delete { path } => (path, None),
+    };
+    let (last, parents) = path.split_last().unwrap();
+    let children = parents.iter().fold(children, |it, &idx| &mut it[idx].children);
+    match change {
+      Change::Insert { .. } => children.insert(*last, tag.unwrap().clone()),
+      Change::Replace { .. } => children[*last] = tag.unwrap().clone(),
+      Change::Delete { .. } => drop(children.remove(*last)),
+    }
+  }
+
+  let old = djot::Document::parse("# Title\n\none\n\ntwo *three*\n\nfour\n");
+  let new = djot::Document::parse("# Title\n\nzero\n\none\n\ntwo _three_\n");
+  let changes = djot::diff(&old, &new);
+  assert_eq!(changes.len(), 3, "{changes:#?}");
+  assert!(matches!(&changes[0], Change::Insert { path, .. } if path == &[0, 1]));
+  assert!(matches!(&changes[1], Change::Replace { path, .. } if path == &[0, 3, 1]));
+  assert!(matches!(&changes[2], Change::Delete { path } if path == &[0, 4]));
+
+  let mut children = old.children.clone();
+  for change in &changes {
+    apply(&mut children, change);
+  }
+  assert_eq!(children, new.children);
+  assert!(djot::diff(&new, &new).is_empty());
+}
+
+#[test]
+fn stable_node_ids() {
+  let old = djot::Document::parse("# Title\n\none *two*\n\nthree\n");
+  let new = old.reparse(djot::ParseOpts::default(), "# Title\n\none *two*\n\nthree!\n");
+  let (old_section, new_section) = (&old.children[0], &new.children[0]);
+  assert_eq!(old_section.id, new_section.id);
+  // the untouched paragraph and all of its descendants keep their ids
+  assert_eq!(old_section.children[1].id, new_section.children[1].id);
+  assert_eq!(old_section.children[1].children[1].id, new_section.children[1].children[1].id);
+  // the edited paragraph is kept, its text node is new
+  assert_eq!(old_section.children[2].id, new_section.children[2].id);
+  let (old_str, new_str) =
+    (&old_section.children[2].children[0], &new_section.children[2].children[0]);
+  assert_ne!(old_str.id, new_str.id);
+
+  let mut ids = Vec::new();
+  collect_ids(&new.children, &mut ids);
+  let count = ids.len();
+  ids.sort();
+  ids.dedup();
+  assert_eq!(ids.len(), count);
+}
+
+fn collect_ids(tags: &[djot::ast::Tag], acc: &mut Vec<djot::ast::NodeId>) {
+  for tag in tags {
+    acc.push(tag.id);
+    collect_ids(&tag.children, acc)
+  }
+}
+
+#[test]
+fn attr_helpers() {
+  use djot::ast::{Attrs, Para, Tag};
+
+  let mut tag = Tag::new(Para::default());
+  tag.add_class("a");
+  tag.add_class("b");
+  tag.add_class("a");
+  assert_eq!(tag.get_attr("class"), Some("a b"));
+  assert!(tag.has_class("b"));
+  tag.set_id("x");
+  assert_eq!(tag.id(), Some("x"));
+
+  let mut attrs = Attrs::new();
+  attrs.insert("class".to_string(), "c a".to_string());
+  attrs.insert("id".to_string(), "y".to_string());
+  tag.merge_attrs(&attrs);
+  assert_eq!(tag.get_attr("class"), Some("a b c"));
+  assert_eq!(tag.id(), Some("y"));
+
+  tag.remove_class("a");
+  tag.remove_class("c");
+  assert_eq!(tag.get_attr("class"), Some("b"));
+  tag.remove_class("b");
+  assert_eq!(tag.get_attr("class"), None);
+  tag.set_attr("class", "z");
+  assert_eq!(tag.get_attr("class"), Some("z"));
+}
+
+#[test]
+fn ast_macro() {
+  let doc = djot::Document::parse("# Hi\n\nSome *bold* [link](/url)\n");
+  let expected = djot::djot_ast! {
+    section { id = "Hi" } [
+      heading(1) ["Hi"],
+      para ["Some ", strong ["bold"], " ", link("/url") ["link"]],
+    ]
+  };
+  assert_eq!(doc.children, vec![expected]);
+}
+
+#[test]
+fn ast_constructors() {
+  use djot::ast::{CodeBlock, Heading, Link, Para, Str, Tag};
+
+  let link = Tag::from(Link::from_reference("ref")).with_children(vec![Str::from("a").into()]);
+  let doc = djot::Document::from(vec![
+    Heading::new(2).into(),
+    Tag::from(Para::default()).with_children(vec![link]),
+    CodeBlock::new("x\n").with_lang("rust").into(),
+  ]);
+  assert_eq!(doc.to_djot(), "## \n\n[a][ref]\n\n``` rust\nx\n```\n");
+}
+
+#[test]
+fn dump() {
+  let doc = djot::Document::parse(
+    "# Hi\n\nSome *bold* text, and a very long [link](https://example.com/a/very/long/path/to/somewhere)\n",
+  );
+  assert_eq!(
+    doc.dump(),
+    r#"doc
+  section {id="Hi"}
+    heading level=1
+      str text="Hi"
+    para
+      str text="Some "
+      strong
+        str text="bold"
+      str text=" text, and a very long "
+      link destination="https://example.com/a/very/long/path/to/…"
+        str text="link"
+"#
+  );
+}
+
+#[test]
+fn code_highlighter() {
+  let highlighter: djot::CodeHighlighter = std::sync::Arc::new(|code, lang| {
+    let lang = lang?;
+    Some(format!("<pre class=\"hl-{lang}\">{}</pre>", code.trim_end()))
+  });
+  let opts = djot::HtmlOpts { highlighter: Some(highlighter), ..djot::HtmlOpts::default() };
+  let doc = djot::Document::parse("``` rust\nfn main() {}\n```\n\n```\nplain\n```\n");
+  assert_eq!(
+    doc.to_html_opts(&opts),
+    "<pre class=\"hl-rust\">fn main() {}</pre>\n<pre><code>plain\n</code></pre>\n"
+  );
+}
+
+#[cfg(feature = "axum")]
+#[test]
+fn axum_response() {
+  use axum::response::IntoResponse;
+
+  let doc = djot::Document::parse("hi\n");
+  let res = djot::web::DjotHtml(doc, djot::HtmlOpts::default()).into_response();
+  assert_eq!(res.status(), 200);
+  assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+  assert_eq!(ready(axum::body::to_bytes(res.into_body(), usize::MAX)).unwrap(), "<p>hi</p>\n");
+
+  let doc = long_document();
+  let opts =
+    djot::HtmlOpts { standalone: true, number_headings: true, ..djot::HtmlOpts::default() };
+  let expected = doc.to_html_opts(&opts);
+  let res = djot::web::DjotHtml(doc, opts).into_response();
+  assert_eq!(ready(axum::body::to_bytes(res.into_body(), usize::MAX)).unwrap(), expected);
+}
+
+#[cfg(feature = "actix")]
+#[test]
+fn actix_response() {
+  use actix_web::{body::MessageBody, Responder};
+
+  let doc = djot::Document::parse("hi\n");
+  let req = actix_web::test::TestRequest::default().to_http_request();
+  let res = djot::web::DjotHtml(doc, djot::HtmlOpts::default()).respond_to(&req);
+  assert_eq!(res.status(), 200);
+  assert_eq!(res.headers().get("content-type").unwrap(), "text/html; charset=utf-8");
+  assert_eq!(ready(actix_web::body::to_bytes(res.into_body())).unwrap(), "<p>hi</p>\n");
+
+  // sent in pieces
+  let doc = long_document();
+  let expected = doc.to_html();
+  let mut body = djot::web::DjotHtml(doc, djot::HtmlOpts::default()).respond_to(&req).into_body();
+  let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+  let mut chunks = Vec::new();
+  while let std::task::Poll::Ready(Some(chunk)) = std::pin::Pin::new(&mut body).poll_next(&mut cx) {
+    chunks.push(chunk.unwrap());
+  }
+  assert!(chunks.len() > 1);
+  assert_eq!(chunks.concat(), expected.as_bytes());
+}
+
+/// Many sections with cross references and footnotes, too long to be
+/// rendered in one piece.
+#[cfg(any(feature = "axum", feature = "actix"))]
+fn long_document() -> djot::Document {
+  let mut source = String::new();
+  let text = "lorem ipsum ".repeat(11);
+  for i in 0..30 {
+    source.push_str(&format!(
+      "# Part {i}\n\nSee [Part 0][] and a note[^{i}]. {text}\n\n[^{i}]: Note {i}.\n\n"
+    ));
+  }
+  djot::Document::parse(&source)
+}
+
+/// Polls a future which never has to wait.
+#[cfg(any(feature = "axum", feature = "actix"))]
+fn ready<F: std::future::Future>(fut: F) -> F::Output {
+  let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+  match std::pin::pin!(fut).poll(&mut cx) {
+    std::task::Poll::Ready(it) => it,
+    std::task::Poll::Pending => panic!("the body had to wait"),
+  }
+}
+
+#[cfg(feature = "minijinja")]
+#[test]
+fn minijinja_filter() {
+  let mut env = minijinja::Environment::new();
+  djot::template::register_minijinja(&mut env, djot::HtmlOpts::default());
+  env.add_template("page.html", "{{ body | djot }}{{ djot(\"_x_\") }}").unwrap();
+  let ctx = minijinja::context! { body => "*hi*" };
+  let html = env.get_template("page.html").unwrap().render(ctx).unwrap();
+  assert_eq!(html, "<p><strong>hi</strong></p>\n<p><em>x</em></p>\n");
+}
+
+#[cfg(feature = "tera")]
+#[test]
+fn tera_filter() {
+  let mut tera = tera::Tera::default();
+  djot::template::register_tera(&mut tera, djot::HtmlOpts::default());
+  tera.add_raw_template("page.html", "{{ body | djot }}{{ djot(text=\"_x_\") }}").unwrap();
+  let mut ctx = tera::Context::new();
+  ctx.insert("body", "*hi*");
+  let html = tera.render("page.html", &ctx).unwrap();
+  assert_eq!(html, "<p><strong>hi</strong></p>\n<p><em>x</em></p>\n");
+}
+
+#[test]
+fn lint() {
+  use djot::lint::{Context, Linter, Rule};
+
+  let source = "# One\n\n### Three\n\nSee https://example.com  \nand ![](a.png).\n\n``` txt\ntrailing  \n```\n";
+  let diagnostics = djot::lint::lint(source);
+  let codes: Vec<_> = diagnostics.iter().map(|it| it.code.unwrap()).collect();
+  assert_eq!(codes, ["heading-increment", "bare-urls", "trailing-whitespace", "missing-alt-text"]);
+  assert_eq!(&source[diagnostics[0].range.clone()], "### Three");
+  assert_eq!(&source[diagnostics[1].range.clone()], "https://example.com");
+  assert_eq!(&source[diagnostics[3].range.clone()], "![](a.png)");
+
+  // the suggested hard break
+  let mut fixed = source.to_string();
+  fixed.replace_range(diagnostics[2].range.clone(), "\\");
+  let html = djot::Document::parse(&fixed).to_html();
+  assert!(html.contains("<p>See https://example.com<br>\nand"));
+
+  let long = format!("{}\n", "a".repeat(130));
+  let diagnostics = djot::lint::lint(&long);
+  assert_eq!(diagnostics[0].code, Some("long-lines"));
+  assert_eq!(diagnostics[0].range, 0..130);
+
+  struct NoEmph;
+  impl Rule for NoEmph {
+    fn name(&self) -> &'static str {
+      "no-emph"
+    }
+    fn check(&self, cx: &Context<'_>, diagnostics: &mut Vec<djot::Diagnostic>) {
+      cx.walk(&mut |tag| {
+        if let djot::ast::TagKind::Emph(_) = tag.kind {
+          diagnostics.push(djot::Diagnostic::warning(tag.span.clone(), "emphasis"))
+        }
+      })
+    }
+  }
+  let diagnostics = Linter::new().rule(NoEmph).check("a _b_\n");
+  assert_eq!(diagnostics, [djot::Diagnostic::warning(2..5, "emphasis").with_code("no-emph")]);
+}
+
+#[test]
+fn prose_segments() {
+  let source = "# Teh title\n\nSome _emphasis_ and `code`\nacross \\*lines* <https://x.y>.\n\n```\nfn main() {}\n```\n";
+  let doc = djot::Document::parse(source);
+  let segments = doc.prose();
+  let texts: Vec<_> = segments.iter().map(|it| it.text.as_str()).collect();
+  assert_eq!(texts, ["Teh title", "Some emphasis and ", "\nacross *lines* ", "."]);
+
+  let emphasis = &segments[1];
+  assert_eq!(&source[emphasis.range.clone()], "Some _emphasis_ and ");
+  let offset = emphasis.source_offset(emphasis.text.find("emphasis").unwrap());
+  assert_eq!(&source[offset..offset + 8], "emphasis");
+  let lines = &segments[2];
+  let offset = lines.source_offset(lines.text.find("lines").unwrap());
+  assert_eq!(&source[offset..offset + 5], "lines");
+}
+
+#[test]
+fn search_records() {
+  let source = "intro\n\n# Guide\n\nFirst *step*.\n\n## Install\n\n```\ncargo add djot\n```\n";
+  let mut doc = djot::Document::parse(source);
+  doc.children[1].children[0].set_attr("search-weight", "2");
+  let records = doc.search_records();
+  assert_eq!(records.len(), 3);
+  assert_eq!(records[0].id, None);
+  assert_eq!(records[0].text, "intro");
+  assert_eq!(records[1].id.as_deref(), Some("Guide"));
+  assert_eq!(records[1].path, ["Guide"]);
+  assert_eq!(records[1].text, "First step.");
+  assert_eq!(records[1].weight, Some(2.0));
+  assert_eq!(records[2].path, ["Guide", "Install"]);
+  assert_eq!(records[2].text, "cargo add djot");
+  assert_eq!(
+    serde_json::to_string(&records[2]).unwrap(),
+    r#"{"id":"Install","path":["Guide","Install"],"text":"cargo add djot"}"#
+  );
+}
+
+#[test]
+fn excerpt() {
+  use djot::{ExcerptFormat, ExcerptOpts};
+
+  let source = "# Post\n\nFirst *paragraph*.\n\nSecond.\n\nRest.\n";
+  let mut doc = djot::Document::parse(source);
+  assert_eq!(doc.excerpt(&ExcerptOpts::default()), "First paragraph.");
+  let html = ExcerptOpts {
+    format: ExcerptFormat::Html(djot::HtmlOpts::default()),
+    ..ExcerptOpts::default()
+  };
+  assert_eq!(doc.excerpt(&html), "<p>First <strong>paragraph</strong>.</p>\n");
+
+  doc.children[0].children[3].add_class("more");
+  assert_eq!(doc.excerpt(&ExcerptOpts::default()), "Post\n\nFirst paragraph.\n\nSecond.");
+}
+
+#[test]
+fn parse_from_reader() {
+  let source = "# Title\r\n\nsome *text*\nacross lines\n\n```\ncode\n```\n\nno newline";
+  let opts = djot::ParseOpts::default();
+  let doc = djot::Document::parse_from_reader(source.as_bytes(), opts.clone()).unwrap();
+  // compares spans and ids too
+  assert_eq!(format!("{doc:?}"), format!("{:?}", djot::Document::parse(source)));
+
+  // blocks are built as they are read, which mustn't make a difference
+  let mut all = String::new();
+  for path in fs::read_dir("./tests/data").unwrap() {
+    let path = path.unwrap().path();
+    if path.extension().unwrap_or_default() == "test" {
+      for test_case in parse_test(&fs::read_to_string(&path).unwrap()) {
+        let doc = djot::Document::parse_from_reader(test_case.djot.as_bytes(), opts.clone());
+        let expected = djot::Document::parse(&test_case.djot);
+        assert_eq!(format!("{:?}", doc.unwrap()), format!("{expected:?}"), "{}", test_case.djot);
+        all.push_str(&test_case.djot);
+        all.push('\n');
+      }
+    }
+  }
+  let doc = djot::Document::parse_from_reader(all.as_bytes(), opts).unwrap();
+  assert_eq!(format!("{doc:?}"), format!("{:?}", djot::Document::parse(&all)));
+
+  let invalid: &[u8] = b"ok\n\xff\n";
+  let err = djot::Document::parse_from_reader(invalid, djot::ParseOpts::default()).unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn parse_lines() {
+  let source = "# Title\n\nsome *text*\nacross lines\n\n```\ncode\n```\n";
+  let doc = djot::Document::parse_lines(source.lines());
+  assert_eq!(format!("{doc:?}"), format!("{:?}", djot::Document::parse(source)));
+
+  let lines = vec![String::from("a\r\n"), String::from("_b_")];
+  let doc = djot::Document::parse_lines(&lines);
+  assert_eq!(doc.to_html(), djot::Document::parse("a\r\n_b_\n").to_html());
+}
+
+#[test]
+fn parse_bytes() {
+  use djot::{Document, ParseOpts, Utf8Validation};
+
+  let doc = Document::parse_bytes(b"*x*\n", ParseOpts::default(), Utf8Validation::Strict).unwrap();
+  assert_eq!(doc.to_html(), "<p><strong>x</strong></p>\n");
+  let invalid = b"a\xffb\n";
+  let err =
+    Document::parse_bytes(invalid, ParseOpts::default(), Utf8Validation::Strict).unwrap_err();
+  assert!(matches!(err, djot::ParseError::Utf8(err) if err.valid_up_to() == 1));
+  let doc = Document::parse_bytes(invalid, ParseOpts::default(), Utf8Validation::Lossy).unwrap();
+  assert_eq!(doc.to_html(), "<p>a\u{FFFD}b</p>\n");
+
+  // valid input is parsed in place, which mustn't make a difference
+  let parse_bytes = |opts: ParseOpts, source: &str| {
+    Document::parse_bytes(source.as_bytes(), opts, Utf8Validation::Strict)
+  };
+  let opts = ParseOpts { source_positions: true, ..ParseOpts::default() };
+  for source in ["# Title\n\nsome *text*\n", "no newline", "", "caf\u{e9}"] {
+    let doc = parse_bytes(opts.clone(), source).unwrap();
+    assert_eq!(format!("{doc:?}"), format!("{:?}", Document::parse_opts(opts.clone(), source)));
+  }
+  let opts = ParseOpts { max_input_bytes: Some(4), ..ParseOpts::default() };
+  let err = parse_bytes(opts.clone(), "caf\u{e9} *bien*\n").unwrap_err();
+  assert_eq!(err, djot::ParseError::InputTooLarge { len: 13, limit: 4 });
+  let opts = ParseOpts { truncate_input: true, ..opts };
+  let doc = parse_bytes(opts.clone(), "caf\u{e9} *bien*\n").unwrap();
+  assert_eq!(doc, Document::parse_opts(opts, "caf\u{e9} *bien*\n"));
+  let source = "first *para*\n\nsecond para\n";
+  let opts = ParseOpts { max_steps: Some(5), ..ParseOpts::default() };
+  let err = parse_bytes(opts.clone(), source).unwrap_err();
+  let djot::ParseError::BudgetExceeded { partial } = err else { panic!("{err:?}") };
+  assert_eq!(*partial, Document::parse_opts(opts, source));
+}
+
+#[test]
+fn validate() {
+  let opts = djot::ParseOpts::default();
+  assert_eq!(djot::validate("*fine*\n\n```\ncode\n```\n", opts.clone()), []);
+
+  let source = "some `code\n\n~~~\nnever closed\n";
+  let diagnostics = djot::validate(source, opts);
+  let messages: Vec<_> = diagnostics.iter().map(|it| it.message.as_str()).collect();
+  assert_eq!(messages, ["unclosed verbatim", "unclosed code block"]);
+  assert_eq!(&source[diagnostics[0].range.clone()], "`code");
+  assert_eq!(&source[diagnostics[1].range.clone()], "~~~");
+}
+
+#[test]
+fn escape_html() {
+  let mut doc = djot::Document::parse("a < b && c > d\n");
+  doc.children[0].set_attr("title", "say \"hi\" & <bye>");
+  assert_eq!(
+    doc.to_html(),
+    "<p title=\"say &quot;hi&quot; &amp; &lt;bye&gt;\">a &lt; b &amp;&amp; c &gt; d</p>\n"
+  );
+
+  // quotes only need escaping in attribute values
+  let doc = djot::Document::parse(
+    "[a<b>]{title=\"x&y\\\"z\"} `a\"<b>` [l](http://x?a=1&b=\"2\")\n\n```\n<&>\"\n```\n",
+  );
+  assert_eq!(
+    doc.to_html(),
+    "<p><span title=\"x&amp;y&quot;z\">a&lt;b&gt;</span> <code>a\"&lt;b&gt;</code> \
+     <a href=\"http://x?a=1&amp;b=&quot;2&quot;\">l</a></p>\n\
+     <pre><code>&lt;&amp;&gt;\"\n</code></pre>\n"
+  );
+}
+
+#[test]
+fn lazy_inlines() {
+  let source =
+    "# A *heading*\n\nSome _text_\nand [a link][].\n\n> quoted `code`\n\n[a link]: /url\n";
+  let eager = djot::Document::parse(source);
+  let opts = djot::ParseOpts { lazy_inlines: true, ..djot::ParseOpts::default() };
+  let mut lazy = djot::Document::parse_opts(opts, source);
+  assert!(matches!(lazy.children[0].children[1].children[0].kind, djot::ast::TagKind::Unparsed(_)));
+  assert_eq!(lazy.to_html(), eager.to_html());
+
+  lazy.parse_inlines();
+  assert_eq!(format!("{:?}", lazy.children), format!("{:?}", eager.children));
+}
+
+#[test]
+fn session() {
+  let opts = djot::HtmlOpts { compact: true, ..djot::HtmlOpts::default() };
+  let mut session = djot::Session::new(djot::ParseOpts::default(), opts.clone());
+  for source in ["# One\n\n*a*\n", "two `b`", "", "three\n\n```\ncode\n```\n"] {
+    let want = djot::Document::parse(source).to_html_opts(&opts);
+    assert_eq!(session.to_html(source).unwrap(), want);
+  }
+}
+
+#[test]
+fn stats() {
+  let opts = djot::ParseOpts { stats: true, ..djot::ParseOpts::default() };
+  let doc = djot::Document::parse_opts(opts, "# A\n\n*b* c `d\n");
+  let stats = doc.stats.unwrap();
+  assert_eq!(stats.bytes, 14);
+  assert_eq!(stats.warnings, 1);
+  assert_eq!(stats.nodes["Para"], 1);
+  assert_eq!(stats.nodes["Str"], 4);
+  assert!(djot::Document::parse("a").stats.is_none());
+}
+
+#[test]
+fn parse_lossy() {
+  let (doc, diagnostics) = djot::Document::parse_lossy(b"caf\xe9 *\xff\xfe*\n");
+  assert_eq!(doc.to_html(), "<p>caf\u{fffd} <strong>\u{fffd}\u{fffd}</strong></p>\n");
+  let ranges: Vec<_> = diagnostics.iter().map(|it| it.range.clone()).collect();
+  assert_eq!(ranges, [3..4, 6..8]);
+
+  let mut parser = djot::Parser::new(djot::ParseOpts::default());
+  let (doc, diagnostics) = parser.parse_lossy(b"caf\xe9\n", djot::Utf8Validation::Latin1);
+  assert_eq!(doc.to_html(), "<p>caf\u{e9}</p>\n");
+  assert_eq!(diagnostics.len(), 1);
+  let (_, diagnostics) = parser.parse_lossy("caf\u{e9}\n".as_bytes(), djot::Utf8Validation::Latin1);
+  assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn max_input_bytes() {
+  let opts = djot::ParseOpts { max_input_bytes: Some(8), ..djot::ParseOpts::default() };
+  let err = djot::Document::try_parse_opts(opts.clone(), "*strong*\n").unwrap_err();
+  assert_eq!(err, djot::ParseError::InputTooLarge { len: 9, limit: 8 });
+  assert!(djot::Document::try_parse_opts(opts.clone(), "*strong*").is_ok());
+  // which the infallible entry points truncate instead
+  assert_eq!(
+    djot::Document::parse_opts(opts.clone(), "*strong*\n").to_html(),
+    "<p><strong>strong</strong></p>\n"
+  );
+  let doc = djot::Parser::new(opts.clone()).parse_lines(["*strong*", "more"]);
+  assert_eq!(doc.to_html(), "<p><strong>strong</strong></p>\n");
+
+  // never cut a character in half
+  let opts = djot::ParseOpts { max_input_bytes: Some(4), truncate_input: true, ..opts };
+  let doc = djot::Document::parse_opts(opts.clone(), "caf\u{e9} *bien*\n");
+  assert_eq!(doc.to_html(), "<p>caf</p>\n");
+  let diagnostics = djot::Parser::new(opts).validate("caf\u{e9} *bien*\n");
+  assert_eq!(diagnostics.len(), 1);
+  assert_eq!(diagnostics[0].range, 3..13);
+}
+
+#[test]
+fn budget() {
+  let source = "first *para*\n\nsecond para\n\nthird para\n";
+  let opts = djot::ParseOpts { max_steps: Some(5), ..djot::ParseOpts::default() };
+  let err = djot::Document::try_parse_opts(opts.clone(), source).unwrap_err();
+  let djot::ParseError::BudgetExceeded { partial } = err else { panic!("{err:?}") };
+  assert_eq!(partial.to_html(), "<p>first <strong>para</strong></p>\n");
+  assert_eq!(djot::Parser::new(opts.clone()).validate(source).len(), 1);
+  // the infallible entry points return what was parsed
+  assert_eq!(djot::Document::parse_opts(opts.clone(), source), *partial);
+  let lines = djot::Parser::new(opts).parse_lines(source.lines());
+  assert_eq!(lines.to_html(), partial.to_html());
+
+  let opts = djot::ParseOpts { max_steps: Some(100), ..djot::ParseOpts::default() };
+  assert!(djot::Document::try_parse_opts(opts, source).is_ok());
+}
+
+#[test]
+fn invisible_chars() {
+  let source = "access\u{202e}\u{2066}level\n\n`x\u{200b}y` \u{1f468}\u{200d}\u{1f469}\n";
+  let diagnostics = djot::lint::Linter::new().rule(djot::lint::InvisibleCharacters).check(source);
+  let ranges: Vec<_> = diagnostics.iter().map(|it| it.range.clone()).collect();
+  assert_eq!(ranges, [6..9, 9..12, 21..24]);
+  assert_eq!(diagnostics[0].message, "bidi control character U+202E");
+
+  let doc = djot::Document::parse(source);
+  let opts = |invisible_chars| djot::HtmlOpts { invisible_chars, ..djot::HtmlOpts::default() };
+  assert_eq!(
+    doc.to_html_opts(&opts(djot::InvisibleChars::Strip)),
+    "<p>accesslevel</p>\n<p><code>xy</code> \u{1f468}\u{200d}\u{1f469}</p>\n"
+  );
+  assert_eq!(
+    doc.to_html_opts(&opts(djot::InvisibleChars::Escape)),
+    "<p>access&lt;U+202E&gt;&lt;U+2066&gt;level</p>\n\
+     <p><code>x&lt;U+200B&gt;y</code> \u{1f468}\u{200d}\u{1f469}</p>\n"
+  );
+  // attribute values too
+  let doc = djot::Document::parse("{title=\"a\u{202e}b\"}\npara\n");
+  assert_eq!(doc.to_html_opts(&opts(djot::InvisibleChars::Strip)), "<p title=\"ab\">para</p>\n");
+  assert_eq!(
+    doc.to_html_opts(&opts(djot::InvisibleChars::Escape)),
+    "<p title=\"a&lt;U+202E&gt;b\">para</p>\n"
+  );
+}
+
+#[test]
+fn max_nesting() {
+  let opts = djot::ParseOpts { max_nesting: Some(2), ..djot::ParseOpts::default() };
+  let source = "*a _b ^c^ [d](u) b_ a*\n";
+  let doc = djot::Document::parse_opts(opts.clone(), source);
+  assert_eq!(doc.to_html(), "<p><strong>a <em>b ^c^ [d](u) b</em> a</strong></p>\n");
+  let mut parser = djot::Parser::new(opts.clone());
+  let diagnostics = parser.validate(source);
+  assert_eq!(diagnostics.len(), 1);
+  assert_eq!(diagnostics[0].range, 6..7);
+
+  assert!(parser.validate("> > a\n").is_empty());
+  assert!(parser.validate("> > ***\n").is_empty());
+  let doc = djot::Document::parse_opts(opts, "> > > a\n");
+  assert_eq!(
+    doc.to_html(),
+    "<blockquote>\n<blockquote>\n<p>&gt; a</p>\n</blockquote>\n</blockquote>\n"
+  );
+  let diagnostics = parser.validate("> > > a\n");
+  assert_eq!(diagnostics.len(), 1);
+  assert_eq!(diagnostics[0].range, 4..7);
+}
+
+#[test]
+fn roundtrip() {
+  let source = "# A *heading*\n\
+                \n\
+                Some _text_ with {_ spaces _}\n\
+                and a [link](/u)! ![img](i.png), ``a`b``.\n\
+                \n\
+                a*b <http://x.y> \"q\" ~s~ ^p^ # x: y\n\
+                \n\
+                \\# not a heading\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(
+    doc.to_djot(),
+    "# A *heading*\n\
+     \n\
+     Some _text_ with {_ spaces _}\n\
+     and a [link](/u)! ![img](i.png), ``a`b``.\n\
+     \n\
+     a*b <http://x.y> \"q\" ~s~ ^p^ # x: y\n\
+     \n\
+     \\# not a heading\n"
+  );
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn roundtrip_plain_text() {
+  for text in ["a < b", "x_y", "see [1] above", "f(x) = {y}", "1 + 1 - 2 * 3", "a: b | c"] {
+    assert_eq!(djot::Document::parse(text).to_djot(), format!("{text}\n"));
+    djot::roundtrip_check(text).unwrap();
+  }
+
+  let words = [
+    "a", "b", "x_y", "<", ">", "[1]", "f(x)", "=", "{y}", "{a", "b}", "1.", "a)", "#", "-", "--",
+    "*", "+", "~", "^", ":", "::", "|", "!", "?", "...", "a.b", "$5", "100%", "(c)", "[", "]",
+    "<b>", "\\", "_", "x^2", "a-b", "c~d", "e*f", ":::",
+  ];
+  let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+  let mut next = |n: usize| {
+    seed ^= seed << 13;
+    seed ^= seed >> 7;
+    seed ^= seed << 17;
+    seed as usize % n
+  };
+  for _ in 0..5000 {
+    let lines = 1 + next(3);
+    let text = (0..lines)
+      .map(|_| {
+        // lines start with a word, as block markers aren't plain text
+        let len = next(6);
+        let line = (0..len).map(|_| words[next(words.len())]).collect::<Vec<_>>().join(" ");
+        format!("{} {line}", ["a", "b", "see", "f(x)"][next(4)])
+      })
+      .collect::<Vec<_>>()
+      .join("\n");
+    if let Err(err) = djot::roundtrip_check(&text) {
+      panic!("{text:?}: {err}\n{}", err.djot)
+    }
+  }
+}
+
+#[cfg(feature = "rhai")]
+#[test]
+fn script_filter() {
+  let filter = djot::script::ScriptFilter::new(
+    r#"
+      fn str(node) {
+        node.text = node.text.to_upper();
+        node
+      }
+      fn emph(node) {
+        node.children
+      }
+      fn verbatim(node) {
+        []
+      }
+    "#,
+  )
+  .unwrap();
+  let mut doc = djot::Document::parse("a _b_ `c` *d*\n");
+  filter.apply(&mut doc).unwrap();
+  assert_eq!(doc.to_html(), "<p>A B  <strong>D</strong></p>\n");
+  assert_eq!(doc.children[0].children[0].span, 0..2);
+
+  let filter = djot::script::ScriptFilter::new("fn str(node) { node.nope() }").unwrap();
+  assert!(filter.apply(&mut doc).is_err());
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn config_file() {
+  let dir = std::env::temp_dir().join("djot-config-test");
+  std::fs::create_dir_all(&dir).unwrap();
+  let path = dir.join("djot.toml");
+  std::fs::write(
+    &path,
+    r#"
+      filters = ["links.rhai"]
+
+      [parse]
+      max_nesting = 2
+      time_limit_ms = 500
+
+      [html]
+      heading_offset = 1
+      compact = true
+
+      [html.labels]
+      footnotes = "Notes"
+    "#,
+  )
+  .unwrap();
+  let config = djot::config::Config::from_config_file(&path).unwrap();
+  assert_eq!(config.parse.max_nesting, Some(2));
+  assert_eq!(config.parse.time_limit, Some(std::time::Duration::from_millis(500)));
+  assert_eq!(config.filters, vec![dir.join("links.rhai")]);
+  let doc = djot::Document::parse("# a\n");
+  assert_eq!(doc.to_html_opts(&config.html), "<h2 id=\"a\">a</h2>");
+  assert_eq!(config.html.labels.footnotes, "Notes");
+  assert_eq!(config.html.labels.note, "Note");
+
+  let err = djot::config::Config::from_toml("[html]\nsection = true\n").err().unwrap();
+  assert!(err.to_string().contains("section"));
+}
+
+#[test]
+fn text_direction() {
+  let opts = djot::HtmlOpts { dir: Some(djot::TextDirection::Rtl), ..djot::HtmlOpts::default() };
+  let doc = djot::Document::parse("שלום `x` עולם\n\nhello `y`\n\n```\nz\n```\n");
+  assert_eq!(
+    doc.to_html_opts(&opts),
+    "<p>שלום <code dir=\"ltr\">x</code> עולם</p>\n<p dir=\"ltr\">hello <code>y</code></p>\n\
+     <pre dir=\"ltr\"><code>z\n</code></pre>\n"
+  );
+  assert!(!doc.to_html().contains("dir="));
+}
+
+#[test]
+fn accessibility() {
+  let source = "a ![](cat.png) [](/x) [ok](/y)\n";
+  let diagnostics = djot::lint::lint(source);
+  let codes: Vec<_> = diagnostics.iter().filter_map(|it| it.code).collect();
+  assert_eq!(codes, ["missing-alt-text", "empty-links"]);
+  assert_eq!(&source[diagnostics[1].range.clone()], "[](/x)");
+
+  let opts = djot::HtmlOpts { a11y_placeholders: true, ..djot::HtmlOpts::default() };
+  assert_eq!(
+    djot::Document::parse(source).to_html_opts(&opts),
+    "<p>a <img class=\"missing-alt-text\" alt=\"Missing alt text\" src=\"cat.png\"> \
+     <a href=\"/x\" class=\"empty-link\">Empty link</a> <a href=\"/y\">ok</a></p>\n"
+  );
+  // next to the classes of the element
+  let source = "![](cat.png){.foo} [](/x){.foo}\n";
+  assert_eq!(
+    djot::Document::parse(source).to_html_opts(&opts),
+    "<p><img class=\"missing-alt-text foo\" alt=\"Missing alt text\" src=\"cat.png\"> \
+     <a href=\"/x\" class=\"empty-link foo\">Empty link</a></p>\n"
+  );
+}
+
+#[test]
+fn github_alerts() {
+  let source = "> [!WARNING]\n> Be *careful*\n\n> [!NOTE] not an alert\n";
+  let opts = djot::ParseOpts { github_alerts: true, ..djot::ParseOpts::default() };
+  let doc = djot::Document::parse_opts(opts, source);
+  assert_eq!(
+    doc.to_html(),
+    "<div class=\"callout warning\">\n<p class=\"callout-title\">Warning</p>\n\
+     <p>Be <strong>careful</strong></p>\n</div>\n\
+     <blockquote>\n<p>[!NOTE] not an alert</p>\n</blockquote>\n"
+  );
+  assert!(doc.to_djot().starts_with("> [!WARNING]\n> Be *careful*\n"));
+
+  let html = djot::Document::parse(source).to_html();
+  assert!(html.starts_with("<blockquote>\n<p>[!WARNING]\nBe <strong>careful</strong></p>"));
+}
+
+#[test]
+fn abbreviations() {
+  let source = "The HTML spec, not HTMLX or `HTML`.\n\n*[HTML]: HyperText Markup Language\n";
+  let opts = djot::ParseOpts { abbreviations: true, ..djot::ParseOpts::default() };
+  let doc = djot::Document::parse_opts(opts, source);
+  assert_eq!(
+    doc.to_html(),
+    "<p>The <abbr title=\"HyperText Markup Language\">HTML</abbr> spec, not HTMLX or \
+     <code>HTML</code>.</p>\n"
+  );
+  let abbr = &doc.children[0].children[1];
+  assert_eq!(&source[abbr.span.clone()], "HTML");
+  assert!(doc.to_djot().ends_with("\n*[HTML]: HyperText Markup Language\n"));
+
+  let html = djot::Document::parse(source).to_html();
+  assert!(html.contains("<p>*[HTML]: HyperText Markup Language</p>"));
+}
+
+#[test]
+fn citations() {
+  let source = "As shown [@knuth84, p. 3], see @lamport94 or me@example.com, [@knuth84].\n";
+  let opts = djot::ParseOpts { citations: true, ..djot::ParseOpts::default() };
+  let doc = djot::Document::parse_opts(opts, source);
+  assert_eq!(doc.citation_keys(), ["knuth84", "lamport94"]);
+  assert_eq!(doc.to_djot(), source);
+  assert_eq!(
+    doc.to_html(),
+    "<p>As shown <cite>[<a href=\"#ref-knuth84\" role=\"doc-biblioref\">knuth84</a>, p. 3]</cite>, \
+     see <cite><a href=\"#ref-lamport94\" role=\"doc-biblioref\">lamport94</a></cite> or \
+     me@example.com, <cite>[<a href=\"#ref-knuth84\" role=\"doc-biblioref\">knuth84</a>]</cite>.</p>\n"
+  );
+
+  let opts = djot::HtmlOpts {
+    citation_resolver: Some(std::sync::Arc::new(|citation: &djot::ast::Citation| {
+      (citation.key == "knuth84").then(|| "(Knuth 1984)".to_string())
+    })),
+    bibliography: Some(std::sync::Arc::new(|key: &str| {
+      (key == "knuth84").then(|| "Knuth, D. <i>The TeXbook</i>.".to_string())
+    })),
+    ..djot::HtmlOpts::default()
+  };
+  assert_eq!(
+    doc.to_html_opts(&opts),
+    "<p>As shown (Knuth 1984), \
+     see <cite><a href=\"#ref-lamport94\" role=\"doc-biblioref\">lamport94</a></cite> or \
+     me@example.com, (Knuth 1984).</p>\n\
+     <div id=\"refs\" role=\"doc-bibliography\">\n\
+     <div id=\"ref-knuth84\">Knuth, D. <i>The TeXbook</i>.</div>\n\
+     </div>\n"
+  );
+}
+
+#[test]
+fn index_terms() {
+  use djot::djot_ast;
+
+  let doc = djot::Document::new(vec![
+    djot_ast! {
+      para ["Using ", span { index = "" } ["TeX"], " for ", span { index = "typesetting" } ["it"]]
+    },
+    djot_ast! { para [span { index = "", id = "tex" } ["TeX"], " again."] },
+  ]);
+  let entries = doc.index_entries();
+  assert_eq!(entries.len(), 2);
+  assert_eq!(entries[0].term, "TeX");
+  let ids: Vec<_> = entries[0].locations.iter().map(|it| it.id.as_str()).collect();
+  assert_eq!(ids, ["index-term-1", "tex"]);
+  assert_eq!(entries[1].term, "typesetting");
+
+  let opts = djot::HtmlOpts { index: true, ..djot::HtmlOpts::default() };
+  assert_eq!(
+    doc.to_html_opts(&opts),
+    "<p>Using <span id=\"index-term-1\">TeX</span> for <span id=\"index-term-2\">it</span></p>\n\
+     <p><span id=\"tex\">TeX</span> again.</p>\n\
+     <section class=\"index\" role=\"doc-index\">\n\
+     <h2>Index</h2>\n\
+     <ul>\n\
+     <li>TeX <a href=\"#index-term-1\">1</a>, <a href=\"#tex\">2</a></li>\n\
+     <li>typesetting <a href=\"#index-term-2\">1</a></li>\n\
+     </ul>\n\
+     </section>\n"
+  );
+
+  // nested terms, and a span without any text, which is left out
+  let source = "Using [TeX]{index=\"\"} and [[TeX]{index=\"\"} macros]{index=\"TeX macros\"}.\n\n\
+                []{index=\"\"} nothing\n";
+  let doc = djot::Document::parse(source);
+  let entries = doc.index_entries();
+  let terms: Vec<_> = entries.iter().map(|it| it.term.as_str()).collect();
+  assert_eq!(terms, ["TeX", "TeX macros"]);
+  let ids: Vec<_> = entries[0].locations.iter().map(|it| it.id.as_str()).collect();
+  assert_eq!(ids, ["index-term-1", "index-term-3"]);
+  assert_eq!(&source[entries[0].locations[0].span.clone()], "[TeX]{index=\"\"}");
+  assert_eq!(entries[1].locations[0].id, "index-term-2");
+  assert_eq!(
+    doc.to_html_opts(&opts),
+    "<p>Using <span id=\"index-term-1\">TeX</span> and \
+     <span id=\"index-term-2\"><span id=\"index-term-3\">TeX</span> macros</span>.</p>\n\
+     <p><span id=\"index-term-4\"></span> nothing</p>\n\
+     <section class=\"index\" role=\"doc-index\">\n\
+     <h2>Index</h2>\n\
+     <ul>\n\
+     <li>TeX <a href=\"#index-term-1\">1</a>, <a href=\"#index-term-3\">2</a></li>\n\
+     <li>TeX macros <a href=\"#index-term-2\">1</a></li>\n\
+     </ul>\n\
+     </section>\n"
+  );
+}
+
+#[test]
+fn code_line_numbers() {
+  let doc = djot::Document::parse("```\nlet a = 1;\nlet b = a;\n```\n");
+  let opts = djot::HtmlOpts { line_numbers: true, ..djot::HtmlOpts::default() };
+  assert_eq!(
+    doc.to_html_opts(&opts),
+    "<pre class=\"line-numbers\"><code>\
+     <span class=\"line\"><span class=\"line-number\">1</span>let a = 1;\n</span>\
+     <span class=\"line\"><span class=\"line-number\">2</span>let b = a;\n</span>\
+     </code></pre>\n"
+  );
+
+  let code = djot::djot_ast! { code_block("a\nb\nc\n") { highlight = "1,3-4", start = "10" } };
+  assert_eq!(
+    djot::Document::new(vec![code]).to_html(),
+    "<pre class=\"line-numbers\"><code>\
+     <span class=\"line highlighted\"><span class=\"line-number\">10</span>a\n</span>\
+     <span class=\"line\"><span class=\"line-number\">11</span>b\n</span>\
+     <span class=\"line highlighted\"><span class=\"line-number\">12</span>c\n</span>\
+     </code></pre>\n"
+  );
+}
+
+#[test]
+fn render_section() {
+  let doc = djot::Document::parse("# One\n\nFirst.\n\n# Two\n\nSecond.\n");
+  let opts = djot::HtmlOpts::default();
+  assert_eq!(
+    doc.render_section("Two", &opts).unwrap(),
+    "<h1 id=\"Two\">Two</h1>\n<p>Second.</p>\n"
+  );
+  assert_eq!(doc.render_section("Three", &opts), None);
+
+  let para = &doc.find_by_id("One").unwrap().children[1];
+  assert_eq!(para.to_html(&opts), "<p>First.</p>\n");
+}
+
+#[test]
+fn standalone_seo() {
+  let doc = djot::Document::parse("# Cats & dogs\n\nAll about pets & more.\n\n![a cat](cat.png)\n");
+  let seo = djot::SeoOpts { site_name: Some("Pets".to_string()), ..djot::SeoOpts::default() };
+  let opts = djot::HtmlOpts { standalone: true, seo: Some(seo), ..djot::HtmlOpts::default() };
+  let html = doc.to_html_opts(&opts);
+  assert!(html.starts_with(
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+     <title>Cats &amp; dogs</title>\n\
+     <meta name=\"description\" content=\"All about pets &amp; more.\">\n\
+     <meta property=\"og:type\" content=\"article\">\n\
+     <meta property=\"og:title\" content=\"Cats &amp; dogs\">\n\
+     <meta property=\"og:description\" content=\"All about pets &amp; more.\">\n\
+     <meta property=\"og:image\" content=\"cat.png\">\n\
+     <meta property=\"og:site_name\" content=\"Pets\">\n\
+     <meta name=\"twitter:card\" content=\"summary_large_image\">\n\
+     </head>\n<body>\n<h1 id=\"Cats-dogs\">"
+  ));
+  assert!(html.ends_with("</p>\n</body>\n</html>\n"));
+  assert_eq!(html, doc.html(&opts).to_string());
+}
+
+#[test]
+fn broken_anchors() {
+  use djot::lint::{BrokenAnchors, Linter};
+
+  let source = "# One\n\nSee [x](#One), [y](#Two), [z](#) and [w](#nav).\n";
+  let diagnostics = Linter::new().rule(BrokenAnchors::default()).check(source);
+  let messages: Vec<_> = diagnostics.iter().map(|it| it.message.as_str()).collect();
+  assert_eq!(messages, ["link to missing identifier `Two`", "link to missing identifier `nav`"]);
+  assert_eq!(&source[diagnostics[0].range.clone()], "[y](#Two)");
+
+  let rule = BrokenAnchors { known_ids: ["nav".to_string()].into() };
+  assert_eq!(Linter::new().rule(rule).check(source).len(), 1);
+}
+
+#[test]
+fn cross_references() {
+  let source = "# Intro\n\n## Details\n\nSee [@Intro] and @Details, not @nobody.\n";
+  let opts = djot::ParseOpts { cross_references: true, ..djot::ParseOpts::default() };
+  let doc = djot::Document::parse_opts(opts, source);
+  let para = djot::Document::new(vec![doc.children[0].children[1].children[1].clone()]);
+  assert_eq!(para.to_djot(), "See [@Intro] and @Details, not @nobody.\n",);
+  assert_eq!(para.children[0].children.last().unwrap().text(), ", not @nobody.");
+  assert!(doc
+    .to_html()
+    .starts_with("<h1 id=\"Intro\">Intro</h1>\n<h2 id=\"Details\">Details</h2>\n"));
+  assert!(doc.to_html().ends_with(
+    "<p>See <a href=\"#Intro\" class=\"cross-reference\">Intro</a> and \
+     <a href=\"#Details\" class=\"cross-reference\">Details</a>, not @nobody.</p>\n"
+  ));
+  let opts = djot::HtmlOpts { number_headings: true, ..djot::HtmlOpts::default() };
+  assert!(doc.to_html_opts(&opts).ends_with(
+    "<p>See <a href=\"#Intro\" class=\"cross-reference\">Section 1</a> and \
+     <a href=\"#Details\" class=\"cross-reference\">Section 1.1</a>, not @nobody.</p>\n"
+  ));
+}
+
+#[test]
+fn typography() {
+  let source = "Copyright (c) 2024: 1/2 cup, not 11/2 or 1/25 or `(c)`.\n";
+  let opts = djot::ParseOpts { typography: djot::Typography::all(), ..djot::ParseOpts::default() };
+  assert_eq!(
+    djot::Document::parse_opts(opts, source).to_html(),
+    "<p>Copyright © 2024: ½ cup, not 11/2 or 1/25 or <code>(c)</code>.</p>\n"
+  );
+
+  let mut doc = djot::Document::new(vec![djot::djot_ast! {
+    para ["a -> b <=> c <= d (tm)", verbatim("->")]
+  }]);
+  let typography = djot::Typography { arrows: true, ..djot::Typography::default() };
+  doc.substitute_typography(&typography);
+  assert_eq!(doc.to_html(), "<p>a → b ⇔ c &lt;= d (tm)<code>-&gt;</code></p>\n");
+}
+
+#[test]
+fn text_wrapping() {
+  let doc = djot::Document::new(vec![djot::djot_ast! {
+    para ["A para\u{ad}graph with extra\u{ad}ordinarily long well-known words."]
+  }]);
+  assert_eq!(
+    doc.to_text(&djot::TextOpts::default()),
+    "A paragraph with extraordinarily long well-known words.\n"
+  );
+  let opts = djot::TextOpts { width: Some(12), hyphenate: true, ..djot::TextOpts::default() };
+  assert_eq!(
+    doc.to_text(&opts),
+    "A paragraph\nwith extra-\nordinarily\nlong well-\nknown words.\n"
+  );
+  let opts = djot::TextOpts { hyphenate: false, ..opts };
+  assert_eq!(doc.to_text(&opts), "A paragraph\nwith\nextraordinarily\nlong\nwell-known\nwords.\n");
+
+  let doc = djot::Document::new(vec![djot::djot_ast! { para ["hyphenation"] }]);
+  let opts = djot::TextOpts {
+    width: Some(8),
+    hyphenate: true,
+    hyphenator: Some(std::sync::Arc::new(
+      |word: &str| if word == "hyphenation" { vec![2, 6] } else { vec![] },
+    )),
+  };
+  assert_eq!(doc.to_text(&opts), "hyphen-\nation\n");
+}
+
+#[test]
+fn list_of_figures() {
+  let src = "![cat](cat.png)\n\n^ A cat\n\n> Be brave.\n\n^ A quote\n";
+  let doc = djot::Document::parse(src);
+  let figures = doc.figures();
+  assert_eq!(figures.len(), 2);
+  assert_eq!((figures[1].number, figures[1].id.as_str()), (2, "figure-2"));
+  assert_eq!(figures[1].caption, "A quote");
+  assert_eq!(&src[figures[0].span.clone()], "![cat](cat.png)\n\n^ A cat");
+
+  let opts = djot::HtmlOpts { list_of_figures: true, ..djot::HtmlOpts::default() };
+  assert_eq!(
+    doc.to_html_opts(&opts),
+    "<nav class=\"list-of-figures\">
+<h2>Figures</h2>
+<ol>
+<li><a href=\"#figure-1\">Figure 1: A cat</a></li>
+<li><a href=\"#figure-2\">Figure 2: A quote</a></li>
+</ol>
+</nav>
+<figure id=\"figure-1\">
+<img alt=\"cat\" src=\"cat.png\">
+<figcaption>Figure 1: A cat</figcaption>
+</figure>
+<figure id=\"figure-2\">
+<blockquote>
+<p>Be brave.</p>
+</blockquote>
+<figcaption>Figure 2: A quote</figcaption>
+</figure>
+"
+  );
+  assert_eq!(doc.to_djot(), src);
+}
+
+#[test]
+fn concat_documents() {
+  let one = djot::Document::parse("# Intro\n\nSee [home][site].\n\n[site]: /one\n");
+  let two = djot::Document::parse(
+    "# Intro\n\nBack to [the start](#Intro), [home][site].\n\n[site]: /two\n",
+  );
+  let end = one.children.last().unwrap().span.end;
+  let doc = djot::Document::concat([one, two]);
+  assert_eq!(doc.children.len(), 2);
+  assert_eq!(doc.children[1].id(), Some("Intro-1"));
+  assert_eq!(doc.children[1].span.start, end);
+  assert_eq!(
+    doc.to_djot(),
+    "# Intro
+
+See [home][site].
+
+[site]: /one
+
+# Intro
+
+Back to [the start](#Intro-1), [home][site-1].
+
+[site-1]: /two
+"
+  );
+
+  let mut doc = djot::Document::parse("[a]: /a\n");
+  doc.append(djot::Document::parse("[a]: /a\n"));
+  assert_eq!(doc.to_djot(), "[a]: /a\n");
+
+  let mut doc = djot::Document::parse("One[^n].\n\n[^n]: First.\n");
+  doc.append(djot::Document::parse("Two[^n].\n\n[^n]: Second.\n"));
+  assert_eq!(doc.to_djot(), "One[^n].\n\n[^n]: First.\n\nTwo[^n-1].\n\n[^n-1]: Second.\n");
+}
+
+#[test]
+fn parse_attributes() {
+  let attrs = djot::parse_attributes(
+    "{#intro .wide key=val .note %comment% title=\"say \\\"hi\\\"\nagain\"}",
+  )
+  .unwrap();
+  let attrs: Vec<_> = attrs.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+  assert_eq!(
+    attrs,
+    [("id", "intro"), ("class", "wide note"), ("key", "val"), ("title", "say \"hi\" again")]
+  );
+  assert!(djot::parse_attributes("{}").unwrap().is_empty());
+
+  let err = djot::parse_attributes("{.a !}").unwrap_err();
+  assert_eq!((err.message.as_str(), err.range), ("invalid attribute syntax", 4..5));
+  assert_eq!(djot::parse_attributes("{.a").unwrap_err().message, "unclosed attributes");
+  assert_eq!(djot::parse_attributes("{.a} b").unwrap_err().message, "text after attributes");
+}
+
+#[cfg(feature = "from-html")]
+#[test]
+fn from_html() {
+  let html = r#"<!DOCTYPE html>
+<html><head><title>Notes</title><style>p { color: red }</style></head>
+<body>
+<h2 id="x">Shopping &amp; <i>more</i></h2>
+<p>Buy <strong>milk </strong>and <code>eggs</code>.<br>
+Then <a href="https://example.com">https://example.com</a>
+<p><img src="cat.png" alt="A cat"></p>
+<ul>
+  <li>One
+  <li><input type="checkbox" checked> Two
+</ul>
+<ol start="3"><li><p>Three</p><p>More</p></li><li>Four</li></ol>
+<blockquote><p>Quoted</p><pre><code class="language-rust">let a = 1;
+</code></pre></blockquote>
+<table><tr><th>A</th><th>B</th></tr><tr><td>1 | 2</td><td>*</td></tr></table>
+<!-- gone -->
+</body></html>"#;
+  assert_eq!(
+    djot::from_html::to_djot(html),
+    r#"## Shopping & _more_
+
+Buy *milk* and `eggs`.\
+Then <https://example.com>
+
+![A cat](cat.png)
+
+- One
+- [x] Two
+
+3. Three
+
+   More
+
+4. Four
+
+> Quoted
+>
+> ``` rust
+> let a = 1;
+> ```
+
+| A | B |
+|---|---|
+| 1 \| 2 | \* |
+"#
+  );
+  let doc = djot::from_html::to_document("<p>Some <em>text</em></p>");
+  assert_eq!(doc.to_html(), "<p>Some <em>text</em></p>\n");
+  let doc = djot::from_html::to_document("<p>a<br>b</p>");
+  assert_eq!(doc.to_html(), "<p>a<br>\nb</p>\n");
+}
+
+#[test]
+fn inline_attributes() {
+  let html = |source: &str| djot::Document::parse(source).to_html();
+  assert_eq!(
+    html("*strong*{.big #s key=\"a b\"} and some word{.w}\n"),
+    "<p><strong class=\"big\" id=\"s\" key=\"a b\">strong</strong> and some \
+     <span class=\"w\">word</span></p>\n"
+  );
+  assert_eq!(
+    html("a [bracketed span]{#x} b\n"),
+    "<p>a <span id=\"x\">bracketed span</span> b</p>\n"
+  );
+  // continued on the next line
+  assert_eq!(html("_emph_{.a\n.b}\n"), "<p><em class=\"a b\">emph</em></p>\n");
+  // not attributes after all
+  assert_eq!(html("x {.a ! y} z\n"), "<p>x {.a ! y} z</p>\n");
+  assert_eq!(html("x{.a\ny\n"), "<p>x{.a\ny</p>\n");
+  assert_eq!(html("[a]{.x\n"), "<p>[a]{.x</p>\n");
+  assert_eq!(html("[a]{.x\nb\n"), "<p>[a]{.x\nb</p>\n");
+  assert_eq!(html("[a]{.x\n.y}\n"), "<p><span class=\"x y\">a</span></p>\n");
+
+  let source = "*strong*{.big} and some word{.w}\n";
+  assert_eq!(djot::Document::parse(source).to_djot(), "*strong*{.big} and some [word]{.w}\n");
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn block_attributes() {
+  let html = |source: &str| djot::Document::parse(source).to_html();
+  assert_eq!(
+    html("{#water .important}\n{.large key=\"a b\"}\nDon't forget.\n"),
+    "<p id=\"water\" class=\"important large\" key=\"a b\">Don&rsquo;t forget.</p>\n"
+  );
+  // continued on indented lines
+  assert_eq!(html("{#a\n .b}\n\n***\n"), "<hr id=\"a\" class=\"b\">\n");
+  assert_eq!(html("{.x}\n- a\n- b\n"), "<ul class=\"x\">\n<li>\na\n</li>\n<li>\nb\n</li>\n</ul>\n");
+  assert_eq!(html("::: note\n{.a}\n:::\n\npara\n"), "<div class=\"note\">\n</div>\n<p>para</p>\n");
+  // not attributes after all
+  assert_eq!(html("{.a} text\n"), "<p> text</p>\n");
+  assert_eq!(html("{#a\nb}\n"), "<p>{#a\nb}</p>\n");
+  assert_eq!(html("{#a\n .b !}\n"), "<p>{#a\n.b !}</p>\n");
+
+  let source = "{.c}\n# A\n\n> # A\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(doc.to_djot(), "{.c}\n# A\n\n> {#A-1}\n> # A\n");
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn math() {
+  let source = "Euler: $`e^{i\\pi} + 1 = 0`, and $$`\\sum_{i=1}^n i`. Not \\$`code`.\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(
+    doc.to_html(),
+    "<p>Euler: <span class=\"math inline\">\\(e^{i\\pi} + 1 = 0\\)</span>, and \
+     <span class=\"math display\">\\[\\sum_{i=1}^n i\\]</span>. Not $<code>code</code>.</p>\n"
+  );
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn smart_quotes() {
+  let source = "\"It's 'quoted',\" {'they'} said. 'Tis x'}.\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(
+    doc.to_html(),
+    "<p>&ldquo;It&rsquo;s &lsquo;quoted&rsquo;,&rdquo; &lsquo;they&rsquo; said. \
+     &rsquo;Tis x&rsquo;.</p>\n"
+  );
+  assert_eq!(doc.children[0].text(), "“It’s ‘quoted’,” ‘they’ said. ’Tis x’.");
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn dashes() {
+  let source = "pages 1--2 -- a---b, four ---- five ----- and well-known...\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(
+    doc.to_html(),
+    "<p>pages 1&ndash;2 &ndash; a&mdash;b, four &ndash;&ndash; five &mdash;&ndash; and \
+     well-known&hellip;</p>\n"
+  );
+  assert_eq!(doc.children[0].text(), "pages 1–2 – a—b, four –– five —– and well-known…");
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn insert_delete_mark() {
+  let source = "{+new+} {-old -- gone-} {=hot=}, but a+b=c-d.\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(
+    doc.to_html(),
+    "<p><ins>new</ins> <del>old &ndash; gone</del> <mark>hot</mark>, but a+b=c-d.</p>\n"
+  );
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn lists() {
+  let source = "- a\n- b\n\n- [ ] c\n- [x] d\n\n(iv) one\n\n(v) two\n\n   more\n\ni. x\nj. y\n";
+  djot::roundtrip_check(source).unwrap();
+  let doc = djot::Document::parse("- [ ] c\n- [x] d\n\ni. x\nj. y\n");
+  assert_eq!(
+    doc.to_html(),
+    "<ul class=\"task-list\">\n<li>\n<input disabled=\"\" type=\"checkbox\">\nc\n</li>\n\
+     <li>\n<input disabled=\"\" type=\"checkbox\" checked=\"\">\nd\n</li>\n</ul>\n\
+     <ol start=\"9\" type=\"a\">\n<li>\nx\n</li>\n<li>\ny\n</li>\n</ol>\n"
+  );
+
+  let opts = djot::HtmlOpts { interactive_tasks: true, ..djot::HtmlOpts::default() };
+  let html = djot::Document::parse("* [x] done\n").to_html_opts(&opts);
+  assert!(html.contains("<input type=\"checkbox\" checked=\"\" data-task-index=\"0\">"));
+}
+
+#[test]
+fn tables() {
+  let source = "| a | *b* |\n|:--|--:|\n| 1 | `x|y` \\| z |\n^ The caption\n\n| no | table\n";
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn footnotes() {
+  let source = "Text[^a] and[^b], again[^a].\n\n[^b]: Second.\n\n    > quoted\n\n[^a]: First.\n";
+  djot::roundtrip_check(source).unwrap();
+
+  let doc = djot::Document::parse(source);
+  let labels = djot::Labels { footnotes: "Notes".to_string(), ..djot::Labels::default() };
+  let opts = djot::HtmlOpts { labels, label_footnotes: true, ..djot::HtmlOpts::default() };
+  assert!(doc.to_html_opts(&opts).contains("<section role=\"doc-endnotes\" aria-label=\"Notes\">"));
+}
+
+#[test]
+fn block_quotes() {
+  let source = "> > nested\nlazy\n>\n> outer\n> > not nested\n\n> a\n# heading\n";
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn divs() {
+  let source = ":::: warning {#w}\nBe careful.\n\n::: note\n```\n:::\n```\n:::\n::::\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(
+    doc.to_html(),
+    "<div class=\"warning\" id=\"w\">\n<p>Be careful.</p>\n\
+     <div class=\"note\">\n<pre><code>:::\n</code></pre>\n</div>\n</div>\n"
+  );
+  assert_eq!(
+    doc.to_djot(),
+    ":::: warning {#w}\nBe careful.\n\n::: note\n```\n:::\n```\n:::\n::::\n"
+  );
+  djot::roundtrip_check(source).unwrap();
+
+  let doc = djot::Document::parse("::: {.a}\ntext\n");
+  assert_eq!(doc.to_html(), "<div class=\"a\">\n<p>text</p>\n</div>\n");
+  assert_eq!(doc.to_djot(), "::: a\ntext\n:::\n");
+}
+
+#[test]
+fn raw_content() {
+  let source = "A `<b>x</b>`{=html} `\\LaTeX`{=latex}\n\n> ```=html\n> <video></video>\n> ```\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(
+    doc.children[0].children[1].kind,
+    djot::djot_ast! { raw_inline("html", "<b>x</b>") }.kind
+  );
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn thematic_breaks() {
+  let source = "- a\n\n* * *\n\n- b\n-----\ntext\n---\n";
+  djot::roundtrip_check(source).unwrap();
+  let doc = djot::Document::parse("- b\n-----\ntext\n---\n");
+  assert_eq!(doc.to_html(), "<ul>\n<li>\nb\n</li>\n</ul>\n<hr>\n<p>text\n&mdash;</p>\n");
+}
+
+#[test]
+fn definition_lists() {
+  let source = ": apple\n\n  red fruit\n\n  > quoted\n\n: *banana*\n";
+  let doc = djot::Document::parse(source);
+  let expected = djot::djot_ast! {
+    definition_list(false) [
+      definition_list_item [term ["apple"], definition [para ["red fruit"], block_quote [para ["quoted"]]]],
+      definition_list_item [term [strong ["banana"]], definition],
+    ]
+  };
+  assert_eq!(doc.children, vec![expected]);
+  assert_eq!(doc.to_djot(), source);
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn reference_links() {
+  let source = "[link][] and ![image][a\nb] and [missing][]\n\n> [link]:\n>  /url\n>   /more\n\n[a b]: img.png\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(
+    doc.to_html(),
+    "<p><a href=\"/url/more\">link</a> and <img alt=\"image\" src=\"img.png\"> and <a>missing</a></p>\n\
+     <blockquote>\n</blockquote>\n"
+  );
+  let djot::ast::TagKind::Link(link) = &doc.children[0].children[0].kind else { panic!() };
+  assert_eq!(link.destination.as_deref(), Some("/url/more"));
+  djot::roundtrip_check(source).unwrap();
+}
+
+#[test]
+fn identifiers() {
+  use djot::lint::{BrokenAnchors, Linter};
+
+  let source = "# Intro\n\nSee [Intro][] and [Notes][].\n\n> ## Notes\n\n# Intro-1\n\n# Intro\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(
+    doc.to_html(),
+    "<h1 id=\"Intro\">Intro</h1>\n\
+     <p>See <a href=\"#Intro\">Intro</a> and <a href=\"#Notes\">Notes</a>.</p>\n\
+     <blockquote>\n<h2 id=\"Notes\">Notes</h2>\n</blockquote>\n\
+     <h1 id=\"Intro-1\">Intro-1</h1>\n<h1 id=\"Intro-2\">Intro</h1>\n"
+  );
+  let sections = doc.sections_by_id();
+  let mut ids: Vec<_> = sections.keys().copied().collect();
+  ids.sort();
+  assert_eq!(ids, ["Intro", "Intro-1", "Intro-2", "Notes"]);
+  assert!(matches!(sections["Notes"].kind, djot::ast::TagKind::Heading(_)));
+  djot::roundtrip_check(source).unwrap();
+  // every link has its target
+  let diagnostics = Linter::new().rule(BrokenAnchors::default()).check(source);
+  assert!(diagnostics.is_empty());
+
+  // a reference definition wins over the heading
+  let doc = djot::Document::parse("# Intro\n\n[Intro][]\n\n[Intro]: /intro\n");
+  assert!(doc.to_html().contains("<a href=\"/intro\">"));
+}
+
+#[test]
+fn events() {
+  use djot::events::{Atom, Container, EventKind};
+
+  let source = "hi *there*\n";
+  let events: Vec<_> = djot::parse_events(source).collect();
+  let strong = events.iter().find(|it| it.kind == EventKind::Start(Container::Strong)).unwrap();
+  assert_eq!(&source[strong.range.clone()], "*");
+  let words: Vec<_> = events
+    .iter()
+    .filter(|it| it.kind == EventKind::Atom(Atom::Str))
+    .map(|it| &source[it.range.clone()])
+    .collect();
+  assert_eq!(words, ["hi ", "there"]);
+  let names: Vec<_> = events.iter().map(|it| it.to_string()).collect();
+  assert_eq!(names.first().map(String::as_str), Some("+para 1-1"));
+  assert_eq!(names.last().map(String::as_str), Some("-para 11-11"));
+}
+
+#[test]
+fn json() {
+  let doc = djot::Document::parse("- [x] *a*\n\n[b]: /b\n\nc[^n]\n\n[^n]: note\n");
+  let json: serde_json::Value = serde_json::from_str(&doc.to_json()).unwrap();
+  let list = &json["children"][0];
+  assert_eq!(list["tag"], "task_list");
+  assert_eq!(list["children"][0]["checkbox"], "checked");
+  assert_eq!(list["children"][0]["children"][0]["children"][0]["tag"], "strong");
+  assert_eq!(json["references"]["b"]["destination"], "/b");
+  assert_eq!(json["footnotes"]["n"]["children"][0]["tag"], "para");
+  assert_eq!(json["children"].as_array().unwrap().len(), 2);
+
+  // leaves carry their content as `text` only
+  let doc = djot::Document::parse("```\ncode\n```\n\n`v`\n");
+  let json: serde_json::Value = serde_json::from_str(&doc.to_json()).unwrap();
+  assert_eq!(json["children"][0], serde_json::json!({ "tag": "code_block", "text": "code\n" }));
+  assert_eq!(
+    json["children"][1]["children"][0],
+    serde_json::json!({ "tag": "verbatim", "text": "v" })
+  );
+  let read = djot::Document::from_json(&doc.to_json()).unwrap();
+  assert_eq!(read.to_html(), doc.to_html());
+}
+
+#[test]
+fn from_json() {
+  let source = "1. a 'b' c...\n\n- [ ] _d_ [e][]{.x}\n\nf[^n]\n\n[e]: /e\n\n[^n]: note\n";
+  let doc = djot::Document::parse(source);
+  let json = doc.to_json();
+  let read = djot::Document::from_json(&json).unwrap();
+  assert_eq!(read.children, doc.children);
+  assert_eq!(read.to_json(), json);
+
+  // definitions and footnotes end up at the end of the document, outside
+  // of the section
+  let doc = djot::Document::parse(&format!("# Title\n\n{source}"));
+  let read = djot::Document::from_json(&doc.to_json()).unwrap();
+  assert_eq!(read.to_html(), doc.to_html());
+
+  let doc = djot::Document::from_json(
+    r#"{"tag": "doc", "children": [{"tag": "para", "children": [{"tag": "str", "text": "hi"}]}]}"#,
+  )
+  .unwrap();
+  assert_eq!(doc.to_html(), "<p>hi</p>\n");
+  assert!(djot::Document::from_json(r#"{"children": [{"tag": "bogus"}]}"#).is_err());
+}
+
+#[test]
+fn filters() {
+  use djot::{
+    ast::{Tag, TagKind},
+    djot_ast,
+    filter::{Action, Filter},
+  };
+
+  // unwraps emphasis and drops verbatim, children before parents
+  let mut doc = djot::Document::parse("_a *b*_ `c` d\n");
+  doc.apply_filter(&mut |tag: &mut Tag| match tag.kind {
+    TagKind::Emph(_) => Action::Replace(std::mem::take(&mut tag.children)),
+    TagKind::Verbatim(_) => Action::Delete,
+    TagKind::Strong(_) => {
+      tag.add_class("b");
+      Action::Keep
+    }
+    _ => Action::Keep,
+  });
+  assert_eq!(doc.to_html(), "<p>a <strong class=\"b\">b</strong>  d</p>\n");
+
+  struct Upper {
+    exits: usize,
+  }
+  impl Filter for Upper {
+    fn enter(&mut self, tag: &mut Tag) -> Action {
+      match tag.kind {
+        TagKind::BlockQuote(_) => Action::SkipChildren,
+        _ => Action::Keep,
+      }
+    }
+    fn exit(&mut self, tag: &mut Tag) -> Action {
+      self.exits += 1;
+      if let TagKind::Str(str) = &mut tag.kind {
+        str.text = str.text.to_uppercase();
+      }
+      Action::Keep
+    }
+  }
+  let mut doc = djot::Document::parse("a\n\n> b\n");
+  let mut filter = Upper { exits: 0 };
+  doc.apply_filter(&mut filter);
+  assert_eq!(doc.children, [djot_ast! { para ["A"] }, djot_ast! { block_quote [para ["b"]] }]);
+  assert_eq!(filter.exits, 3);
+}
+
+#[test]
+fn source_positions() {
+  let opts = djot::ParseOpts { source_positions: true, ..djot::ParseOpts::default() };
+  let doc = djot::Document::parse_opts(opts, "para\n\n- a *b*\n- c\n");
+  let html_opts = djot::HtmlOpts { include_positions: true, ..djot::HtmlOpts::default() };
+  assert_eq!(
+    doc.to_html_opts(&html_opts),
+    "<p data-pos=\"1:1-1:4\">para</p>\n<ul data-pos=\"3:1-4:3\">\n\
+     <li data-pos=\"3:1-3:7\">\na <strong data-pos=\"3:5-3:7\">b</strong>\n</li>\n\
+     <li data-pos=\"4:1-4:3\">\nc\n</li>\n</ul>\n"
+  );
+  let item = &doc.children[1].children[1];
+  let pos = doc.position(item).unwrap();
+  assert_eq!((pos.start.line, pos.start.col), (3, 0));
+  assert!(doc.to_json().contains("\"pos\": \"3:5-3:7\""));
+  assert!(!doc.to_html().contains("data-pos"));
+  assert_eq!(djot::Document::parse("para").position(&doc.children[0]), None);
+}
+
+#[test]
+fn warnings() {
+  let source = "```\ncode\n\n`verbatim\n";
+  let res = djot::Document::parse_with_warnings(djot::ParseOpts::default(), source).unwrap();
+  assert_eq!(res.document, djot::Document::parse(source));
+  let messages: Vec<_> = res.warnings.iter().map(|it| it.message.as_str()).collect();
+  assert!(!messages.is_empty());
+  assert!(res.warnings.iter().all(|it| it.severity == djot::Severity::Warning));
+
+  let strict = djot::ParseOpts { strict: true, ..djot::ParseOpts::default() };
+  match djot::Document::try_parse_opts(strict.clone(), source) {
+    Err(djot::ParseError::Warnings(errors)) => {
+      assert_eq!(errors.iter().map(|it| it.message.as_str()).collect::<Vec<_>>(), messages);
+      assert!(errors.iter().all(|it| it.severity == djot::Severity::Error));
+    }
+    res => panic!("{res:?}"),
+  }
+  assert!(djot::validate(source, strict.clone())
+    .iter()
+    .all(|it| it.severity == djot::Severity::Error));
+  assert!(djot::Document::try_parse_opts(strict.clone(), "fine\n").is_ok());
+  // only the fallible methods are strict
+  assert_eq!(djot::Document::parse_opts(strict, source), res.document);
+}
+
+#[test]
+fn braced_emphasis() {
+  // `{_` is only closed by `_}`, and `_` only by `_`
+  let cases = [
+    ("{_foo{_bar_}_}", "<p><em>foo<em>bar</em></em></p>\n"),
+    ("{_foo_bar_}", "<p><em>foo_bar</em></p>\n"),
+    ("_foo_}", "<p>_foo_}</p>\n"),
+    ("{*a* b*}", "<p><strong>a* b</strong></p>\n"),
+    ("a {_b", "<p>a {_b</p>\n"),
+  ];
+  for (source, html) in cases {
+    assert_eq!(djot::Document::parse(source).to_html(), html, "{source}");
+  }
+}
+
+#[cfg(feature = "latex")]
+#[test]
+fn latex() {
+  let source = "# Intro\n\nSome _emph_ and `a_b` with 50% -- see [here](#Intro).[^n]\n\n\
+                [^n]: A note.\n\n3. c\n4. d\n\n- [x] done\n\n``` rust\nfn main() {}\n```\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(
+    doc.to_latex(&djot::LatexOpts::default()),
+    r#"\section{Intro}\label{Intro}
+
+Some \emph{emph} and \texttt{a\_b} with 50\% -- see \hyperref[Intro]{here}.\footnote{A note.}
+
+\begin{enumerate}
+\setcounter{enumi}{2}
+\item c
+\item d
+\end{enumerate}
+
+\begin{itemize}
+\item[$\boxtimes$] done
+\end{itemize}
+
+\begin{verbatim}
+fn main() {}
+\end{verbatim}
+"#
+  );
+  let doc = djot::Document::parse("![cat](cat.png)\n");
+  let latex = doc.to_latex(&djot::LatexOpts { standalone: true });
+  assert!(latex.starts_with("\\documentclass{article}\n"));
+  assert!(latex.ends_with("\\includegraphics{cat.png}\n\\end{document}\n"));
+
+  // the code can't end the environment early
+  let doc = djot::Document::parse("```\n\\end{verbatim} {x}\n```\n");
+  assert_eq!(
+    doc.to_latex(&djot::LatexOpts::default()),
+    "\\begin{alltt}\n\\textbackslash{}end\\{verbatim\\} \\{x\\}\n\\end{alltt}\n"
+  );
+}
+
+#[test]
+fn hard_breaks() {
+  let source = "a line  \\\nbreak and a\\ non-breaking space\n";
+  let doc = djot::Document::parse(source);
+  assert_eq!(doc.to_html(), "<p>a line<br>\nbreak and a&nbsp;non-breaking space</p>\n");
+  assert_eq!(doc.to_djot(), "a line\\\nbreak and a\\ non-breaking space\n");
+  djot::roundtrip_check(source).unwrap();
+}
+
 #[derive(Debug, Default)]
 struct TestCase {
   djot: String,